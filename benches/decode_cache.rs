@@ -0,0 +1,30 @@
+//! Compares execution speed with and without the decode cache:
+//! `cargo bench --bench decode_cache`
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use libchip8::emulator::Emulator;
+
+const STEPS: u64 = 10_000;
+
+/// Tight loop of arithmetic, skips and jumps that never halts
+const LOOP: [u16; 7] = [0x7001, 0x8014, 0x4000, 0x7101, 0x3155, 0x1200, 0x1200];
+
+fn decode_cache(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode_cache");
+    group.throughput(Throughput::Elements(STEPS));
+    for (name, cache) in [("uncached", false), ("cached", true)].iter() {
+        let mut e = Emulator::new();
+        e.set_decode_cache(*cache);
+        e.store_instr(&LOOP);
+        group.bench_function(BenchmarkId::new("loop", name), |b| {
+            b.iter(|| {
+                for _ in 0..STEPS {
+                    e.step().expect("loop executes valid instructions");
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode_cache);
+criterion_main!(benches);
@@ -0,0 +1,41 @@
+//! Compares DRW speed of the byte-wise `BitScreen` with the pixel-wise
+//! `Screen` and the color-keeping `FrameBuffer`: `cargo bench --bench draw`
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use libchip8::display::{BitScreen, FrameBuffer, Scr, Screen, HIRES_COLS, HIRES_ROWS};
+use libchip8::emulator::Emulator;
+
+const STEPS: u64 = 10_000;
+
+/// Draws 15-row sprites from memory at 0 at ever moving, unaligned
+/// positions, overlapping and wrapping around screen edges
+const LOOP: [u16; 6] = [0xA000, 0xD01F, 0x7007, 0x7103, 0xD01F, 0x1202];
+
+/// Screen implementation benchmarked under name
+fn screen(name: &str) -> Box<dyn Scr> {
+    match name {
+        "Screen" => Box::new(Screen::new()),
+        "BitScreen" => Box::new(BitScreen::new()),
+        _ => Box::new(FrameBuffer::new(vec![0; HIRES_COLS * HIRES_ROWS])),
+    }
+}
+
+fn draw(c: &mut Criterion) {
+    let mut group = c.benchmark_group("draw");
+    group.throughput(Throughput::Elements(STEPS));
+    for name in ["Screen", "BitScreen", "FrameBuffer"].iter() {
+        let mut e = Emulator::with_screen(screen(name));
+        e.store_font();
+        e.store_instr(&LOOP);
+        group.bench_function(BenchmarkId::new("sprites", name), |b| {
+            b.iter(|| {
+                for _ in 0..STEPS {
+                    e.step().expect("loop executes valid instructions");
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, draw);
+criterion_main!(benches);
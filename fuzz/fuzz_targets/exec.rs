@@ -0,0 +1,15 @@
+#![no_main]
+use libchip8::emulator::Emulator;
+use libfuzzer_sys::fuzz_target;
+
+// Runs arbitrary bytes as a ROM for a bounded number of steps;
+// any panic is a bug in the emulator.
+fuzz_target!(|data: &[u8]| {
+    let mut e = Emulator::new();
+    e.store_font();
+    e.store_bytes(data);
+    for _ in 0..10_000 {
+        let _ = e.step();
+        e.tick();
+    }
+});
@@ -0,0 +1,59 @@
+/* C API of libchip8: build with `cargo build --release --features ffi`
+ * and link against liblibchip8 (-llibchip8) */
+#ifndef LIBCHIP8_H
+#define LIBCHIP8_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* status codes; negative ones are errors */
+#define CHIP8_OK 0
+#define CHIP8_HALTED 1
+#define CHIP8_BREAKPOINT 2
+#define CHIP8_KEY_WAIT 3
+#define CHIP8_INVALID_ARGUMENT (-1)
+#define CHIP8_ROM_TOO_LARGE (-2)
+#define CHIP8_INVALID_OPCODE (-3)
+#define CHIP8_EXECUTION_ERROR (-4)
+
+/* profiles accepted by chip8_new */
+#define CHIP8_PROFILE_DEFAULT 0
+#define CHIP8_PROFILE_VIP 1
+#define CHIP8_PROFILE_CHIP48 2
+#define CHIP8_PROFILE_SCHIP 3
+#define CHIP8_PROFILE_XOCHIP 4
+
+typedef struct Chip8 Chip8;
+
+/* NULL for unknown profile */
+Chip8 *chip8_new(int profile);
+void chip8_free(Chip8 *c);
+
+int chip8_load_rom(Chip8 *c, const uint8_t *rom, size_t len);
+int chip8_reset(Chip8 *c);
+
+/* one instruction, timers not ticked */
+int chip8_step(Chip8 *c);
+/* one 60 Hz frame */
+int chip8_run_frame(Chip8 *c);
+
+/* width * height color indices, row by row; valid until next call on c */
+const uint8_t *chip8_framebuffer(Chip8 *c);
+size_t chip8_screen_width(const Chip8 *c);
+size_t chip8_screen_height(const Chip8 *c);
+
+/* key 0..15 of the hex keypad */
+int chip8_key_down(Chip8 *c, int key);
+int chip8_key_up(Chip8 *c, int key);
+
+int chip8_sound_active(const Chip8 *c);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,445 @@
+//! Assembler for the mnemonic dialect produced by [`crate::disasm`].
+//!
+//! Each line holds optional labels (`name:`), an instruction or a data
+//! directive (`DB 1, 0x02` bytes, `DW 0x1234` words) and an optional
+//! comment starting with `;`. Mnemonics and registers are case-insensitive;
+//! numbers are decimal, `0x` hexadecimal or `0b` binary. Jump, call and
+//! `LD I` targets as well as `DW` values may refer to labels.
+use crate::cpu::{Addr, Opcode};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// Assembles statements separated by `;` into bytes loadable at
+/// [`START_ADDR`], e.g. `chip8_asm! { start: LD V0, 5; ADD V0, 1; JP start; }`.
+///
+/// Panics with the offending statement number on invalid source, which is
+/// what tests and examples using it want.
+#[macro_export]
+macro_rules! chip8_asm {
+    ($($t:tt)*) => {
+        match $crate::asm::assemble_statements(stringify!($($t)*), $crate::asm::START_ADDR) {
+            Ok(bytes) => bytes,
+            Err(e) => panic!("chip8_asm!: statement {}: {}", e.line, e.message),
+        }
+    };
+}
+
+/// Default load address of assembled programs
+pub const START_ADDR: Addr = 0x200;
+
+/// Assembly failure with 1-based source line
+#[derive(Debug, PartialEq, Clone)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+/// Assembles source meant to be loaded at [`START_ADDR`]
+pub fn assemble(src: &str) -> Result<Vec<u8>, AsmError> {
+    assemble_at(src, START_ADDR)
+}
+
+/// Assembles source meant to be loaded at base address
+pub fn assemble_at(src: &str, base: Addr) -> Result<Vec<u8>, AsmError> {
+    assemble_lines(src.lines(), base)
+}
+
+/// Assembles statements separated by `;` instead of newlines,
+/// as produced by `stringify!` in macros; comments are not supported
+pub fn assemble_statements(src: &str, base: Addr) -> Result<Vec<u8>, AsmError> {
+    assemble_lines(src.split(';'), base)
+}
+
+/// Assembles single instruction; `LD I, LONG` may omit its address,
+/// which is not part of the opcode
+pub fn assemble_opcode(src: &str) -> Result<Opcode, AsmError> {
+    let src = src.trim();
+    let normalized: Vec<String> = src
+        .split_whitespace()
+        .map(|w| w.to_ascii_uppercase())
+        .collect();
+    if normalized == ["LD", "I,", "LONG"] {
+        return Ok(Opcode::LDIL);
+    }
+    match parse_stmt(src).map_err(|m| err(1, &m))? {
+        Stmt::Instr(m, ops) => match (m.as_str(), &ops[..]) {
+            ("LD", [Operand::I, Operand::Long(_)]) => Ok(Opcode::LDIL),
+            _ => encode_instr(&m, &ops, &HashMap::new()).map_err(|m| err(1, &m)),
+        },
+        _ => Err(err(1, "expected instruction")),
+    }
+}
+
+#[derive(Debug)]
+enum Value {
+    Num(u32),
+    Label(String),
+}
+
+#[derive(Debug)]
+enum Operand {
+    V(usize),
+    I,
+    IndirectI,
+    K,
+    DT,
+    ST,
+    F,
+    HF,
+    R,
+    B,
+    Long(Value),
+    Val(Value),
+}
+
+enum Stmt {
+    Instr(String, Vec<Operand>),
+    Bytes(Vec<Value>),
+    Words(Vec<Value>),
+}
+
+impl Stmt {
+    fn size(&self) -> usize {
+        match self {
+            Stmt::Instr(m, ops) if m == "LD" && matches!(ops.get(1), Some(Operand::Long(_))) => 4,
+            Stmt::Instr(..) => 2,
+            Stmt::Bytes(v) => v.len(),
+            Stmt::Words(v) => v.len() * 2,
+        }
+    }
+}
+
+fn err(line: usize, msg: &str) -> AsmError {
+    AsmError {
+        line,
+        message: msg.to_string(),
+    }
+}
+
+fn assemble_lines<'a, I>(lines: I, base: Addr) -> Result<Vec<u8>, AsmError>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut labels: HashMap<String, u32> = HashMap::new();
+    let mut stmts = vec![];
+    let mut addr = base as u32;
+    for (idx, line) in lines.enumerate() {
+        let lineno = idx + 1;
+        let mut rest = line.split(';').next().unwrap_or("").trim();
+        while let Some(colon) = rest.find(':') {
+            let label = rest[..colon].trim();
+            if !is_ident(label) {
+                return Err(err(lineno, &format!("invalid label '{}'", label)));
+            }
+            if labels.insert(label.to_string(), addr).is_some() {
+                return Err(err(lineno, &format!("duplicate label '{}'", label)));
+            }
+            rest = rest[colon + 1..].trim();
+        }
+        if rest.is_empty() {
+            continue;
+        }
+        let stmt = parse_stmt(rest).map_err(|m| err(lineno, &m))?;
+        addr += stmt.size() as u32;
+        stmts.push((lineno, stmt));
+    }
+
+    let mut out = vec![];
+    for (lineno, stmt) in stmts {
+        encode(&stmt, &labels, &mut out).map_err(|m| err(lineno, &m))?;
+    }
+    Ok(out)
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_stmt(s: &str) -> Result<Stmt, String> {
+    let (mnemonic, args) = match s.find(char::is_whitespace) {
+        Some(pos) => (&s[..pos], s[pos..].trim()),
+        None => (s, ""),
+    };
+    let mnemonic = mnemonic.to_ascii_uppercase();
+    let args: Vec<&str> = if args.is_empty() {
+        vec![]
+    } else {
+        args.split(',').map(str::trim).collect()
+    };
+    match mnemonic.as_str() {
+        "DB" => Ok(Stmt::Bytes(
+            args.iter()
+                .map(|a| parse_value(a))
+                .collect::<Result<_, _>>()?,
+        )),
+        "DW" => Ok(Stmt::Words(
+            args.iter()
+                .map(|a| parse_value(a))
+                .collect::<Result<_, _>>()?,
+        )),
+        _ => Ok(Stmt::Instr(
+            mnemonic,
+            args.iter()
+                .map(|a| parse_operand(a))
+                .collect::<Result<_, _>>()?,
+        )),
+    }
+}
+
+fn parse_operand(s: &str) -> Result<Operand, String> {
+    let upper = s.to_ascii_uppercase();
+    let op = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "K" => Operand::K,
+        "DT" => Operand::DT,
+        "ST" => Operand::ST,
+        "F" => Operand::F,
+        "HF" => Operand::HF,
+        "R" => Operand::R,
+        "B" => Operand::B,
+        _ if upper.starts_with("LONG ") => Operand::Long(parse_value(s[5..].trim())?),
+        _ if upper.len() == 2 && upper.starts_with('V') => {
+            match usize::from_str_radix(&upper[1..], 16) {
+                Ok(r) => Operand::V(r),
+                Err(_) => Operand::Val(parse_value(s)?),
+            }
+        }
+        _ => Operand::Val(parse_value(s)?),
+    };
+    Ok(op)
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+    let lower = s.to_ascii_lowercase();
+    let num = if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        u32::from_str_radix(bin, 2)
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse()
+    } else if is_ident(s) {
+        return Ok(Value::Label(s.to_string()));
+    } else {
+        return Err(format!("invalid operand '{}'", s));
+    };
+    num.map(Value::Num)
+        .map_err(|_| format!("invalid number '{}'", s))
+}
+
+fn resolve(v: &Value, labels: &HashMap<String, u32>, max: u32) -> Result<u32, String> {
+    let n = match v {
+        Value::Num(n) => *n,
+        Value::Label(l) => *labels
+            .get(l)
+            .ok_or_else(|| format!("unknown label '{}'", l))?,
+    };
+    if n > max {
+        Err(format!("value 0x{:X} exceeds 0x{:X}", n, max))
+    } else {
+        Ok(n)
+    }
+}
+
+fn encode(stmt: &Stmt, labels: &HashMap<String, u32>, out: &mut Vec<u8>) -> Result<(), String> {
+    match stmt {
+        Stmt::Bytes(vals) => {
+            for v in vals {
+                out.push(resolve(v, labels, 0xFF)? as u8);
+            }
+        }
+        Stmt::Words(vals) => {
+            for v in vals {
+                out.extend_from_slice(&(resolve(v, labels, 0xFFFF)? as u16).to_be_bytes());
+            }
+        }
+        Stmt::Instr(m, ops) => {
+            if let (true, [Operand::I, Operand::Long(v)]) = (m == "LD", &ops[..]) {
+                out.extend_from_slice(&Opcode::LDIL.to_instr().to_be_bytes());
+                out.extend_from_slice(&(resolve(v, labels, 0xFFFF)? as u16).to_be_bytes());
+            } else {
+                let op = encode_instr(m, ops, labels)?;
+                out.extend_from_slice(&op.to_instr().to_be_bytes());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn encode_instr(m: &str, ops: &[Operand], labels: &HashMap<String, u32>) -> Result<Opcode, String> {
+    use Operand::*;
+    let addr = |v: &Value| resolve(v, labels, 0xFFF).map(|a| a as u16);
+    let byte = |v: &Value| resolve(v, labels, 0xFF).map(|a| a as u8);
+    let nibble = |v: &Value| resolve(v, labels, 0xF).map(|a| a as u8);
+    let reg = |r: &usize| {
+        if *r < 16 {
+            Ok(*r)
+        } else {
+            Err(format!("invalid register V{:X}", r))
+        }
+    };
+    let op = match (m, ops) {
+        ("CLS", []) => Opcode::CLS,
+        ("RET", []) => Opcode::RET,
+        ("SCR", []) => Opcode::SCR,
+        ("SCL", []) => Opcode::SCL,
+        ("EXIT", []) => Opcode::EXIT,
+        ("LOW", []) => Opcode::LOW,
+        ("HIGH", []) => Opcode::HIGH,
+        ("SCD", [Val(n)]) => Opcode::SCD(nibble(n)?),
+        ("PLANE", [Val(n)]) => Opcode::PLANE(nibble(n)? as usize),
+        ("JP", [Val(a)]) => Opcode::JP(addr(a)?),
+        ("JP", [V(0), Val(a)]) => Opcode::JPOFF(addr(a)?),
+        ("CALL", [Val(a)]) => Opcode::CALL(addr(a)?),
+        ("SE", [V(x), Val(b)]) => Opcode::SE(reg(x)?, byte(b)?),
+        ("SE", [V(x), V(y)]) => Opcode::SER(reg(x)?, reg(y)?),
+        ("SNE", [V(x), Val(b)]) => Opcode::SNE(reg(x)?, byte(b)?),
+        ("SNE", [V(x), V(y)]) => Opcode::SNER(reg(x)?, reg(y)?),
+        ("LD", [V(x), Val(b)]) => Opcode::LD(reg(x)?, byte(b)?),
+        ("LD", [V(x), V(y)]) => Opcode::LDR(reg(x)?, reg(y)?),
+        ("LD", [I, Val(a)]) => Opcode::LDI(addr(a)?),
+        ("LD", [V(x), DT]) => Opcode::DTGET(reg(x)?),
+        ("LD", [V(x), K]) => Opcode::KEYSET(reg(x)?),
+        ("LD", [DT, V(x)]) => Opcode::DTSET(reg(x)?),
+        ("LD", [ST, V(x)]) => Opcode::STSET(reg(x)?),
+        ("LD", [F, V(x)]) => Opcode::IDIG(reg(x)?),
+        ("LD", [HF, V(x)]) => Opcode::LDHF(reg(x)?),
+        ("LD", [R, V(x)]) => Opcode::RPLSTORE(reg(x)?),
+        ("LD", [V(x), R]) => Opcode::RPLLOAD(reg(x)?),
+        ("LD", [B, V(x)]) => Opcode::BCD(reg(x)?),
+        ("LD", [IndirectI, V(x)]) => Opcode::REGSSTORE(reg(x)?),
+        ("LD", [V(x), IndirectI]) => Opcode::REGLOAD(reg(x)?),
+        ("ADD", [V(x), Val(b)]) => Opcode::ADD(reg(x)?, byte(b)?),
+        ("ADD", [V(x), V(y)]) => Opcode::ADDR(reg(x)?, reg(y)?),
+        ("ADD", [I, V(x)]) => Opcode::IINC(reg(x)?),
+        ("OR", [V(x), V(y)]) => Opcode::OR(reg(x)?, reg(y)?),
+        ("AND", [V(x), V(y)]) => Opcode::AND(reg(x)?, reg(y)?),
+        ("XOR", [V(x), V(y)]) => Opcode::XOR(reg(x)?, reg(y)?),
+        ("SUB", [V(x), V(y)]) => Opcode::SUBR(reg(x)?, reg(y)?),
+        ("SUBN", [V(x), V(y)]) => Opcode::SUBRN(reg(x)?, reg(y)?),
+        ("SHR", [V(x)]) => Opcode::SHR(reg(x)?, 0),
+        ("SHR", [V(x), V(y)]) => Opcode::SHR(reg(x)?, reg(y)?),
+        ("SHL", [V(x)]) => Opcode::SHL(reg(x)?, 0),
+        ("SHL", [V(x), V(y)]) => Opcode::SHL(reg(x)?, reg(y)?),
+        ("RND", [V(x), Val(b)]) => Opcode::RND(reg(x)?, byte(b)?),
+        ("DRW", [V(x), V(y), Val(n)]) => Opcode::DRW(reg(x)?, reg(y)?, nibble(n)?),
+        ("SKP", [V(x)]) => Opcode::SKP(reg(x)?),
+        ("SKNP", [V(x)]) => Opcode::SKNP(reg(x)?),
+        ("SAVE", [V(x), V(y)]) => Opcode::SAVE(reg(x)?, reg(y)?),
+        ("LOAD", [V(x), V(y)]) => Opcode::LOAD(reg(x)?, reg(y)?),
+        _ => {
+            return Err(format!(
+                "invalid instruction '{}' with {} operand(s)",
+                m,
+                ops.len()
+            ))
+        }
+    };
+    Ok(op)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn assemble_test() {
+        let src = "
+            start:  LD V0, 5      ; counter
+                    LD v1, 0b1
+            loop:   ADD V0, 0xFF
+                    SE V0, 0
+                    JP loop
+                    LD I, sprite
+                    JP start
+            sprite: db 0xF0, 144
+                    dw 0x1234
+        ";
+        assert_eq!(
+            vec![
+                0x60, 0x05, 0x61, 0x01, 0x70, 0xFF, 0x30, 0x00, 0x12, 0x04, 0xA2, 0x0E, 0x12, 0x00,
+                0xF0, 0x90, 0x12, 0x34
+            ],
+            assemble(src).unwrap()
+        );
+    }
+
+    #[test]
+    fn run_assembled_test() {
+        let mut e = Emulator::new();
+        e.store_bytes(&assemble("LD V1, 5\nLD V2, 9\nADD V1, V2\nEXIT").unwrap());
+        e.run().unwrap();
+        assert_eq!(14, e.cpu.regs[1]);
+    }
+
+    #[test]
+    fn long_i_test() {
+        let bytes = assemble("LD I, LONG data\ndata: db 1").unwrap();
+        assert_eq!(vec![0xF0, 0x00, 0x02, 0x04, 0x01], bytes);
+    }
+
+    #[test]
+    fn disasm_roundtrip_test() {
+        for op in Opcode::iter_examples().filter(|op| *op != Opcode::LDIL) {
+            let text = disasm::mnemonic(&op);
+            assert_eq!(
+                Ok(op.to_instr()),
+                assemble_opcode(&text).map(|o| o.to_instr()),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn errors_test() {
+        assert_eq!(2, assemble("CLS\nJP nowhere").unwrap_err().line);
+        assert_eq!(1, assemble("LD V0, 256").unwrap_err().line);
+        assert_eq!(1, assemble("FOO V0").unwrap_err().line);
+        assert_eq!(3, assemble("a:\nCLS\na: RET").unwrap_err().line);
+    }
+
+    #[test]
+    fn macro_test() {
+        let bytes = chip8_asm! {
+            start: LD v0, 5;
+            loop: ADD v0, 0xFF;
+            SE v0, 0;
+            JP loop;
+            LD I, data;
+            LD [I], V0;
+            EXIT;
+            data: DB 0x12;
+        };
+        assert_eq!(assemble("start: LD V0, 5\nloop: ADD V0, 0xFF\nSE V0, 0\nJP loop\nLD I, data\nLD [I], V0\nEXIT\ndata: DB 0x12").unwrap(), bytes);
+        let mut e = Emulator::new();
+        e.store_bytes(&bytes);
+        e.run().unwrap();
+        assert_eq!(Some(&0), e.mem.get(0x20E));
+    }
+
+    #[test]
+    #[should_panic(expected = "statement 2")]
+    fn macro_error_test() {
+        chip8_asm! { CLS; JP nowhere; };
+    }
+
+    #[test]
+    fn statements_test() {
+        let bytes = assemble_statements("start : LD V0 , 5 ; JP start ;", 0x300).unwrap();
+        assert_eq!(vec![0x60, 0x05, 0x13, 0x00], bytes);
+    }
+}
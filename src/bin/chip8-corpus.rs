@@ -0,0 +1,95 @@
+//! ROM corpus report: `chip8-corpus [--frames N] [--profile vip|chip48|schip|xochip] [--baseline REPORT] DIR`
+//!
+//! Runs every ROM of DIR headlessly and prints one line per ROM with its
+//! status, instructions executed and screen checksum, followed by counts
+//! of each status on stderr. With `--baseline` prints only ROMs whose
+//! outcome changed since the given report and exits with status 2 if
+//! any did.
+use libchip8::corpus::{self, Config, Report};
+use libchip8::quirks::Profile;
+use std::fs;
+use std::process;
+
+struct Args {
+    config: Config,
+    baseline: Option<String>,
+    dir: String,
+}
+
+const USAGE: &str =
+    "usage: chip8-corpus [--frames N] [--profile vip|chip48|schip|xochip] [--baseline REPORT] DIR";
+
+fn parse_args() -> Result<Args, String> {
+    let mut config = Config::default();
+    let mut baseline = None;
+    let mut dir = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => {
+                let v = args.next().ok_or("--frames needs a value")?;
+                config.frames = v
+                    .parse()
+                    .map_err(|_| format!("invalid --frames value '{}'", v))?;
+            }
+            "--profile" => {
+                let v = args.next().ok_or("--profile needs a value")?;
+                config.profile = Some(v.parse::<Profile>()?);
+            }
+            "--baseline" => baseline = Some(args.next().ok_or("--baseline needs a value")?),
+            _ if dir.is_none() && !arg.starts_with('-') => dir = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    let dir = dir.ok_or(USAGE)?;
+    Ok(Args {
+        config,
+        baseline,
+        dir,
+    })
+}
+
+/// Returns whether outcomes changed since the baseline
+fn run(args: Args) -> Result<bool, String> {
+    let baseline = match &args.baseline {
+        Some(path) => {
+            let text =
+                fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+            Some(Report::parse(&text).map_err(|e| format!("{}: {}", path, e))?)
+        }
+        None => None,
+    };
+    let report = corpus::run_dir(&args.dir, &args.config)
+        .map_err(|e| format!("cannot read {}: {}", args.dir, e))?;
+    let changed = match baseline {
+        Some(baseline) => {
+            let changes = report.changes(&baseline);
+            for c in &changes {
+                println!("{}", c);
+            }
+            !changes.is_empty()
+        }
+        None => {
+            print!("{}", report);
+            false
+        }
+    };
+    let counts: Vec<String> = report
+        .summary()
+        .iter()
+        .map(|(status, n)| format!("{} {}", n, status))
+        .collect();
+    eprintln!("{} ROMs: {}", report.roms.len(), counts.join(", "));
+    Ok(changed)
+}
+
+fn main() {
+    match parse_args().and_then(run) {
+        Ok(false) => {}
+        Ok(true) => process::exit(2),
+        Err(msg) => {
+            eprintln!("chip8-corpus: {}", msg);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Disassembler: `chip8-dasm [--start ADDR] [--source] ROM`
+//!
+//! Prints address, raw word and mnemonic of every instruction of ROM
+//! loaded at ADDR (0x200 by default). With `--source` prints labeled
+//! source that `asm::assemble` turns back into the same bytes.
+use libchip8::cpu::Addr;
+use libchip8::disasm;
+use libchip8::emulator::Emulator;
+use std::fs;
+use std::process;
+
+#[path = "common/addr.rs"]
+mod addr;
+
+struct Args {
+    start: Addr,
+    source: bool,
+    rom: String,
+}
+
+const USAGE: &str = "usage: chip8-dasm [--start ADDR] [--source] ROM";
+
+fn parse_args() -> Result<Args, String> {
+    let mut start = Emulator::DEFAULT_START_ADDR;
+    let mut source = false;
+    let mut rom = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--start" => {
+                start = addr::parse_addr(&args.next().ok_or("--start needs a value")?, 10)?
+            }
+            "--source" => source = true,
+            _ if rom.is_none() && !arg.starts_with('-') => rom = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    let rom = rom.ok_or(USAGE)?;
+    Ok(Args { start, source, rom })
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let bytes = fs::read(&args.rom).map_err(|e| format!("cannot read {}: {}", args.rom, e))?;
+    if args.source {
+        print!("{}", disasm::disassemble_labeled(&bytes, args.start));
+    } else {
+        for (addr, word, text) in disasm::disassemble(&bytes, args.start) {
+            println!("{:03X}: {:04X}  {}", addr, word, text);
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let result = parse_args().and_then(run);
+    if let Err(msg) = result {
+        eprintln!("chip8-dasm: {}", msg);
+        process::exit(1);
+    }
+}
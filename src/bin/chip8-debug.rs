@@ -0,0 +1,411 @@
+//! Terminal debugger: `chip8-debug [--ips N] [--profile vip|chip48|schip|xochip] ROM`
+//!
+//! Shows the screen, registers, call stack, disassembly around pc and a
+//! memory view, and reads commands typed at the prompt:
+//!
+//! - `s [N]` steps N instructions (1 by default)
+//! - `c` runs in real time until a breakpoint, watchpoint, EXIT or Esc;
+//!   keys `1234 qwer asdf zxcv` press the hex keypad meanwhile
+//! - `b ADDR` toggles breakpoint, `w ADDR [r|w|rw]` toggles watchpoint
+//! - `m ADDR` moves the memory view, `k KEY` presses and releases a key
+//! - `r` restarts the ROM, `q` quits
+//!
+//! An empty line repeats the last command; Ctrl-C quits at any time.
+use libchip8::cpu::Addr;
+use libchip8::debug::{Access, StopReason};
+use libchip8::disasm;
+use libchip8::display::Scr;
+use libchip8::emulator::{Emulator, LoopConfig};
+use libchip8::input::{KeyEvent, KeyMap};
+use libchip8::loader;
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::ops::{ControlFlow, Range};
+use std::process;
+use std::thread;
+use std::time::Duration;
+use term::{parse_args, poll_keys, Args, Poll, RawTerminal};
+
+#[path = "common/addr.rs"]
+mod addr;
+#[path = "common/term.rs"]
+mod term;
+
+/// Instructions shown before and after pc
+const CONTEXT: u16 = 6;
+
+/// Rows of 16 bytes in the memory view
+const MEMORY_ROWS: u16 = 6;
+
+/// Width of the register column, in characters
+const PANEL_WIDTH: usize = 32;
+
+/// Input polling interval at the prompt
+const POLL: Duration = Duration::from_millis(10);
+
+const USAGE: &str = "usage: chip8-debug [--ips N] [--profile vip|chip48|schip|xochip] ROM";
+
+/// Command typed at the prompt
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Step(usize),
+    Continue,
+    Breakpoint(Addr),
+    Watchpoint(Addr, Access),
+    Memory(Addr),
+    Key(usize),
+    Reset,
+    Quit,
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let addr = || addr::parse_addr(words.get(1).ok_or("missing address")?, 16);
+    match words.first().copied().unwrap_or("") {
+        "s" | "step" => match words.get(1) {
+            Some(n) => n
+                .parse()
+                .map(Command::Step)
+                .map_err(|_| format!("invalid count '{}'", n)),
+            None => Ok(Command::Step(1)),
+        },
+        "c" | "continue" => Ok(Command::Continue),
+        "b" | "break" => addr().map(Command::Breakpoint),
+        "w" | "watch" => {
+            let access = match words.get(2).copied().unwrap_or("w") {
+                "r" => Access::Read,
+                "w" => Access::Write,
+                "rw" => Access::ReadWrite,
+                other => return Err(format!("invalid access '{}'", other)),
+            };
+            Ok(Command::Watchpoint(addr()?, access))
+        }
+        "m" | "mem" => addr().map(Command::Memory),
+        "k" | "key" => {
+            let k = words.get(1).ok_or("missing key")?;
+            match usize::from_str_radix(k, 16) {
+                Ok(key) if key < 16 => Ok(Command::Key(key)),
+                _ => Err(format!("invalid key '{}'", k)),
+            }
+        }
+        "r" | "reset" => Ok(Command::Reset),
+        "q" | "quit" => Ok(Command::Quit),
+        other => Err(format!("unknown command '{}'", other)),
+    }
+}
+
+/// Debugger state kept between commands
+struct Debugger {
+    e: Emulator,
+    breakpoints: BTreeSet<Addr>,
+    watchpoints: Vec<(Range<Addr>, Access)>,
+    memory: Addr,
+    last: Command,
+    status: String,
+}
+
+impl Debugger {
+    fn new(e: Emulator) -> Self {
+        let memory = e.start_addr();
+        Debugger {
+            e,
+            breakpoints: BTreeSet::new(),
+            watchpoints: vec![],
+            memory,
+            last: Command::Step(1),
+            status: "s [N] step, c continue, b/w ADDR toggle break/watch, m ADDR, k KEY, r, q"
+                .to_string(),
+        }
+    }
+
+    /// Executes command; returns false on quit
+    fn apply(&mut self, cmd: Command) -> bool {
+        self.status.clear();
+        match cmd {
+            Command::Step(n) => match self.e.step_n(n) {
+                Ok(summary) => {
+                    self.status = format!("executed {}", summary.executed);
+                    if let Some(reason) = summary.stop {
+                        self.status.push_str(&format!(", stopped: {:?}", reason));
+                    } else if summary.blocked_on_input {
+                        self.status.push_str(", waiting for key");
+                    }
+                }
+                Err(err) => self.status = format!("error: {}", err),
+            },
+            Command::Continue => return self.cont(),
+            Command::Breakpoint(addr) => {
+                if self.e.add_breakpoint(addr) {
+                    self.breakpoints.insert(addr);
+                    self.status = format!("breakpoint at 0x{:03X}", addr);
+                } else {
+                    self.e.remove_breakpoint(addr);
+                    self.breakpoints.remove(&addr);
+                    self.status = format!("removed breakpoint at 0x{:03X}", addr);
+                }
+            }
+            Command::Watchpoint(addr, access) => {
+                let range = addr..addr.wrapping_add(1);
+                if self.e.remove_watchpoint(&range) {
+                    self.watchpoints.retain(|(r, _)| *r != range);
+                    self.status = format!("removed watchpoint at 0x{:03X}", addr);
+                } else {
+                    self.e.add_watchpoint(range.clone(), access);
+                    self.watchpoints.push((range, access));
+                    self.status = format!("watching {:?} at 0x{:03X}", access, addr);
+                }
+            }
+            Command::Memory(addr) => self.memory = addr & !0xF,
+            Command::Key(key) => {
+                self.e.kbd.push(KeyEvent::Pressed(key));
+                self.e.kbd.push(KeyEvent::Released(key));
+                self.status = format!("pressed key {:X}", key);
+            }
+            Command::Reset => {
+                self.e.soft_reset();
+                self.status = "restarted".to_string();
+            }
+            Command::Quit => return false,
+        }
+        true
+    }
+
+    /// Runs in real time until a stop or Esc; returns false on Ctrl-C
+    fn cont(&mut self) -> bool {
+        let keymap = KeyMap::qwerty();
+        let mut held = [0u8; 16];
+        let mut quit = false;
+        let mut stop = None;
+        self.status = "running, Esc stops".to_string();
+        let breakpoints = &self.breakpoints;
+        let watchpoints = &self.watchpoints;
+        let memory = self.memory;
+        let status = &self.status;
+        // step off a breakpoint at pc before running frames
+        let result = match self.e.step() {
+            Ok(Some(reason)) if reason != StopReason::KeyWait => {
+                stop = Some(reason);
+                Ok(())
+            }
+            Ok(_) => self.e.run_with(LoopConfig::default(), |frame| {
+                if frame.changed_rows != 0 {
+                    print!(
+                        "{}",
+                        render(frame.emulator, breakpoints, watchpoints, memory, status)
+                    );
+                    let _ = io::stdout().flush();
+                }
+                if let Some(reason) = frame.stop.filter(|r| *r != StopReason::KeyWait) {
+                    stop = Some(reason);
+                    return ControlFlow::Break(());
+                }
+                match poll_keys(frame.emulator, &keymap, &mut held) {
+                    Poll::Keys => ControlFlow::Continue(()),
+                    Poll::Escape => ControlFlow::Break(()),
+                    Poll::Interrupt => {
+                        quit = true;
+                        ControlFlow::Break(())
+                    }
+                }
+            }),
+            Err(err) => Err(err),
+        };
+        for (key, frames) in held.iter().enumerate() {
+            if *frames > 0 {
+                self.e.kbd.push(KeyEvent::Released(key));
+            }
+        }
+        self.status = match (result, stop) {
+            (Err(err), _) => format!("error: {}", err),
+            (Ok(()), Some(reason)) => format!("stopped: {:?}", reason),
+            (Ok(()), None) => "interrupted".to_string(),
+        };
+        !quit
+    }
+
+    fn render(&self, prompt: &str) -> String {
+        let mut out = render(
+            &self.e,
+            &self.breakpoints,
+            &self.watchpoints,
+            self.memory,
+            &self.status,
+        );
+        out.push_str(&format!("> {}\x1b[K", prompt));
+        out
+    }
+}
+
+/// Screen as lines of half-block characters, two pixel rows per line
+fn screen_lines(scr: &dyn Scr) -> Vec<String> {
+    let mut lines = vec![];
+    for y in (0..scr.height()).step_by(2) {
+        let mut line = String::new();
+        for x in 0..scr.width() {
+            let c = match (scr.pixel(x, y) != 0, scr.pixel(x, y + 1) != 0) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            };
+            line.push(c);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Registers, timers and call stack
+fn register_lines(e: &Emulator) -> Vec<String> {
+    let cpu = &e.cpu;
+    let mut lines = vec![format!(
+        "PC {:03X}  I {:03X}  DT {:02X}  ST {:02X}",
+        cpu.pc, cpu.i, cpu.dt, cpu.st
+    )];
+    for (r, regs) in cpu.regs.chunks(4).enumerate() {
+        let cells: Vec<String> = regs
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("V{:X} {:02X}", r * 4 + i, v))
+            .collect();
+        lines.push(cells.join("  "));
+    }
+    lines.push(String::new());
+    let state = if e.halted() {
+        "halted"
+    } else if e.waiting_for_key() {
+        "waiting for key"
+    } else {
+        "stopped"
+    };
+    lines.push(state.to_string());
+    lines.push("call stack:".to_string());
+    for frame in e.call_trace() {
+        lines.push(format!("  {}", frame));
+    }
+    lines
+}
+
+/// Disassembly around pc; `=>` marks pc, `*` breakpoints
+fn disassembly_lines(e: &Emulator, breakpoints: &BTreeSet<Addr>) -> Vec<String> {
+    let pc = e.cpu.pc;
+    let size = e.mem.size().min(Addr::MAX as usize + 1) as u32;
+    let start = (pc as u32).saturating_sub(CONTEXT as u32 * 2);
+    let end = (pc as u32 + (CONTEXT as u32 + 1) * 2).min(size);
+    disasm::disassemble_mem(&e.mem, start as Addr..end as Addr)
+        .into_iter()
+        .map(|(addr, word, text)| {
+            let marker = if addr == pc { "=>" } else { "  " };
+            let bp = if breakpoints.contains(&addr) {
+                '*'
+            } else {
+                ' '
+            };
+            format!("{}{}{:03X}  {:04X}  {}", marker, bp, addr, word, text)
+        })
+        .collect()
+}
+
+/// Whole debugger view, drawn from the top left corner
+fn render(
+    e: &Emulator,
+    breakpoints: &BTreeSet<Addr>,
+    watchpoints: &[(Range<Addr>, Access)],
+    memory: Addr,
+    status: &str,
+) -> String {
+    let screen = screen_lines(e.scr.as_ref());
+    let mut side = register_lines(e);
+    side.push(String::new());
+    side.extend(disassembly_lines(e, breakpoints));
+    let width = e.scr.width();
+    let mut out = String::from("\x1b[H");
+    for i in 0..screen.len().max(side.len()) {
+        let left = screen.get(i).map_or(String::new(), |l| l.clone());
+        let pad = width.saturating_sub(left.chars().count());
+        let right = side.get(i).map_or("", |s| s.as_str());
+        out.push_str(&format!(
+            "{}{} │ {:<w$}\x1b[K\r\n",
+            left,
+            " ".repeat(pad),
+            right,
+            w = PANEL_WIDTH
+        ));
+    }
+    out.push_str("\x1b[K\r\n");
+    let end = (memory as u32 + MEMORY_ROWS as u32 * 16).min(e.mem.size() as u32);
+    for line in e.mem.hexdump(memory..end as Addr).lines() {
+        out.push_str(&format!("{}\x1b[K\r\n", line));
+    }
+    let watched: Vec<String> = watchpoints
+        .iter()
+        .map(|(r, a)| format!("{:03X} {:?}", r.start, a))
+        .collect();
+    out.push_str(&format!("watch: {}\x1b[K\r\n", watched.join(", ")));
+    out.push_str(&format!("{}\x1b[K\r\n\x1b[J", status));
+    out
+}
+
+/// Reads a line at the prompt, echoing it; returns None on Ctrl-C
+fn read_line(d: &Debugger) -> Option<String> {
+    let mut line = String::new();
+    print!("{}", d.render(&line));
+    let _ = io::stdout().flush();
+    let mut buf = [0u8; 64];
+    loop {
+        let n = io::stdin().read(&mut buf).unwrap_or(0);
+        if n == 0 {
+            thread::sleep(POLL);
+            continue;
+        }
+        for &b in &buf[..n] {
+            match b {
+                0x03 => return None,
+                b'\r' | b'\n' => return Some(line),
+                0x7f | 0x08 => {
+                    line.pop();
+                }
+                b if b.is_ascii_graphic() || b == b' ' => line.push(b as char),
+                _ => {}
+            }
+        }
+        print!("\r> {}\x1b[K", line);
+        let _ = io::stdout().flush();
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let mut builder = Emulator::builder().ips(args.ips);
+    if let Some(profile) = args.profile {
+        builder = builder.profile(profile);
+    }
+    let mut e = builder.build();
+    loader::load(&mut e, &args.rom).map_err(|err| format!("{}: {}", args.rom, err))?;
+    let _term = RawTerminal::new(false).map_err(|err| format!("terminal: {}", err))?;
+    let mut d = Debugger::new(e);
+    while let Some(line) = read_line(&d) {
+        let cmd = if line.trim().is_empty() {
+            Ok(d.last.clone())
+        } else {
+            parse_command(&line)
+        };
+        match cmd {
+            Ok(cmd) => {
+                d.last = cmd.clone();
+                if !d.apply(cmd) {
+                    break;
+                }
+            }
+            Err(msg) => d.status = msg,
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let result = parse_args(USAGE).and_then(run);
+    if let Err(msg) = result {
+        eprintln!("chip8-debug: {}", msg);
+        process::exit(1);
+    }
+}
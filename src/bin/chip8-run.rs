@@ -0,0 +1,72 @@
+//! Terminal runner: `chip8-run [--ips N] [--profile vip|chip48|schip|xochip] ROM`
+//!
+//! Renders the screen with half-block characters and maps keys
+//! `1234 qwer asdf zxcv` to the hex keypad `123C 456D 789E A0BF`.
+//! Terminals report no key releases, so a key counts as held for a few
+//! frames after its last (auto-repeated) press. Esc or Ctrl-C quits.
+use libchip8::debug::StopReason;
+use libchip8::display::Scr;
+use libchip8::emulator::{Emulator, LoopConfig};
+use libchip8::input::KeyMap;
+use libchip8::loader;
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::process;
+use term::{parse_args, poll_keys, Args, Poll, RawTerminal};
+
+#[path = "common/term.rs"]
+mod term;
+
+const USAGE: &str = "usage: chip8-run [--ips N] [--profile vip|chip48|schip|xochip] ROM";
+
+/// Screen as lines of half-block characters, two pixel rows per line
+fn render(scr: &dyn Scr) -> String {
+    let mut out = String::from("\x1b[H");
+    for y in (0..scr.height()).step_by(2) {
+        for x in 0..scr.width() {
+            let c = match (scr.pixel(x, y) != 0, scr.pixel(x, y + 1) != 0) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            };
+            out.push(c);
+        }
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let mut builder = Emulator::builder().ips(args.ips);
+    if let Some(profile) = args.profile {
+        builder = builder.profile(profile);
+    }
+    let mut e = builder.build();
+    loader::load(&mut e, &args.rom).map_err(|err| format!("{}: {}", args.rom, err))?;
+    let _term = RawTerminal::new(true).map_err(|err| format!("terminal: {}", err))?;
+    let keymap = KeyMap::qwerty();
+    let mut held = [0u8; 16];
+    e.run_with(LoopConfig::default(), |frame| {
+        if frame.changed_rows != 0 {
+            print!("{}", render(frame.emulator.scr.as_ref()));
+            let _ = io::stdout().flush();
+        }
+        if frame.stop == Some(StopReason::Halted)
+            || poll_keys(frame.emulator, &keymap, &mut held) != Poll::Keys
+        {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    })
+    .map_err(|err| format!("{}\n{}", err, e.dump_state(false)))
+}
+
+fn main() {
+    let result = parse_args(USAGE).and_then(run);
+    if let Err(msg) = result {
+        eprintln!("chip8-run: {}", msg);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,12 @@
+//! Address parsing shared by `chip8-dasm` and `chip8-debug`
+use libchip8::cpu::Addr;
+
+/// Parses `0x` prefixed hexadecimal address, or address in radix
+/// without prefix
+pub fn parse_addr(s: &str, radix: u32) -> Result<Addr, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => Addr::from_str_radix(hex, 16),
+        None => Addr::from_str_radix(s, radix),
+    };
+    parsed.map_err(|_| format!("invalid address '{}'", s))
+}
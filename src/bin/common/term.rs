@@ -0,0 +1,117 @@
+//! Raw terminal input and command line shared by `chip8-run` and
+//! `chip8-debug`
+use libchip8::emulator::Emulator;
+use libchip8::input::{HostKey, KeyEvent, KeyMap};
+use libchip8::quirks::Profile;
+use std::io::{self, Read, Write};
+
+/// Frames a key stays pressed after its last press
+const HOLD_FRAMES: u8 = 8;
+
+/// Terminal switched to raw non-blocking input, restored when dropped
+pub struct RawTerminal {
+    saved: libc::termios,
+}
+
+impl RawTerminal {
+    pub fn new(hide_cursor: bool) -> io::Result<Self> {
+        // SAFETY: termios is plain data filled in by tcgetattr
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if hide_cursor {
+            print!("\x1b[?25l");
+        }
+        print!("\x1b[2J");
+        Ok(RawTerminal { saved })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved) };
+        println!("\x1b[?25h");
+        let _ = io::stdout().flush();
+    }
+}
+
+/// What [`poll_keys`] stopped at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Poll {
+    /// all pending input was read
+    Keys,
+    /// Esc was pressed
+    Escape,
+    /// Ctrl-C was pressed
+    Interrupt,
+}
+
+/// Reads pending input, pressing mapped keys and releasing them
+/// [`HOLD_FRAMES`] calls after their last press
+pub fn poll_keys(e: &mut Emulator, keymap: &KeyMap, held: &mut [u8; 16]) -> Poll {
+    let mut buf = [0u8; 64];
+    let n = io::stdin().read(&mut buf).unwrap_or(0);
+    for &b in &buf[..n] {
+        match b {
+            0x03 => return Poll::Interrupt,
+            0x1b => return Poll::Escape,
+            _ => {}
+        }
+        if let Some(key) = keymap.get(HostKey::Char(b as char)) {
+            if held[key] == 0 {
+                e.kbd.push(KeyEvent::Pressed(key));
+            }
+            held[key] = HOLD_FRAMES;
+        }
+    }
+    for (key, frames) in held.iter_mut().enumerate() {
+        if *frames > 0 {
+            *frames -= 1;
+            if *frames == 0 {
+                e.kbd.push(KeyEvent::Released(key));
+            }
+        }
+    }
+    Poll::Keys
+}
+
+pub struct Args {
+    pub ips: u32,
+    pub profile: Option<Profile>,
+    pub rom: String,
+}
+
+/// Parses `[--ips N] [--profile NAME] ROM`, failing with usage when ROM
+/// is missing
+pub fn parse_args(usage: &str) -> Result<Args, String> {
+    let mut ips = Emulator::DEFAULT_IPS;
+    let mut profile = None;
+    let mut rom = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ips" => {
+                let v = args.next().ok_or("--ips needs a value")?;
+                ips = v
+                    .parse()
+                    .map_err(|_| format!("invalid --ips value '{}'", v))?;
+            }
+            "--profile" => {
+                let v = args.next().ok_or("--profile needs a value")?;
+                profile = Some(v.parse()?);
+            }
+            _ if rom.is_none() && !arg.starts_with('-') => rom = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    let rom = rom.ok_or(usage)?;
+    Ok(Args { ips, profile, rom })
+}
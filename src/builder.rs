@@ -0,0 +1,257 @@
+//! Step-by-step configuration of a new [`Emulator`]
+use crate::clock::Timing;
+use crate::cpu::{Addr, CPU};
+use crate::display::{BitScreen, Scr};
+use crate::emulator::Emulator;
+use crate::mem::{FontSet, Mem};
+use crate::quirks::{Profile, Quirks};
+use crate::rng::XorShift;
+
+/// Builder returned by [`Emulator::builder`].
+///
+/// Defaults match [`Emulator::new`] except that the font is stored in memory.
+pub struct EmulatorBuilder {
+    start_addr: Addr,
+    ips: u32,
+    timing: Timing,
+    quirks: Quirks,
+    screen: Option<Box<dyn Scr>>,
+    seed: Option<u64>,
+    font: bool,
+    font_set: FontSet,
+    stack_limit: usize,
+    memory_size: usize,
+    two_page_hires: bool,
+    profile: Option<Profile>,
+}
+
+impl EmulatorBuilder {
+    pub(crate) fn new() -> Self {
+        EmulatorBuilder {
+            start_addr: Emulator::DEFAULT_START_ADDR,
+            ips: Emulator::DEFAULT_IPS,
+            timing: Timing::Instructions,
+            quirks: Quirks::new(),
+            screen: None,
+            seed: None,
+            font: true,
+            font_set: FontSet::DEFAULT,
+            stack_limit: CPU::STACK_SIZE,
+            memory_size: Mem::DEFAULT_SIZE,
+            two_page_hires: false,
+            profile: None,
+        }
+    }
+
+    /// Address programs are stored at and started from
+    pub fn start_addr(mut self, addr: Addr) -> Self {
+        self.start_addr = addr;
+        self
+    }
+
+    /// Instructions per second executed by [`Emulator::run_for`]
+    pub fn ips(mut self, ips: u32) -> Self {
+        self.ips = ips;
+        self
+    }
+
+    /// Pacing model of [`Emulator::run_for`], see [`Emulator::set_timing`]
+    pub fn timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Applies quirks and limits of given platform
+    pub fn profile(mut self, profile: Profile) -> Self {
+        self.quirks = profile.quirks();
+        self.stack_limit = profile.stack_limit();
+        self.memory_size = profile.memory_size();
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Screen implementation, [`BitScreen`] by default
+    pub fn screen(mut self, screen: Box<dyn Scr>) -> Self {
+        self.screen = Some(screen);
+        self
+    }
+
+    /// Seeds [`XorShift`] used for RND, making runs deterministic
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Whether fonts are stored at address 0 (default true)
+    pub fn font(mut self, font: bool) -> Self {
+        self.font = font;
+        self
+    }
+
+    /// Digits stored at address 0 instead of [`FontSet::DEFAULT`]; turns
+    /// [`EmulatorBuilder::font`] on
+    pub fn font_set(mut self, set: FontSet) -> Self {
+        self.font = true;
+        self.font_set = set;
+        self
+    }
+
+    /// Maximum subroutine nesting depth, 16 by default
+    pub fn stack_limit(mut self, limit: usize) -> Self {
+        self.stack_limit = limit;
+        self
+    }
+
+    /// Bytes of memory, 4096 by default; other sizes up to
+    /// [`Mem::MAX_SIZE`] are heap-allocated (see [`Mem::with_size`]);
+    /// [`EmulatorBuilder::build`] panics for 0 or larger sizes
+    pub fn memory_size(mut self, size: usize) -> Self {
+        self.memory_size = size;
+        self
+    }
+
+    /// Whether two-page hi-res chip-8 programs get a 64x64 screen (default
+    /// false), see [`Emulator::two_page_hires`] and
+    /// [`crate::loader::is_two_page_hires`]. Stays off with profiles later
+    /// than [`Profile::CosmacVip`], which have no such mode.
+    pub fn two_page_hires(mut self, on: bool) -> Self {
+        self.two_page_hires = on;
+        self
+    }
+
+    pub fn build(self) -> Emulator {
+        let screen = self.screen.unwrap_or_else(|| Box::new(BitScreen::new()));
+        let mut e = Emulator::with_screen(screen);
+        if self.memory_size != e.mem.size() {
+            e.mem = Mem::with_size(self.memory_size);
+        }
+        e.set_start_addr(self.start_addr);
+        e.set_ips(self.ips);
+        e.set_timing(self.timing);
+        e.quirks = self.quirks;
+        e.stack_limit = self.stack_limit;
+        e.two_page_hires =
+            self.two_page_hires && self.profile.is_none_or(|p| p == Profile::CosmacVip);
+        if let Some(seed) = self.seed {
+            e.rng = Box::new(XorShift::new(seed));
+        }
+        if self.font {
+            e.store_font_set(&self.font_set);
+        }
+        e.cpu.pc(self.start_addr);
+        e
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::Timing;
+    use crate::cpu::Opcode;
+    use crate::display::Screen;
+    use crate::emulator::Emulator;
+    use crate::mem::{FontSet, Mem};
+    use crate::quirks::{Profile, Quirks};
+
+    #[test]
+    fn defaults_test() {
+        let e = Emulator::builder().build();
+        assert_eq!(0x200, e.start_addr());
+        assert_eq!(0x200, e.cpu.pc);
+        assert_eq!(Emulator::DEFAULT_IPS, e.ips());
+        assert_eq!(Some(&0xF0), e.mem.get(0));
+    }
+
+    #[test]
+    fn configured_test() {
+        let build = || {
+            Emulator::builder()
+                .start_addr(0x600)
+                .ips(1000)
+                .quirks(Quirks::cosmac())
+                .screen(Box::new(Screen::new()))
+                .seed(3)
+                .font(false)
+                .stack_limit(12)
+                .timing(Timing::CosmacVip)
+                .build()
+        };
+        let mut e = build();
+        assert_eq!((0x600, 1000, 12), (e.start_addr(), e.ips(), e.stack_limit));
+        assert_eq!(Quirks::cosmac(), e.quirks);
+        assert_eq!(Timing::CosmacVip, e.timing());
+        assert_eq!(Some(&0), e.mem.get(0));
+        e.store(&[Opcode::RND(0, 0xFF), Opcode::EXIT]);
+        assert_eq!(0x600, e.cpu.pc);
+        e.run().unwrap();
+        let mut other = build();
+        other.store(&[Opcode::RND(0, 0xFF), Opcode::EXIT]);
+        other.run().unwrap();
+        assert_eq!(e.cpu.regs[0], other.cpu.regs[0]);
+    }
+
+    #[test]
+    fn profile_test() {
+        let e = Emulator::builder().profile(Profile::CosmacVip).build();
+        assert_eq!(Quirks::cosmac(), e.quirks);
+        assert_eq!(12, e.stack_limit);
+        let e = Emulator::builder()
+            .profile(Profile::XoChip)
+            .stack_limit(8)
+            .build();
+        assert_eq!(Quirks::xochip(), e.quirks);
+        assert_eq!(8, e.stack_limit);
+    }
+
+    #[test]
+    fn two_page_hires_test() {
+        assert!(!Emulator::builder().build().two_page_hires);
+        let on = |profile| {
+            Emulator::builder()
+                .two_page_hires(true)
+                .profile(profile)
+                .build()
+                .two_page_hires
+        };
+        assert!(on(Profile::CosmacVip));
+        assert!(!on(Profile::SuperChip) && !on(Profile::XoChip));
+    }
+
+    #[test]
+    fn memory_size_test() {
+        let mut e = Emulator::builder().profile(Profile::XoChip).build();
+        assert_eq!(Mem::MAX_SIZE, e.mem.size());
+        // LD I, LONG 0xF000; LD V0, 7; LD [I], V0; EXIT
+        e.store_instr(&[0xF000, 0xF000, 0x6007, 0xF055, 0x00FD]);
+        e.run().unwrap();
+        assert_eq!(7, e.mem.load(0xF000));
+
+        let mut e = Emulator::builder().memory_size(0x800).build();
+        assert!(e.mem.is_heap());
+        e.mem.store(0x801, 3);
+        assert_eq!(3, e.mem.load(1));
+        e.reset();
+        assert_eq!((0x800, Some(&0xF0)), (e.mem.size(), e.mem.get(0)));
+        assert!(!Emulator::builder().build().mem.is_heap());
+    }
+
+    #[test]
+    fn font_set_test() {
+        let mut e = Emulator::builder()
+            .font(false)
+            .font_set(FontSet::VIP)
+            .build();
+        // LD V0, 1; LD F, V0; EXIT
+        e.store(&[Opcode::LD(0, 1), Opcode::IDIG(0), Opcode::EXIT]);
+        e.run().unwrap();
+        assert_eq!(Some(&0x60), e.mem.get(e.cpu.i as usize));
+        e.mem.store(5, 0);
+        e.reset();
+        assert_eq!(Some(&FontSet::VIP), e.mem.font_set());
+        assert_eq!(Some(&0x60), e.mem.get(5));
+    }
+}
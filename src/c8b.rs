@@ -0,0 +1,381 @@
+//! C8B container: ROM bytecode for one or more platforms plus metadata
+//!
+//! All numbers are big-endian. A file starts with an 8 byte header:
+//!
+//! ```text
+//! 0  "CBF"  magic
+//! 3  u8     version, 0
+//! 4  u16    offset of bytecode table
+//! 6  u16    offset of property table
+//! ```
+//!
+//! The bytecode table is a u8 count followed by that many
+//! `u8 platform, u32 offset, u32 length` entries locating each program.
+//! The property table is a u8 count followed by `u8 tag, u16 length,
+//! data` entries; see [`Metadata`] for the tags understood, others are
+//! skipped. Platforms are 0 for COSMAC VIP chip-8, 1 for CHIP-48, 2 for
+//! SUPER-CHIP 1.1 and 3 for XO-CHIP.
+
+use crate::builder::EmulatorBuilder;
+use crate::cpu::Addr;
+use crate::display::Palette;
+use crate::quirks::Profile;
+use crate::savestate::{Reader, SaveStateError};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+const MAGIC: &[u8; 3] = b"CBF";
+const VERSION: u8 = 0;
+const HEADER_LEN: usize = 8;
+
+const TAG_NAME: u8 = 0x00;
+const TAG_DESCRIPTION: u8 = 0x01;
+const TAG_AUTHOR: u8 = 0x02;
+const TAG_URL: u8 = 0x03;
+const TAG_RELEASE_DATE: u8 = 0x04;
+const TAG_TICKS: u8 = 0x05;
+const TAG_COLORS: u8 = 0x06;
+const TAG_START_ADDR: u8 = 0x07;
+
+/// Reasons bytes cannot be decoded as C8B container
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum C8bError {
+    /// bytes do not start with `CBF`
+    BadMagic,
+    UnsupportedVersion(u8),
+    /// a table or program lies past the end of the bytes
+    Truncated,
+    /// bytecode table lists no program
+    NoProgram,
+    UnknownPlatform(u8),
+    /// text property (tag) is not UTF-8
+    BadText(u8),
+    /// more than 255 programs or properties, a property longer than 65535
+    /// bytes or a program past 4 GiB cannot be encoded
+    TooLarge,
+}
+
+impl fmt::Display for C8bError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            C8bError::BadMagic => write!(f, "not a c8b container"),
+            C8bError::UnsupportedVersion(v) => write!(f, "unsupported c8b version {}", v),
+            C8bError::Truncated => write!(f, "c8b container is truncated"),
+            C8bError::NoProgram => write!(f, "c8b container holds no program"),
+            C8bError::UnknownPlatform(p) => write!(f, "unknown c8b platform {}", p),
+            C8bError::BadText(tag) => write!(f, "c8b property {} is not UTF-8", tag),
+            C8bError::TooLarge => write!(f, "c8b container exceeds format limits"),
+        }
+    }
+}
+
+impl Error for C8bError {}
+
+impl From<SaveStateError> for C8bError {
+    fn from(_: SaveStateError) -> Self {
+        C8bError::Truncated
+    }
+}
+
+/// Descriptive properties of a container
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// tag 0
+    pub name: Option<String>,
+    /// tag 1
+    pub description: Option<String>,
+    /// tag 2, once per author
+    pub authors: Vec<String>,
+    /// tag 3, once per URL
+    pub urls: Vec<String>,
+    /// tag 4, u32 unix time
+    pub release_date: Option<u32>,
+    /// tag 5, u16 instructions per 60Hz frame
+    pub ticks_per_frame: Option<u16>,
+    /// tag 6, RGB triples for color indices 0 (background) to 3
+    pub colors: Vec<[u8; 3]>,
+    /// tag 7, u16 address programs are loaded at, 0x200 if absent
+    pub start_addr: Option<Addr>,
+}
+
+impl Metadata {
+    /// Colors hint as palette, unspecified entries taken from default
+    pub fn palette(&self, default: &Palette) -> Palette {
+        let mut p = *default;
+        for (entry, [r, g, b]) in p.iter_mut().zip(self.colors.iter()) {
+            *entry = [*r, *g, *b, 0xFF];
+        }
+        p
+    }
+}
+
+/// Program for one platform
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub platform: Profile,
+    pub bytes: Vec<u8>,
+}
+
+/// Decoded C8B container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct C8b {
+    pub metadata: Metadata,
+    /// in file order, at least one in parsed containers
+    pub programs: Vec<Program>,
+}
+
+fn platform_id(p: Profile) -> u8 {
+    match p {
+        Profile::CosmacVip => 0,
+        Profile::Chip48 => 1,
+        Profile::SuperChip => 2,
+        Profile::XoChip => 3,
+    }
+}
+
+fn platform(id: u8) -> Result<Profile, C8bError> {
+    match id {
+        0 => Ok(Profile::CosmacVip),
+        1 => Ok(Profile::Chip48),
+        2 => Ok(Profile::SuperChip),
+        3 => Ok(Profile::XoChip),
+        _ => Err(C8bError::UnknownPlatform(id)),
+    }
+}
+
+/// Whether bytes start with the C8B signature; raw ROMs rarely do
+pub fn is_c8b(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+impl C8b {
+    pub fn parse(bytes: &[u8]) -> Result<C8b, C8bError> {
+        if !is_c8b(bytes) {
+            return Err(C8bError::BadMagic);
+        }
+        let mut header = Reader::new(&bytes[MAGIC.len()..]);
+        let version = header.u8()?;
+        if version != VERSION {
+            return Err(C8bError::UnsupportedVersion(version));
+        }
+        let code_table = header.u16()? as usize;
+        let prop_table = header.u16()? as usize;
+        let table = |offset: usize| bytes.get(offset..).ok_or(C8bError::Truncated);
+
+        let mut r = Reader::new(table(code_table)?);
+        let mut programs = vec![];
+        for _ in 0..r.u8()? {
+            let platform = platform(r.u8()?)?;
+            let offset = r.u32()? as usize;
+            let len = r.u32()? as usize;
+            let code = offset
+                .checked_add(len)
+                .and_then(|end| bytes.get(offset..end))
+                .ok_or(C8bError::Truncated)?;
+            programs.push(Program {
+                platform,
+                bytes: code.to_vec(),
+            });
+        }
+        if programs.is_empty() {
+            return Err(C8bError::NoProgram);
+        }
+
+        let mut r = Reader::new(table(prop_table)?);
+        let mut m = Metadata::default();
+        for _ in 0..r.u8()? {
+            let tag = r.u8()?;
+            let len = r.u16()? as usize;
+            let data = r.take(len)?;
+            let text = || String::from_utf8(data.to_vec()).map_err(|_| C8bError::BadText(tag));
+            let mut value = Reader::new(data);
+            match tag {
+                TAG_NAME => m.name = Some(text()?),
+                TAG_DESCRIPTION => m.description = Some(text()?),
+                TAG_AUTHOR => m.authors.push(text()?),
+                TAG_URL => m.urls.push(text()?),
+                TAG_RELEASE_DATE => m.release_date = Some(value.u32()?),
+                TAG_TICKS => m.ticks_per_frame = Some(value.u16()?),
+                TAG_COLORS => m.colors = data.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+                TAG_START_ADDR => m.start_addr = Some(value.u16()?),
+                _ => {}
+            }
+        }
+        Ok(C8b {
+            metadata: m,
+            programs,
+        })
+    }
+
+    /// Encodes container with the bytecode table right after the header,
+    /// followed by the property table and programs
+    pub fn to_bytes(&self) -> Result<Vec<u8>, C8bError> {
+        let u8_len = |n: usize| u8::try_from(n).map_err(|_| C8bError::TooLarge);
+        let u16_len = |n: usize| u16::try_from(n).map_err(|_| C8bError::TooLarge);
+        let u32_len = |n: usize| u32::try_from(n).map_err(|_| C8bError::TooLarge);
+        let m = &self.metadata;
+        let mut props: Vec<(u8, Vec<u8>)> = vec![];
+        let texts = m
+            .name
+            .iter()
+            .map(|t| (TAG_NAME, t))
+            .chain(m.description.iter().map(|t| (TAG_DESCRIPTION, t)))
+            .chain(m.authors.iter().map(|t| (TAG_AUTHOR, t)))
+            .chain(m.urls.iter().map(|t| (TAG_URL, t)));
+        props.extend(texts.map(|(tag, t)| (tag, t.as_bytes().to_vec())));
+        if let Some(d) = m.release_date {
+            props.push((TAG_RELEASE_DATE, d.to_be_bytes().to_vec()));
+        }
+        if let Some(t) = m.ticks_per_frame {
+            props.push((TAG_TICKS, t.to_be_bytes().to_vec()));
+        }
+        if !m.colors.is_empty() {
+            props.push((TAG_COLORS, m.colors.concat()));
+        }
+        if let Some(a) = m.start_addr {
+            props.push((TAG_START_ADDR, a.to_be_bytes().to_vec()));
+        }
+
+        let code_table = HEADER_LEN;
+        let prop_table = code_table + 1 + 9 * self.programs.len();
+        let mut v = MAGIC.to_vec();
+        v.push(VERSION);
+        v.extend_from_slice(&(code_table as u16).to_be_bytes());
+        v.extend_from_slice(&u16_len(prop_table)?.to_be_bytes());
+
+        let props_len: usize = props.iter().map(|(_, d)| 3 + d.len()).sum();
+        let mut offset = prop_table + 1 + props_len;
+        v.push(u8_len(self.programs.len())?);
+        for p in &self.programs {
+            v.push(platform_id(p.platform));
+            v.extend_from_slice(&u32_len(offset)?.to_be_bytes());
+            v.extend_from_slice(&u32_len(p.bytes.len())?.to_be_bytes());
+            offset += p.bytes.len();
+        }
+        u32_len(offset)?;
+        v.push(u8_len(props.len())?);
+        for (tag, data) in &props {
+            v.push(*tag);
+            v.extend_from_slice(&u16_len(data.len())?.to_be_bytes());
+            v.extend_from_slice(data);
+        }
+        for p in &self.programs {
+            v.extend_from_slice(&p.bytes);
+        }
+        Ok(v)
+    }
+
+    /// First program, which is loaded by default
+    pub fn first_program(&self) -> Result<&Program, C8bError> {
+        self.programs.first().ok_or(C8bError::NoProgram)
+    }
+
+    /// Program for platform, or the first one if there is none for it
+    pub fn program_for(&self, platform: Profile) -> Result<&Program, C8bError> {
+        match self.programs.iter().find(|p| p.platform == platform) {
+            Some(p) => Ok(p),
+            None => self.first_program(),
+        }
+    }
+
+    /// Applies profile of the first program, speed and start address
+    pub fn configure(&self, builder: EmulatorBuilder) -> Result<EmulatorBuilder, C8bError> {
+        let m = &self.metadata;
+        let builder = builder.profile(self.first_program()?.platform);
+        let builder = match m.ticks_per_frame {
+            Some(t) => builder.ips(t as u32 * 60),
+            None => builder,
+        };
+        Ok(match m.start_addr {
+            Some(a) => builder.start_addr(a),
+            None => builder,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::DEFAULT_PALETTE;
+    use crate::emulator::Emulator;
+
+    fn sample() -> C8b {
+        C8b {
+            metadata: Metadata {
+                name: Some("Blink".into()),
+                authors: vec!["a".into(), "b".into()],
+                ticks_per_frame: Some(30),
+                colors: vec![[1, 2, 3], [4, 5, 6]],
+                ..Metadata::default()
+            },
+            programs: vec![
+                Program {
+                    platform: Profile::SuperChip,
+                    bytes: vec![0x00, 0xFF, 0x00, 0xFD],
+                },
+                Program {
+                    platform: Profile::CosmacVip,
+                    bytes: vec![0x00, 0xFD],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn roundtrip_test() {
+        let c = sample();
+        let bytes = c.to_bytes().unwrap();
+        assert!(is_c8b(&bytes));
+        assert_eq!(Ok(c.clone()), C8b::parse(&bytes));
+        let program = |p| c.program_for(p).unwrap();
+        assert_eq!(vec![0x00, 0xFD], program(Profile::CosmacVip).bytes);
+        assert_eq!(Profile::SuperChip, program(Profile::XoChip).platform);
+        let p = c.metadata.palette(&DEFAULT_PALETTE);
+        assert_eq!([[1, 2, 3, 0xFF], [4, 5, 6, 0xFF]], p[..2]);
+        assert_eq!(DEFAULT_PALETTE[2..], p[2..]);
+
+        let e = c.configure(Emulator::builder()).unwrap().build();
+        assert_eq!(1800, e.ips());
+        assert_eq!(Profile::SuperChip.quirks(), e.quirks);
+    }
+
+    #[test]
+    fn parse_errors_test() {
+        let bytes = sample().to_bytes().unwrap();
+        assert_eq!(Err(C8bError::BadMagic), C8b::parse(&[0x00, 0xE0]));
+        assert_eq!(
+            Err(C8bError::Truncated),
+            C8b::parse(&bytes[..bytes.len() - 1])
+        );
+        let mut v = bytes.clone();
+        v[3] = 9;
+        assert_eq!(Err(C8bError::UnsupportedVersion(9)), C8b::parse(&v));
+        let mut v = bytes.clone();
+        v[HEADER_LEN + 1] = 7;
+        assert_eq!(Err(C8bError::UnknownPlatform(7)), C8b::parse(&v));
+        let empty = C8b {
+            programs: vec![],
+            ..sample()
+        };
+        assert_eq!(
+            Err(C8bError::NoProgram),
+            C8b::parse(&empty.to_bytes().unwrap())
+        );
+        assert_eq!(Err(C8bError::NoProgram), empty.program_for(Profile::XoChip));
+        assert!(empty.configure(Emulator::builder()).is_err());
+    }
+
+    #[test]
+    fn to_bytes_limits_test() {
+        let mut c = sample();
+        c.metadata.name = Some("x".repeat(0x10000));
+        assert_eq!(Err(C8bError::TooLarge), c.to_bytes());
+        let mut c = sample();
+        c.metadata.authors = vec![String::new(); 256];
+        assert_eq!(Err(C8bError::TooLarge), c.to_bytes());
+        let mut c = sample();
+        c.programs = vec![c.programs[1].clone(); 256];
+        assert_eq!(Err(C8bError::TooLarge), c.to_bytes());
+    }
+}
@@ -0,0 +1,218 @@
+//! Emulated time bookkeeping for [`crate::emulator::Emulator::run_for`]
+use crate::cpu::Opcode;
+use std::time::Duration;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Length of single 60Hz frame
+pub const FRAME: Duration = Duration::from_nanos((NANOS_PER_SEC / 60) as u64);
+
+/// Machine cycles per second of the COSMAC VIP (1.76064 MHz clock,
+/// 8 clock periods per machine cycle)
+pub const VIP_CYCLES_PER_SEC: u32 = 1_760_640 / 8;
+
+/// Machine cycles the VIP interpreter spends fetching and decoding
+/// each instruction
+const VIP_FETCH_CYCLES: u32 = 40;
+
+/// How [`Emulator::run_for`](crate::emulator::Emulator::run_for) paces
+/// execution
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// every instruction takes the same time, [`Emulator::ips`] of them
+    /// a second
+    ///
+    /// [`Emulator::ips`]: crate::emulator::Emulator::ips
+    #[default]
+    Instructions,
+    /// instructions take as many machine cycles as on the COSMAC VIP
+    /// (see [`vip_cycles`]), at [`VIP_CYCLES_PER_SEC`]
+    CosmacVip,
+}
+
+/// Approximate machine cycles the COSMAC VIP interpreter needs for op,
+/// including fetch and decode. Waiting for vertical blank before drawing
+/// is not included; instructions the VIP did not have count as the
+/// cheapest ones.
+pub fn vip_cycles(op: &Opcode) -> u32 {
+    let exec = match *op {
+        Opcode::CLS => 3078,
+        Opcode::RET => 10,
+        Opcode::JP(_) | Opcode::LDI(_) => 12,
+        Opcode::CALL(_) => 26,
+        Opcode::SE(..) | Opcode::SNE(..) | Opcode::DTGET(_) | Opcode::DTSET(_) => 10,
+        Opcode::STSET(_) => 10,
+        Opcode::SER(..) | Opcode::SNER(..) | Opcode::SKP(_) | Opcode::SKNP(_) => 14,
+        Opcode::LD(..) => 6,
+        Opcode::ADD(..) => 10,
+        Opcode::LDR(..)
+        | Opcode::OR(..)
+        | Opcode::AND(..)
+        | Opcode::XOR(..)
+        | Opcode::ADDR(..)
+        | Opcode::SUBR(..)
+        | Opcode::SHR(..)
+        | Opcode::SUBRN(..)
+        | Opcode::SHL(..) => 44,
+        Opcode::JPOFF(_) => 22,
+        Opcode::RND(..) => 36,
+        Opcode::DRW(_, _, n) => 22 + 46 * n as u32,
+        Opcode::KEYSET(_) => 18,
+        Opcode::IINC(_) | Opcode::IDIG(_) => 16,
+        Opcode::BCD(_) => 84,
+        Opcode::REGSSTORE(x) | Opcode::REGLOAD(x) => 14 + 14 * (x as u32 + 1),
+        _ => 6,
+    };
+    VIP_FETCH_CYCLES + exec
+}
+
+/// Tracks emulated time together with work units (instructions or
+/// machine cycles, see [`Timing`]) and frames it accounts for, so pacing
+/// stays exact across arbitrarily short `run_for` slices
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct Clock {
+    nanos: u128,
+    units: u128,
+    frames: u128,
+}
+
+impl Clock {
+    pub(crate) fn now(&self) -> u128 {
+        self.nanos
+    }
+
+    /// Time of next frame boundary
+    pub(crate) fn next_frame(&self) -> u128 {
+        (self.frames + 1) * NANOS_PER_SEC / 60
+    }
+
+    /// Units still due by time t at rate units per second
+    pub(crate) fn due(&self, t: u128, rate: u32) -> u128 {
+        (t * rate as u128 / NANOS_PER_SEC).saturating_sub(self.units)
+    }
+
+    pub(crate) fn executed(&mut self, units: u32) {
+        self.units += units as u128;
+    }
+
+    /// Accounts current time in units of new rate
+    pub(crate) fn resync(&mut self, rate: u32) {
+        self.units = self.nanos * rate as u128 / NANOS_PER_SEC;
+    }
+
+    pub(crate) fn frame(&mut self) {
+        self.frames += 1;
+    }
+
+    pub(crate) fn advance_to(&mut self, t: u128) {
+        self.nanos = t;
+    }
+
+    /// Moves time back to moment last executed instruction was due,
+    /// used when execution stops early
+    pub(crate) fn stop_at_instruction(&mut self, rate: u32) {
+        if rate > 0 {
+            self.nanos = self.nanos.min(self.units * NANOS_PER_SEC / rate as u128);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{Addr, Opcode};
+    use crate::debug::StopReason;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn due_test() {
+        let mut c = Clock::default();
+        assert_eq!(0, c.due(NANOS_PER_SEC / 1000, 600));
+        assert_eq!(6, c.due(NANOS_PER_SEC / 100, 600));
+        c.executed(1);
+        assert_eq!(5, c.due(NANOS_PER_SEC / 100, 600));
+        assert_eq!(NANOS_PER_SEC / 60, c.next_frame());
+    }
+
+    #[test]
+    fn run_for_test() {
+        let mut e = Emulator::new();
+        e.set_ips(600);
+        e.cpu.dt = 100;
+        e.store(&[Opcode::JP(0x200)]);
+        assert_eq!(None, e.run_for(Duration::from_millis(500)).unwrap());
+        assert_eq!(300, e.stats.executed);
+        assert_eq!(70, e.cpu.dt);
+        for _ in 0..50 {
+            e.run_for(Duration::from_millis(10)).unwrap();
+        }
+        assert_eq!(600, e.stats.executed);
+        assert_eq!(40, e.cpu.dt);
+        // past time is not run again at the new rate
+        e.set_ips(1200);
+        e.run_for(Duration::from_millis(100)).unwrap();
+        assert_eq!(720, e.stats.executed);
+    }
+
+    #[test]
+    fn run_for_stops_test() {
+        let mut e = Emulator::new();
+        e.store(&[Opcode::LD(0, 1), Opcode::EXIT]);
+        let reason = e.run_for(Duration::from_secs(1)).unwrap();
+        assert_eq!(Some(StopReason::Halted), reason);
+        assert_eq!(2, e.stats.executed);
+    }
+
+    #[test]
+    fn vip_timing_test() {
+        let mut e = Emulator::new();
+        e.set_timing(Timing::CosmacVip);
+        e.store(&[Opcode::LD(0, 1), Opcode::JP(0x200)]);
+        e.run_for(Duration::from_secs(1)).unwrap();
+        // LD and JP take 46 and 52 cycles
+        let pairs = VIP_CYCLES_PER_SEC as u64 / 98;
+        assert!(e.stats.executed.abs_diff(2 * pairs) <= 2);
+        let mut slow = Emulator::new();
+        slow.set_timing(Timing::CosmacVip);
+        slow.store(&[Opcode::DRW(0, 0, 15), Opcode::JP(0x200)]);
+        slow.run_for(Duration::from_secs(1)).unwrap();
+        assert!(slow.stats.executed * 5 < e.stats.executed);
+        assert_eq!(Timing::CosmacVip, slow.timing());
+    }
+
+    #[test]
+    fn vip_timing_io_reads_test() {
+        use crate::mem::IoHandler;
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        /// Serves JP 0x200, counting reads
+        struct Device(Rc<Cell<u64>>);
+
+        impl IoHandler for Device {
+            fn read(&mut self, addr: Addr) -> u8 {
+                self.0.set(self.0.get() + 1);
+                [0x12, 0x00][addr as usize & 1]
+            }
+
+            fn write(&mut self, _addr: Addr, _value: u8) {}
+        }
+
+        let reads = Rc::new(Cell::new(0));
+        let mut e = Emulator::new();
+        e.set_timing(Timing::CosmacVip);
+        e.store(&[Opcode::JP(0x200)]);
+        e.mem.map_io(0x200..0x202, Box::new(Device(reads.clone())));
+        e.run_for(Duration::from_millis(100)).unwrap();
+        assert!(e.stats.executed > 0);
+        assert_eq!(2 * e.stats.executed, reads.get());
+    }
+
+    #[test]
+    fn run_realtime_test() {
+        let mut e = Emulator::new();
+        e.store(&[Opcode::LD(0, 1), Opcode::EXIT]);
+        assert_eq!(StopReason::Halted, e.run_realtime(1000).unwrap());
+        assert_eq!(1000, e.ips());
+    }
+}
@@ -0,0 +1,232 @@
+//! Lockstep execution of one ROM on two differently configured emulators
+//!
+//! [`first_divergence`] steps both machines together and reports the
+//! first instruction after which their state differs, naming quirks that
+//! affect it. Running a ROM under two [`Profile`]s this way shows which
+//! quirk it depends on.
+
+use crate::cpu::{Addr, Opcode};
+use crate::emulator::Emulator;
+use crate::quirks::{Profile, Quirks};
+use std::fmt;
+
+/// Seed of RND in both emulators of [`compare_profiles`]
+const SEED: u64 = 1;
+
+/// First instruction after which two emulators differ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    /// number of instructions executed by each emulator, including this one
+    pub step: u64,
+    /// address of the instruction
+    pub pc: Addr,
+    pub op: Option<Opcode>,
+    /// descriptions of differing state, first emulator's value first
+    pub differences: Vec<String>,
+    /// names of [`Quirks`] fields (or `stack_limit`) that differ between
+    /// the emulators and affect the instruction
+    pub quirks: Vec<&'static str>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = self.op.map_or("??".to_string(), |op| op.name().to_string());
+        write!(
+            f,
+            "diverged after step {} at 0x{:03X} ({}): {}",
+            self.step,
+            self.pc,
+            op,
+            self.differences.join(", ")
+        )?;
+        if !self.quirks.is_empty() {
+            write!(f, "; quirks: {}", self.quirks.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Loads rom into emulators built with each profile and a fixed RND seed,
+/// then runs them with [`first_divergence`]
+pub fn compare_profiles(rom: &[u8], a: Profile, b: Profile, max_steps: u64) -> Option<Divergence> {
+    let build = |p| {
+        let mut e = Emulator::builder().profile(p).seed(SEED).build();
+        e.store_bytes(rom);
+        e
+    };
+    first_divergence(&mut build(a), &mut build(b), max_steps)
+}
+
+/// Steps both emulators up to max_steps times, ticking timers of both
+/// every `ips / 60` steps of the first one. Returns `None` if they agree
+/// until both halt, fail the same way or run out of steps.
+pub fn first_divergence(a: &mut Emulator, b: &mut Emulator, max_steps: u64) -> Option<Divergence> {
+    let per_tick = (a.ips() / 60).max(1) as u64;
+    let differences = state_differences(a, b);
+    if !differences.is_empty() {
+        return Some(Divergence {
+            step: 0,
+            pc: a.cpu.pc,
+            op: None,
+            differences,
+            quirks: vec![],
+        });
+    }
+    for step in 1..=max_steps {
+        let pc = a.cpu.pc;
+        let results = (a.step(), b.step());
+        let op = a.cpu.instr;
+        let mut differences = vec![];
+        if results.0 != results.1 {
+            differences.push(format!("result {:?} vs {:?}", results.0, results.1));
+        }
+        if step % per_tick == 0 {
+            a.tick();
+            b.tick();
+        }
+        differences.extend(state_differences(a, b));
+        if !differences.is_empty() {
+            return Some(Divergence {
+                step,
+                pc,
+                op,
+                differences,
+                quirks: op.map_or(vec![], |op| affecting_quirks(&op, a, b)),
+            });
+        }
+        if a.halted() || results.0.is_err() {
+            break;
+        }
+    }
+    None
+}
+
+fn state_differences(a: &Emulator, b: &Emulator) -> Vec<String> {
+    let mut out = vec![];
+    let (ca, cb) = (&a.cpu, &b.cpu);
+    let mut diff = |name: String, x: String, y: String| {
+        if x != y {
+            out.push(format!("{}: {} vs {}", name, x, y));
+        }
+    };
+    diff(
+        "PC".into(),
+        format!("0x{:03X}", ca.pc),
+        format!("0x{:03X}", cb.pc),
+    );
+    diff(
+        "I".into(),
+        format!("0x{:03X}", ca.i),
+        format!("0x{:03X}", cb.i),
+    );
+    for r in 0..ca.regs.len() {
+        diff(
+            format!("V{:X}", r),
+            format!("0x{:02X}", ca.regs[r]),
+            format!("0x{:02X}", cb.regs[r]),
+        );
+    }
+    diff("DT".into(), ca.dt.to_string(), cb.dt.to_string());
+    diff("ST".into(), ca.st.to_string(), cb.st.to_string());
+    diff(
+        "stack".into(),
+        format!("{:03X?}", ca.stack()),
+        format!("{:03X?}", cb.stack()),
+    );
+    diff(
+        "halted".into(),
+        a.halted().to_string(),
+        b.halted().to_string(),
+    );
+    let (ma, mb) = (a.mem.get(..).unwrap_or(&[]), b.mem.get(..).unwrap_or(&[]));
+    if ma.len() != mb.len() {
+        out.push(format!("memory size: {} vs {}", ma.len(), mb.len()));
+    } else if let Some(addr) = (0..ma.len()).find(|&i| ma[i] != mb[i]) {
+        out.push(format!(
+            "memory at 0x{:03X}: 0x{:02X} vs 0x{:02X}",
+            addr, ma[addr], mb[addr]
+        ));
+    }
+    let (sa, sb) = (a.scr.save(), b.scr.save());
+    if (sa.width, sa.height) != (sb.width, sb.height) {
+        out.push(format!(
+            "resolution: {}x{} vs {}x{}",
+            sa.width, sa.height, sb.width, sb.height
+        ));
+    } else if let Some(idx) = (0..sa.pixels.len()).find(|&i| sa.pixels[i] != sb.pixels[i]) {
+        out.push(format!(
+            "screen at ({}, {}): {} vs {}",
+            idx % sa.width,
+            idx / sa.width,
+            sa.pixels[idx],
+            sb.pixels[idx]
+        ));
+    }
+    out
+}
+
+/// Differing settings consulted when executing op
+fn affecting_quirks(op: &Opcode, a: &Emulator, b: &Emulator) -> Vec<&'static str> {
+    let (qa, qb): (&Quirks, &Quirks) = (&a.quirks, &b.quirks);
+    let mut out = vec![];
+    match op {
+        Opcode::SHR(..) | Opcode::SHL(..) if qa.shift_vy != qb.shift_vy => out.push("shift_vy"),
+        Opcode::REGSSTORE(_) | Opcode::REGLOAD(_) if qa.load_store_inc_i != qb.load_store_inc_i => {
+            out.push("load_store_inc_i")
+        }
+        Opcode::JPOFF(_) if qa.jump_vx != qb.jump_vx => out.push("jump_vx"),
+        Opcode::AND(..) | Opcode::OR(..) | Opcode::XOR(..) if qa.vf_reset != qb.vf_reset => {
+            out.push("vf_reset")
+        }
+        Opcode::DRW(..) if qa.draw_mode != qb.draw_mode => out.push("draw_mode"),
+        Opcode::CALL(_) if a.stack_limit != b.stack_limit => out.push("stack_limit"),
+        _ => {}
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_profiles_test() {
+        // LD V0, 1; LD V1, 6; SHR V0, V1; EXIT
+        let rom = [0x60, 0x01, 0x61, 0x06, 0x80, 0x16, 0x00, 0xFD];
+        let d = compare_profiles(&rom, Profile::CosmacVip, Profile::SuperChip, 100).unwrap();
+        assert_eq!((3, 0x204, Some(Opcode::SHR(0, 1))), (d.step, d.pc, d.op));
+        assert_eq!(vec!["V0: 0x03 vs 0x00", "VF: 0x00 vs 0x01"], d.differences);
+        assert_eq!(vec!["shift_vy"], d.quirks);
+        assert!(d.to_string().ends_with("; quirks: shift_vy"));
+
+        assert_eq!(
+            None,
+            compare_profiles(&rom, Profile::Chip48, Profile::SuperChip, 100)
+        );
+    }
+
+    #[test]
+    fn first_divergence_test() {
+        let mut a = Emulator::new();
+        let mut b = Emulator::new();
+        // LD V0, 0x10; LD I, 0x300; LD [I], V0; JP 0x206
+        a.store_instr(&[0x6010, 0xA300, 0xF055, 0x1206]);
+        b.store_instr(&[0x6010, 0xA300, 0xF055, 0x1206]);
+        assert_eq!(None, first_divergence(&mut a, &mut b, 50));
+        b.mem.store(0x300, 7);
+        let d = first_divergence(&mut a, &mut b, 50).unwrap();
+        assert_eq!(0, d.step);
+        assert_eq!(vec!["memory at 0x300: 0x10 vs 0x07"], d.differences);
+
+        let mut a = Emulator::new();
+        let mut b = Emulator::new();
+        a.stack_limit = 1;
+        // CALL 0x202; CALL 0x204
+        a.store_instr(&[0x2202, 0x2204]);
+        b.store_instr(&[0x2202, 0x2204]);
+        let d = first_divergence(&mut a, &mut b, 50).unwrap();
+        assert_eq!(2, d.step);
+        assert!(d.differences[0].starts_with("result Err(StackOverflow"));
+        assert_eq!(vec!["stack_limit"], d.quirks);
+    }
+}
@@ -0,0 +1,411 @@
+//! Compatibility report of a ROM collection
+//!
+//! [`run_dir`] runs every ROM of a directory headlessly for a number of
+//! frames and records how it ended, how many instructions it executed
+//! and a checksum of its final screen. A [`Report`] is written as one
+//! tab-separated line per ROM and read back with [`Report::parse`], so a
+//! report of one libchip8 version can be compared against another with
+//! [`Report::changes`].
+
+use crate::clock::FRAME;
+use crate::cpu::{Addr, Instr};
+use crate::emulator::Emulator;
+use crate::error::Chip8Error;
+use crate::hash;
+use crate::loader;
+use crate::quirks::Profile;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::str::FromStr;
+
+/// How a ROM run ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// ran for all frames
+    Completed,
+    /// executed EXIT
+    Halted,
+    InvalidOpcode {
+        addr: Addr,
+        raw: Instr,
+    },
+    /// failed with another [`Chip8Error`]
+    Error(String),
+    /// emulator panicked with given message
+    Panicked(String),
+    /// ROM could not be read or loaded
+    LoadFailed(String),
+}
+
+/// Keyword and details, e.g. `invalid-opcode 0x204 5121`; messages are
+/// kept on one line
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let one_line = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+        match self {
+            Status::Completed => write!(f, "completed"),
+            Status::Halted => write!(f, "halted"),
+            Status::InvalidOpcode { addr, raw } => {
+                write!(f, "invalid-opcode 0x{:03X} {:04X}", addr, raw)
+            }
+            Status::Error(msg) => write!(f, "error {}", one_line(msg)),
+            Status::Panicked(msg) => write!(f, "panicked {}", one_line(msg)),
+            Status::LoadFailed(msg) => write!(f, "load-failed {}", one_line(msg)),
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (word, rest) = s.split_once(' ').unwrap_or((s, ""));
+        match word {
+            "completed" => Ok(Status::Completed),
+            "halted" => Ok(Status::Halted),
+            "invalid-opcode" => {
+                let (addr, raw) = rest.split_once(' ').ok_or("missing opcode")?;
+                let addr = addr.strip_prefix("0x").unwrap_or(addr);
+                Ok(Status::InvalidOpcode {
+                    addr: Addr::from_str_radix(addr, 16).map_err(|e| e.to_string())?,
+                    raw: Instr::from_str_radix(raw, 16).map_err(|e| e.to_string())?,
+                })
+            }
+            "error" => Ok(Status::Error(rest.to_string())),
+            "panicked" => Ok(Status::Panicked(rest.to_string())),
+            "load-failed" => Ok(Status::LoadFailed(rest.to_string())),
+            _ => Err(format!("unknown status '{}'", s)),
+        }
+    }
+}
+
+/// Settings of a corpus run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// frames each ROM runs for at 60 frames a second
+    pub frames: u32,
+    /// profile of every ROM; `None` keeps the builder default (c8b
+    /// containers pick their own)
+    pub profile: Option<Profile>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            frames: 600,
+            profile: None,
+        }
+    }
+}
+
+/// Outcome of running one ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomReport {
+    pub name: String,
+    pub status: Status,
+    pub instructions: u64,
+    /// CRC-32 of the final screen's size and pixels, 0 if not loaded
+    pub screen: u32,
+}
+
+/// `name<TAB>status<TAB>instructions<TAB>screen` with screen checksum
+/// as 8 hex digits
+impl fmt::Display for RomReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{:08x}",
+            self.name, self.status, self.instructions, self.screen
+        )
+    }
+}
+
+/// ROM whose outcome differs between two reports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomChange {
+    pub name: String,
+    /// outcome in the baseline, `None` for ROMs added since
+    pub before: Option<RomReport>,
+    /// current outcome, `None` for ROMs no longer present
+    pub after: Option<RomReport>,
+}
+
+/// E.g. `pong.ch8: halted -> invalid-opcode 0x204 5121, screen 1a2b3c4d -> 00000000`
+impl fmt::Display for RomChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.name)?;
+        let (before, after) = match (&self.before, &self.after) {
+            (Some(b), Some(a)) => (b, a),
+            (None, Some(a)) => return write!(f, "new, {}", a.status),
+            (Some(_), None) => return write!(f, "missing"),
+            (None, None) => return Ok(()),
+        };
+        let mut parts = vec![];
+        if before.status != after.status {
+            parts.push(format!("{} -> {}", before.status, after.status));
+        }
+        if before.instructions != after.instructions {
+            parts.push(format!(
+                "instructions {} -> {}",
+                before.instructions, after.instructions
+            ));
+        }
+        if before.screen != after.screen {
+            parts.push(format!(
+                "screen {:08x} -> {:08x}",
+                before.screen, after.screen
+            ));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Outcomes of all ROMs of a corpus, ordered by name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub roms: Vec<RomReport>,
+}
+
+impl Report {
+    /// Reads report written with `Display`; blank lines are skipped
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut roms = vec![];
+        for (n, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let err = |msg: String| format!("line {}: {}", n + 1, msg);
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 4 {
+                return Err(err("expected 4 tab-separated fields".to_string()));
+            }
+            roms.push(RomReport {
+                name: fields[0].to_string(),
+                status: fields[1].parse().map_err(err)?,
+                instructions: fields[2]
+                    .parse()
+                    .map_err(|_| err(format!("invalid count '{}'", fields[2])))?,
+                screen: u32::from_str_radix(fields[3], 16)
+                    .map_err(|_| err(format!("invalid checksum '{}'", fields[3])))?,
+            });
+        }
+        Ok(Report { roms })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RomReport> {
+        self.roms.iter().find(|r| r.name == name)
+    }
+
+    /// ROMs whose outcome differs from baseline, including ROMs present
+    /// in only one report; in order of this report, then baseline
+    pub fn changes(&self, baseline: &Report) -> Vec<RomChange> {
+        let mut out = vec![];
+        for r in &self.roms {
+            let before = baseline.get(&r.name);
+            if before != Some(r) {
+                out.push(RomChange {
+                    name: r.name.clone(),
+                    before: before.cloned(),
+                    after: Some(r.clone()),
+                });
+            }
+        }
+        for b in baseline.roms.iter().filter(|b| self.get(&b.name).is_none()) {
+            out.push(RomChange {
+                name: b.name.clone(),
+                before: Some(b.clone()),
+                after: None,
+            });
+        }
+        out
+    }
+
+    /// Number of ROMs with each status keyword, e.g. `halted`
+    pub fn summary(&self) -> Vec<(String, usize)> {
+        let mut out: Vec<(String, usize)> = vec![];
+        for r in &self.roms {
+            let status = r.status.to_string();
+            let word = status.split(' ').next().unwrap_or("").to_string();
+            match out.iter_mut().find(|(w, _)| *w == word) {
+                Some((_, n)) => *n += 1,
+                None => out.push((word, 1)),
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for r in &self.roms {
+            writeln!(f, "{}", r)?;
+        }
+        Ok(())
+    }
+}
+
+fn emulator(config: &Config) -> Emulator {
+    let mut builder = Emulator::builder();
+    if let Some(p) = config.profile {
+        builder = builder.profile(p);
+    }
+    builder.build()
+}
+
+/// Runs loaded emulator for the configured frames, catching panics
+fn run_loaded(name: String, mut e: Emulator, config: &Config) -> RomReport {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let result = e.run_for(FRAME * config.frames);
+        (result, e.halted())
+    }));
+    let status = match result {
+        Ok((Ok(_), true)) => Status::Halted,
+        Ok((Ok(_), false)) => Status::Completed,
+        Ok((Err(Chip8Error::InvalidOpcode { addr, raw }), _)) => {
+            Status::InvalidOpcode { addr, raw }
+        }
+        Ok((Err(err), _)) => Status::Error(err.to_string()),
+        Err(payload) => Status::Panicked(
+            payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default(),
+        ),
+    };
+    let screen = e.scr.save();
+    let mut data = vec![screen.width as u8, screen.height as u8];
+    data.extend_from_slice(&screen.pixels);
+    RomReport {
+        name,
+        status,
+        instructions: e.stats.executed,
+        screen: hash::crc32(&data),
+    }
+}
+
+/// Runs ROM given as bytes (raw or c8b container)
+pub fn run_rom(name: &str, rom: &[u8], config: &Config) -> RomReport {
+    let mut e = emulator(config);
+    match loader::load_from_bytes(&mut e, rom) {
+        Ok(_) => run_loaded(name.to_string(), e, config),
+        Err(err) => load_failed(name, err.to_string()),
+    }
+}
+
+fn load_failed(name: &str, msg: String) -> RomReport {
+    RomReport {
+        name: name.to_string(),
+        status: Status::LoadFailed(msg),
+        instructions: 0,
+        screen: 0,
+    }
+}
+
+/// Runs every file of dir (not recursing into subdirectories), loading
+/// it with [`loader::load`]; ROMs are named by file name
+pub fn run_dir<P: AsRef<Path>>(dir: P, config: &Config) -> io::Result<Report> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    paths.sort();
+    let roms = paths
+        .iter()
+        .map(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let mut e = emulator(config);
+            match loader::load(&mut e, path) {
+                Ok(_) => run_loaded(name.into_owned(), e, config),
+                Err(err) => load_failed(&name, err.to_string()),
+            }
+        })
+        .collect();
+    Ok(Report { roms })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: Config = Config {
+        frames: 10,
+        profile: None,
+    };
+
+    #[test]
+    fn run_rom_test() {
+        // draw digit 0; EXIT
+        let halts = run_rom("halts", &[0xD0, 0x15, 0x00, 0xFD], &CONFIG);
+        assert_eq!(
+            (Status::Halted, 2),
+            (halts.status.clone(), halts.instructions)
+        );
+        let blank = run_rom("blank", &[0x00, 0xFD], &CONFIG);
+        assert_ne!(blank.screen, halts.screen);
+        // JP 0x200
+        let loops = run_rom("loops", &[0x12, 0x00], &CONFIG);
+        assert_eq!(Status::Completed, loops.status);
+        // 10 frames at 700 instructions a second
+        assert!((116..=117).contains(&loops.instructions));
+        let invalid = run_rom("invalid", &[0x00, 0xE0, 0x51, 0x21], &CONFIG);
+        assert!(invalid
+            .to_string()
+            .starts_with("invalid\tinvalid-opcode 0x202 5121\t1\t"));
+        let large = run_rom("large", &[0; 0x1000], &CONFIG);
+        assert!(matches!(large.status, Status::LoadFailed(_)));
+    }
+
+    #[test]
+    fn report_test() {
+        let dir = std::env::temp_dir().join(format!("libchip8-corpus-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("b.ch8"), [0x12, 0x00]).unwrap();
+        fs::write(dir.join("a.ch8"), [0x00, 0xFD]).unwrap();
+        let report = run_dir(&dir, &CONFIG).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let names: Vec<&str> = report.roms.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(vec!["a.ch8", "b.ch8"], names);
+        assert_eq!(
+            vec![("halted".to_string(), 1), ("completed".to_string(), 1)],
+            report.summary()
+        );
+        assert_eq!(Ok(report.clone()), Report::parse(&report.to_string()));
+        assert!(report.changes(&report).is_empty());
+
+        let mut now = report.clone();
+        now.roms[0].status = Status::Error("stack overflow".to_string());
+        now.roms.remove(1);
+        let changes = now.changes(&report);
+        assert_eq!(2, changes.len());
+        assert_eq!(
+            "a.ch8: halted -> error stack overflow",
+            changes[0].to_string()
+        );
+        assert_eq!("b.ch8: missing", changes[1].to_string());
+        assert!(Report::parse("a.ch8\thalted\t1").is_err());
+    }
+
+    #[test]
+    fn status_test() {
+        let statuses = [
+            Status::Completed,
+            Status::InvalidOpcode {
+                addr: 0x204,
+                raw: 0x5121,
+            },
+            Status::Panicked("index out of bounds".to_string()),
+            Status::LoadFailed(String::new()),
+        ];
+        for s in statuses.iter() {
+            assert_eq!(Ok(s.clone()), s.to_string().parse());
+        }
+        assert_eq!("error a b", Status::Error("a\nb".to_string()).to_string());
+    }
+}
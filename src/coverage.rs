@@ -0,0 +1,99 @@
+//! Opt-in map of executed addresses enabled with
+//! [`crate::emulator::Emulator::enable_coverage`]
+use crate::cpu::Addr;
+
+/// One bit per memory address, set once an instruction starting there
+/// was executed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    bits: Vec<u8>,
+}
+
+impl Coverage {
+    /// Empty map of memory with size bytes
+    pub fn new(size: usize) -> Self {
+        Coverage {
+            bits: vec![0; size.div_ceil(8)],
+        }
+    }
+
+    pub(crate) fn record(&mut self, addr: Addr) {
+        if let Some(b) = self.bits.get_mut(addr as usize / 8) {
+            *b |= 0x80 >> (addr % 8);
+        }
+    }
+
+    /// Whether instruction at addr was executed
+    pub fn is_covered(&self, addr: Addr) -> bool {
+        self.bits
+            .get(addr as usize / 8)
+            .is_some_and(|b| b & 0x80 >> (addr % 8) != 0)
+    }
+
+    /// Bitmap with address 0 in the most significant bit of first byte
+    pub fn bitmap(&self) -> &[u8] {
+        &self.bits
+    }
+
+    /// Executed addresses in ascending order
+    pub fn addresses(&self) -> Vec<Addr> {
+        (0..self.bits.len() * 8)
+            .map(|a| a as Addr)
+            .filter(|&a| self.is_covered(a))
+            .collect()
+    }
+
+    /// Number of executed addresses
+    pub fn count(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    /// Executed addresses in range as fraction of its words, e.g. of
+    /// program loaded there
+    pub fn ratio(&self, start: Addr, len: usize) -> f32 {
+        let words = len.div_ceil(2);
+        if words == 0 {
+            return 0.0;
+        }
+        let covered = (start as usize..start as usize + len)
+            .filter(|&a| self.is_covered(a as Addr))
+            .count();
+        covered as f32 / words as f32
+    }
+
+    pub fn reset(&mut self) {
+        self.bits.iter_mut().for_each(|b| *b = 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disasm;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn coverage_test() {
+        let mut e = Emulator::new();
+        // LD V0, 0x0A; SE V0, 0x0A; JP 0x20C; JP V0, 0x200; sprite; EXIT
+        let rom = [
+            0x60, 0x0A, 0x30, 0x0A, 0x12, 0x0C, 0xB2, 0x00, 0xFF, 0xFF, 0x00, 0xFD,
+        ];
+        e.store_bytes(&rom);
+        assert!(e.coverage().is_none());
+        e.enable_coverage();
+        e.run().unwrap();
+        let c = e.coverage().unwrap();
+        assert_eq!(vec![0x200, 0x202, 0x206, 0x20A], c.addresses());
+        assert_eq!(&[0xA2, 0x20][..], &c.bitmap()[0x40..0x42]);
+        assert!(c.is_covered(0x20A) && !c.is_covered(0x204));
+        assert_eq!(4, c.count());
+        assert_eq!(4.0 / 6.0, c.ratio(0x200, rom.len()));
+
+        // computed jump target is found only with coverage
+        assert!(!disasm::analyze(&rom, 0x200).code.contains(&0x20A));
+        let a = disasm::analyze_with(&rom, 0x200, &c.addresses());
+        assert!(a.code.contains(&0x20A));
+        e.disable_coverage();
+        assert!(e.coverage().is_none());
+    }
+}
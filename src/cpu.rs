@@ -1,16 +1,23 @@
-/// Address in chip-8 memory  (4096 B, byte-addressable)
+use crate::asm::{self, AsmError};
+use crate::disasm;
+use crate::error::Chip8Error;
+use crate::quirks::Profile;
+use std::fmt;
+use std::str::FromStr;
+
+/// Address in chip-8 memory (4096 B by default, up to 64 KB; byte-addressable)
 /// - used by pc, i, sp and stack etc.
 pub type Addr = u16;
-///
+/// Raw 16-bit chip-8 instruction word
 pub type Instr = u16;
 /// Type of value stored in chip-8 register (u8)
 pub type Reg = u8;
 /// Number of cpu registers
 const REGS_COUNT: usize = 0x10;
 
-#[derive(Default, PartialEq, Debug)]
+#[derive(Default, PartialEq, Debug, Clone)]
 pub struct CPU {
-    ///
+    /// program counter
     pub pc: Addr,
     /// I register stroring address for sprites
     pub i: Addr,
@@ -29,6 +36,9 @@ pub struct CPU {
 }
 
 impl CPU {
+    /// Maximum subroutine nesting depth
+    pub const STACK_SIZE: usize = 16;
+
     pub fn from(
         pc: Addr,
         i: Addr,
@@ -59,26 +69,55 @@ impl CPU {
         self
     }
 
+    /// Addresses of CALL instructions currently on stack, oldest first;
+    /// RET continues after them
+    pub fn stack(&self) -> &[Addr] {
+        &self.stack
+    }
+
+    pub(crate) fn set_stack(&mut self, stack: Vec<Addr>) {
+        self.sp = stack.len() as Addr;
+        self.stack = stack;
+    }
+
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len()
+    }
+
     pub fn inc_pc(&mut self) {
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
-    pub fn ret(&mut self) {
-        if let Some(addr) = self.stack.pop() {
-            self.sp -= 1;
-            self.pc = addr;
-            assert_eq!(self.sp as usize, self.stack.len());
-        }
+    /// Returns from subroutine; fails with empty stack leaving pc untouched
+    pub fn ret(&mut self) -> Result<(), Chip8Error> {
+        let addr = self
+            .stack
+            .pop()
+            .ok_or(Chip8Error::StackUnderflow(self.pc))?;
+        self.sp = self.stack.len() as Addr;
+        self.pc = addr;
+        Ok(())
     }
 
-    pub fn call(&mut self, a: Addr) {
+    /// Calls subroutine; fails when stack already holds [`CPU::STACK_SIZE`]
+    /// addresses leaving pc untouched
+    pub fn call(&mut self, a: Addr) -> Result<(), Chip8Error> {
+        self.call_with_limit(a, CPU::STACK_SIZE)
+    }
+
+    /// Like [`CPU::call`] with custom maximum stack depth
+    pub fn call_with_limit(&mut self, a: Addr, limit: usize) -> Result<(), Chip8Error> {
+        if self.stack.len() >= limit {
+            return Err(Chip8Error::StackOverflow(self.pc));
+        }
         self.stack.push(self.pc);
-        self.sp += 1;
+        self.sp = self.stack.len() as Addr;
         self.pc = a;
+        Ok(())
     }
 
     pub fn skip_if(&mut self, pred: bool) {
-        self.pc += if pred { 4 } else { 2 };
+        self.pc = self.pc.wrapping_add(if pred { 4 } else { 2 });
     }
 
     pub fn skip_eq(&mut self, vx: usize, byte: Reg) {
@@ -134,10 +173,11 @@ impl CPU {
         self.regs[vx] = diff;
     }
 
+    /// Shifts vx right by one; VF gets the bit shifted out
     pub fn shr(&mut self, vx: usize) {
-        let (res, overflow) = self.regs[vx].overflowing_shr(1);
-        self.regs[0xF] = if overflow { 1 } else { 0 };
-        self.regs[vx] = res;
+        let out = self.regs[vx] & 0x01;
+        self.regs[vx] >>= 1;
+        self.regs[0xF] = out;
     }
 
     pub fn subrn(&mut self, vx: usize, vy: usize) {
@@ -146,21 +186,29 @@ impl CPU {
         self.regs[vx] = diff;
     }
 
+    /// Shifts vx left by one; VF gets the bit shifted out
     pub fn shl(&mut self, vx: usize) {
-        let (res, overflow) = self.regs[vx].overflowing_shl(1);
-        self.regs[0xF] = if overflow { 1 } else { 0 };
-        self.regs[vx] = res;
+        let out = self.regs[vx] >> 7;
+        self.regs[vx] <<= 1;
+        self.regs[0xF] = out;
     }
     pub fn ldi(&mut self, addr: Addr) {
         self.i = addr;
     }
 
     pub fn jpoff(&mut self, addr: Addr) {
-        self.pc = self.regs[0] as u16 + addr;
+        self.pc = (self.regs[0] as u16).wrapping_add(addr);
     }
 
-    pub fn rnd(&mut self, vx: usize, byte: u8) {
-        self.regs[vx] = rand::random::<u8>() & byte;
+    /// Jumps to addr + VX where X is the highest nibble of addr
+    pub fn jpoff_vx(&mut self, addr: Addr) {
+        let vx = (addr >> 8 & 0xF) as usize;
+        self.pc = (self.regs[vx] as u16).wrapping_add(addr);
+    }
+
+    /// Sets vx to random byte r masked with byte
+    pub fn rnd(&mut self, vx: usize, byte: u8, r: u8) {
+        self.regs[vx] = r & byte;
     }
 
     pub fn dtset(&mut self, vx: usize) {
@@ -176,7 +224,7 @@ impl CPU {
     }
 
     pub fn iinc(&mut self, vx: usize) {
-        self.i += self.regs[vx] as u16;
+        self.i = self.i.wrapping_add(self.regs[vx] as u16);
     }
 }
 
@@ -231,8 +279,116 @@ pub enum Opcode {
     BCD(usize),
     REGSSTORE(usize),
     REGLOAD(usize),
+    // SUPER-CHIP
+    /// scroll screen down by given number of rows
+    SCD(u8),
+    /// scroll screen right by 4 pixels
+    SCR,
+    /// scroll screen left by 4 pixels
+    SCL,
+    /// exit interpreter
+    EXIT,
+    /// switch to 64x32 resolution
+    LOW,
+    /// switch to 128x64 resolution
+    HIGH,
+    /// sets I to large (8x10) font sprite of digit in vx
+    LDHF(usize),
+    /// stores v0..=vx in persistent user flags
+    RPLSTORE(usize),
+    /// loads v0..=vx from persistent user flags
+    RPLLOAD(usize),
+    // XO-CHIP
+    /// stores vx..=vy at I (I unchanged)
+    SAVE(usize, usize),
+    /// loads vx..=vy from I (I unchanged)
+    LOAD(usize, usize),
+    /// sets I to the 16-bit address stored in the next instruction word
+    LDIL,
+    /// selects drawing planes given as bit mask
+    PLANE(usize),
+    /// word that is no instruction, as kept by [`Opcode::decode`]
+    Unknown(Instr),
 }
 
+/// Encoding family of an instruction: words `w` with `w & mask == bits`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Pattern {
+    /// name of matching [`Opcode`] variant
+    pub name: &'static str,
+    /// bits fixed by the encoding
+    pub mask: Instr,
+    /// values of fixed bits
+    pub bits: Instr,
+}
+
+impl Pattern {
+    const fn new(name: &'static str, mask: Instr, bits: Instr) -> Self {
+        Pattern { name, mask, bits }
+    }
+
+    pub fn matches(&self, instr: Instr) -> bool {
+        instr & self.mask == self.bits
+    }
+
+    /// Decoded representative of the family with operand nibbles set to
+    /// 1, 2, 3; None only if the pattern does not match the decoder
+    pub fn example(&self) -> Option<Opcode> {
+        let operands = 0x0123 & !self.mask;
+        Opcode::from(self.bits | operands)
+    }
+}
+
+const PATTERNS: [Pattern; 47] = [
+    Pattern::new("CLS", 0xFFFF, 0x00E0),
+    Pattern::new("RET", 0xFFFF, 0x00EE),
+    Pattern::new("JP", 0xF000, 0x1000),
+    Pattern::new("CALL", 0xF000, 0x2000),
+    Pattern::new("SE", 0xF000, 0x3000),
+    Pattern::new("SNE", 0xF000, 0x4000),
+    Pattern::new("SER", 0xF00F, 0x5000),
+    Pattern::new("LD", 0xF000, 0x6000),
+    Pattern::new("ADD", 0xF000, 0x7000),
+    Pattern::new("LDR", 0xF00F, 0x8000),
+    Pattern::new("OR", 0xF00F, 0x8001),
+    Pattern::new("AND", 0xF00F, 0x8002),
+    Pattern::new("XOR", 0xF00F, 0x8003),
+    Pattern::new("ADDR", 0xF00F, 0x8004),
+    Pattern::new("SUBR", 0xF00F, 0x8005),
+    Pattern::new("SHR", 0xF00F, 0x8006),
+    Pattern::new("SUBRN", 0xF00F, 0x8007),
+    Pattern::new("SHL", 0xF00F, 0x800E),
+    Pattern::new("SNER", 0xF00F, 0x9000),
+    Pattern::new("LDI", 0xF000, 0xA000),
+    Pattern::new("JPOFF", 0xF000, 0xB000),
+    Pattern::new("RND", 0xF000, 0xC000),
+    Pattern::new("DRW", 0xF000, 0xD000),
+    Pattern::new("SKP", 0xF0FF, 0xE09E),
+    Pattern::new("SKNP", 0xF0FF, 0xE0A1),
+    Pattern::new("DTGET", 0xF0FF, 0xF007),
+    Pattern::new("KEYSET", 0xF0FF, 0xF00A),
+    Pattern::new("DTSET", 0xF0FF, 0xF015),
+    Pattern::new("STSET", 0xF0FF, 0xF018),
+    Pattern::new("IINC", 0xF0FF, 0xF01E),
+    Pattern::new("IDIG", 0xF0FF, 0xF029),
+    Pattern::new("BCD", 0xF0FF, 0xF033),
+    Pattern::new("REGSSTORE", 0xF0FF, 0xF055),
+    Pattern::new("REGLOAD", 0xF0FF, 0xF065),
+    Pattern::new("SCD", 0xFFF0, 0x00C0),
+    Pattern::new("SCR", 0xFFFF, 0x00FB),
+    Pattern::new("SCL", 0xFFFF, 0x00FC),
+    Pattern::new("EXIT", 0xFFFF, 0x00FD),
+    Pattern::new("LOW", 0xFFFF, 0x00FE),
+    Pattern::new("HIGH", 0xFFFF, 0x00FF),
+    Pattern::new("LDHF", 0xF0FF, 0xF030),
+    Pattern::new("RPLSTORE", 0xF0FF, 0xF075),
+    Pattern::new("RPLLOAD", 0xF0FF, 0xF085),
+    Pattern::new("SAVE", 0xF00F, 0x5002),
+    Pattern::new("LOAD", 0xF00F, 0x5003),
+    Pattern::new("LDIL", 0xFFFF, 0xF000),
+    Pattern::new("PLANE", 0xF0FF, 0xF001),
+];
+
 impl Opcode {
     fn xyn(op: u16) -> (usize, usize, u8) {
         (
@@ -259,6 +415,126 @@ impl Opcode {
         (op >> 8 & 0xF) as usize
     }
 
+    /// Encoding families of all instructions, one per variant
+    pub fn all_patterns() -> &'static [Pattern] {
+        &PATTERNS
+    }
+
+    /// Encoding families legal on given platform, i.e. provided by it or
+    /// an earlier one (see [`crate::opinfo::OpInfo::platform`])
+    pub fn patterns_for(profile: Profile) -> impl Iterator<Item = &'static Pattern> {
+        PATTERNS
+            .iter()
+            .filter(move |p| p.example().is_some_and(|op| op.info().platform <= profile))
+    }
+
+    /// Iterates over one representative of every variant
+    pub fn iter_examples() -> impl Iterator<Item = Opcode> {
+        PATTERNS.iter().filter_map(Pattern::example)
+    }
+
+    /// Name of the variant, as used in [`Pattern::name`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Opcode::CLS => "CLS",
+            Opcode::RET => "RET",
+            Opcode::JP(..) => "JP",
+            Opcode::CALL(..) => "CALL",
+            Opcode::SE(..) => "SE",
+            Opcode::SNE(..) => "SNE",
+            Opcode::SER(..) => "SER",
+            Opcode::LD(..) => "LD",
+            Opcode::ADD(..) => "ADD",
+            Opcode::LDR(..) => "LDR",
+            Opcode::OR(..) => "OR",
+            Opcode::AND(..) => "AND",
+            Opcode::XOR(..) => "XOR",
+            Opcode::ADDR(..) => "ADDR",
+            Opcode::SUBR(..) => "SUBR",
+            Opcode::SHR(..) => "SHR",
+            Opcode::SUBRN(..) => "SUBRN",
+            Opcode::SHL(..) => "SHL",
+            Opcode::SNER(..) => "SNER",
+            Opcode::LDI(..) => "LDI",
+            Opcode::JPOFF(..) => "JPOFF",
+            Opcode::RND(..) => "RND",
+            Opcode::DRW(..) => "DRW",
+            Opcode::SKP(..) => "SKP",
+            Opcode::SKNP(..) => "SKNP",
+            Opcode::DTGET(..) => "DTGET",
+            Opcode::KEYSET(..) => "KEYSET",
+            Opcode::DTSET(..) => "DTSET",
+            Opcode::STSET(..) => "STSET",
+            Opcode::IINC(..) => "IINC",
+            Opcode::IDIG(..) => "IDIG",
+            Opcode::BCD(..) => "BCD",
+            Opcode::REGSSTORE(..) => "REGSSTORE",
+            Opcode::REGLOAD(..) => "REGLOAD",
+            Opcode::SCD(..) => "SCD",
+            Opcode::SCR => "SCR",
+            Opcode::SCL => "SCL",
+            Opcode::EXIT => "EXIT",
+            Opcode::LOW => "LOW",
+            Opcode::HIGH => "HIGH",
+            Opcode::LDHF(..) => "LDHF",
+            Opcode::RPLSTORE(..) => "RPLSTORE",
+            Opcode::RPLLOAD(..) => "RPLLOAD",
+            Opcode::SAVE(..) => "SAVE",
+            Opcode::LOAD(..) => "LOAD",
+            Opcode::LDIL => "LDIL",
+            Opcode::PLANE(..) => "PLANE",
+            Opcode::Unknown(..) => "UNKNOWN",
+        }
+    }
+
+    /// Highest register index used as operand, if any
+    pub fn max_register(&self) -> Option<usize> {
+        match *self {
+            Opcode::SE(x, _)
+            | Opcode::SNE(x, _)
+            | Opcode::LD(x, _)
+            | Opcode::ADD(x, _)
+            | Opcode::RND(x, _)
+            | Opcode::SKP(x)
+            | Opcode::SKNP(x)
+            | Opcode::DTGET(x)
+            | Opcode::KEYSET(x)
+            | Opcode::DTSET(x)
+            | Opcode::STSET(x)
+            | Opcode::IINC(x)
+            | Opcode::IDIG(x)
+            | Opcode::BCD(x)
+            | Opcode::REGSSTORE(x)
+            | Opcode::REGLOAD(x)
+            | Opcode::LDHF(x)
+            | Opcode::RPLSTORE(x)
+            | Opcode::RPLLOAD(x) => Some(x),
+            Opcode::SER(x, y)
+            | Opcode::LDR(x, y)
+            | Opcode::OR(x, y)
+            | Opcode::AND(x, y)
+            | Opcode::XOR(x, y)
+            | Opcode::ADDR(x, y)
+            | Opcode::SUBR(x, y)
+            | Opcode::SHR(x, y)
+            | Opcode::SUBRN(x, y)
+            | Opcode::SHL(x, y)
+            | Opcode::SNER(x, y)
+            | Opcode::DRW(x, y, _)
+            | Opcode::SAVE(x, y)
+            | Opcode::LOAD(x, y) => Some(x.max(y)),
+            Opcode::JPOFF(_) => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Decodes every word, keeping undecodable ones as [`Opcode::Unknown`];
+    /// `Opcode::decode(w).to_instr() == w` for all w
+    pub fn decode(op: Instr) -> Opcode {
+        Opcode::from(op).unwrap_or(Opcode::Unknown(op))
+    }
+
+    /// Decoded instruction, `None` if word is no instruction
     pub fn from(op: Instr) -> Option<Opcode> {
         let nnn = Opcode::nnn(op);
         let (x, kk) = Opcode::xkk(op);
@@ -269,6 +545,12 @@ impl Opcode {
             0x0000 => match op {
                 0x00E0 => Some(Opcode::CLS),
                 0x00EE => Some(Opcode::RET),
+                0x00FB => Some(Opcode::SCR),
+                0x00FC => Some(Opcode::SCL),
+                0x00FD => Some(Opcode::EXIT),
+                0x00FE => Some(Opcode::LOW),
+                0x00FF => Some(Opcode::HIGH),
+                _ if op & 0xFFF0 == 0x00C0 => Some(Opcode::SCD(nn)),
                 _ => None,
             },
             0x1000 => Some(Opcode::JP(nnn)),
@@ -277,6 +559,8 @@ impl Opcode {
             0x4000 => Some(Opcode::SNE(x, kk)),
             0x5000 => match op & 0xF {
                 0 => Some(Opcode::SER(xm, ym)),
+                2 => Some(Opcode::SAVE(xm, ym)),
+                3 => Some(Opcode::LOAD(xm, ym)),
                 _ => None,
             },
             0x6000 => Some(Opcode::LD(x, kk)),
@@ -293,7 +577,7 @@ impl Opcode {
                 0xE => Some(Opcode::SHL(xm, ym)),
                 _ => None,
             },
-            0x9000 => Some(Opcode::SNER(xm, ym)),
+            0x9000 if op & 0xF == 0 => Some(Opcode::SNER(xm, ym)),
             0xA000 => Some(Opcode::LDI(nnn)),
             0xB000 => Some(Opcode::JPOFF(nnn)),
             0xC000 => Some(Opcode::RND(x, kk)),
@@ -304,15 +588,20 @@ impl Opcode {
                 _ => None,
             },
             0xF000 => match op & 0xFF {
+                0x00 if op == 0xF000 => Some(Opcode::LDIL),
+                0x01 => Some(Opcode::PLANE(xs)),
                 0x07 => Some(Opcode::DTGET(xs)),
                 0x0A => Some(Opcode::KEYSET(xs)),
                 0x15 => Some(Opcode::DTSET(xs)),
                 0x18 => Some(Opcode::STSET(xs)),
                 0x1E => Some(Opcode::IINC(xs)),
                 0x29 => Some(Opcode::IDIG(xs)),
+                0x30 => Some(Opcode::LDHF(xs)),
                 0x33 => Some(Opcode::BCD(xs)),
                 0x55 => Some(Opcode::REGSSTORE(xs)),
                 0x65 => Some(Opcode::REGLOAD(xs)),
+                0x75 => Some(Opcode::RPLSTORE(xs)),
+                0x85 => Some(Opcode::RPLLOAD(xs)),
                 _ => None,
             },
 
@@ -341,7 +630,7 @@ impl Opcode {
     }
 
     pub fn to_instr(&self) -> Instr {
-        let res = match self {
+        match self {
             Opcode::CLS => 0x00E0,
             Opcode::RET => 0x00EE,
             Opcode::JP(a) => Opcode::innn(0x1000, a),
@@ -357,9 +646,9 @@ impl Opcode {
             Opcode::XOR(vx, vy) => Opcode::vx_vy(0x8003, vx, vy),
             Opcode::ADDR(vx, vy) => Opcode::vx_vy(0x8004, vx, vy),
             Opcode::SUBR(vx, vy) => Opcode::vx_vy(0x8005, vx, vy),
-            Opcode::SHR(vx, _) => Opcode::ibyte(0x8006, vx),
+            Opcode::SHR(vx, vy) => Opcode::vx_vy(0x8006, vx, vy),
             Opcode::SUBRN(vx, vy) => Opcode::vx_vy(0x8007, vx, vy),
-            Opcode::SHL(vx, _) => Opcode::ibyte(0x800E, vx),
+            Opcode::SHL(vx, vy) => Opcode::vx_vy(0x800E, vx, vy),
             Opcode::SNER(vx, vy) => Opcode::vx_vy(0x9000, vx, vy),
             Opcode::LDI(a) => Opcode::innn(0xA000, a),
             Opcode::JPOFF(a) => Opcode::innn(0xB000, a),
@@ -376,14 +665,83 @@ impl Opcode {
             Opcode::BCD(a) => Opcode::ibyte(0xF033, a),
             Opcode::REGSSTORE(a) => Opcode::ibyte(0xF055, a),
             Opcode::REGLOAD(a) => Opcode::ibyte(0xF065, a),
-        };
-        res
+            Opcode::SCD(n) => 0x00C0 | (*n as u16 & 0xF),
+            Opcode::SCR => 0x00FB,
+            Opcode::SCL => 0x00FC,
+            Opcode::EXIT => 0x00FD,
+            Opcode::LOW => 0x00FE,
+            Opcode::HIGH => 0x00FF,
+            Opcode::LDHF(a) => Opcode::ibyte(0xF030, a),
+            Opcode::RPLSTORE(a) => Opcode::ibyte(0xF075, a),
+            Opcode::RPLLOAD(a) => Opcode::ibyte(0xF085, a),
+            Opcode::SAVE(vx, vy) => Opcode::vx_vy(0x5002, vx, vy),
+            Opcode::LOAD(vx, vy) => Opcode::vx_vy(0x5003, vx, vy),
+            Opcode::LDIL => 0xF000,
+            Opcode::PLANE(a) => Opcode::ibyte(0xF001, a),
+            Opcode::Unknown(raw) => *raw,
+        }
+    }
+}
+
+/// Formats opcode as mnemonic produced by [`disasm::mnemonic`]
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&disasm::mnemonic(self))
+    }
+}
+
+/// Parses single mnemonic as accepted by [`asm::assemble_opcode`]
+impl FromStr for Opcode {
+    type Err = AsmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        asm::assemble_opcode(s)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Opcode;
+    use super::{Opcode, Profile, PATTERNS};
+
+    #[test]
+    fn display_from_str_test() {
+        assert_eq!("ADD V6, 0xFF", Opcode::ADD(6, 0xFF).to_string());
+        assert_eq!(Ok(Opcode::ADD(6, 0xFF)), "ADD V6, 0xFF".parse());
+        assert_eq!(Ok(Opcode::DRW(1, 2, 3)), "drw v1, v2, 3".parse());
+        assert!("ADD V6".parse::<Opcode>().is_err());
+        for op in Opcode::iter_examples() {
+            assert_eq!(Ok(op), op.to_string().parse(), "{}", op);
+        }
+    }
+
+    #[test]
+    fn patterns_test() {
+        let examples: Vec<Opcode> = Opcode::iter_examples().collect();
+        assert_eq!(Opcode::all_patterns().len(), examples.len());
+        for (p, op) in Opcode::all_patterns().iter().zip(examples) {
+            assert_eq!(p.name, op.name());
+            assert!(p.matches(op.to_instr()), "{:?} does not match {:?}", op, p);
+            let others = Opcode::all_patterns().iter().filter(|o| o.matches(p.bits));
+            assert_eq!(1, others.count(), "{} overlaps other patterns", p.name);
+        }
+    }
+
+    #[test]
+    fn patterns_for_test() {
+        let names = |profile| {
+            Opcode::patterns_for(profile)
+                .map(|p| p.name)
+                .collect::<Vec<_>>()
+        };
+        let vip = names(Profile::CosmacVip);
+        assert_eq!(34, vip.len());
+        assert!(vip.contains(&"DRW") && !vip.contains(&"EXIT"));
+        assert_eq!(vip, names(Profile::Chip48));
+        let schip = names(Profile::SuperChip);
+        assert!(schip.contains(&"HIGH") && !schip.contains(&"PLANE"));
+        assert_eq!(43, schip.len());
+        assert_eq!(PATTERNS.len(), names(Profile::XoChip).len());
+    }
 
     #[test]
     fn cls_test() {
@@ -484,7 +842,7 @@ mod test {
     #[test]
     fn shr_test() {
         assert_eq!(Opcode::from(0x8DA6), Some(Opcode::SHR(0xD, 0xA)));
-        assert_eq!(0x8D06, Opcode::SHR(0xD, 0xA).to_instr());
+        assert_eq!(0x8DA6, Opcode::SHR(0xD, 0xA).to_instr());
     }
 
     #[test]
@@ -496,7 +854,7 @@ mod test {
     #[test]
     fn shl_test() {
         assert_eq!(Opcode::from(0x8DAE), Some(Opcode::SHL(0xD, 0xA)));
-        assert_eq!(0x8D0E, Opcode::SHL(0xD, 0xA).to_instr());
+        assert_eq!(0x8DAE, Opcode::SHL(0xD, 0xA).to_instr());
     }
 
     #[test]
@@ -529,9 +887,49 @@ mod test {
         assert_eq!(0xE19E, Opcode::SKP(1).to_instr());
     }
 
+    #[test]
+    fn schip_test() {
+        assert_eq!(Opcode::from(0x00C5), Some(Opcode::SCD(5)));
+        assert_eq!(0x00C5, Opcode::SCD(5).to_instr());
+        assert_eq!(Opcode::from(0x00FB), Some(Opcode::SCR));
+        assert_eq!(Opcode::from(0x00FC), Some(Opcode::SCL));
+        assert_eq!(Opcode::from(0x00FD), Some(Opcode::EXIT));
+        assert_eq!(Opcode::from(0x00FE), Some(Opcode::LOW));
+        assert_eq!(Opcode::from(0x00FF), Some(Opcode::HIGH));
+        assert_eq!(Opcode::from(0xF330), Some(Opcode::LDHF(3)));
+        assert_eq!(0xF330, Opcode::LDHF(3).to_instr());
+        assert_eq!(Opcode::from(0xF575), Some(Opcode::RPLSTORE(5)));
+        assert_eq!(0xF575, Opcode::RPLSTORE(5).to_instr());
+        assert_eq!(Opcode::from(0xF585), Some(Opcode::RPLLOAD(5)));
+        assert_eq!(0xF585, Opcode::RPLLOAD(5).to_instr());
+        assert_eq!(Opcode::from(0xD120), Some(Opcode::DRW(1, 2, 0)));
+    }
+
+    #[test]
+    fn xochip_test() {
+        assert_eq!(Opcode::from(0x5122), Some(Opcode::SAVE(1, 2)));
+        assert_eq!(0x5122, Opcode::SAVE(1, 2).to_instr());
+        assert_eq!(Opcode::from(0x5123), Some(Opcode::LOAD(1, 2)));
+        assert_eq!(0x5123, Opcode::LOAD(1, 2).to_instr());
+        assert_eq!(Opcode::from(0xF000), Some(Opcode::LDIL));
+        assert_eq!(Opcode::from(0xF100), None);
+        assert_eq!(Opcode::from(0xF301), Some(Opcode::PLANE(3)));
+        assert_eq!(0xF301, Opcode::PLANE(3).to_instr());
+    }
+
     #[test]
     fn sknp_test() {
         assert_eq!(Opcode::from(0xE1A1), Some(Opcode::SKNP(1)));
         assert_eq!(0xE1A1, Opcode::SKNP(1).to_instr());
     }
+
+    #[test]
+    fn decode_test() {
+        assert_eq!(Opcode::SKNP(1), Opcode::decode(0xE1A1));
+        assert_eq!(Opcode::Unknown(0x5124), Opcode::decode(0x5124));
+        assert_eq!("DW 0x5124", Opcode::decode(0x5124).to_string());
+        for w in 0..=0xFFFF {
+            assert_eq!(w, Opcode::decode(w).to_instr(), "0x{:04X}", w);
+        }
+    }
 }
@@ -0,0 +1,200 @@
+//! Breakpoints and memory watchpoints checked by [`crate::emulator::Emulator::step`]
+use crate::cpu::Addr;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
+
+/// Kind of memory access performed by an instruction
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    /// True if watching `self` should report access `other`
+    pub fn covers(self, other: Access) -> bool {
+        self == Access::ReadWrite || other == Access::ReadWrite || self == other
+    }
+}
+
+/// Reason execution stopped
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StopReason {
+    /// pc reached address with breakpoint; instruction there is not executed yet
+    Breakpoint(Addr),
+    /// executed instruction accessed watched memory at addr
+    Watchpoint { addr: Addr, access: Access },
+    /// EXIT instruction was executed
+    Halted,
+    /// FX0A waits for key to be pressed and released; pc does not advance
+    /// until it is, timers keep running
+    KeyWait,
+    /// emulator was paused with
+    /// [`Emulator::pause`](crate::emulator::Emulator::pause)
+    Paused,
+}
+
+/// Subroutine call active on the stack, see
+/// [`Emulator::call_trace`](crate::emulator::Emulator::call_trace)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CallFrame {
+    /// stack depth inside the called subroutine, 1 for the outermost call
+    pub depth: usize,
+    /// address of the CALL instruction
+    pub call_site: Addr,
+    /// subroutine called; `None` if memory at call_site no longer holds a
+    /// CALL (self-modifying code or CPU state set by hand)
+    pub target: Option<Addr>,
+}
+
+impl CallFrame {
+    /// Address execution continues at after RET
+    pub fn return_addr(&self) -> Addr {
+        self.call_site.wrapping_add(2)
+    }
+}
+
+/// Backtrace line, e.g. `#2 0x340 called from 0x20A`
+impl fmt::Display for CallFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.target {
+            Some(t) => write!(f, "#{} 0x{:03X}", self.depth, t)?,
+            None => write!(f, "#{} ???", self.depth)?,
+        }
+        write!(f, " called from 0x{:03X}", self.call_site)
+    }
+}
+
+/// Breakpoint and watchpoint registry
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Breakpoints {
+    addrs: HashSet<Addr>,
+    watches: Vec<(Range<Addr>, Access)>,
+}
+
+impl Breakpoints {
+    pub(crate) fn add(&mut self, addr: Addr) -> bool {
+        self.addrs.insert(addr)
+    }
+
+    pub(crate) fn remove(&mut self, addr: Addr) -> bool {
+        self.addrs.remove(&addr)
+    }
+
+    pub(crate) fn is_set(&self, addr: Addr) -> bool {
+        self.addrs.contains(&addr)
+    }
+
+    pub(crate) fn add_watch(&mut self, range: Range<Addr>, access: Access) {
+        self.watches.push((range, access));
+    }
+
+    pub(crate) fn remove_watch(&mut self, range: &Range<Addr>) -> bool {
+        let before = self.watches.len();
+        self.watches.retain(|(r, _)| r != range);
+        before != self.watches.len()
+    }
+
+    pub(crate) fn has_watches(&self) -> bool {
+        !self.watches.is_empty()
+    }
+
+    /// First watched address among `addrs` accessed with `access`
+    pub(crate) fn watched<I>(&self, addrs: I, access: Access) -> Option<StopReason>
+    where
+        I: IntoIterator<Item = Addr>,
+    {
+        addrs.into_iter().find_map(|addr| {
+            self.watches
+                .iter()
+                .find(|(r, a)| r.contains(&addr) && a.covers(access))
+                .map(|_| StopReason::Watchpoint { addr, access })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Opcode;
+    use crate::emulator::Emulator;
+    use crate::error::Chip8Error;
+
+    #[test]
+    fn breakpoint_test() {
+        let mut e = Emulator::new();
+        e.store(&[
+            Opcode::LD(1, 1),
+            Opcode::LD(2, 2),
+            Opcode::LD(3, 3),
+            Opcode::EXIT,
+        ]);
+        e.add_breakpoint(0x204);
+        assert_eq!(StopReason::Breakpoint(0x204), e.run().unwrap());
+        assert_eq!((1, 2, 0), (e.cpu.regs[1], e.cpu.regs[2], e.cpu.regs[3]));
+        assert_eq!(StopReason::Halted, e.cont().unwrap());
+        assert_eq!(3, e.cpu.regs[3]);
+    }
+
+    #[test]
+    fn breakpoint_at_start_test() {
+        let mut e = Emulator::new();
+        e.store(&[Opcode::LD(1, 1), Opcode::EXIT]);
+        e.add_breakpoint(0x200);
+        assert_eq!(StopReason::Breakpoint(0x200), e.run().unwrap());
+        assert_eq!(0, e.cpu.regs[1]);
+        assert!(e.remove_breakpoint(0x200));
+        assert_eq!(StopReason::Halted, e.cont().unwrap());
+    }
+
+    #[test]
+    fn step_reports_test() {
+        let mut e = Emulator::new();
+        e.store(&[Opcode::LD(1, 1), Opcode::EXIT]);
+        e.add_breakpoint(0x202);
+        assert_eq!(Some(StopReason::Breakpoint(0x202)), e.step().unwrap());
+        assert_eq!(Some(StopReason::Halted), e.step().unwrap());
+    }
+
+    #[test]
+    fn watchpoint_test() {
+        let mut e = Emulator::new();
+        e.store(&[
+            Opcode::LDI(0x300),
+            Opcode::LD(0, 7),
+            Opcode::REGLOAD(0),
+            Opcode::BCD(0),
+            Opcode::EXIT,
+        ]);
+        e.add_watchpoint(0x301..0x302, Access::Write);
+        let hit = StopReason::Watchpoint {
+            addr: 0x301,
+            access: Access::Write,
+        };
+        assert_eq!(hit, e.run().unwrap());
+        assert_eq!(0x208, e.cpu.pc);
+        assert!(e.remove_watchpoint(&(0x301..0x302)));
+        e.add_watchpoint(0x300..0x400, Access::Read);
+        e.cpu.pc(0x204);
+        let hit = StopReason::Watchpoint {
+            addr: 0x300,
+            access: Access::Read,
+        };
+        assert_eq!(hit, e.cont().unwrap());
+    }
+
+    #[test]
+    fn invalid_opcode_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x6101, 0xFFFF]);
+        let err = Chip8Error::InvalidOpcode {
+            addr: 0x202,
+            raw: 0xFFFF,
+        };
+        assert_eq!(Err(err), e.run());
+        assert_eq!(Err(err), e.step());
+        assert_eq!(2, e.stats.unknown_total());
+    }
+}
@@ -0,0 +1,256 @@
+//! Differences between two machine snapshots
+//!
+//! [`StateDiff::between`] compares two [`SaveState`]s and lists changed
+//! registers, memory ranges and screen rows, answering "what did this
+//! instruction or frame change" when combined with
+//! [`Emulator::save_state`](crate::emulator::Emulator::save_state).
+//!
+//! ```
+//! use libchip8::diff::StateDiff;
+//! use libchip8::emulator::Emulator;
+//!
+//! let mut e = Emulator::new();
+//! e.store_instr(&[0x6105, 0x00FD]);
+//! let before = e.save_state();
+//! e.step().unwrap();
+//! println!("{}", StateDiff::between(&before, &e.save_state()));
+//! ```
+
+use crate::cpu::{Addr, CPU};
+use crate::savestate::SaveState;
+use std::fmt;
+use std::ops::Range;
+
+/// Cpu register, or memory byte changed by an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    PC,
+    V(usize),
+    I,
+    DT,
+    ST,
+    /// stack depth
+    SP,
+    /// byte at address, reported by [`crate::journal::Journal`]
+    Mem(Addr),
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::PC => f.write_str("PC"),
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => f.write_str("I"),
+            Register::DT => f.write_str("DT"),
+            Register::ST => f.write_str("ST"),
+            Register::SP => f.write_str("SP"),
+            Register::Mem(addr) => write!(f, "[0x{:03X}]", addr),
+        }
+    }
+}
+
+/// Register value before and after
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub reg: Register,
+    pub old: u16,
+    pub new: u16,
+}
+
+/// `V1=0x00->0x05` style formatting
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.reg {
+            Register::V(_) | Register::DT | Register::ST | Register::Mem(_) => {
+                write!(f, "{}=0x{:02X}->0x{:02X}", self.reg, self.old, self.new)
+            }
+            Register::PC | Register::I => {
+                write!(f, "{}=0x{:03X}->0x{:03X}", self.reg, self.old, self.new)
+            }
+            Register::SP => write!(f, "{}={}->{}", self.reg, self.old, self.new),
+        }
+    }
+}
+
+/// Registers other than pc differing between two cpu states, V0..VF first
+pub(crate) fn register_changes(old: &CPU, new: &CPU) -> Vec<Change> {
+    let mut out: Vec<Change> = (0..old.regs.len())
+        .filter(|&x| old.regs[x] != new.regs[x])
+        .map(|x| Change {
+            reg: Register::V(x),
+            old: old.regs[x] as u16,
+            new: new.regs[x] as u16,
+        })
+        .collect();
+    let others = [
+        (Register::I, old.i, new.i),
+        (Register::DT, old.dt as u16, new.dt as u16),
+        (Register::ST, old.st as u16, new.st as u16),
+        (
+            Register::SP,
+            old.stack_depth() as u16,
+            new.stack_depth() as u16,
+        ),
+    ];
+    out.extend(
+        others
+            .iter()
+            .filter(|(_, o, n)| o != n)
+            .map(|&(reg, old, new)| Change { reg, old, new }),
+    );
+    out
+}
+
+/// Everything differing between two snapshots
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StateDiff {
+    /// changed registers, pc first
+    pub registers: Vec<Change>,
+    /// stack contents before and after, if they differ
+    pub stack: Option<(Vec<Addr>, Vec<Addr>)>,
+    /// maximal runs of changed bytes, ascending
+    pub memory: Vec<Range<usize>>,
+    /// resolution before and after, if it changed; rows are not compared
+    /// then
+    pub resolution: Option<((usize, usize), (usize, usize))>,
+    /// indices of screen rows with a changed pixel
+    pub screen_rows: Vec<usize>,
+    /// halted flag before and after, if it changed
+    pub halted: Option<(bool, bool)>,
+}
+
+impl StateDiff {
+    /// Compares a (before) with b (after)
+    pub fn between(a: &SaveState, b: &SaveState) -> StateDiff {
+        let mut registers = vec![];
+        if a.cpu.pc != b.cpu.pc {
+            registers.push(Change {
+                reg: Register::PC,
+                old: a.cpu.pc,
+                new: b.cpu.pc,
+            });
+        }
+        registers.extend(register_changes(&a.cpu, &b.cpu));
+        let stack = (a.cpu.stack() != b.cpu.stack())
+            .then(|| (a.cpu.stack().to_vec(), b.cpu.stack().to_vec()));
+
+        let (sa, sb) = (&a.screen, &b.screen);
+        let (ra, rb) = ((sa.width, sa.height), (sb.width, sb.height));
+        let screen_rows = if ra == rb && sa.width > 0 {
+            (0..sa.height)
+                .filter(|&y| {
+                    let row = y * sa.width..(y + 1) * sa.width;
+                    sa.pixels.get(row.clone()) != sb.pixels.get(row)
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        StateDiff {
+            registers,
+            stack,
+            memory: memory_changes(a, b),
+            resolution: (ra != rb).then_some((ra, rb)),
+            screen_rows,
+            halted: (a.halted != b.halted).then_some((a.halted, b.halted)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == StateDiff::default()
+    }
+}
+
+/// Runs of differing bytes; bytes present in only one memory count as changed
+fn memory_changes(a: &SaveState, b: &SaveState) -> Vec<Range<usize>> {
+    let (ma, mb) = (a.mem.get(..).unwrap_or(&[]), b.mem.get(..).unwrap_or(&[]));
+    let len = ma.len().max(mb.len());
+    let mut out: Vec<Range<usize>> = vec![];
+    for addr in (0..len).filter(|&i| ma.get(i) != mb.get(i)) {
+        match out.last_mut() {
+            Some(r) if r.end == addr => r.end += 1,
+            _ => out.push(addr..addr + 1),
+        }
+    }
+    out
+}
+
+/// One line per kind of change, e.g. `registers PC=0x200->0x202 V1=0x00->0x05`
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no changes");
+        }
+        if !self.registers.is_empty() {
+            let regs: Vec<String> = self.registers.iter().map(Change::to_string).collect();
+            writeln!(f, "registers {}", regs.join(" "))?;
+        }
+        if let Some((old, new)) = &self.stack {
+            writeln!(f, "stack {:03X?} -> {:03X?}", old, new)?;
+        }
+        if !self.memory.is_empty() {
+            let ranges: Vec<String> = self
+                .memory
+                .iter()
+                .map(|r| match r.len() {
+                    1 => format!("0x{:03X}", r.start),
+                    _ => format!("0x{:03X}..0x{:03X}", r.start, r.end),
+                })
+                .collect();
+            writeln!(f, "memory {}", ranges.join(", "))?;
+        }
+        if let Some(((w0, h0), (w1, h1))) = self.resolution {
+            writeln!(f, "resolution {}x{} -> {}x{}", w0, h0, w1, h1)?;
+        }
+        if !self.screen_rows.is_empty() {
+            let rows: Vec<String> = self.screen_rows.iter().map(usize::to_string).collect();
+            writeln!(f, "screen rows {}", rows.join(", "))?;
+        }
+        if let Some((old, new)) = self.halted {
+            writeln!(f, "halted {} -> {}", old, new)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn between_test() {
+        let mut e = Emulator::new();
+        e.store_font();
+        // LD V1, 5; LD I, 0x300; LD [I], V1; LD F, V1; DRW V0, V0, 5; CALL 0x20E; EXIT
+        e.store_instr(&[0x6105, 0xA300, 0xF155, 0xF129, 0xD005, 0x220E, 0x00FD]);
+        let start = e.save_state();
+        assert!(StateDiff::between(&start, &start).is_empty());
+        assert_eq!(
+            "no changes\n",
+            StateDiff::between(&start, &start).to_string()
+        );
+
+        e.step().unwrap();
+        let d = StateDiff::between(&start, &e.save_state());
+        assert_eq!("registers PC=0x200->0x202 V1=0x00->0x05\n", d.to_string());
+
+        e.step().unwrap();
+        let before = e.save_state();
+        e.step().unwrap();
+        let d = StateDiff::between(&before, &e.save_state());
+        assert_eq!(vec![0x301..0x302], d.memory);
+        assert!(d.screen_rows.is_empty());
+
+        e.step().unwrap();
+        let before = e.save_state();
+        e.step().unwrap();
+        e.step().unwrap();
+        let d = StateDiff::between(&before, &e.save_state());
+        assert_eq!(vec![0, 1, 2, 3, 4], d.screen_rows);
+        assert_eq!(Some((vec![], vec![0x20A])), d.stack);
+        assert!(d.to_string().contains("screen rows 0, 1, 2, 3, 4\n"));
+        assert!(d.to_string().contains("stack [] -> [20A]\n"));
+    }
+}
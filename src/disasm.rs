@@ -0,0 +1,360 @@
+use crate::cpu::{Addr, Instr, Opcode};
+use crate::mem::Mem;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+/// Disassembled instruction: address, raw word and mnemonic
+pub type Line = (Addr, Instr, String);
+
+/// Canonical mnemonic of decoded instruction, e.g. "DRW V2, V3, 5".
+///
+/// LDIL is rendered without its address, which lives in the next word;
+/// see [`disassemble`] for the complete form.
+pub fn mnemonic(op: &Opcode) -> String {
+    match op {
+        Opcode::CLS => "CLS".to_string(),
+        Opcode::RET => "RET".to_string(),
+        Opcode::JP(a) => format!("JP 0x{:03X}", a),
+        Opcode::CALL(a) => format!("CALL 0x{:03X}", a),
+        Opcode::SE(x, kk) => format!("SE V{:X}, 0x{:02X}", x, kk),
+        Opcode::SNE(x, kk) => format!("SNE V{:X}, 0x{:02X}", x, kk),
+        Opcode::SER(x, y) => format!("SE V{:X}, V{:X}", x, y),
+        Opcode::LD(x, kk) => format!("LD V{:X}, 0x{:02X}", x, kk),
+        Opcode::ADD(x, kk) => format!("ADD V{:X}, 0x{:02X}", x, kk),
+        Opcode::LDR(x, y) => format!("LD V{:X}, V{:X}", x, y),
+        Opcode::OR(x, y) => format!("OR V{:X}, V{:X}", x, y),
+        Opcode::AND(x, y) => format!("AND V{:X}, V{:X}", x, y),
+        Opcode::XOR(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+        Opcode::ADDR(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+        Opcode::SUBR(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+        Opcode::SHR(x, y) => shift("SHR", *x, *y),
+        Opcode::SUBRN(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+        Opcode::SHL(x, y) => shift("SHL", *x, *y),
+        Opcode::SNER(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+        Opcode::LDI(a) => format!("LD I, 0x{:03X}", a),
+        Opcode::JPOFF(a) => format!("JP V0, 0x{:03X}", a),
+        Opcode::RND(x, kk) => format!("RND V{:X}, 0x{:02X}", x, kk),
+        Opcode::DRW(x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Opcode::SKP(x) => format!("SKP V{:X}", x),
+        Opcode::SKNP(x) => format!("SKNP V{:X}", x),
+        Opcode::KEYSET(x) => format!("LD V{:X}, K", x),
+        Opcode::DTSET(x) => format!("LD DT, V{:X}", x),
+        Opcode::DTGET(x) => format!("LD V{:X}, DT", x),
+        Opcode::STSET(x) => format!("LD ST, V{:X}", x),
+        Opcode::IINC(x) => format!("ADD I, V{:X}", x),
+        Opcode::IDIG(x) => format!("LD F, V{:X}", x),
+        Opcode::BCD(x) => format!("LD B, V{:X}", x),
+        Opcode::REGSSTORE(x) => format!("LD [I], V{:X}", x),
+        Opcode::REGLOAD(x) => format!("LD V{:X}, [I]", x),
+        Opcode::SCD(n) => format!("SCD {}", n),
+        Opcode::SCR => "SCR".to_string(),
+        Opcode::SCL => "SCL".to_string(),
+        Opcode::EXIT => "EXIT".to_string(),
+        Opcode::LOW => "LOW".to_string(),
+        Opcode::HIGH => "HIGH".to_string(),
+        Opcode::LDHF(x) => format!("LD HF, V{:X}", x),
+        Opcode::RPLSTORE(x) => format!("LD R, V{:X}", x),
+        Opcode::RPLLOAD(x) => format!("LD V{:X}, R", x),
+        Opcode::SAVE(x, y) => format!("SAVE V{:X}, V{:X}", x, y),
+        Opcode::LOAD(x, y) => format!("LOAD V{:X}, V{:X}", x, y),
+        Opcode::LDIL => "LD I, LONG".to_string(),
+        Opcode::PLANE(n) => format!("PLANE {}", n),
+        Opcode::Unknown(raw) => format!("DW 0x{:04X}", raw),
+    }
+}
+
+/// Shifts show Vy only when it is encoded
+fn shift(name: &str, x: usize, y: usize) -> String {
+    if y == 0 {
+        format!("{} V{:X}", name, x)
+    } else {
+        format!("{} V{:X}, V{:X}", name, x, y)
+    }
+}
+
+/// Mnemonic of raw instruction word; undecodable words become "DW" data
+pub fn instr(i: Instr) -> String {
+    mnemonic(&Opcode::decode(i))
+}
+
+/// Disassembles byte slice loaded at base, two bytes per instruction.
+///
+/// Long I load is returned as single line covering both words; odd
+/// trailing byte is returned as "DB" data.
+pub fn disassemble(bytes: &[u8], base: Addr) -> Vec<Line> {
+    let mut res = vec![];
+    let mut off = 0;
+    while off < bytes.len() {
+        let addr = base.wrapping_add(off as u16);
+        if off + 1 == bytes.len() {
+            res.push((
+                addr,
+                (bytes[off] as Instr) << 8,
+                format!("DB 0x{:02X}", bytes[off]),
+            ));
+            break;
+        }
+        let word = (bytes[off] as Instr) << 8 | bytes[off + 1] as Instr;
+        off += 2;
+        if Opcode::from(word) == Some(Opcode::LDIL) && off + 1 < bytes.len() {
+            let long = (bytes[off] as Instr) << 8 | bytes[off + 1] as Instr;
+            res.push((addr, word, format!("LD I, LONG 0x{:04X}", long)));
+            off += 2;
+        } else {
+            res.push((addr, word, instr(word)));
+        }
+    }
+    res
+}
+
+/// Disassembles memory region
+pub fn disassemble_mem(mem: &Mem, range: Range<Addr>) -> Vec<Line> {
+    let bytes: Vec<u8> = range.clone().map(|a| mem.load(a)).collect();
+    disassemble(&bytes, range.start)
+}
+
+/// Reachable code and target names found by [`analyze`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Analysis {
+    /// addresses of reachable instructions
+    pub code: BTreeSet<Addr>,
+    /// names of entry point, jump and call targets and I load addresses
+    pub labels: BTreeMap<Addr, String>,
+}
+
+/// Follows control flow from base through jumps, calls and skips,
+/// telling code from data such as sprites. Paths end at RET, EXIT,
+/// undecodable words and the end of bytes; `JP V0, nnn` is assumed to
+/// jump to a table starting at nnn.
+pub fn analyze(bytes: &[u8], base: Addr) -> Analysis {
+    analyze_with(bytes, base, &[])
+}
+
+/// Like [`analyze`], also following control flow from entries, e.g.
+/// targets of computed jumps found by [`crate::coverage::Coverage`]
+pub fn analyze_with(bytes: &[u8], base: Addr, entries: &[Addr]) -> Analysis {
+    let end = base as usize + bytes.len();
+    let inside = |a: Addr| (base as usize..end).contains(&(a as usize));
+    let word = |a: Addr| {
+        let off = a.wrapping_sub(base) as usize;
+        bytes
+            .get(off..off + 2)
+            .map(|w| (w[0] as Instr) << 8 | w[1] as Instr)
+    };
+    let size = |a: Addr| match word(a).and_then(Opcode::from) {
+        Some(Opcode::LDIL) => 4,
+        _ => 2,
+    };
+    let mut res = Analysis::default();
+    res.labels.insert(base, "start".to_string());
+    let label = |res: &mut Analysis, a: Addr, prefix: &str| {
+        if inside(a) {
+            res.labels
+                .entry(a)
+                .or_insert_with(|| format!("{}_{:03X}", prefix, a));
+        }
+    };
+    let mut todo = vec![base];
+    todo.extend(entries);
+    while let Some(pc) = todo.pop() {
+        if !inside(pc) || res.code.contains(&pc) {
+            continue;
+        }
+        let op = match word(pc).and_then(Opcode::from) {
+            Some(op) => op,
+            None => continue,
+        };
+        res.code.insert(pc);
+        let next = pc.wrapping_add(size(pc));
+        match op {
+            Opcode::RET | Opcode::EXIT => {}
+            Opcode::JP(a) => {
+                label(&mut res, a, "L");
+                todo.push(a);
+            }
+            Opcode::CALL(a) => {
+                label(&mut res, a, "sub");
+                todo.extend(&[a, next]);
+            }
+            Opcode::JPOFF(a) => {
+                label(&mut res, a, "table");
+                todo.push(a);
+            }
+            Opcode::SE(..)
+            | Opcode::SNE(..)
+            | Opcode::SER(..)
+            | Opcode::SNER(..)
+            | Opcode::SKP(_)
+            | Opcode::SKNP(_) => todo.extend(&[next, next.wrapping_add(size(next))]),
+            Opcode::LDI(a) => {
+                label(&mut res, a, "data");
+                todo.push(next);
+            }
+            _ => todo.push(next),
+        }
+    }
+    res
+}
+
+/// Line of [`disassemble_labeled`] output
+enum Item {
+    /// instruction, with the address word following `LD I, LONG`
+    Code(Opcode, Option<Instr>),
+    Data(Vec<u8>),
+}
+
+/// Disassembly that assembles back (see [`assemble_at`]) to the same
+/// bytes at base: targets within bytes are replaced by labels from
+/// [`analyze`] and everything not reached as code becomes `DB` data.
+///
+/// [`assemble_at`]: crate::asm::assemble_at
+pub fn disassemble_labeled(bytes: &[u8], base: Addr) -> String {
+    let analysis = analyze(bytes, base);
+    let mut lines = vec![];
+    let mut off = 0;
+    while off < bytes.len() {
+        let addr = base.wrapping_add(off as u16);
+        if analysis.code.contains(&addr) {
+            let word = (bytes[off] as Instr) << 8 | bytes[off + 1] as Instr;
+            let op = Opcode::from(word).expect("analyzed code decodes");
+            let long = match op {
+                Opcode::LDIL if off + 3 < bytes.len() => {
+                    off += 2;
+                    Some((bytes[off] as Instr) << 8 | bytes[off + 1] as Instr)
+                }
+                _ => None,
+            };
+            lines.push((addr, Item::Code(op, long)));
+            off += 2;
+            continue;
+        }
+        let mut data = vec![];
+        while off < bytes.len() && data.len() < 8 {
+            let a = base.wrapping_add(off as u16);
+            if !data.is_empty() && (analysis.code.contains(&a) || analysis.labels.contains_key(&a))
+            {
+                break;
+            }
+            data.push(bytes[off]);
+            off += 1;
+        }
+        lines.push((addr, Item::Data(data)));
+    }
+    // labels inside a line, e.g. jump targets in the middle of an
+    // instruction, are never defined, so addresses stay numeric there
+    let starts: BTreeSet<Addr> = lines.iter().map(|(a, _)| *a).collect();
+    let label = |a: Addr| analysis.labels.get(&a).filter(|_| starts.contains(&a));
+    let name = |a: Addr| label(a).cloned().unwrap_or_else(|| format!("0x{:03X}", a));
+    let mut out = String::new();
+    for (addr, line) in lines {
+        if let Some(l) = label(addr) {
+            out.push_str(&format!("{}:\n", l));
+        }
+        let text = match line {
+            Item::Code(Opcode::JP(a), _) => format!("JP {}", name(a)),
+            Item::Code(Opcode::CALL(a), _) => format!("CALL {}", name(a)),
+            Item::Code(Opcode::JPOFF(a), _) => format!("JP V0, {}", name(a)),
+            Item::Code(Opcode::LDI(a), _) => format!("LD I, {}", name(a)),
+            Item::Code(_, Some(long)) => format!("LD I, LONG 0x{:04X}", long),
+            Item::Code(op, None) => mnemonic(&op),
+            Item::Data(data) => {
+                let data: Vec<String> = data.iter().map(|b| format!("0x{:02X}", b)).collect();
+                format!("DB {}", data.join(", "))
+            }
+        };
+        out.push_str(&format!("    {}\n", text));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mnemonic_test() {
+        assert_eq!("LD V3, 0x1A", mnemonic(&Opcode::LD(3, 0x1A)));
+        assert_eq!("DRW V2, V3, 5", mnemonic(&Opcode::DRW(2, 3, 5)));
+        assert_eq!("JP V0, 0x123", mnemonic(&Opcode::JPOFF(0x123)));
+        assert_eq!("SHR VA", instr(0x8A06));
+        assert_eq!("SHL VA, VB", instr(0x8ABE));
+        assert_eq!("DW 0xFFFF", instr(0xFFFF));
+    }
+
+    #[test]
+    fn every_opcode_has_mnemonic_test() {
+        for op in Opcode::iter_examples() {
+            assert!(!mnemonic(&op).is_empty());
+        }
+    }
+
+    #[test]
+    fn disassemble_test() {
+        let lines = disassemble(
+            &[0x60, 0x05, 0xF0, 0x00, 0x12, 0x34, 0x00, 0x00, 0xAB],
+            0x200,
+        );
+        assert_eq!(
+            vec![
+                (0x200, 0x6005, "LD V0, 0x05".to_string()),
+                (0x202, 0xF000, "LD I, LONG 0x1234".to_string()),
+                (0x206, 0x0000, "DW 0x0000".to_string()),
+                (0x208, 0xAB00, "DB 0xAB".to_string()),
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn disassemble_mem_test() {
+        let mut m = Mem::new();
+        m.store_arr(0x300, &[0x00, 0xE0, 0x00, 0xEE]);
+        let lines = disassemble_mem(&m, 0x300..0x304);
+        assert_eq!((0x302, 0x00EE, "RET".to_string()), lines[1]);
+    }
+
+    /// Program with a subroutine, a skip and a sprite between code
+    const PROGRAM: &str = "
+        start:
+            LD I, ball
+            CALL draw
+        loop:
+            SE V0, 1
+            JP loop
+            EXIT
+        ball:
+            DB 0x60, 0xF0, 0xF0, 0x60
+        draw:
+            DRW V1, V2, 4
+            RET
+    ";
+
+    #[test]
+    fn analyze_test() {
+        let bytes = crate::asm::assemble(PROGRAM).unwrap();
+        let a = analyze(&bytes, 0x200);
+        let code: Vec<Addr> = a.code.iter().copied().collect();
+        assert_eq!(vec![0x200, 0x202, 0x204, 0x206, 0x208, 0x20E, 0x210], code);
+        assert_eq!(Some(&"start".to_string()), a.labels.get(&0x200));
+        assert_eq!(Some(&"L_204".to_string()), a.labels.get(&0x204));
+        assert_eq!(Some(&"data_20A".to_string()), a.labels.get(&0x20A));
+        assert_eq!(Some(&"sub_20E".to_string()), a.labels.get(&0x20E));
+    }
+
+    #[test]
+    fn disassemble_labeled_test() {
+        let bytes = crate::asm::assemble(PROGRAM).unwrap();
+        let text = disassemble_labeled(&bytes, 0x200);
+        assert!(text.contains("    CALL sub_20E\n"));
+        assert!(text.contains("data_20A:\n    DB 0x60, 0xF0, 0xF0, 0x60\n"));
+        assert_eq!(bytes, crate::asm::assemble(&text).unwrap());
+        let odd = [0xF0, 0x00, 0x12, 0x34, 0x00, 0xFD, 0xAB];
+        let text = disassemble_labeled(&odd, 0x200);
+        assert_eq!(&odd[..], &crate::asm::assemble(&text).unwrap()[..]);
+        // CALL 0x203 into the middle of CLS
+        let inner = [0x22, 0x03, 0x00, 0xE0, 0xEE];
+        let text = disassemble_labeled(&inner, 0x200);
+        assert!(text.starts_with("start:\n    CALL 0x203\n    CLS\n"));
+        assert_eq!(&inner[..], &crate::asm::assemble(&text).unwrap()[..]);
+    }
+}
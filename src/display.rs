@@ -1,55 +1,699 @@
+use std::collections::VecDeque;
+
 /// number of collumns in chip-8 display
 pub const COLS: usize = 64;
 
 /// number of rows in chip-8 display
 pub const ROWS: usize = 32;
 
+/// number of collumns in SUPER-CHIP hi-res display
+pub const HIRES_COLS: usize = 128;
+
+/// number of rows in SUPER-CHIP hi-res display
+pub const HIRES_ROWS: usize = 64;
+
+/// number of XO-CHIP bit planes
+pub const PLANES: usize = 2;
+
+/// RGBA colors of pixel color indices 0..=3 (see [`Scr::pixel`])
+pub type Palette = [[u8; 4]; 4];
+
+/// Black background, white first plane, gray second plane and both planes
+pub const DEFAULT_PALETTE: Palette = [
+    [0x00, 0x00, 0x00, 0xFF],
+    [0xFF, 0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA, 0xFF],
+    [0x55, 0x55, 0x55, 0xFF],
+];
+
+/// Contents of a screen as saved by [`Scr::save`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScreenState {
+    pub hires: bool,
+    /// selected planes mask
+    pub planes: u8,
+    pub width: usize,
+    pub height: usize,
+    /// color index of each pixel (see [`Scr::pixel`]), row by row
+    pub pixels: Vec<u8>,
+}
+
+/// Chip-8 display.
+///
+/// Drawing, reading and clearing operate on selected planes only; plain
+/// chip-8 programs only ever use the first plane, XO-CHIP programs may
+/// select the second one or both.
 pub trait Scr {
     fn xor(&mut self, x: usize, y: usize, v: bool) -> bool;
     fn xor_bytes(&mut self, x: usize, y: usize, bytes: &[u8]) -> bool;
+    /// Returns if pixel is lit in any selected plane
     fn get(&self, x: usize, y: usize) -> bool;
     fn clear(&mut self);
+    /// Switches between 64x32 and 128x64 resolution, clearing the screen
+    /// and leaving 64x64 mode
+    fn set_hires(&mut self, hires: bool);
+    fn hires(&self) -> bool;
+
+    /// Switches between 64x32 and 64x64 resolution of two-page hi-res
+    /// chip-8, clearing the screen and leaving 128x64 mode. Screens without
+    /// it ignore it.
+    fn set_two_page(&mut self, _two_page: bool) {}
+
+    fn two_page(&self) -> bool {
+        false
+    }
+
+    /// Selects planes affected by drawing (bit 0 - first plane,
+    /// bit 1 - second plane). Screens without planes ignore it.
+    fn select_planes(&mut self, _mask: u8) {}
+
+    /// Mask of selected planes
+    fn planes(&self) -> u8 {
+        1
+    }
+
+    /// Color index of pixel combining all planes, irrespective of selection
+    /// (bit 0 - first plane, bit 1 - second plane)
+    fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.get(x, y) as u8
+    }
+
+    /// Number of collumns in current resolution
+    fn width(&self) -> usize {
+        if self.hires() {
+            HIRES_COLS
+        } else {
+            COLS
+        }
+    }
+
+    /// Number of rows in current resolution
+    fn height(&self) -> usize {
+        if self.hires() || self.two_page() {
+            HIRES_ROWS
+        } else {
+            ROWS
+        }
+    }
+
+    /// Captures resolution, plane selection and all pixels
+    fn save(&self) -> ScreenState {
+        let (width, height) = (self.width(), self.height());
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(self.pixel(x, y));
+            }
+        }
+        ScreenState {
+            hires: self.hires(),
+            planes: self.planes(),
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Replaces contents with previously saved state
+    fn restore(&mut self, state: &ScreenState) {
+        self.set_hires(state.hires);
+        if !state.hires && state.height == HIRES_ROWS {
+            self.set_two_page(true);
+        }
+        for plane in plane_masks(0xFF) {
+            self.select_planes(plane);
+            self.clear();
+            for (idx, &color) in state.pixels.iter().enumerate() {
+                if color & plane != 0 {
+                    self.xor(idx % state.width, idx / state.width, true);
+                }
+            }
+        }
+        self.select_planes(state.planes);
+    }
+
+    /// Pixels lit in any plane packed 8 per byte, row by row, leftmost pixel
+    /// in the most significant bit; width * height / 8 bytes
+    /// (256 in low resolution)
+    fn to_packed_bytes(&self) -> Vec<u8> {
+        let (width, height) = (self.width(), self.height());
+        let mut out = vec![0u8; width * height / 8];
+        for y in 0..height {
+            for x in 0..width {
+                if self.pixel(x, y) != 0 {
+                    out[(y * width + x) / 8] |= 0x80 >> (x % 8);
+                }
+            }
+        }
+        out
+    }
+
+    /// Pixels as RGBA bytes, row by row, colored by palette
+    fn to_rgba(&self, palette: &Palette) -> Vec<u8> {
+        let (width, height) = (self.width(), self.height());
+        let mut out = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                out.extend_from_slice(&palette[self.pixel(x, y) as usize & 3]);
+            }
+        }
+        out
+    }
+
+    /// Pixels already converted to colors, row by row in current
+    /// resolution, for screens maintaining them (see [`FrameBuffer`]);
+    /// frontends can hand it to the window without copying
+    fn frame_buffer(&self) -> Option<&[u32]> {
+        None
+    }
+
+    /// Returns mask of rows changed since previous call (bit n - row n) and
+    /// resets it. Screens not tracking changes report all rows as changed.
+    fn take_dirty(&mut self) -> u64 {
+        !0
+    }
+
+    /// Moves contents n rows down; rows scrolled in at the top are blank
+    fn scroll_down(&mut self, n: usize) {
+        scroll_by(self, 0, n as isize);
+    }
+
+    /// Moves contents 4 collumns left
+    fn scroll_left(&mut self) {
+        scroll_by(self, -4, 0);
+    }
+
+    /// Moves contents 4 collumns right
+    fn scroll_right(&mut self) {
+        scroll_by(self, 4, 0);
+    }
+}
+
+/// Pixel by pixel scroll of each selected plane usable by any screen
+fn scroll_by<S: Scr + ?Sized>(s: &mut S, dx: isize, dy: isize) {
+    let (w, h) = (s.width() as isize, s.height() as isize);
+    let selected = s.planes();
+    for plane in plane_masks(selected) {
+        s.select_planes(plane);
+        let mut lit = vec![];
+        for y in 0..h {
+            for x in 0..w {
+                if s.get(x as usize, y as usize) {
+                    lit.push((x + dx, y + dy));
+                }
+            }
+        }
+        s.clear();
+        for (x, y) in lit {
+            if x >= 0 && x < w && y >= 0 && y < h {
+                s.xor(x as usize, y as usize, true);
+            }
+        }
+    }
+    s.select_planes(selected);
+}
+
+/// Textual picture of the screen for snapshot tests: `█` for lit and `.`
+/// for blank pixels, one line per row, each ending with newline
+pub fn render_text<S: Scr + ?Sized>(s: &S) -> String {
+    let mut out = String::with_capacity((s.width() + 1) * s.height() * 3);
+    for y in 0..s.height() {
+        for x in 0..s.width() {
+            out.push(if s.pixel(x, y) != 0 { '█' } else { '.' });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Screen as binary PBM (P4) image, lit pixels black
+pub fn export_pbm<S: Scr + ?Sized>(s: &S) -> Vec<u8> {
+    let mut out = format!("P4\n{} {}\n", s.width(), s.height()).into_bytes();
+    out.extend(s.to_packed_bytes());
+    out
+}
+
+/// Screen as PNG image, each pixel drawn as scale x scale square colored
+/// by palette (`png` feature)
+#[cfg(feature = "png")]
+pub fn export_png<S: Scr + ?Sized>(s: &S, scale: usize, palette: &Palette) -> Vec<u8> {
+    let scale = scale.max(1);
+    let (width, height) = (s.width() * scale, s.height() * scale);
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for x in 0..width {
+            rgba.extend_from_slice(&palette[s.pixel(x / scale, y / scale) as usize & 3]);
+        }
+    }
+    crate::png::encode_rgba(width, height, &rgba)
+}
+
+/// Lit pixels of pattern made of rows of `#` (lit) and `.` (blank);
+/// blank lines and surrounding whitespace are ignored
+fn pattern_pixels(pattern: &str) -> (bool, Vec<(usize, usize)>) {
+    let rows: Vec<&str> = pattern
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    let width = rows.iter().map(|r| r.chars().count()).max().unwrap_or(0);
+    let hires = width > COLS || rows.len() > ROWS;
+    let lit = rows
+        .iter()
+        .enumerate()
+        .flat_map(|(y, r)| {
+            r.chars()
+                .enumerate()
+                .filter(|&(_, c)| c == '#')
+                .map(move |(x, _)| (x, y))
+        })
+        .collect();
+    (hires, lit)
+}
+
+/// Sprite bytes drawn from rows of `X` or `#` (lit) and `.` or space
+/// (blank), leftmost pixel in the most significant bit. Rows up to 8 pixels
+/// wide give one byte each, wider ones (up to 16, as in SUPER-CHIP 16x16
+/// sprites) two. Blank lines and surrounding whitespace are ignored.
+pub fn sprite_from_art(art: &str) -> Result<Vec<u8>, String> {
+    let rows: Vec<&str> = art
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    let width = rows.iter().map(|r| r.chars().count()).max().unwrap_or(0);
+    if width > 16 {
+        return Err(format!("sprite is {} pixels wide, at most 16 fit", width));
+    }
+    let mut out = vec![];
+    for (y, row) in rows.iter().enumerate() {
+        let mut bits = 0u16;
+        for (x, c) in row.chars().enumerate() {
+            match c {
+                'X' | 'x' | '#' => bits |= 0x8000 >> x,
+                '.' | ' ' => {}
+                _ => return Err(format!("unexpected {:?} in sprite row {}", c, y)),
+            }
+        }
+        let [hi, lo] = bits.to_be_bytes();
+        out.push(hi);
+        if width > 8 {
+            out.push(lo);
+        }
+    }
+    Ok(out)
+}
+
+fn fill_from_pattern<S: Scr>(mut s: S, pattern: &str) -> S {
+    let (hires, lit) = pattern_pixels(pattern);
+    s.set_hires(hires);
+    for (x, y) in lit {
+        s.xor(x, y, true);
+    }
+    s
+}
+
+/// Whether screens have the same resolution and pixel colors
+pub fn same_pixels<A: Scr + ?Sized, B: Scr + ?Sized>(a: &A, b: &B) -> bool {
+    a.width() == b.width()
+        && a.height() == b.height()
+        && (0..a.height()).all(|y| (0..a.width()).all(|x| a.pixel(x, y) == b.pixel(x, y)))
+}
+
+/// Picture of differences between screens, `None` if they are the same.
+///
+/// Pixels lit on both are `#`, blank on both `.`, lit only on the first
+/// screen `+` and only on the second `-`.
+pub fn screen_diff<A: Scr + ?Sized, B: Scr + ?Sized>(a: &A, b: &B) -> Option<String> {
+    if (a.width(), a.height()) != (b.width(), b.height()) {
+        return Some(format!(
+            "resolution {}x{} != {}x{}",
+            a.width(),
+            a.height(),
+            b.width(),
+            b.height()
+        ));
+    }
+    if same_pixels(a, b) {
+        return None;
+    }
+    let mut out = String::new();
+    for y in 0..a.height() {
+        for x in 0..a.width() {
+            out.push(match (a.pixel(x, y), b.pixel(x, y)) {
+                (l, r) if l == r && l != 0 => '#',
+                (l, r) if l == r => '.',
+                (_, 0) => '+',
+                _ => '-',
+            });
+        }
+        out.push('\n');
+    }
+    Some(out)
+}
+
+/// Asserts that two screens are equal (see [`display::same_pixels`]),
+/// printing a picture of differences (see [`display::screen_diff`]) if not
+///
+/// [`display::same_pixels`]: crate::display::same_pixels
+/// [`display::screen_diff`]: crate::display::screen_diff
+#[macro_export]
+macro_rules! assert_screen_eq {
+    ($left:expr, $right:expr) => {
+        if let Some(diff) = $crate::display::screen_diff(&$left, &$right) {
+            panic!("screens differ (+ left only, - right only):\n{}", diff);
+        }
+    };
+}
+
+/// Compact picture of the screen packing 2x4 pixels into each braille
+/// character, one line per four rows, each ending with newline
+pub fn render_braille<S: Scr + ?Sized>(s: &S) -> String {
+    // dot bit for pixel at (dx, dy) within 2x4 cell
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    let mut out = String::new();
+    for cy in (0..s.height()).step_by(4) {
+        for cx in (0..s.width()).step_by(2) {
+            let mut bits = 0;
+            for (dy, row) in DOTS.iter().enumerate() {
+                for (dx, dot) in row.iter().enumerate() {
+                    if cy + dy < s.height() && s.pixel(cx + dx, cy + dy) != 0 {
+                        bits |= dot;
+                    }
+                }
+            }
+            out.push(std::char::from_u32(0x2800 + bits).unwrap_or(' '));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Row indices set in mask returned by [`Scr::take_dirty`], limited to height
+pub fn dirty_rows(mask: u64, height: usize) -> impl Iterator<Item = usize> {
+    (0..height.min(64)).filter(move |r| mask & (1 << r) != 0)
+}
+
+/// Single-plane masks contained in mask, first plane first
+pub fn plane_masks(mask: u8) -> impl Iterator<Item = u8> {
+    (0..PLANES as u8)
+        .map(|p| 1u8 << p)
+        .filter(move |m| mask & m != 0)
+}
+
+/// How [`Phosphor`] combines successive frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Persistence {
+    /// Lit pixels get full intensity, unlit ones keep given fraction
+    /// (0.0..1.0) of previous intensity each frame
+    Decay(f32),
+    /// Intensity is the fraction of last n frames the pixel was lit in
+    Blend(usize),
+}
+
+/// Presentation layer smoothing XOR flicker.
+///
+/// Feed it the screen once per frame with [`Phosphor::update`] and show
+/// [`Phosphor::to_gray`] or [`Phosphor::to_rgba`] instead of the screen.
+pub struct Phosphor {
+    mode: Persistence,
+    width: usize,
+    height: usize,
+    levels: Vec<f32>,
+    frames: VecDeque<Vec<bool>>,
+}
+
+impl Phosphor {
+    pub fn new(mode: Persistence) -> Self {
+        Phosphor {
+            mode,
+            width: 0,
+            height: 0,
+            levels: vec![],
+            frames: VecDeque::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Intensity (0.0..=1.0) of each pixel, row by row
+    pub fn levels(&self) -> &[f32] {
+        &self.levels
+    }
+
+    /// Forgets previous frames
+    pub fn reset(&mut self) {
+        self.levels.iter_mut().for_each(|l| *l = 0.0);
+        self.frames.clear();
+    }
+
+    /// Adds current screen contents as next frame; resolution change
+    /// starts over
+    pub fn update<S: Scr + ?Sized>(&mut self, s: &S) {
+        let (width, height) = (s.width(), s.height());
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.levels = vec![0.0; width * height];
+            self.frames.clear();
+        }
+        let lit: Vec<bool> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| s.pixel(x, y) != 0)
+            .collect();
+        match self.mode {
+            Persistence::Decay(keep) => {
+                for (l, &on) in self.levels.iter_mut().zip(&lit) {
+                    *l = if on { 1.0 } else { *l * keep };
+                }
+            }
+            Persistence::Blend(n) => {
+                self.frames.push_back(lit);
+                while self.frames.len() > n.max(1) {
+                    self.frames.pop_front();
+                }
+                let count = self.frames.len() as f32;
+                for (i, l) in self.levels.iter_mut().enumerate() {
+                    *l = self.frames.iter().filter(|f| f[i]).count() as f32 / count;
+                }
+            }
+        }
+    }
+
+    /// One byte per pixel, row by row; 0 - dark, 255 - fully lit
+    pub fn to_gray(&self) -> Vec<u8> {
+        self.levels
+            .iter()
+            .map(|l| (l * 255.0).round() as u8)
+            .collect()
+    }
+
+    /// Four bytes per pixel, row by row, blending off and on colors
+    pub fn to_rgba(&self, off: [u8; 4], on: [u8; 4]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.levels.len() * 4);
+        for l in &self.levels {
+            for c in 0..4 {
+                let v = off[c] as f32 + (on[c] as f32 - off[c] as f32) * l;
+                out.push(v.round() as u8);
+            }
+        }
+        out
+    }
 }
+
 /// Screen is an 2d array of bool values
 pub struct Screen {
-    pixels: [[bool; COLS]; ROWS],
+    dirty: u64,
+    pixels: [[[bool; HIRES_COLS]; HIRES_ROWS]; PLANES],
+    hires: bool,
+    two_page: bool,
+    planes: u8,
 }
+
+/// Screen storing each row as bits of an integer
 pub struct BitScreen {
-    pixels: [u64; 32],
+    dirty: u64,
+    pixels: [[u128; HIRES_ROWS]; PLANES],
+    hires: bool,
+    two_page: bool,
+    planes: u8,
 }
+
+/// Indices of planes selected by mask
+fn selected(mask: u8) -> impl Iterator<Item = usize> {
+    (0..PLANES).filter(move |p| mask & (1 << p) != 0)
+}
+
 impl BitScreen {
+    /// Screen with pixels lit where pattern has `#` (rows of `#` and `.`),
+    /// in hi-res mode if pattern does not fit 64x32
+    pub fn from_pattern(pattern: &str) -> Self {
+        fill_from_pattern(BitScreen::new(), pattern)
+    }
+
     pub fn new() -> Self {
-        BitScreen { pixels: [0u64; 32] }
+        BitScreen {
+            dirty: !0,
+            pixels: [[0u128; HIRES_ROWS]; PLANES],
+            hires: false,
+            two_page: false,
+            planes: 1,
+        }
+    }
+
+    /// Bit of collumn x; collumn 0 is the most significant bit
+    fn bit(x: usize) -> u128 {
+        1u128 << (HIRES_COLS - 1 - x)
+    }
+
+    /// Sprite byte moved to collumn x, wrapping around the current width
+    fn sprite_row(&self, x: usize, b: u8) -> u128 {
+        let w = self.width();
+        let x = x % w;
+        let low_mask = if w == HIRES_COLS {
+            !0
+        } else {
+            (1u128 << w) - 1
+        };
+        let sprite = (b as u128) << (w - 8);
+        let rotated = if x == 0 {
+            sprite
+        } else {
+            (sprite >> x) | (sprite << (w - x))
+        };
+        (rotated & low_mask) << (HIRES_COLS - w)
     }
 }
+
 impl Scr for BitScreen {
+    fn scroll_down(&mut self, n: usize) {
+        let h = self.height();
+        for p in selected(self.planes) {
+            let rows = &mut self.pixels[p][..h];
+            let n = n.min(h);
+            rows.copy_within(..h - n, n);
+            rows[..n].iter_mut().for_each(|r| *r = 0);
+        }
+        self.dirty = !0;
+    }
+
+    fn scroll_left(&mut self) {
+        let h = self.height();
+        for p in selected(self.planes) {
+            self.pixels[p][..h].iter_mut().for_each(|r| *r <<= 4);
+        }
+        self.dirty = !0;
+    }
+
+    fn scroll_right(&mut self) {
+        let (w, h) = (self.width(), self.height());
+        let window = !0u128 << (HIRES_COLS - w);
+        for p in selected(self.planes) {
+            self.pixels[p][..h]
+                .iter_mut()
+                .for_each(|r| *r = (*r >> 4) & window);
+        }
+        self.dirty = !0;
+    }
+
+    fn to_packed_bytes(&self) -> Vec<u8> {
+        let bytes = self.width() / 8;
+        (0..self.height())
+            .flat_map(|row| {
+                let line = self.pixels.iter().fold(0, |l, p| l | p[row]);
+                line.to_be_bytes().to_vec().into_iter().take(bytes)
+            })
+            .collect()
+    }
+
     fn xor(&mut self, x: usize, y: usize, v: bool) -> bool {
         let prev = self.get(x, y);
-        let val_with_bit = 1u64.rotate_right((x as u32) + 1);
-        self.pixels[y] ^= val_with_bit;
-        prev & !(prev ^ v)
+        if v {
+            let (row, bit) = (y % self.height(), BitScreen::bit(x % self.width()));
+            for p in selected(self.planes) {
+                self.pixels[p][row] ^= bit;
+            }
+            self.dirty |= 1 << row;
+        }
+        prev && v
     }
 
     fn get(&self, x: usize, y: usize) -> bool {
-        self.pixels[y].rotate_left((x as u32).saturating_add(1) % 64) & 1 == 1
+        let (row, bit) = (y % self.height(), BitScreen::bit(x % self.width()));
+        selected(self.planes).any(|p| self.pixels[p][row] & bit != 0)
     }
+
     fn clear(&mut self) {
-        self.pixels.iter_mut().for_each(|e| *e = 0);
+        for p in selected(self.planes) {
+            self.pixels[p].iter_mut().for_each(|e| *e = 0);
+        }
+        self.dirty = !0;
     }
 
     fn xor_bytes(&mut self, x: usize, y: usize, bytes: &[u8]) -> bool {
-        let x = x as u32;
+        let h = self.height();
         let mut overflow = false;
         for (bidx, b) in bytes.iter().enumerate() {
-            let val_to_xor = (*b as u64).rotate_right(x.saturating_add(8) % 64);
-            let old_line = self.pixels[y + bidx];
-            let new_line = old_line ^ val_to_xor;
-            self.pixels[y + bidx] = new_line;
-            overflow = overflow || (old_line & new_line > 0);
+            let row = (y + bidx) % h;
+            let val_to_xor = self.sprite_row(x, *b);
+            if val_to_xor != 0 {
+                self.dirty |= 1 << row;
+            }
+            for p in selected(self.planes) {
+                let old_line = self.pixels[p][row];
+                self.pixels[p][row] = old_line ^ val_to_xor;
+                overflow |= old_line & val_to_xor != 0;
+            }
         }
         overflow
     }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.two_page = false;
+        self.pixels = [[0u128; HIRES_ROWS]; PLANES];
+        self.dirty = !0;
+    }
+
+    fn hires(&self) -> bool {
+        self.hires
+    }
+
+    fn set_two_page(&mut self, two_page: bool) {
+        self.set_hires(false);
+        self.two_page = two_page;
+    }
+
+    fn two_page(&self) -> bool {
+        self.two_page
+    }
+
+    fn select_planes(&mut self, mask: u8) {
+        self.planes = mask & 0b11;
+    }
+
+    fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    fn take_dirty(&mut self) -> u64 {
+        std::mem::take(&mut self.dirty)
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> u8 {
+        let (row, bit) = (y % self.height(), BitScreen::bit(x % self.width()));
+        (0..PLANES)
+            .filter(|&p| self.pixels[p][row] & bit != 0)
+            .fold(0, |c, p| c | 1 << p)
+    }
 }
+
 fn bools_from_byte(v: u8) -> [bool; 8] {
     let mut b = [false; 8];
     for x in 0..8_usize {
@@ -58,61 +702,173 @@ fn bools_from_byte(v: u8) -> [bool; 8] {
     b
 }
 
-fn byte_from_bools(v: &[bool]) -> u8 {
-    let mut r = 0u8;
-    for i in 0..8_usize {
-        if v[i] {
-            r += 1 << (7 - i);
-        }
+impl PartialEq for BitScreen {
+    fn eq(&self, other: &Self) -> bool {
+        same_pixels(self, other)
+    }
+}
+
+impl PartialEq<Screen> for BitScreen {
+    fn eq(&self, other: &Screen) -> bool {
+        same_pixels(self, other)
+    }
+}
+
+impl Default for BitScreen {
+    fn default() -> Self {
+        Self::new()
     }
-    r
 }
 
 impl Screen {
+    /// Screen with pixels lit where pattern has `#` (rows of `#` and `.`),
+    /// in hi-res mode if pattern does not fit 64x32
+    pub fn from_pattern(pattern: &str) -> Self {
+        fill_from_pattern(Screen::new(), pattern)
+    }
+
     pub fn new() -> Self {
         Screen {
-            pixels: [[false; COLS]; ROWS],
+            dirty: !0,
+            pixels: [[[false; HIRES_COLS]; HIRES_ROWS]; PLANES],
+            hires: false,
+            two_page: false,
+            planes: 1,
         }
     }
 }
+
 impl Scr for Screen {
+    fn scroll_down(&mut self, n: usize) {
+        let h = self.height();
+        let n = n.min(h);
+        for p in selected(self.planes) {
+            let rows = &mut self.pixels[p][..h];
+            rows.copy_within(..h - n, n);
+            rows[..n].iter_mut().for_each(|r| *r = [false; HIRES_COLS]);
+        }
+        self.dirty = !0;
+    }
+
+    fn scroll_left(&mut self) {
+        let (w, h) = (self.width(), self.height());
+        for p in selected(self.planes) {
+            for row in self.pixels[p][..h].iter_mut() {
+                row.copy_within(4..w, 0);
+                row[w - 4..w].iter_mut().for_each(|px| *px = false);
+            }
+        }
+        self.dirty = !0;
+    }
+
+    fn scroll_right(&mut self) {
+        let (w, h) = (self.width(), self.height());
+        for p in selected(self.planes) {
+            for row in self.pixels[p][..h].iter_mut() {
+                row.copy_within(..w - 4, 4);
+                row[..4].iter_mut().for_each(|px| *px = false);
+            }
+        }
+        self.dirty = !0;
+    }
+
     /// Xors value v with value at [x, y] coors.
     /// Returns true if [x,y] changed value from true to false
     fn xor(&mut self, x: usize, y: usize, v: bool) -> bool {
-        let x = x % COLS;
-        let y = y % ROWS;
-        let was_pixel = self.pixels[y][x];
-        self.pixels[y][x] = was_pixel ^ v;
-        was_pixel && !self.pixels[y][x]
+        let x = x % self.width();
+        let y = y % self.height();
+        let mut erased = false;
+        for p in selected(self.planes) {
+            let was_pixel = self.pixels[p][y][x];
+            self.pixels[p][y][x] = was_pixel ^ v;
+            erased |= was_pixel && !self.pixels[p][y][x];
+        }
+        if v {
+            self.dirty |= 1 << y;
+        }
+        erased
     }
 
     fn xor_bytes(&mut self, x: usize, y: usize, bytes: &[u8]) -> bool {
         let mut overflow = false;
         for (bidx, b) in bytes.iter().enumerate() {
-            if let Some(pix_bool_arr) = self.pixels[y + bidx].get_mut(x..x + 8) {
-                let pix_byte = byte_from_bools(pix_bool_arr);
-                let xored = pix_byte ^ *b;
-
-                let muts = pix_bool_arr;
-                muts.copy_from_slice(&bools_from_byte(xored)[..]);
-                overflow = overflow || (pix_byte & byte_from_bools(muts) > 0);
+            let row = (y + bidx) % self.height();
+            for (bit, v) in bools_from_byte(*b).iter().enumerate() {
+                if *v {
+                    overflow |= self.xor(x + bit, row, true);
+                }
             }
         }
         overflow
     }
 
     fn get(&self, x: usize, y: usize) -> bool {
-        let x = x % COLS;
-        let y = y % ROWS;
-        self.pixels[y][x]
+        let x = x % self.width();
+        let y = y % self.height();
+        selected(self.planes).any(|p| self.pixels[p][y][x])
     }
 
     fn clear(&mut self) {
-        for c in 0..COLS {
-            for r in 0..ROWS {
-                self.pixels[r][c] = false;
+        for p in selected(self.planes) {
+            for r in self.pixels[p].iter_mut() {
+                for px in r.iter_mut() {
+                    *px = false;
+                }
             }
         }
+        self.dirty = !0;
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.two_page = false;
+        self.pixels = [[[false; HIRES_COLS]; HIRES_ROWS]; PLANES];
+        self.dirty = !0;
+    }
+
+    fn hires(&self) -> bool {
+        self.hires
+    }
+
+    fn set_two_page(&mut self, two_page: bool) {
+        self.set_hires(false);
+        self.two_page = two_page;
+    }
+
+    fn two_page(&self) -> bool {
+        self.two_page
+    }
+
+    fn select_planes(&mut self, mask: u8) {
+        self.planes = mask & 0b11;
+    }
+
+    fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    fn take_dirty(&mut self) -> u64 {
+        std::mem::take(&mut self.dirty)
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> u8 {
+        let x = x % self.width();
+        let y = y % self.height();
+        (0..PLANES)
+            .filter(|&p| self.pixels[p][y][x])
+            .fold(0, |c, p| c | 1 << p)
+    }
+}
+
+impl PartialEq for Screen {
+    fn eq(&self, other: &Self) -> bool {
+        same_pixels(self, other)
+    }
+}
+
+impl PartialEq<BitScreen> for Screen {
+    fn eq(&self, other: &BitScreen) -> bool {
+        same_pixels(self, other)
     }
 }
 
@@ -121,10 +877,311 @@ impl Default for Screen {
         Self::new()
     }
 }
+
+/// Screen keeping a caller-provided buffer of `0x00RRGGBB` colors (as used
+/// by minifb and most framebuffers) up to date as it is drawn on, so that
+/// frontends need not convert pixels every frame.
+///
+/// The buffer must hold at least `HIRES_COLS * HIRES_ROWS` colors; the
+/// first `width() * height()` hold the current resolution row by row.
+/// Only rows changed by an instruction are repainted.
+pub struct FrameBuffer<B> {
+    screen: BitScreen,
+    buffer: B,
+    colors: [u32; 4],
+    dirty: u64,
+}
+
+impl<B: AsRef<[u32]> + AsMut<[u32]>> FrameBuffer<B> {
+    /// Frame buffer colored by [`DEFAULT_PALETTE`]; panics if buffer is
+    /// too small
+    pub fn new(buffer: B) -> Self {
+        FrameBuffer::with_palette(buffer, &DEFAULT_PALETTE)
+    }
+
+    /// Frame buffer colored by RGB components of palette
+    pub fn with_palette(buffer: B, palette: &Palette) -> Self {
+        let mut colors = [0; 4];
+        for (c, [r, g, b, _]) in colors.iter_mut().zip(palette.iter()) {
+            *c = u32::from_be_bytes([0, *r, *g, *b]);
+        }
+        FrameBuffer::with_colors(buffer, colors)
+    }
+
+    /// Frame buffer storing colors[n] for pixels of color index n
+    pub fn with_colors(mut buffer: B, colors: [u32; 4]) -> Self {
+        assert!(
+            buffer.as_ref().len() >= HIRES_COLS * HIRES_ROWS,
+            "frame buffer needs {} pixels",
+            HIRES_COLS * HIRES_ROWS
+        );
+        buffer.as_mut().iter_mut().for_each(|c| *c = colors[0]);
+        FrameBuffer {
+            screen: BitScreen::new(),
+            buffer,
+            colors,
+            dirty: !0,
+        }
+    }
+
+    /// Colors of current resolution
+    pub fn buffer(&self) -> &[u32] {
+        &self.buffer.as_ref()[..self.width() * self.height()]
+    }
+
+    pub fn into_inner(self) -> B {
+        self.buffer
+    }
+
+    /// Repaints rows changed in the inner screen
+    fn sync(&mut self) {
+        let changed = self.screen.take_dirty();
+        if changed == 0 {
+            return;
+        }
+        self.dirty |= changed;
+        let (w, h) = (self.screen.width(), self.screen.height());
+        let [first, second] = &self.screen.pixels;
+        let buf = self.buffer.as_mut();
+        for y in dirty_rows(changed, h) {
+            let (a, b) = (first[y], second[y]);
+            for (x, c) in buf[y * w..(y + 1) * w].iter_mut().enumerate() {
+                let bit = BitScreen::bit(x);
+                let color = (a & bit != 0) as usize | ((b & bit != 0) as usize) << 1;
+                *c = self.colors[color];
+            }
+        }
+    }
+}
+
+impl<B: AsRef<[u32]> + AsMut<[u32]>> Scr for FrameBuffer<B> {
+    fn xor(&mut self, x: usize, y: usize, v: bool) -> bool {
+        let erased = self.screen.xor(x, y, v);
+        self.sync();
+        erased
+    }
+
+    fn xor_bytes(&mut self, x: usize, y: usize, bytes: &[u8]) -> bool {
+        let collision = self.screen.xor_bytes(x, y, bytes);
+        self.sync();
+        collision
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.screen.get(x, y)
+    }
+
+    fn clear(&mut self) {
+        self.screen.clear();
+        self.sync();
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.screen.set_hires(hires);
+        self.sync();
+    }
+
+    fn hires(&self) -> bool {
+        self.screen.hires()
+    }
+
+    fn set_two_page(&mut self, two_page: bool) {
+        self.screen.set_two_page(two_page);
+        self.sync();
+    }
+
+    fn two_page(&self) -> bool {
+        self.screen.two_page()
+    }
+
+    fn select_planes(&mut self, mask: u8) {
+        self.screen.select_planes(mask);
+    }
+
+    fn planes(&self) -> u8 {
+        self.screen.planes()
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.screen.pixel(x, y)
+    }
+
+    fn to_packed_bytes(&self) -> Vec<u8> {
+        self.screen.to_packed_bytes()
+    }
+
+    fn frame_buffer(&self) -> Option<&[u32]> {
+        Some(self.buffer())
+    }
+
+    fn take_dirty(&mut self) -> u64 {
+        std::mem::take(&mut self.dirty)
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        self.screen.scroll_down(n);
+        self.sync();
+    }
+
+    fn scroll_left(&mut self) {
+        self.screen.scroll_left();
+        self.sync();
+    }
+
+    fn scroll_right(&mut self) {
+        self.screen.scroll_right();
+        self.sync();
+    }
+}
+
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn export_test() {
+        let mut a = Screen::new();
+        let mut b = BitScreen::new();
+        for s in [&mut a as &mut dyn Scr, &mut b as &mut dyn Scr].iter_mut() {
+            s.xor_bytes(4, 1, &[0xFF]);
+            s.select_planes(2);
+            s.xor(63, 31, true);
+            let packed = s.to_packed_bytes();
+            assert_eq!(256, packed.len());
+            assert_eq!([0x0F, 0xF0], packed[8..10]);
+            assert_eq!(0x01, packed[255]);
+            assert_eq!(3, packed.iter().filter(|&&b| b != 0).count());
+            let rgba = s.to_rgba(&DEFAULT_PALETTE);
+            assert_eq!(64 * 32 * 4, rgba.len());
+            assert_eq!(DEFAULT_PALETTE[0], rgba[0..4]);
+            assert_eq!(DEFAULT_PALETTE[1], rgba[(64 + 4) * 4..(64 + 5) * 4]);
+            assert_eq!(DEFAULT_PALETTE[2], rgba[rgba.len() - 4..]);
+            s.set_hires(true);
+            assert_eq!(1024, s.to_packed_bytes().len());
+        }
+    }
+
+    #[test]
+    fn frame_buffer_test() {
+        let mut s = FrameBuffer::with_colors(vec![7; HIRES_COLS * HIRES_ROWS], [0, 1, 2, 3]);
+        assert_eq!(COLS * ROWS, s.frame_buffer().unwrap().len());
+        assert!(s.buffer().iter().all(|&c| c == 0));
+        s.xor_bytes(60, 1, &[0xFF]);
+        s.select_planes(3);
+        s.xor(0, 2, true);
+        s.take_dirty();
+        let buf = s.buffer();
+        assert_eq!(&[1, 1, 1, 1], &buf[COLS + 60..COLS * 2]);
+        assert_eq!(&[1, 1, 1, 1], &buf[COLS..COLS + 4]);
+        assert_eq!(3, buf[COLS * 2]);
+        assert_eq!(9, buf.iter().filter(|&&c| c != 0).count());
+        assert_eq!(s.to_rgba(&[[0; 4], [1; 4], [2; 4], [3; 4]])[COLS * 8], 3);
+
+        s.scroll_down(1);
+        assert_eq!(0b10, s.take_dirty() & 0b10);
+        assert_eq!(3, s.buffer()[COLS * 3]);
+        s.set_hires(true);
+        assert_eq!(HIRES_COLS * HIRES_ROWS, s.buffer().len());
+        assert!(s.buffer().iter().all(|&c| c == 0));
+        s.xor(127, 63, true);
+        assert_eq!(3, *s.buffer().last().unwrap());
+
+        let s = FrameBuffer::new(vec![0; HIRES_COLS * HIRES_ROWS]);
+        assert_eq!(COLS * ROWS, s.buffer().len());
+        assert_eq!(HIRES_COLS * HIRES_ROWS, s.into_inner().len());
+    }
+
+    #[test]
+    fn sprite_from_art_test() {
+        let art = "
+            XXXX
+            X..X
+            X  X
+            ####
+        ";
+        assert_eq!(Ok(vec![0xF0, 0x90, 0x90, 0xF0]), sprite_from_art(art));
+        assert_eq!(Ok(vec![0x80, 0x01]), sprite_from_art("X.......\n.......x"));
+        assert_eq!(
+            Ok(vec![0x80, 0x01, 0x00, 0x00]),
+            sprite_from_art("X..............X\n.")
+        );
+        assert_eq!(Ok(vec![]), sprite_from_art(""));
+        assert!(sprite_from_art("XXXXXXXXXXXXXXXXX").is_err());
+        assert_eq!(
+            Err("unexpected 'o' in sprite row 1".to_string()),
+            sprite_from_art("X\no")
+        );
+    }
+
+    #[test]
+    fn render_text_test() {
+        let mut s = BitScreen::new();
+        s.xor_bytes(1, 0, &[0xC0, 0x40]);
+        let text = render_text(&s);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(32, lines.len());
+        assert_eq!(".██.", lines[0].chars().take(4).collect::<String>());
+        assert_eq!("..█.", lines[1].chars().take(4).collect::<String>());
+        assert!(lines[2].chars().all(|c| c == '.'));
+        assert_eq!(64, lines[0].chars().count());
+    }
+
+    #[test]
+    fn export_pbm_test() {
+        let mut s = BitScreen::new();
+        s.xor_bytes(0, 1, &[0xA0]);
+        let pbm = export_pbm(&s);
+        assert_eq!(b"P4\n64 32\n", &pbm[..9]);
+        assert_eq!(9 + 256, pbm.len());
+        assert_eq!(&[0, 0xA0][..], &pbm[9 + 7..9 + 9]);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn export_png_test() {
+        let mut s = Screen::new();
+        s.xor(1, 0, true);
+        let png = export_png(&s, 2, &DEFAULT_PALETTE);
+        // IHDR width and height
+        assert_eq!(&[0, 0, 0, 128, 0, 0, 0, 64][..], &png[16..24]);
+        // first row: filter byte, then 2 black and 2 white pixels
+        let idat = &png[33 + 8 + 2 + 5..];
+        assert_eq!(0, idat[0]);
+        assert_eq!(&[0, 0, 0, 0xFF], &idat[5..9]);
+        assert_eq!(&[0xFF; 4], &idat[9..13]);
+        assert_eq!(&[0xFF; 4], &idat[13..17]);
+    }
+
+    #[test]
+    fn render_braille_test() {
+        let mut s = Screen::new();
+        s.xor_bytes(0, 0, &[0x80, 0x40, 0, 0xC0]);
+        let text = render_braille(&s);
+        assert_eq!(8, text.lines().count());
+        assert_eq!(Some('\u{28d1}'), text.chars().next());
+        assert_eq!(32, text.lines().next().unwrap().chars().count());
+    }
+
+    #[test]
+    fn dirty_rows_test() {
+        let mut a = Screen::new();
+        let mut b = BitScreen::new();
+        for s in [&mut a as &mut dyn Scr, &mut b as &mut dyn Scr].iter_mut() {
+            assert_eq!(!0, s.take_dirty());
+            assert_eq!(0, s.take_dirty());
+            s.xor_bytes(0, 31, &[0x80, 0, 0x01]);
+            assert_eq!(
+                vec![1, 31],
+                dirty_rows(s.take_dirty(), s.height()).collect::<Vec<_>>()
+            );
+            s.xor(5, 7, false);
+            assert_eq!(0, s.take_dirty());
+            s.clear();
+            assert_eq!(32, dirty_rows(s.take_dirty(), s.height()).count());
+        }
+    }
+
     #[test]
     fn setget_no_xor_test() {
         let mut a = Screen::new();
@@ -141,6 +1198,26 @@ mod tests {
         assert_eq!(false, a.get(10, 12));
         assert_eq!(true, of);
     }
+
+    #[test]
+    fn xor_bytes_collision_test() {
+        let mut a = Screen::new();
+        let mut b = BitScreen::new();
+        for s in [&mut a as &mut dyn Scr, &mut b as &mut dyn Scr].iter_mut() {
+            assert!(!s.xor_bytes(62, 3, &[0xF0, 0x0F]));
+            // misses lit pixels of the row, which stay lit
+            assert!(!s.xor_bytes(62, 4, &[0xF0]));
+            assert!(s.xor_bytes(0, 3, &[0x40]));
+            assert!(!s.get(1, 3));
+            assert!(s.xor_bytes(62, 3, &[0xF0, 0x0F]));
+            s.select_planes(3);
+            assert!(!s.xor_bytes(10, 10, &[0x80]));
+            s.select_planes(2);
+            assert!(s.xor_bytes(10, 10, &[0x80]));
+        }
+        assert!(a == b);
+    }
+
     #[test]
     fn display_test() {
         let mut d = Screen::new();
@@ -153,4 +1230,214 @@ mod tests {
         assert_eq!(d.get(36, 4), true);
         assert_eq!(d.get(4, 4), false);
     }
+
+    #[test]
+    fn xor_bytes_wraps_test() {
+        let mut a = Screen::new();
+        let mut b = BitScreen::new();
+        for s in [&mut a as &mut dyn Scr, &mut b as &mut dyn Scr].iter_mut() {
+            s.xor_bytes(60, 31, &[0xFF, 0x81]);
+            assert!(s.get(63, 31));
+            assert!(s.get(3, 31));
+            assert!(s.get(60, 0));
+            assert!(!s.get(61, 0));
+            assert!(s.get(3, 0));
+        }
+    }
+
+    #[test]
+    fn hires_test() {
+        let mut a = Screen::new();
+        let mut b = BitScreen::new();
+        for s in [&mut a as &mut dyn Scr, &mut b as &mut dyn Scr].iter_mut() {
+            s.xor(1, 1, true);
+            s.set_hires(true);
+            assert!(!s.get(1, 1));
+            assert_eq!((128, 64), (s.width(), s.height()));
+            s.xor_bytes(124, 63, &[0xFF]);
+            assert!(s.get(127, 63));
+            assert!(s.get(3, 63));
+            assert!(!s.get(64, 63));
+            assert!(!s.get(4, 63));
+        }
+    }
+
+    #[test]
+    fn two_page_test() {
+        let mut a = Screen::new();
+        let mut b = BitScreen::new();
+        for s in [&mut a as &mut dyn Scr, &mut b as &mut dyn Scr].iter_mut() {
+            s.set_hires(true);
+            s.set_two_page(true);
+            assert!(!s.hires());
+            assert_eq!((64, 64), (s.width(), s.height()));
+            s.xor_bytes(60, 62, &[0xFF, 0x81, 0x80]);
+            assert!(s.get(63, 62));
+            assert!(s.get(3, 62));
+            assert!(s.get(60, 63) && s.get(3, 63));
+            assert!(s.get(60, 0) && !s.get(61, 0));
+            let state = s.save();
+            assert_eq!(64 * 64, state.pixels.len());
+            s.set_hires(false);
+            assert_eq!((64, 32), (s.width(), s.height()));
+            s.restore(&state);
+            assert!(s.two_page() && s.get(60, 0));
+        }
+    }
+
+    /// Pseudo-random pattern in both planes
+    fn scribble(s: &mut dyn Scr) {
+        for plane in [1, 2] {
+            s.select_planes(plane);
+            for y in 0..s.height() {
+                s.xor_bytes(
+                    y * 7 + plane as usize,
+                    y,
+                    &[(y as u8).wrapping_mul(37) | 0x81],
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn scroll_matches_generic_test() {
+        type Op = fn(&mut dyn Scr);
+        let ops: [(Op, Op); 4] = [
+            (|s| s.scroll_down(3), |s| scroll_by(s, 0, 3)),
+            (|s| s.scroll_down(100), |s| scroll_by(s, 0, 100)),
+            (|s| s.scroll_left(), |s| scroll_by(s, -4, 0)),
+            (|s| s.scroll_right(), |s| scroll_by(s, 4, 0)),
+        ];
+        for hires in [false, true] {
+            for planes in [1, 2, 3] {
+                for (fast, generic) in ops.iter() {
+                    let mut a = Screen::new();
+                    let mut b = BitScreen::new();
+                    let mut reference = Screen::new();
+                    for s in [&mut a as &mut dyn Scr, &mut b, &mut reference] {
+                        s.set_hires(hires);
+                        scribble(s);
+                        s.select_planes(planes);
+                    }
+                    fast(&mut a);
+                    fast(&mut b);
+                    generic(&mut reference);
+                    assert_eq!(reference.save(), a.save());
+                    assert_eq!(reference.save(), b.save());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scroll_test() {
+        let mut a = Screen::new();
+        let mut b = BitScreen::new();
+        for s in [&mut a as &mut dyn Scr, &mut b as &mut dyn Scr].iter_mut() {
+            s.xor(10, 0, true);
+            s.xor(62, 31, true);
+            s.scroll_down(2);
+            assert!(s.get(10, 2));
+            assert!(!s.get(10, 0));
+            assert!(!s.get(62, 1));
+            s.scroll_right();
+            assert!(s.get(14, 2));
+            s.scroll_left();
+            s.scroll_left();
+            assert!(s.get(6, 2));
+            assert!(!s.get(14, 2));
+        }
+    }
+
+    #[test]
+    fn planes_test() {
+        let mut a = Screen::new();
+        let mut b = BitScreen::new();
+        for s in [&mut a as &mut dyn Scr, &mut b as &mut dyn Scr].iter_mut() {
+            s.xor_bytes(0, 0, &[0xC0]);
+            s.select_planes(2);
+            assert!(!s.get(0, 0));
+            s.xor_bytes(1, 0, &[0xC0]);
+            assert_eq!(
+                vec![1, 3, 2, 0],
+                (0..4).map(|x| s.pixel(x, 0)).collect::<Vec<_>>()
+            );
+            s.select_planes(3);
+            assert!(s.xor(1, 0, true));
+            assert_eq!(0, s.pixel(1, 0));
+            s.select_planes(1);
+            s.clear();
+            assert_eq!(2, s.pixel(2, 0));
+            assert_eq!(0, s.pixel(0, 0));
+        }
+    }
+
+    #[test]
+    fn phosphor_test() {
+        let mut s = Screen::new();
+        let mut p = Phosphor::new(Persistence::Decay(0.5));
+        s.xor(1, 0, true);
+        p.update(&s);
+        assert_eq!(64 * 32, p.to_gray().len());
+        assert_eq!(255, p.to_gray()[1]);
+        s.xor(1, 0, true);
+        p.update(&s);
+        assert_eq!(128, p.to_gray()[1]);
+        p.update(&s);
+        assert_eq!(64, p.to_gray()[1]);
+        assert_eq!(
+            [0, 0, 64, 255],
+            p.to_rgba([0, 0, 0, 255], [0, 0, 255, 255])[4..8]
+        );
+
+        let mut p = Phosphor::new(Persistence::Blend(4));
+        for on in [true, false, true, false, true].iter() {
+            if s.get(1, 0) != *on {
+                s.xor(1, 0, true);
+            }
+            p.update(&s);
+        }
+        assert_eq!(0.5, p.levels()[1]);
+        assert_eq!(0.0, p.levels()[0]);
+        s.set_hires(true);
+        p.update(&s);
+        assert_eq!(128 * 64, p.levels().len());
+        p.reset();
+        assert!(p.levels().iter().all(|&l| l == 0.0));
+    }
+
+    #[test]
+    fn pattern_test() {
+        let pattern = "
+            .#..
+            ##.#
+        ";
+        let s = Screen::from_pattern(pattern);
+        assert!(!s.hires());
+        assert!(s.get(1, 0) && s.get(0, 1) && s.get(3, 1));
+        assert_eq!(3, (0..4).filter(|&x| s.get(x, 1)).count());
+        let mut b = BitScreen::new();
+        b.xor_bytes(0, 0, &[0x40, 0xD0]);
+        assert!(b == s);
+        assert!(s == b);
+        assert_screen_eq!(s, b);
+        b.xor(5, 5, true);
+        assert!(s != b);
+        let diff = screen_diff(&s, &b).unwrap();
+        assert_eq!(".#....", &diff.lines().next().unwrap()[..6]);
+        assert_eq!("##.#..", &diff.lines().nth(1).unwrap()[..6]);
+        assert_eq!(".....-", &diff.lines().nth(5).unwrap()[..6]);
+        let wide = format!("{}#", ".".repeat(COLS));
+        assert!(BitScreen::from_pattern(&wide).hires());
+        assert_eq!(
+            Some("resolution 128x64 != 64x32".to_string()),
+            screen_diff(&BitScreen::from_pattern(&wide), &s)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "screens differ")]
+    fn assert_screen_eq_test() {
+        assert_screen_eq!(Screen::from_pattern("#"), Screen::new());
+    }
 }
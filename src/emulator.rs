@@ -1,65 +1,371 @@
+use crate::builder::EmulatorBuilder;
+use crate::clock::{self, Clock, Timing};
+use crate::coverage::Coverage;
 use crate::cpu;
 use crate::cpu::Instr;
 use crate::cpu::Opcode;
+use crate::debug::{Access, Breakpoints, CallFrame, StopReason};
+use crate::diff::{Change, Register};
+use crate::disasm;
 use crate::display;
+use crate::error::Chip8Error;
+use crate::flags::{FlagStore, FLAGS};
+use crate::handle::EmulatorHandle;
+use crate::heatmap::HeatMap;
+use crate::hook::{ExecHook, HookId, SysHandler, SysMode};
 use crate::input;
+use crate::input::{KeyEvent, KeySource, KEY_COUNT};
+use crate::journal::{self, Journal};
+use crate::loader::{self, LoaderError};
 use crate::mem;
+use crate::profile::Profiler;
+use crate::quirks::{DrawMode, Quirks};
+use crate::replay::Recording;
+use crate::rewind::History;
+use crate::rng::{self, Rng, XorShift};
+use crate::savestate::SaveState;
+use crate::sound::AudioSink;
+use crate::stats::RunStats;
+use crate::validate;
+use crate::validate::Violation;
 
 use cpu::Addr;
+use std::collections::VecDeque;
+use std::ops::{ControlFlow, Range};
+use std::time::{Duration, Instant};
+
+/// Closure run by the emulator at every emulated frame boundary
+pub type FrameCallback = Box<dyn FnMut(&mut Emulator)>;
+
+/// Handle identifying registered frame callback
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FrameCallbackId(usize);
+
+/// Notification for frontends queued once [`Emulator::enable_events`]
+/// was called
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// screen was cleared, drawn to, scrolled or switched resolution;
+    /// consecutive updates are queued once
+    DisplayUpdated,
+    /// screen switched to given resolution, e.g. by 00FE or 00FF;
+    /// followed by DisplayUpdated
+    ResolutionChanged {
+        width: usize,
+        height: usize,
+    },
+    SoundStarted,
+    SoundStopped,
+    /// FX0A started waiting for key press and release
+    WaitingForKey,
+    /// EXIT was executed
+    Halted,
+    /// instruction at pc wrote protected addr with
+    /// [`mem::ProtectMode::Report`]; queued once per instruction
+    ProtectedWrite {
+        pc: Addr,
+        addr: Addr,
+    },
+}
+
+/// Execution state reported by [`Emulator::state`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+    /// stopped by [`Emulator::pause`] until [`Emulator::resume`]
+    Paused,
+    /// FX0A waits for key press and release
+    WaitingForKey,
+    /// EXIT was executed
+    Halted,
+}
+
+/// What happened during [`Emulator::step_n`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StepSummary {
+    /// instructions executed, not counting polls of a pending FX0A
+    pub executed: usize,
+    /// screen was cleared, drawn to, scrolled or switched resolution
+    pub display_changed: bool,
+    /// sound turned on or off
+    pub sound_changed: bool,
+    /// batch ended waiting in FX0A
+    pub blocked_on_input: bool,
+    /// EXIT, breakpoint or watchpoint that ended the batch early
+    pub stop: Option<StopReason>,
+}
+
+/// What happened during [`Emulator::run_frame`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameReport {
+    /// instructions executed, not counting polls of a pending FX0A
+    pub executed: usize,
+    /// screen was cleared, drawn to, scrolled or switched resolution
+    pub drew: bool,
+    pub sound_started: bool,
+    pub sound_stopped: bool,
+    /// frame ended waiting in FX0A
+    pub waiting_for_key: bool,
+    /// EXIT, breakpoint or watchpoint that ended the frame early
+    pub stop: Option<StopReason>,
+}
+
+/// Settings of [`Emulator::run_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopConfig {
+    /// emulated time per frame, [`clock::FRAME`] by default
+    pub frame: Duration,
+    /// whether to sleep so frames follow each other in real time (default
+    /// true); turned off for tests and hosts driving the loop by vsync
+    pub realtime: bool,
+}
+
+impl Default for LoopConfig {
+    fn default() -> Self {
+        LoopConfig {
+            frame: clock::FRAME,
+            realtime: true,
+        }
+    }
+}
+
+/// Frame passed to the closure of [`Emulator::run_with`] after it was
+/// executed; input given to `emulator` applies to the next frame
+pub struct Frame<'a> {
+    pub emulator: &'a mut Emulator,
+    /// frames run so far, including this one
+    pub number: u64,
+    /// rows changed since previous frame (see [`display::Scr::take_dirty`]),
+    /// 0 if there is nothing to render
+    pub changed_rows: u64,
+    /// sound timer is running
+    pub sound: bool,
+    pub sound_started: bool,
+    pub sound_stopped: bool,
+    /// stop reason the frame ended with; execution continues next frame
+    /// (after EXIT the emulator stays halted)
+    pub stop: Option<StopReason>,
+}
+
+/// State of FX0A waiting for key press and release
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct KeyWait {
+    vx: usize,
+    pressed: Option<usize>,
+    /// keys already held when waiting started; they count only once
+    /// released and pressed again
+    held: u16,
+}
 
 /// Emulator capable of running chip-8 binaries
+///
+/// Executing instructions never panics regardless of memory contents:
+/// addresses wrap around memory, sprite rows and columns wrap around
+/// the screen and out-of-range key indices read as released. This holds
+/// for any ROM loaded with [`Emulator::store_bytes`], so untrusted
+/// programs cannot abort the host. Conditions that cannot be handled this
+/// way (stack underflow or overflow, hand-built opcodes naming registers
+/// above VF) are reported as [`Chip8Error`].
 pub struct Emulator {
     pub cpu: cpu::CPU,
     pub mem: mem::Mem,
     pub scr: Box<dyn display::Scr>,
     pub kbd: input::Keyboard,
+    /// interpreter behavior switches consulted by exec
+    pub quirks: Quirks,
+    /// statistics of executed and undecodable instructions
+    pub stats: RunStats,
+    /// source of RND values
+    pub rng: Box<dyn Rng>,
+    /// maximum subroutine nesting; deeper CALL fails with
+    /// [`Chip8Error::StackOverflow`]
+    pub stack_limit: usize,
+    /// whether timers keep counting down (and frame callbacks keep
+    /// running) while paused
+    pub tick_while_paused: bool,
+    /// runs two-page hi-res chip-8 programs: `JP 0x260` at 0x200 switches
+    /// the screen to 64x64 and continues at 0x2C0, past the interpreter
+    /// patch it jumps to, as on the VIP also when jumped to later; `0230`
+    /// clears the screen in that mode. Off by default, as the same jump
+    /// starts ordinary ROMs too; turn it on for ROMs detected with
+    /// [`loader::is_two_page_hires`].
+    pub two_page_hires: bool,
+    /// memory instructions may not write, unprotected by default
+    pub write_protect: mem::WriteProtect,
+    /// treatment of SYS instructions without a handler set with
+    /// [`Emulator::set_sys_handler`], rejected by default
+    pub sys_mode: SysMode,
+    paused: bool,
+    ips: u32,
+    start_addr: Addr,
+    halted: bool,
+    key_wait: Option<KeyWait>,
+    frame_callbacks: Vec<(FrameCallbackId, FrameCallback)>,
+    next_callback_id: usize,
+    breakpoints: Breakpoints,
+    hooks: Vec<(HookId, Box<dyn ExecHook>)>,
+    clock: Clock,
+    timing: Timing,
+    audio: Option<Box<dyn AudioSink>>,
+    flags: [u8; FLAGS],
+    flag_store: Option<Box<dyn FlagStore>>,
+    key_source: Option<Box<dyn KeySource>>,
+    sys_handler: Option<Box<dyn SysHandler>>,
+    sounding: bool,
+    history: Option<History>,
+    journal: Option<Journal>,
+    profiler: Option<Profiler>,
+    coverage: Option<Coverage>,
+    heat_map: Option<HeatMap>,
+    decoded: Option<Vec<Option<Opcode>>>,
+    /// screen changing instruction executed since last `run_frame`
+    drew: bool,
+    /// bytes last stored at start address, restored by `soft_reset`
+    rom: Vec<u8>,
+    recording: Option<Recording>,
+    events: Option<VecDeque<Event>>,
+}
+
+/// Word executed by the emulator itself instead of decoding it, see
+/// [`Emulator::sys_mode`] and [`Emulator::two_page_hires`]
+#[derive(Debug, Clone, Copy)]
+enum Native {
+    /// SYS call of routine at address
+    Sys(Addr),
+    /// trampoline or screen clear
+    TwoPage(Instr),
 }
 
+/// Instructions before and after pc shown by [`Emulator::dump_state`]
+const DUMP_CONTEXT: u32 = 4;
+
 impl Emulator {
-    fn with_screen(scr: Box<dyn display::Scr>) -> Emulator {
+    /// Default execution speed, instructions per second
+    pub const DEFAULT_IPS: u32 = 700;
+
+    /// Address programs are loaded at unless configured otherwise
+    pub const DEFAULT_START_ADDR: Addr = 0x200;
+
+    /// Address ETI-660 programs are loaded at
+    pub const ETI660_START_ADDR: Addr = 0x600;
+
+    /// First instruction of two-page hi-res chip-8 programs
+    pub const TWO_PAGE_TRAMPOLINE: Instr = 0x1260;
+
+    /// Address two-page hi-res chip-8 programs continue at after the
+    /// trampoline
+    pub const TWO_PAGE_START_ADDR: Addr = 0x2C0;
+
+    /// Screen clear of two-page hi-res chip-8
+    const TWO_PAGE_CLS: Instr = 0x0230;
+
+    /// Starts configuring new emulator
+    pub fn builder() -> EmulatorBuilder {
+        EmulatorBuilder::new()
+    }
+
+    /// Creates emulator with empty memory drawing to given screen,
+    /// e.g. [`display::Screen`] or frontend's own [`display::Scr`] implementation
+    pub fn with_screen(scr: Box<dyn display::Scr>) -> Emulator {
         Emulator {
             cpu: cpu::CPU::new(),
             mem: mem::Mem::new(),
             scr,
             kbd: input::Keyboard::new(),
+            quirks: Quirks::new(),
+            stats: RunStats::new(),
+            ips: Emulator::DEFAULT_IPS,
+            rng: rng::default_rng(),
+            stack_limit: cpu::CPU::STACK_SIZE,
+            tick_while_paused: false,
+            two_page_hires: false,
+            write_protect: mem::WriteProtect::default(),
+            sys_mode: SysMode::default(),
+            paused: false,
+            start_addr: Emulator::DEFAULT_START_ADDR,
+            halted: false,
+            key_wait: None,
+            frame_callbacks: vec![],
+            next_callback_id: 0,
+            breakpoints: Breakpoints::default(),
+            hooks: vec![],
+            clock: Clock::default(),
+            timing: Timing::Instructions,
+            audio: None,
+            flags: [0; FLAGS],
+            flag_store: None,
+            key_source: None,
+            sys_handler: None,
+            sounding: false,
+            history: None,
+            journal: None,
+            profiler: None,
+            coverage: None,
+            heat_map: None,
+            decoded: Some(vec![]),
+            drew: false,
+            rom: vec![],
+            recording: None,
+            events: None,
         }
     }
-    /// Creates emulator with empty memory.
+    /// Creates emulator with empty memory drawing to [`display::BitScreen`].
     pub fn new() -> Self {
-        //Emulator::new_simple_emulator()
         Emulator::with_screen(Box::new(display::BitScreen::new()))
     }
 
+    /// Address programs are stored at and run from
     pub fn start_addr(&self) -> Addr {
-        0x200
+        self.start_addr
+    }
+
+    pub fn set_start_addr(&mut self, addr: Addr) {
+        self.start_addr = addr;
     }
 
     pub fn store_font(&mut self) {
-        self.mem.store_font(0);
+        self.store_font_set(&mem::FontSet::DEFAULT);
+    }
+
+    /// Like [`Emulator::store_font`] with digits of set
+    pub fn store_font_set(&mut self, set: &mem::FontSet) {
+        self.mem.store_font_set(0, set);
         self.cpu.i = 0;
     }
     pub fn store_instr(&mut self, v: &[Instr]) {
         let mut a = self.start_addr();
+        self.mem.set_program(a, v.len() * 2);
+        self.rom = v.iter().flat_map(|i| i.to_be_bytes().to_vec()).collect();
         for instr in v.iter() {
             self.mem.store(a, (instr >> 8) as u8);
-            self.mem.store(a + 1, (instr & 0x00ff) as u8);
-            a += 2;
+            self.mem.store(a.wrapping_add(1), (instr & 0x00ff) as u8);
+            a = a.wrapping_add(2);
         }
         self.cpu.pc(self.start_addr());
     }
 
     fn load_instr(&self, i: Addr) -> Instr {
         let bh: u16 = self.mem.load(i).into();
-        let bl: u16 = self.mem.load(i + 1).into();
+        let bl: u16 = self.mem.load(i.wrapping_add(1)).into();
         (bh << 8) | bl
     }
 
     /// stores slice of bytes at start_addr
     pub fn store_bytes(&mut self, v: &[u8]) {
         self.mem.store_arr(self.start_addr(), v);
+        self.mem.set_program(self.start_addr(), v.len());
+        self.rom = v.to_vec();
         self.cpu.pc(self.start_addr());
     }
 
+    /// Stores sprite drawn as ASCII art (see [`display::sprite_from_art`])
+    /// at addr; returns its length in bytes
+    pub fn store_sprite(&mut self, addr: Addr, art: &str) -> Result<usize, String> {
+        let bytes = display::sprite_from_art(art)?;
+        self.mem.store_arr(addr, &bytes);
+        Ok(bytes.len())
+    }
+
     /// Stores slice of opcodes at start address
     pub fn store(&mut self, v: &[Opcode]) {
         let mut instrs: Vec<Instr> = vec![];
@@ -70,48 +376,583 @@ impl Emulator {
     }
 
     /// Fetches next instruction (Opcode enum) from location
-    /// pointed to by cpu pc register
+    /// pointed to by cpu pc register; undecodable words are counted in stats
     pub fn fetch(&mut self) -> Option<Opcode> {
-        let instr = self.load_instr(self.cpu.pc);
-        let op = Opcode::from(instr);
+        let pc = self.cpu.pc;
+        let op = match self.fetch_word() {
+            Ok(op) => Some(op),
+            Err(instr) => {
+                self.stats.record_unknown(pc, instr);
+                None
+            }
+        };
         self.cpu.instr = op;
         op
     }
 
-    pub fn step(&mut self) {
-        if let Some(op) = self.fetch() {
-            self.exec(op);
+    /// Decoded instruction at pc, or its word if undecodable; memory is
+    /// read once, or not at all for cached instructions
+    fn fetch_word(&mut self) -> Result<Opcode, Instr> {
+        let pc = self.cpu.pc;
+        if let Some(op) = self.cached_opcode(pc) {
+            return Ok(op);
+        }
+        let instr = self.load_instr(pc);
+        let op = Opcode::from(instr).ok_or(instr)?;
+        // words served by devices may change with every read
+        if !self.mem.is_io(pc) && !self.mem.is_io(pc.wrapping_add(1)) {
+            self.cache_opcode(pc, op);
+        }
+        Ok(op)
+    }
+
+    /// Enables or disables cache of decoded instructions (enabled by default)
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decoded = if enabled { Some(vec![]) } else { None };
+    }
+
+    /// Decoded instruction at addr unless memory under it was written
+    fn cached_opcode(&mut self, addr: Addr) -> Option<Opcode> {
+        let cache = self.decoded.as_mut()?;
+        if let Some((lo, hi)) = self.mem.take_writes() {
+            let size = self.mem.size();
+            if lo == 0 && hi == size - 1 || cache.len() != size {
+                *cache = vec![None; size];
+            } else {
+                // instruction at lo - 1 includes byte at lo
+                cache[(lo + size - 1) % size] = None;
+                cache[lo..=hi].iter_mut().for_each(|c| *c = None);
+            }
         }
+        cache[addr as usize % cache.len()]
     }
 
-    pub fn key_pressed(&mut self, oldk: Option<usize>, k: usize) {
-        if let Some(oldidx) = oldk {
-            if oldidx != k {
-                self.kbd.switch(oldidx);
-                self.kbd.switch(k);
+    fn cache_opcode(&mut self, addr: Addr, op: Opcode) {
+        if let Some(cache) = self.decoded.as_mut() {
+            let idx = addr as usize % cache.len();
+            cache[idx] = Some(op);
+        }
+    }
+
+    /// True after EXIT instruction was executed
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Fetches and executes single instruction; undecodable word is reported
+    /// as [`Chip8Error::InvalidOpcode`] leaving pc at it. Does nothing once
+    /// halted or while paused.
+    ///
+    /// Returns stop reason when executed instruction accessed watched memory,
+    /// halted the emulator or moved pc onto a breakpoint.
+    pub fn step(&mut self) -> Result<Option<StopReason>, Chip8Error> {
+        if self.paused {
+            return Ok(Some(StopReason::Paused));
+        }
+        if self.halted {
+            return Ok(Some(StopReason::Halted));
+        }
+        if let Some(rec) = self.recording.as_mut() {
+            rec.record_events(self.kbd.queued());
+            rec.instructions += 1;
+        }
+        if let Some(wait) = self.key_wait {
+            let before = self.journal.is_some().then(|| self.cpu.clone());
+            let reason = self.poll_key_wait(wait);
+            if let (Some(before), None) = (before, self.key_wait) {
+                self.journal_step(&before, Opcode::KEYSET(wait.vx), vec![]);
+            }
+            return Ok(reason);
+        }
+        self.kbd.clear_events();
+        let pc = self.cpu.pc;
+        self.mem.check_range(pc, 2)?;
+        let fetched = self.fetch_word();
+        let native = self.native(pc, fetched);
+        self.cpu.instr = fetched.ok().filter(|_| native.is_none());
+        let op = match (native, fetched) {
+            // hooks see the word as decoded, SYS as unknown
+            (Some(_), fetched) => fetched.unwrap_or_else(Opcode::Unknown),
+            (None, Ok(op)) => op,
+            (None, Err(raw)) => {
+                self.stats.record_unknown(pc, raw);
+                return Err(Chip8Error::InvalidOpcode { addr: pc, raw });
+            }
+        };
+        let snapshot = self.history.is_some().then(|| self.save_state());
+        let watched = if self.breakpoints.has_watches() {
+            self.mem_access(op).and_then(|(start, len, access)| {
+                let size = self.mem.size();
+                let addrs =
+                    (0..len).map(move |off| (start.wrapping_add(off) as usize % size) as Addr);
+                self.breakpoints.watched(addrs, access)
+            })
+        } else {
+            None
+        };
+        let resolution = (self.events.is_some() && matches!(op, Opcode::LOW | Opcode::HIGH))
+            .then(|| self.resolution());
+        let journaled = self
+            .journal
+            .is_some()
+            .then(|| (self.cpu.clone(), self.bytes_written_by(op)));
+        match native {
+            None => self.exec(op)?,
+            Some(native) => self.hooked(op, |e| e.exec_native(native, op))?,
+        }
+        // failed steps leave nothing to rewind
+        if let (Some(state), Some(h)) = (snapshot, self.history.as_mut()) {
+            h.push(state);
+        }
+        if let Some((before, old)) = journaled {
+            self.journal_step(&before, op, old);
+        }
+        let drew = matches!(native, Some(Native::TwoPage(_)))
+            || matches!(
+                op,
+                Opcode::CLS
+                    | Opcode::DRW(..)
+                    | Opcode::SCD(_)
+                    | Opcode::SCR
+                    | Opcode::SCL
+                    | Opcode::LOW
+                    | Opcode::HIGH
+            );
+        self.drew |= drew;
+        if self.events.is_some() {
+            if resolution.is_some_and(|r| r != self.resolution()) {
+                self.emit_resolution();
+            }
+            if drew {
+                self.emit(Event::DisplayUpdated);
             }
+            if matches!(op, Opcode::KEYSET(_)) {
+                self.emit(Event::WaitingForKey);
+            }
+            if self.halted {
+                self.emit(Event::Halted);
+            }
+        }
+        if self.halted {
+            Ok(Some(StopReason::Halted))
+        } else if watched.is_some() {
+            Ok(watched)
+        } else if self.breakpoints.is_set(self.cpu.pc) {
+            Ok(Some(StopReason::Breakpoint(self.cpu.pc)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Addresses op is about to write and their current contents
+    fn bytes_written_by(&self, op: Opcode) -> Vec<(Addr, u8)> {
+        match self.mem_access(op) {
+            Some((start, len, Access::Write)) => {
+                let size = self.mem.size();
+                let mut out: Vec<(Addr, u8)> = (0..len)
+                    .map(|off| (start.wrapping_add(off) as usize % size) as Addr)
+                    .map(|a| (a, self.mem.get(a as usize).copied().unwrap_or(0)))
+                    .collect();
+                out.sort_unstable_by_key(|&(a, _)| a);
+                out.dedup_by_key(|&mut (a, _)| a);
+                out
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Records changes op made since before, given old contents of bytes
+    /// it wrote
+    fn journal_step(&mut self, before: &cpu::CPU, op: Opcode, old: Vec<(Addr, u8)>) {
+        let memory = old
+            .into_iter()
+            .filter_map(|(addr, old)| {
+                let new = self.mem.get(addr as usize).copied()?;
+                (new != old).then_some(Change {
+                    reg: Register::Mem(addr),
+                    old: old as u16,
+                    new: new as u16,
+                })
+            })
+            .collect();
+        let entry = journal::Entry::new(before, op, &self.cpu, memory);
+        if let Some(j) = self.journal.as_mut() {
+            j.push(entry);
+        }
+    }
+
+    /// Routine address of undecodable word if it is a SYS call
+    fn sys_addr(word: Instr) -> Option<Addr> {
+        (word & 0xF000 == 0 && word != 0).then_some(word)
+    }
+
+    /// Fetched instruction at pc executed without decoding: a SYS call
+    /// with a handler set or ignored, or a word of two-page hi-res chip-8
+    fn native(&self, pc: Addr, fetched: Result<Opcode, Instr>) -> Option<Native> {
+        let word = fetched.map_or_else(|word| word, |op| op.to_instr());
+        if self.is_two_page(pc, word) {
+            return Some(Native::TwoPage(word));
+        }
+        match fetched {
+            Err(word) if self.sys_handler.is_some() || self.sys_mode == SysMode::Ignore => {
+                Emulator::sys_addr(word).map(Native::Sys)
+            }
+            _ => None,
+        }
+    }
+
+    /// Executes native word, counting it as op
+    fn exec_native(&mut self, native: Native, op: Opcode) -> Result<(), Chip8Error> {
+        let pc = self.cpu.pc;
+        match native {
+            Native::Sys(addr) => {
+                if let Some(mut handler) = self.sys_handler.take() {
+                    let result = handler.call(addr, &mut self.cpu, &mut self.mem);
+                    self.sys_handler = Some(handler);
+                    result?;
+                }
+                if self.cpu.pc == pc {
+                    self.cpu.inc_pc();
+                }
+            }
+            Native::TwoPage(word) => self.exec_two_page(word),
+        }
+        self.record_exec(pc, op);
+        Ok(())
+    }
+
+    /// True when word at pc is the trampoline or screen clear of two-page
+    /// hi-res chip-8, which have no opcodes in later variants
+    fn is_two_page(&self, pc: Addr, word: Instr) -> bool {
+        let trampoline =
+            word == Emulator::TWO_PAGE_TRAMPOLINE && pc == Emulator::DEFAULT_START_ADDR;
+        self.two_page_hires && (trampoline || word == Emulator::TWO_PAGE_CLS && self.scr.two_page())
+    }
+
+    fn exec_two_page(&mut self, word: Instr) {
+        if word == Emulator::TWO_PAGE_TRAMPOLINE {
+            self.scr.set_two_page(true);
+            self.cpu.pc(Emulator::TWO_PAGE_START_ADDR);
+            self.emit_resolution();
+        } else {
+            self.scr.clear();
+            self.cpu.inc_pc();
+        }
+    }
+
+    /// Executes up to instructions instructions and ticks timers once,
+    /// as frontends do 60 times a second. Stops early on EXIT, breakpoint,
+    /// watchpoint or FX0A; timers are not ticked after a breakpoint or
+    /// watchpoint so that the frame can be resumed by calling it again.
+    pub fn run_frame(&mut self, instructions: usize) -> Result<FrameReport, Chip8Error> {
+        let sounding = self.sounding;
+        let summary = self.step_n(instructions)?;
+        if !matches!(
+            summary.stop,
+            Some(StopReason::Breakpoint(_)) | Some(StopReason::Watchpoint { .. })
+        ) {
+            self.tick();
+        }
+        Ok(FrameReport {
+            executed: summary.executed,
+            drew: summary.display_changed,
+            sound_started: !sounding && self.sounding,
+            sound_stopped: sounding && !self.sounding,
+            waiting_for_key: self.waiting_for_key(),
+            stop: summary.stop,
+        })
+    }
+
+    /// Executes up to n instructions without ticking timers, stopping
+    /// early on EXIT, breakpoint, watchpoint or FX0A. Saves frontends
+    /// calling [`Emulator::step`] in a loop, e.g. across FFI boundaries.
+    pub fn step_n(&mut self, n: usize) -> Result<StepSummary, Chip8Error> {
+        let sounding = self.sounding;
+        self.drew = false;
+        let mut summary = StepSummary::default();
+        for _ in 0..n {
+            if self.paused {
+                summary.stop = Some(StopReason::Paused);
+                break;
+            }
+            let executed = self.stats.executed;
+            let reason = self.step()?;
+            summary.executed += (self.stats.executed - executed) as usize;
+            match reason {
+                None => {}
+                Some(StopReason::KeyWait) => break,
+                reason => {
+                    summary.stop = reason;
+                    break;
+                }
+            }
+        }
+        summary.display_changed = self.drew;
+        summary.sound_changed = sounding != self.sounding;
+        summary.blocked_on_input = self.waiting_for_key();
+        Ok(summary)
+    }
+
+    /// Current (width, height) of the screen; frontends sizing textures
+    /// should check it again after [`Event::ResolutionChanged`] or when
+    /// [`FrameReport::drew`] is set
+    pub fn resolution(&self) -> (usize, usize) {
+        (self.scr.width(), self.scr.height())
+    }
+
+    /// Stops execution: until [`Emulator::resume`], steps do nothing and
+    /// return [`StopReason::Paused`], and timers freeze unless
+    /// `tick_while_paused` is set. Key events arriving meanwhile, e.g.
+    /// ones completing FX0A, are kept for the program.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn state(&self) -> State {
+        if self.paused {
+            State::Paused
+        } else if self.halted {
+            State::Halted
+        } else if self.waiting_for_key() {
+            State::WaitingForKey
         } else {
-            self.kbd.switch(k);
+            State::Running
+        }
+    }
+
+    /// True while FX0A waits for key press and release
+    pub fn waiting_for_key(&self) -> bool {
+        self.key_wait.is_some()
+    }
+
+    /// Completes FX0A once a key newly pressed while waiting is released;
+    /// queued key events are consumed so short taps are not missed and
+    /// other keys held meanwhile do not interfere
+    fn poll_key_wait(&mut self, mut wait: KeyWait) -> Option<StopReason> {
+        self.poll_keys(0..KEY_COUNT);
+        let mut released = false;
+        while let Some(ev) = self.kbd.pop_event() {
+            match (ev, wait.pressed) {
+                (KeyEvent::Pressed(k), None) => wait.pressed = Some(k),
+                (KeyEvent::Released(k), Some(p)) if k == p => {
+                    released = true;
+                    break;
+                }
+                (KeyEvent::Released(k), _) => wait.held &= !(1 << k),
+                _ => {}
+            }
+        }
+        if wait.pressed.is_none() {
+            let held = wait.held;
+            wait.pressed = self.kbd.down_keys().find(|k| held & 1 << k == 0);
+        }
+        match wait.pressed {
+            Some(key) if released || !self.kbd.get(key) => {
+                self.key_wait = None;
+                self.cpu.regs[wait.vx] = key as u8;
+                self.cpu.inc_pc();
+                if self.breakpoints.is_set(self.cpu.pc) {
+                    Some(StopReason::Breakpoint(self.cpu.pc))
+                } else {
+                    None
+                }
+            }
+            _ => {
+                self.key_wait = Some(wait);
+                Some(StopReason::KeyWait)
+            }
+        }
+    }
+
+    /// Memory range (start, length) and access kind of data accessed by op
+    pub(crate) fn mem_access(&self, op: Opcode) -> Option<(Addr, u16, Access)> {
+        let i = self.cpu.i;
+        match op {
+            Opcode::DRW(_, _, n) => {
+                let len = if n == 0 { 32 } else { n as u16 };
+                let planes = display::plane_masks(self.scr.planes()).count() as u16;
+                Some((i, len * planes, Access::Read))
+            }
+            Opcode::BCD(_) => Some((i, 3, Access::Write)),
+            Opcode::REGSSTORE(vx) => Some((i, vx as u16 + 1, Access::Write)),
+            Opcode::REGLOAD(vx) => Some((i, vx as u16 + 1, Access::Read)),
+            Opcode::SAVE(vx, vy) => {
+                Some((i, Emulator::reg_range(vx, vy).count() as u16, Access::Write))
+            }
+            Opcode::LOAD(vx, vy) => {
+                Some((i, Emulator::reg_range(vx, vy).count() as u16, Access::Read))
+            }
+            _ => None,
         }
     }
+
+    /// Reports or rejects (see [`mem::ProtectMode`]) op writing protected
+    /// memory
+    fn check_protected(&mut self, pc: Addr, op: Opcode) -> Result<(), Chip8Error> {
+        let (start, len) = match self.mem_access(op) {
+            Some((start, len, access)) if access.covers(Access::Write) => (start, len),
+            _ => return Ok(()),
+        };
+        let size = self.mem.size();
+        let protected = (0..len)
+            .map(|k| (start as usize + k as usize) % size)
+            .find(|&a| {
+                a < self.start_addr as usize
+                    || (self.write_protect.rom && self.mem.in_program(a as Addr))
+            });
+        match (protected, self.write_protect.mode) {
+            (Some(addr), mem::ProtectMode::Error) => Err(Chip8Error::WriteProtected {
+                addr: pc,
+                target: addr as Addr,
+            }),
+            (Some(addr), mem::ProtectMode::Report) => {
+                self.emit(Event::ProtectedWrite {
+                    pc,
+                    addr: addr as Addr,
+                });
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets breakpoint at addr; returns false if it was already set
+    pub fn add_breakpoint(&mut self, addr: Addr) -> bool {
+        self.breakpoints.add(addr)
+    }
+
+    /// Removes breakpoint at addr; returns false if none was set
+    pub fn remove_breakpoint(&mut self, addr: Addr) -> bool {
+        self.breakpoints.remove(addr)
+    }
+
+    /// Watches memory range for given kind of access by executed instructions
+    /// (instruction fetches are not reported)
+    pub fn add_watchpoint(&mut self, range: Range<Addr>, access: Access) {
+        self.breakpoints.add_watch(range, access);
+    }
+
+    /// Removes all watchpoints on range; returns false if none was set
+    pub fn remove_watchpoint(&mut self, range: &Range<Addr>) -> bool {
+        self.breakpoints.remove_watch(range)
+    }
+
+    /// Presses key k, releasing previously pressed oldk if different
+    pub fn key_pressed(&mut self, oldk: Option<usize>, k: usize) {
+        if let Some(oldidx) = oldk.filter(|&o| o != k) {
+            self.kbd.push(KeyEvent::Released(oldidx));
+        }
+        self.kbd.push(KeyEvent::Pressed(k));
+    }
+
+    /// Releases first pressed key
     pub fn key_released(&mut self) {
         if let Some(key) = self.kbd.down_key() {
-            self.kbd.switch(key);
+            self.kbd.push(KeyEvent::Released(key));
+        }
+    }
+
+    /// Executes op, calling registered exec hooks before and after it
+    pub fn exec(&mut self, op: Opcode) -> Result<(), Chip8Error> {
+        self.hooked(op, |e| e.exec_op(op))
+    }
+
+    /// Runs exec as execution of op, calling exec hooks around it
+    fn hooked<F>(&mut self, op: Opcode, exec: F) -> Result<(), Chip8Error>
+    where
+        F: FnOnce(&mut Emulator) -> Result<(), Chip8Error>,
+    {
+        if self.hooks.is_empty() {
+            return exec(self);
+        }
+        let pc = self.cpu.pc;
+        let mut hooks = std::mem::take(&mut self.hooks);
+        for (_, h) in hooks.iter_mut() {
+            h.before(pc, op, &self.cpu);
+        }
+        let res = exec(self);
+        for (_, h) in hooks.iter_mut() {
+            h.after(pc, op, &mut self.cpu);
+        }
+        self.hooks = hooks;
+        res
+    }
+
+    /// Registers hook observing every executed instruction
+    pub fn add_hook(&mut self, hook: Box<dyn ExecHook>) -> HookId {
+        let id = HookId(self.next_callback_id);
+        self.next_callback_id += 1;
+        self.hooks.push((id, hook));
+        id
+    }
+
+    /// Unregisters exec hook; returns false if id was not registered
+    pub fn remove_hook(&mut self, id: HookId) -> bool {
+        let before = self.hooks.len();
+        self.hooks.retain(|(hid, _)| *hid != id);
+        before != self.hooks.len()
+    }
+
+    /// Counts op at pc in statistics, profile, coverage and heat map
+    fn record_exec(&mut self, pc: Addr, op: Opcode) {
+        self.stats.executed += 1;
+        if let Some(p) = self.profiler.as_mut() {
+            p.record(pc, &op);
+        }
+        if let Some(c) = self.coverage.as_mut() {
+            c.record(pc);
+        }
+        if self.heat_map.is_some() {
+            if let Some((start, len, access)) = self.mem_access(op) {
+                if let Some(h) = self.heat_map.as_mut() {
+                    h.record(start, len, access);
+                }
+            }
         }
     }
 
-    pub fn exec(&mut self, op: Opcode) {
+    fn exec_op(&mut self, op: Opcode) -> Result<(), Chip8Error> {
+        let pc = self.cpu.pc;
+        if let Opcode::Unknown(raw) = op {
+            return Err(Chip8Error::InvalidOpcode { addr: pc, raw });
+        }
+        if let Some(reg) = op.max_register().filter(|&r| r >= self.cpu.regs.len()) {
+            return Err(Chip8Error::InvalidRegister { addr: pc, reg });
+        }
+        if let Some((start, len, _)) = self.mem_access(op) {
+            self.mem.check_range(start, len as usize)?;
+        }
+        if op == Opcode::LDIL {
+            self.mem.check_range(pc, 4)?;
+        }
+        if self.write_protect.mode != mem::ProtectMode::Off {
+            self.check_protected(pc, op)?;
+        }
+        match op {
+            Opcode::RET if self.cpu.stack_depth() == 0 => {
+                return Err(Chip8Error::StackUnderflow(pc));
+            }
+            Opcode::CALL(_) if self.cpu.stack_depth() >= self.stack_limit => {
+                return Err(Chip8Error::StackOverflow(pc));
+            }
+            _ => {}
+        }
+        self.record_exec(pc, op);
         match op {
             Opcode::CLS => {
                 self.scr.clear();
                 self.cpu.inc_pc();
             }
             Opcode::RET => {
-                self.cpu.ret();
+                self.cpu.ret()?;
                 self.cpu.inc_pc();
             }
             Opcode::JP(addr) => self.cpu.pc = addr,
-            Opcode::CALL(addr) => self.cpu.call(addr),
+            Opcode::CALL(addr) => self.cpu.call_with_limit(addr, self.stack_limit)?,
             Opcode::SE(vx, byte) => self.cpu.skip_eq(vx, byte),
             Opcode::SNE(vx, byte) => self.cpu.skip_neq(vx, byte),
             Opcode::SER(vx, vy) => self.cpu.skip_eq_reg(vx, vy),
@@ -129,14 +970,17 @@ impl Emulator {
             }
             Opcode::AND(vx, vy) => {
                 self.cpu.and(vx, vy);
+                self.vf_reset();
                 self.cpu.inc_pc();
             }
             Opcode::OR(vx, vy) => {
                 self.cpu.or(vx, vy);
+                self.vf_reset();
                 self.cpu.inc_pc();
             }
             Opcode::XOR(vx, vy) => {
                 self.cpu.xor(vx, vy);
+                self.vf_reset();
                 self.cpu.inc_pc();
             }
             Opcode::ADDR(vx, vy) => {
@@ -147,7 +991,8 @@ impl Emulator {
                 self.cpu.subr(vx, vy);
                 self.cpu.inc_pc();
             }
-            Opcode::SHR(vx, _) => {
+            Opcode::SHR(vx, vy) => {
+                self.shift_source(vx, vy);
                 self.cpu.shr(vx);
                 self.cpu.inc_pc();
             }
@@ -157,7 +1002,8 @@ impl Emulator {
                 self.cpu.inc_pc();
             }
 
-            Opcode::SHL(vx, _) => {
+            Opcode::SHL(vx, vy) => {
+                self.shift_source(vx, vy);
                 self.cpu.shl(vx);
                 self.cpu.inc_pc();
             }
@@ -166,20 +1012,38 @@ impl Emulator {
                 self.cpu.ldi(a);
                 self.cpu.inc_pc();
             }
-            Opcode::JPOFF(a) => self.cpu.jpoff(a),
+            Opcode::JPOFF(a) => {
+                if self.quirks.jump_vx {
+                    self.cpu.jpoff_vx(a)
+                } else {
+                    self.cpu.jpoff(a)
+                }
+            }
             Opcode::RND(vx, byte) => {
-                self.cpu.rnd(vx, byte);
+                let r = self.rng.next_u8();
+                self.cpu.rnd(vx, byte, r);
                 self.cpu.inc_pc();
             }
             Opcode::DRW(vx, vy, n) => {
                 self.draw(vx, vy, n);
                 self.cpu.inc_pc();
             }
-            Opcode::SKP(vx) => self.cpu.skip_if(self.keyget(vx)),
-            Opcode::SKNP(vx) => self.cpu.skip_if(!self.keyget(vx)),
+            Opcode::SKP(vx) => {
+                let down = self.keyget(vx);
+                self.cpu.skip_if(down)
+            }
+            Opcode::SKNP(vx) => {
+                let down = self.keyget(vx);
+                self.cpu.skip_if(!down)
+            }
             Opcode::KEYSET(vx) => {
-                self.keyset(vx);
-                self.cpu.inc_pc();
+                self.poll_keys(0..KEY_COUNT);
+                let held = self.kbd.down_keys().fold(0, |m, k| m | 1 << k);
+                self.key_wait = Some(KeyWait {
+                    vx,
+                    pressed: None,
+                    held,
+                })
             }
             Opcode::DTSET(vx) => {
                 self.cpu.dtset(vx);
@@ -191,6 +1055,7 @@ impl Emulator {
             }
             Opcode::STSET(vx) => {
                 self.cpu.stset(vx);
+                self.update_sound();
                 self.cpu.inc_pc();
             }
             Opcode::IINC(vx) => {
@@ -207,12 +1072,124 @@ impl Emulator {
             }
             Opcode::REGSSTORE(vx) => {
                 self.regsstore(vx);
+                self.load_store_inc_i(vx);
                 self.cpu.inc_pc();
             }
             Opcode::REGLOAD(vx) => {
                 self.regsload(vx);
+                self.load_store_inc_i(vx);
+                self.cpu.inc_pc();
+            }
+            Opcode::SCD(n) => {
+                self.scr.scroll_down(n as usize);
+                self.cpu.inc_pc();
+            }
+            Opcode::SCR => {
+                self.scr.scroll_right();
+                self.cpu.inc_pc();
+            }
+            Opcode::SCL => {
+                self.scr.scroll_left();
+                self.cpu.inc_pc();
+            }
+            Opcode::EXIT => self.halted = true,
+            Opcode::LOW => {
+                self.scr.set_hires(false);
+                self.cpu.inc_pc();
+            }
+            Opcode::HIGH => {
+                self.scr.set_hires(true);
+                self.cpu.inc_pc();
+            }
+            Opcode::LDHF(vx) => {
+                self.cpu.i = self.mem.addr_of_large_font(self.cpu.regs[vx]);
+                self.cpu.inc_pc();
+            }
+            Opcode::RPLSTORE(vx) => {
+                self.flags[..=vx].copy_from_slice(&self.cpu.regs[..=vx]);
+                if let Some(store) = self.flag_store.as_mut() {
+                    store.save(&self.flags);
+                }
+                self.cpu.inc_pc();
+            }
+            Opcode::RPLLOAD(vx) => {
+                self.cpu.regs[..=vx].copy_from_slice(&self.flags[..=vx]);
+                self.cpu.inc_pc();
+            }
+            Opcode::SAVE(vx, vy) => {
+                for (offset, r) in Emulator::reg_range(vx, vy).enumerate() {
+                    let addr = self.cpu.i.wrapping_add(offset as u16);
+                    self.mem.store(addr, self.cpu.regs[r]);
+                }
+                self.cpu.inc_pc();
+            }
+            Opcode::LOAD(vx, vy) => {
+                for (offset, r) in Emulator::reg_range(vx, vy).enumerate() {
+                    let addr = self.cpu.i.wrapping_add(offset as u16);
+                    self.cpu.regs[r] = self.mem.load(addr);
+                }
+                self.cpu.inc_pc();
+            }
+            Opcode::LDIL => {
+                self.cpu.i = self.load_instr(pc.wrapping_add(2));
+                self.cpu.inc_pc();
+                self.cpu.inc_pc();
+            }
+            Opcode::PLANE(mask) => {
+                self.scr.select_planes(mask as u8);
                 self.cpu.inc_pc();
             }
+            // rejected above
+            Opcode::Unknown(_) => {}
+        }
+        self.skip_long(op, pc);
+        Ok(())
+    }
+
+    /// Skipping instructions skip both words of XO-CHIP long I load
+    fn skip_long(&mut self, op: Opcode, pc: Addr) {
+        let is_skip = matches!(
+            op,
+            Opcode::SE(..)
+                | Opcode::SNE(..)
+                | Opcode::SER(..)
+                | Opcode::SNER(..)
+                | Opcode::SKP(..)
+                | Opcode::SKNP(..)
+        );
+        if is_skip
+            && self.cpu.pc == pc.wrapping_add(4)
+            && self.load_instr(pc.wrapping_add(2)) == Opcode::LDIL.to_instr()
+        {
+            self.cpu.inc_pc();
+        }
+    }
+
+    /// Registers vx to vy in order, descending if vx > vy
+    fn reg_range(vx: usize, vy: usize) -> Box<dyn Iterator<Item = usize>> {
+        if vx <= vy {
+            Box::new(vx..=vy)
+        } else {
+            Box::new((vy..=vx).rev())
+        }
+    }
+
+    /// With `shift_vy` quirk shifts operate on vy copied into vx
+    fn shift_source(&mut self, vx: usize, vy: usize) {
+        if self.quirks.shift_vy {
+            self.cpu.load_r(vx, vy);
+        }
+    }
+
+    fn vf_reset(&mut self) {
+        if self.quirks.vf_reset {
+            self.cpu.regs[0xF] = 0;
+        }
+    }
+
+    fn load_store_inc_i(&mut self, vx: usize) {
+        if self.quirks.load_store_inc_i {
+            self.cpu.i = self.cpu.i.wrapping_add(vx as u16 + 1);
         }
     }
 
@@ -222,10 +1199,8 @@ impl Emulator {
 
     fn regsload(&mut self, vx: usize) {
         for i_offset in 0..=vx {
-            let memidx: usize = self.cpu.i as usize + i_offset;
-            if let Some(val) = self.mem.get(memidx) {
-                self.cpu.regs[i_offset as usize] = *val;
-            }
+            let addr = self.cpu.i.wrapping_add(i_offset as u16);
+            self.cpu.regs[i_offset] = self.mem.load(addr);
         }
     }
 
@@ -238,8 +1213,8 @@ impl Emulator {
         match Emulator::split_val(val) {
             [h, t, d] => {
                 self.mem.store(self.cpu.i, h);
-                self.mem.store(self.cpu.i + 1, t);
-                self.mem.store(self.cpu.i + 2, d);
+                self.mem.store(self.cpu.i.wrapping_add(1), t);
+                self.mem.store(self.cpu.i.wrapping_add(2), d);
             }
         }
     }
@@ -248,74 +1223,783 @@ impl Emulator {
         self.cpu.i = self.mem.addr_of_font(self.cpu.regs[vx]);
     }
 
-    /// Sets contents ov vx register to index of pressed key (if any is pressed;
-    /// otherwise does nothing)
-    fn keyset(&mut self, vx: usize) {
-        if let Some(idx) = self.kbd.down_key() {
-            self.cpu.regs[vx] = idx as u8;
-        }
-    }
-
     /// Returns if key given in vx register is pressed
-    fn keyget(&self, vx: usize) -> bool {
+    fn keyget(&mut self, vx: usize) -> bool {
         let idx = self.cpu.regs[vx] as usize;
+        self.poll_keys(idx..(idx + 1).min(KEY_COUNT));
         self.kbd.get(idx)
     }
 
-    fn draw(&mut self, vx: usize, vy: usize, n: u8) {
-        let x: usize = self.cpu.regs[vx] as usize;
-        let y: usize = self.cpu.regs[vy] as usize;
-        let bytes = self
-            .mem
-            .get(self.cpu.i as usize..(self.cpu.i.wrapping_add(n as u16) as usize));
-        if let Some(bytes) = bytes {
-            self.cpu.regs[0xF] = if self.scr.xor_bytes(x, y, bytes) {
-                1
-            } else {
-                0
+    /// Queues events for keys whose state in the key source differs from
+    /// kbd, so key waits, recordings and save states see them
+    fn poll_keys(&mut self, keys: Range<usize>) {
+        if let Some(source) = self.key_source.as_mut() {
+            for k in keys {
+                let down = source.is_down(k);
+                if down != self.kbd.get(k) {
+                    self.kbd.push(if down {
+                        KeyEvent::Pressed(k)
+                    } else {
+                        KeyEvent::Released(k)
+                    });
+                }
             }
         }
     }
 
-    pub fn run(&mut self) {
-        self.cpu.pc(self.start_addr());
-        loop {
-            if let Some(op) = self.fetch() {
-                self.exec(op);
-            } else {
-                break;
-            }
+    /// Draws n-byte sprite from I at (vx, vy); n = 0 draws SUPER-CHIP
+    /// 16x16 sprite made of 32 bytes, two per row. With several XO-CHIP
+    /// planes selected, sprite data for each plane follows the previous one.
+    /// The origin wraps; `quirks.draw_mode` decides about the rest.
+    fn draw(&mut self, vx: usize, vy: usize, n: u8) {
+        let x: usize = self.cpu.regs[vx] as usize % self.scr.width();
+        let y: usize = self.cpu.regs[vy] as usize % self.scr.height();
+        let len = if n == 0 { 32 } else { n as u16 };
+        let planes = self.scr.planes();
+        let mut addr = self.cpu.i;
+        let mut overflow = false;
+        for plane in display::plane_masks(planes) {
+            let bytes: Vec<u8> = (0..len)
+                .map(|off| self.mem.load(addr.wrapping_add(off)))
+                .collect();
+            addr = addr.wrapping_add(len);
+            self.scr.select_planes(plane);
+            overflow |= self.draw_sprite(x, y, n, &bytes);
         }
+        self.scr.select_planes(planes);
+        self.cpu.regs[0xF] = if overflow { 1 } else { 0 }
     }
 
-    pub fn tick(&mut self) -> (u8, u8) {
-        if let Some(v) = self.cpu.dt.checked_sub(1) {
-            self.cpu.dt = v;
+    fn draw_sprite(&mut self, x: usize, y: usize, n: u8, bytes: &[u8]) -> bool {
+        if n == 0 {
+            let left: Vec<u8> = bytes.iter().step_by(2).copied().collect();
+            let right: Vec<u8> = bytes.iter().skip(1).step_by(2).copied().collect();
+            let l = self.draw_column(x, y, left);
+            self.draw_column(x + 8, y, right) | l
+        } else {
+            self.draw_column(x, y, bytes.to_vec())
         }
-        if let Some(v) = self.cpu.st.checked_sub(1) {
-            self.cpu.st = v;
+    }
+
+    /// Draws one byte wide column of sprite rows, dropping rows and bits
+    /// past the screen edge in clip mode
+    fn draw_column(&mut self, x: usize, y: usize, mut bytes: Vec<u8>) -> bool {
+        if self.quirks.draw_mode == DrawMode::Clip {
+            let (width, height) = (self.scr.width(), self.scr.height());
+            if x >= width {
+                return false;
+            }
+            bytes.truncate(height.saturating_sub(y));
+            if x + 8 > width {
+                let mask = 0xFFu8 << (x + 8 - width);
+                bytes.iter_mut().for_each(|b| *b &= mask);
+            }
         }
-        (self.cpu.dt, self.cpu.st)
+        self.scr.xor_bytes(x, y, &bytes)
     }
-}
 
-impl Default for Emulator {
-    fn default() -> Self {
-        Self::new()
+    /// Runs program from start address until EXIT, breakpoint or watchpoint;
+    /// undecodable instruction and other execution errors end it with `Err`
+    pub fn run(&mut self) -> Result<StopReason, Chip8Error> {
+        self.halted = false;
+        self.key_wait = None;
+        self.cpu.pc(self.start_addr());
+        if self.breakpoints.is_set(self.cpu.pc) {
+            return Ok(StopReason::Breakpoint(self.cpu.pc));
+        }
+        self.cont()
     }
-}
 
-#[cfg(test)]
-mod loadingtest {
-    use super::Emulator;
+    /// Returns machine to power-on state: clears registers, timers, stack,
+    /// screen, keys and memory (storing fonts again if they were stored)
+    /// and moves pc to start address. Configuration such as quirks,
+    /// breakpoints, hooks and user flags is kept.
+    pub fn reset(&mut self) {
+        let blank = self.mem.blank();
+        self.replace_mem(blank);
+        self.restart();
+    }
 
-    #[test]
-    fn simple_test() {
-        let mut e = Emulator::new();
-        e.store_bytes(&vec![0x61, 0x05, 0x62, 0x09, 0x81, 0x24]);
-        assert_eq!(0x6105, e.load_instr(0x200));
-        e.run();
-        assert_eq!(e.cpu.regs[1], 14);
+    /// Swaps in blank memory of size bytes, as for [`Emulator::reset`]
+    pub(crate) fn resize_mem(&mut self, size: usize) {
+        let blank = if size == mem::Mem::DEFAULT_SIZE {
+            mem::Mem::new()
+        } else {
+            mem::Mem::with_size(size)
+        };
+        self.replace_mem(blank);
+    }
+
+    /// Swaps in blank memory keeping policy, io mappings and stored font
+    fn replace_mem(&mut self, blank: mem::Mem) {
+        let stored_font = self.mem.font_set().map(|set| (self.mem.font_start(), *set));
+        let old = std::mem::replace(&mut self.mem, blank);
+        self.mem.inherit(&old);
+        if let Some((start, set)) = stored_font {
+            self.mem.store_font_set(start, &set);
+        }
+    }
+
+    /// Swaps in another program: resets the machine like
+    /// [`Emulator::reset`], drops rewind history and journal, restarts coverage,
+    /// heat map and profiler counts if enabled, then stores rom at start
+    /// address. Quirks, speed, screen, hooks and breakpoints are kept.
+    /// Returns rom size; fails without touching the emulator if rom does
+    /// not fit in memory.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<usize, LoaderError> {
+        loader::check_size(self, rom.len())?;
+        self.reset();
+        if let Some(h) = self.history.as_mut() {
+            h.clear();
+        }
+        if let Some(j) = self.journal.as_mut() {
+            j.clear();
+        }
+        if self.coverage.is_some() {
+            self.enable_coverage();
+        }
+        if self.heat_map.is_some() {
+            self.enable_heat_map();
+        }
+        if let Some(p) = self.profiler.as_mut() {
+            p.reset();
+        }
+        self.store_bytes(rom);
+        Ok(rom.len())
+    }
+
+    /// Like [`Emulator::reset`], storing last loaded ROM at start address
+    /// again, so a game restarts without loading its file
+    pub fn soft_reset(&mut self) {
+        let rom = std::mem::take(&mut self.rom);
+        self.reset();
+        self.store_bytes(&rom);
+    }
+
+    fn restart(&mut self) {
+        self.cpu = cpu::CPU::new();
+        self.cpu.pc(self.start_addr());
+        let planes = display::plane_masks(0xFF).fold(0, |m, p| m | p);
+        let resolution = self.resolution();
+        self.scr.set_hires(false);
+        if resolution != self.resolution() {
+            self.emit_resolution();
+        }
+        self.scr.select_planes(planes);
+        self.scr.clear();
+        self.scr.select_planes(1);
+        self.kbd = input::Keyboard::new();
+        self.halted = false;
+        self.key_wait = None;
+        self.clock = Clock::default();
+        self.clock.resync(self.rate());
+        self.update_sound();
+    }
+
+    /// Seeds RND with seed, restarts last loaded ROM with
+    /// [`Emulator::soft_reset`] and starts logging key events and timer
+    /// ticks for [`Emulator::replay`]
+    pub fn start_recording(&mut self, seed: u64) {
+        self.rng = Box::new(XorShift::new(seed));
+        self.soft_reset();
+        self.recording = Some(Recording::new(seed, self.rom.clone()));
+    }
+
+    /// Ends recording started by [`Emulator::start_recording`]
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Restarts recorded ROM and repeats the recorded run: steps, timer
+    /// ticks and key events in their original order. Ends in the state the
+    /// recorded emulator was in when recording stopped, or returns the
+    /// first execution error the run hit. Breakpoints do not stop it.
+    pub fn replay(&mut self, rec: &Recording) -> Result<(), Chip8Error> {
+        self.recording = None;
+        self.rng = Box::new(XorShift::new(rec.seed));
+        self.rom = rec.rom.clone();
+        self.soft_reset();
+        let mut ticks = rec.ticks.iter().peekable();
+        let mut inputs = rec.inputs.iter().peekable();
+        for n in 0..=rec.instructions {
+            while ticks.next_if(|&&t| t == n).is_some() {
+                self.tick();
+            }
+            while let Some(input) = inputs.next_if(|i| i.instruction == n) {
+                self.kbd.push(input.event);
+            }
+            if n < rec.instructions {
+                self.step()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Continues execution from current pc (also when it is at a breakpoint)
+    /// until one of the conditions described in [`Emulator::run`]
+    pub fn cont(&mut self) -> Result<StopReason, Chip8Error> {
+        loop {
+            if let Some(reason) = self.step()? {
+                return Ok(reason);
+            }
+        }
+    }
+
+    /// Sets instructions per second executed by [`Emulator::run_for`];
+    /// time already run is not caught up on at the new rate
+    pub fn set_ips(&mut self, ips: u32) {
+        self.ips = ips;
+        self.clock.resync(self.rate());
+    }
+
+    pub fn ips(&self) -> u32 {
+        self.ips
+    }
+
+    /// Selects how [`Emulator::run_for`] paces execution
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.clock.resync(self.rate());
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Clock units per second of current timing model
+    fn rate(&self) -> u32 {
+        match self.timing {
+            Timing::Instructions => self.ips,
+            Timing::CosmacVip => clock::VIP_CYCLES_PER_SEC,
+        }
+    }
+
+    /// Clock units last step took, charged for the instruction it fetched
+    /// so that memory is not read again
+    fn cost(&self) -> u32 {
+        match self.timing {
+            Timing::Instructions => 1,
+            Timing::CosmacVip => clock::vip_cycles(&self.cpu.instr.unwrap_or(Opcode::LD(0, 0))),
+        }
+    }
+
+    /// Executes instructions due in next `d` of emulated time, calling
+    /// [`Emulator::tick`] at every 60Hz frame boundary; waiting for key does
+    /// not end it. Execution is paced at [`Emulator::ips`] instructions or
+    /// at [`clock::VIP_CYCLES_PER_SEC`] machine cycles per second, as
+    /// selected by [`Emulator::set_timing`]. Does not sleep; fractions of
+    /// instructions and frames carry over to the next call. Returns early
+    /// with stop reason like [`Emulator::cont`].
+    pub fn run_for(&mut self, d: Duration) -> Result<Option<StopReason>, Chip8Error> {
+        let end = self.clock.now() + d.as_nanos();
+        if self.paused {
+            while self.clock.next_frame() <= end {
+                let frame = self.clock.next_frame();
+                self.clock.advance_to(frame);
+                self.tick();
+                self.clock.frame();
+            }
+            self.clock.advance_to(end);
+            // paused time owes no instructions
+            self.clock.resync(self.rate());
+            return Ok(Some(StopReason::Paused));
+        }
+        loop {
+            let frame = self.clock.next_frame();
+            let until = frame.min(end);
+            let rate = self.rate();
+            while self.clock.due(until, rate) > 0 {
+                let reason = self.step()?;
+                self.clock.executed(self.cost());
+                match reason {
+                    None | Some(StopReason::KeyWait) => {}
+                    reason => {
+                        self.clock.advance_to(until);
+                        self.clock.stop_at_instruction(rate);
+                        return Ok(reason);
+                    }
+                }
+            }
+            self.clock.advance_to(until);
+            if until == end && until < frame {
+                return Ok(None);
+            }
+            self.tick();
+            self.clock.frame();
+            if until == end {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Runs from current pc at ips instructions per second in real time,
+    /// sleeping between frames, until stop reason or error
+    pub fn run_realtime(&mut self, ips: u32) -> Result<StopReason, Chip8Error> {
+        self.set_ips(ips);
+        let mut deadline = Instant::now();
+        loop {
+            if let Some(reason) = self.run_for(clock::FRAME)? {
+                return Ok(reason);
+            }
+            deadline += clock::FRAME;
+            let now = Instant::now();
+            if deadline > now {
+                std::thread::sleep(deadline - now);
+            }
+        }
+    }
+
+    /// Main loop of a frontend: runs frames of `config.frame` with
+    /// [`Emulator::run_for`] and hands each to f for rendering, polling
+    /// input and playing sound, until f returns [`ControlFlow::Break`].
+    /// Stops are reported in [`Frame::stop`], so f decides whether to
+    /// quit on EXIT.
+    pub fn run_with<F>(&mut self, config: LoopConfig, mut f: F) -> Result<(), Chip8Error>
+    where
+        F: FnMut(Frame) -> ControlFlow<()>,
+    {
+        let mut deadline = Instant::now();
+        for number in 1.. {
+            let sounding = self.sounding;
+            let stop = self.run_for(config.frame)?;
+            let frame = Frame {
+                number,
+                changed_rows: self.scr.take_dirty(),
+                sound: self.sounding,
+                sound_started: !sounding && self.sounding,
+                sound_stopped: sounding && !self.sounding,
+                stop,
+                emulator: self,
+            };
+            if f(frame).is_break() {
+                break;
+            }
+            if config.realtime {
+                deadline += config.frame;
+                let now = Instant::now();
+                if deadline > now {
+                    std::thread::sleep(deadline - now);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Readable report of registers, timers, stack and disassembly of the
+    /// instructions around pc, followed by the screen drawn with
+    /// [`display::render_text`] if screen is set; meant for bug reports
+    pub fn dump_state(&self, screen: bool) -> String {
+        let cpu = &self.cpu;
+        let mut out = format!(
+            "PC 0x{:03X}  I 0x{:03X}  DT {}  ST {}",
+            cpu.pc, cpu.i, cpu.dt, cpu.st
+        );
+        if self.halted {
+            out.push_str("  halted");
+        } else if self.waiting_for_key() {
+            out.push_str("  waiting for key");
+        }
+        out.push('\n');
+        for (r, regs) in cpu.regs.chunks(8).enumerate() {
+            let line: Vec<String> = regs
+                .iter()
+                .enumerate()
+                .map(|(i, v)| format!("V{:X} {:02X}", r * 8 + i, v))
+                .collect();
+            out.push_str(&line.join("  "));
+            out.push('\n');
+        }
+        let stack: Vec<String> = cpu.stack().iter().map(|a| format!("0x{:03X}", a)).collect();
+        out.push_str(&format!("stack [{}]\n", stack.join(", ")));
+        let start = (cpu.pc as usize).saturating_sub(DUMP_CONTEXT as usize * 2);
+        let end = (cpu.pc as usize + (DUMP_CONTEXT as usize + 1) * 2).min(self.mem.size());
+        if let Some(bytes) = self.mem.get(start..end) {
+            for (addr, word, text) in disasm::disassemble(bytes, start as Addr) {
+                let marker = if addr == cpu.pc { "=>" } else { "  " };
+                out.push_str(&format!(
+                    "{} 0x{:03X}  {:04X}  {}\n",
+                    marker, addr, word, text
+                ));
+            }
+        }
+        if screen {
+            out.push_str(&display::render_text(&*self.scr));
+        }
+        out
+    }
+
+    /// Active subroutine calls, innermost first, for rendering a backtrace;
+    /// call targets are decoded from memory without triggering mapped io
+    pub fn call_trace(&self) -> Vec<CallFrame> {
+        let stack = self.cpu.stack();
+        stack
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, &call_site)| {
+                let word = self
+                    .mem
+                    .get(call_site as usize..call_site as usize + 2)
+                    .map(|b| (b[0] as Instr) << 8 | b[1] as Instr);
+                let target = match word.map(Opcode::decode) {
+                    Some(Opcode::CALL(addr)) => Some(addr),
+                    _ => None,
+                };
+                CallFrame {
+                    depth: i + 1,
+                    call_site,
+                    target,
+                }
+            })
+            .collect()
+    }
+
+    /// 64-bit FNV-1a digest of registers, timers, stack, memory and screen
+    /// pixels, for comparing runs against a recorded value. Keyboard,
+    /// configuration and RNG state are not included, so runs using RND
+    /// should be seeded (see [`EmulatorBuilder::seed`]).
+    pub fn state_hash(&self) -> u64 {
+        let cpu = &self.cpu;
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&cpu.pc.to_be_bytes());
+        bytes.extend_from_slice(&cpu.i.to_be_bytes());
+        bytes.extend_from_slice(&cpu.regs);
+        bytes.extend_from_slice(&[cpu.dt, cpu.st]);
+        bytes.extend_from_slice(&(cpu.stack().len() as u32).to_be_bytes());
+        cpu.stack()
+            .iter()
+            .for_each(|a| bytes.extend_from_slice(&a.to_be_bytes()));
+        bytes.extend_from_slice(self.mem.get(..).unwrap_or_default());
+        let screen = self.scr.save();
+        bytes.push(screen.hires as u8);
+        bytes.extend_from_slice(&screen.pixels);
+        bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+            (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Snapshots cpu (including stack), memory, screen and keyboard
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            cpu: self.cpu.clone(),
+            mem: self.mem.clone(),
+            screen: self.scr.save(),
+            kbd: self.kbd.clone(),
+            halted: self.halted,
+        }
+    }
+
+    /// Restores state taken by [`Emulator::save_state`]; configuration
+    /// such as quirks and callbacks is kept
+    pub fn load_state(&mut self, state: &SaveState) {
+        self.cpu = state.cpu.clone();
+        let old = std::mem::replace(&mut self.mem, state.mem.clone());
+        self.mem.inherit(&old);
+        self.scr.restore(&state.screen);
+        self.kbd = state.kbd.clone();
+        self.halted = state.halted;
+        // pending FX0A is executed again from restored pc
+        self.key_wait = None;
+        self.update_sound();
+    }
+
+    /// Starts counting executions per opcode kind and address
+    /// (restarting from zero if already enabled)
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    pub fn disable_profiler(&mut self) {
+        self.profiler = None;
+    }
+
+    /// Counts collected since profiler was enabled
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Runs emulator built by make on a new thread in real time, see
+    /// [`crate::handle`]
+    pub fn spawn<F>(make: F) -> EmulatorHandle
+    where
+        F: FnOnce() -> Emulator + Send + 'static,
+    {
+        EmulatorHandle::spawn(make)
+    }
+
+    /// Starts queueing [`Event`]s for [`Emulator::poll_event`], dropping
+    /// previously queued ones
+    pub fn enable_events(&mut self) {
+        self.events = Some(VecDeque::new());
+    }
+
+    pub fn disable_events(&mut self) {
+        self.events = None;
+    }
+
+    /// Removes oldest queued event
+    pub fn poll_event(&mut self) -> Option<Event> {
+        self.events.as_mut().and_then(VecDeque::pop_front)
+    }
+
+    /// Removes all queued events, oldest first
+    pub fn drain_events(&mut self) -> Vec<Event> {
+        self.events
+            .as_mut()
+            .map(|q| q.drain(..).collect())
+            .unwrap_or_default()
+    }
+
+    fn emit_resolution(&mut self) {
+        let (width, height) = self.resolution();
+        self.emit(Event::ResolutionChanged { width, height });
+    }
+
+    fn emit(&mut self, ev: Event) {
+        if let Some(q) = self.events.as_mut() {
+            if ev != Event::DisplayUpdated || q.back() != Some(&ev) {
+                q.push_back(ev);
+            }
+        }
+    }
+
+    /// Starts marking addresses of executed instructions (clearing marks
+    /// if already enabled)
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new(self.mem.size()));
+    }
+
+    pub fn disable_coverage(&mut self) {
+        self.coverage = None;
+    }
+
+    /// Addresses executed since coverage was enabled
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Starts counting memory reads and writes of executed instructions
+    /// (clearing counts if already enabled)
+    pub fn enable_heat_map(&mut self) {
+        self.heat_map = Some(HeatMap::new(self.mem.size()));
+    }
+
+    pub fn disable_heat_map(&mut self) {
+        self.heat_map = None;
+    }
+
+    /// Accesses counted since heat map was enabled
+    pub fn heat_map(&self) -> Option<&HeatMap> {
+        self.heat_map.as_ref()
+    }
+
+    /// Starts recording state before every executed step, keeping
+    /// at most capacity most recent ones for [`Emulator::rewind`]
+    pub fn enable_rewind(&mut self, capacity: usize) {
+        self.history = Some(History::new(capacity));
+    }
+
+    /// Stops recording states and drops recorded ones
+    pub fn disable_rewind(&mut self) {
+        self.history = None;
+    }
+
+    /// Number of steps that can currently be rewound
+    pub fn rewind_len(&self) -> usize {
+        self.history.as_ref().map_or(0, History::len)
+    }
+
+    /// Restores state from n steps ago (or the oldest recorded one);
+    /// returns number of steps actually rewound
+    pub fn rewind(&mut self, n: usize) -> usize {
+        match self.history.as_mut().and_then(|h| h.pop(n)) {
+            Some((state, steps)) => {
+                self.load_state(&state);
+                steps
+            }
+            None => 0,
+        }
+    }
+
+    /// Starts recording registers and memory bytes changed by every
+    /// executed instruction, keeping entries of at most capacity most
+    /// recent ones
+    pub fn enable_journal(&mut self, capacity: usize) {
+        self.journal = Some(Journal::new(capacity));
+    }
+
+    /// Stops recording changes and drops recorded entries
+    pub fn disable_journal(&mut self) {
+        self.journal = None;
+    }
+
+    /// Changes recorded since journal was enabled
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// Runs handler for SYS `0NNN` instructions instead of applying
+    /// [`Emulator::sys_mode`]
+    pub fn set_sys_handler(&mut self, handler: Box<dyn SysHandler>) {
+        self.sys_handler = Some(handler);
+    }
+
+    pub fn remove_sys_handler(&mut self) -> Option<Box<dyn SysHandler>> {
+        self.sys_handler.take()
+    }
+
+    /// Makes SKP, SKNP and FX0A query source for key states as they
+    /// execute; kbd is updated to match the keys queried
+    pub fn set_key_source(&mut self, source: Box<dyn KeySource>) {
+        self.key_source = Some(source);
+    }
+
+    pub fn remove_key_source(&mut self) -> Option<Box<dyn KeySource>> {
+        self.key_source.take()
+    }
+
+    /// Whether sound timer is running, as last reported to the audio sink
+    pub fn sounding(&self) -> bool {
+        self.sounding
+    }
+
+    /// Sets sink notified when sound timer starts and stops
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.audio = Some(sink);
+        self.sounding = false;
+        self.update_sound();
+    }
+
+    /// Installs persistence of SUPER-CHIP user flags, replacing current
+    /// flags with ones loaded from it
+    pub fn set_flag_store(&mut self, mut store: Box<dyn FlagStore>) {
+        self.flags = store.load();
+        self.flag_store = Some(store);
+    }
+
+    /// Current SUPER-CHIP user flags (see [`Opcode::RPLSTORE`])
+    pub fn flags(&self) -> [u8; FLAGS] {
+        self.flags
+    }
+
+    /// Notifies audio sink when sound timer crossed zero
+    fn update_sound(&mut self) {
+        let on = self.cpu.st > 0;
+        if on != self.sounding {
+            self.sounding = on;
+            self.emit(if on {
+                Event::SoundStarted
+            } else {
+                Event::SoundStopped
+            });
+            if let Some(sink) = self.audio.as_mut() {
+                if on {
+                    sink.start()
+                } else {
+                    sink.stop()
+                }
+            }
+        }
+    }
+
+    /// Checks invariants of current state (stack pointer, pc and I bounds,
+    /// stack depth, font placement and screen mode agreeing with
+    /// configuration) and returns all violations found; empty vector means
+    /// state is consistent.
+    pub fn validate(&self) -> Vec<Violation> {
+        validate::check(self, false)
+    }
+
+    /// Like [`Emulator::validate`], additionally requiring pc to be even
+    pub fn validate_strict(&self) -> Vec<Violation> {
+        validate::check(self, true)
+    }
+
+    /// Registers closure called on every frame boundary, i.e. at the end of
+    /// each [`Emulator::tick`] after timers are decremented and before the
+    /// host executes next batch of instructions.
+    pub fn on_frame<F>(&mut self, f: F) -> FrameCallbackId
+    where
+        F: FnMut(&mut Emulator) + 'static,
+    {
+        let id = FrameCallbackId(self.next_callback_id);
+        self.next_callback_id += 1;
+        self.frame_callbacks.push((id, Box::new(f)));
+        id
+    }
+
+    /// Unregisters frame callback; returns false if id was not registered
+    pub fn remove_frame_callback(&mut self, id: FrameCallbackId) -> bool {
+        let before = self.frame_callbacks.len();
+        self.frame_callbacks.retain(|(cid, _)| *cid != id);
+        before != self.frame_callbacks.len()
+    }
+
+    /// Decrements delay and sound timers (60Hz frame boundary) and runs
+    /// frame callbacks; does nothing while paused unless
+    /// `tick_while_paused` is set. Returns timer values after decrement.
+    pub fn tick(&mut self) -> (u8, u8) {
+        if self.paused && !self.tick_while_paused {
+            return (self.cpu.dt, self.cpu.st);
+        }
+        if let Some(v) = self.cpu.dt.checked_sub(1) {
+            self.cpu.dt = v;
+        }
+        if let Some(v) = self.cpu.st.checked_sub(1) {
+            self.cpu.st = v;
+        }
+        let timers = (self.cpu.dt, self.cpu.st);
+        if let Some(rec) = self.recording.as_mut() {
+            rec.record_tick();
+        }
+        self.update_sound();
+        self.run_frame_callbacks();
+        timers
+    }
+
+    fn run_frame_callbacks(&mut self) {
+        let mut callbacks = std::mem::take(&mut self.frame_callbacks);
+        for (_, cb) in callbacks.iter_mut() {
+            cb(self);
+        }
+        // keep callbacks registered from within callbacks
+        callbacks.append(&mut self.frame_callbacks);
+        self.frame_callbacks = callbacks;
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::useless_vec)]
+mod loadingtest {
+    use super::{Emulator, Event, State};
+    use crate::cpu::{Addr, Opcode, CPU};
+    use crate::debug::{CallFrame, StopReason};
+    use crate::error::Chip8Error;
+    use crate::quirks::Profile;
+    use std::time::Duration;
+
+    /// Error ending programs that run into zeroed memory at addr
+    fn end_at(addr: Addr) -> Result<StopReason, Chip8Error> {
+        Err(Chip8Error::InvalidOpcode { addr, raw: 0 })
+    }
+
+    #[test]
+    fn simple_test() {
+        let mut e = Emulator::new();
+        e.store_bytes(&vec![0x61, 0x05, 0x62, 0x09, 0x81, 0x24]);
+        assert_eq!(0x6105, e.load_instr(0x200));
+        assert_eq!(end_at(0x206), e.run());
+        assert_eq!(e.cpu.regs[1], 14);
     }
 
     #[test]
@@ -323,7 +2007,7 @@ mod loadingtest {
         let mut e = Emulator::new();
         e.store_instr(&[0xA124]);
         assert_eq!(0xA124, e.load_instr(0x200));
-        e.run();
+        assert_eq!(end_at(0x202), e.run());
         assert_eq!(e.cpu.i, 0x124);
     }
     #[test]
@@ -332,7 +2016,7 @@ mod loadingtest {
         e.store_instr(&[0x6001, 0xB124]);
         assert_eq!(0x6001, e.load_instr(0x200));
         assert_eq!(0xB124, e.load_instr(0x202));
-        e.run();
+        assert_eq!(end_at(0x125), e.run());
         assert_eq!(e.cpu.pc, 0x125);
     }
 
@@ -341,12 +2025,153 @@ mod loadingtest {
         let mut e = Emulator::new();
         e.store_font();
         e.store_instr(&[0x6201, 0x6302, 0xD232]);
-        e.run();
+        assert_eq!(end_at(0x206), e.run());
         assert_eq!(0, e.cpu.i);
         assert_eq!(true, e.scr.get(1, 2), "checking scr(1,2) is true");
         assert_eq!(e.cpu.pc, 0x200 + 6);
     }
 
+    #[test]
+    fn schip_test() {
+        let mut e = Emulator::new();
+        e.store_font();
+        // hires, V0 = 3: large 3 at (0, 0), scroll down 2, right 4, exit
+        e.store_instr(&[0x00FF, 0x6003, 0xF030, 0xD110, 0x00C2, 0x00FB, 0x00FD]);
+        e.run().unwrap();
+        assert!(e.halted());
+        assert_eq!(0x20C, e.cpu.pc);
+        assert_eq!(128, e.scr.width());
+        assert_eq!(e.mem.addr_of_large_font(3), e.cpu.i);
+        // top row of large 3 is 0xFF in left byte, 0xFF in right byte
+        assert!(e.scr.get(4, 2));
+        assert!(e.scr.get(19, 2));
+        assert!(!e.scr.get(3, 2));
+        assert!(!e.scr.get(4, 1));
+
+        e.step().unwrap();
+        assert_eq!(0x20C, e.cpu.pc);
+    }
+
+    #[test]
+    fn two_page_test() {
+        let mut rom = vec![0u8; 0xC0];
+        rom[..2].copy_from_slice(&[0x12, 0x60]);
+        // V1 = 60: digit 0 at (0, 60) wrapping to the top, clear, exit
+        rom.extend_from_slice(&[0x61, 0x3C, 0xD0, 0x15, 0x02, 0x30, 0x00, 0xFD]);
+        let mut e = Emulator::builder().two_page_hires(true).build();
+        e.store_bytes(&rom);
+        e.add_breakpoint(0x2C4);
+        assert_eq!(Ok(None), e.step());
+        assert_eq!(0x2C0, e.cpu.pc);
+        assert_eq!((64, 64), (e.scr.width(), e.scr.height()));
+        assert_eq!(Ok(StopReason::Breakpoint(0x2C4)), e.run());
+        assert!(e.scr.get(0, 60));
+        assert!(e.scr.get(0, 0));
+        assert!(!e.scr.get(0, 28));
+        e.cont().unwrap();
+        assert!(!e.scr.get(0, 60));
+
+        // jumping back to 0x200 reenters the patch
+        e.cpu.pc(0x200);
+        e.halted = false;
+        e.step().unwrap();
+        assert_eq!((0x2C0, 64), (e.cpu.pc, e.scr.height()));
+    }
+
+    #[test]
+    fn two_page_bookkeeping_test() {
+        use crate::hook::AfterFn;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut e = Emulator::builder().two_page_hires(true).build();
+        let hooked = Rc::new(RefCell::new(vec![]));
+        let log = hooked.clone();
+        e.add_hook(Box::new(AfterFn(move |pc, _, _: &mut CPU| {
+            log.borrow_mut().push(pc)
+        })));
+        e.enable_rewind(10);
+        e.enable_journal(10);
+        e.enable_coverage();
+        e.enable_profiler();
+        let mut rom = vec![0x12, 0x60];
+        rom.resize(0xC0, 0);
+        rom.extend_from_slice(&[0x02, 0x30]);
+        e.store_bytes(&rom);
+        e.step().unwrap();
+        e.step().unwrap();
+        assert_eq!((0x2C2, 2), (e.cpu.pc, e.stats.executed));
+        assert_eq!(vec![0x200, 0x2C0], *hooked.borrow());
+        assert!(e.coverage().unwrap().is_covered(0x2C0));
+        assert_eq!(1, e.profiler().unwrap().addr_count(0x2C0));
+        let ops: Vec<Opcode> = e.journal().unwrap().iter().map(|en| en.op).collect();
+        assert_eq!(vec![Opcode::JP(0x260), Opcode::Unknown(0x0230)], ops);
+        assert_eq!(2, e.rewind(2));
+        assert_eq!(0x200, e.cpu.pc);
+    }
+
+    #[test]
+    fn jump_to_0x260_test() {
+        // JP 0x260 over data, LD V1, 7; EXIT
+        let mut rom = vec![0x12, 0x60];
+        rom.resize(0x60, 0xAA);
+        rom.extend_from_slice(&[0x61, 0x07, 0x00, 0xFD]);
+        let profiled = |profile| {
+            Emulator::builder()
+                .two_page_hires(true)
+                .profile(profile)
+                .build()
+        };
+        let emulators = [
+            Emulator::new(),
+            profiled(Profile::SuperChip),
+            profiled(Profile::XoChip),
+        ];
+        for mut e in emulators {
+            e.store_bytes(&rom);
+            e.step().unwrap();
+            assert_eq!((0x260, 32), (e.cpu.pc, e.scr.height()));
+            e.run().unwrap();
+            assert_eq!((7, 32), (e.cpu.regs[1], e.scr.height()));
+        }
+    }
+
+    #[test]
+    fn xochip_test() {
+        let mut e = Emulator::new();
+        // long I = 0x0300, save V1..V3, reversed load into V6..V4
+        e.store_instr(&[0x6101, 0x6202, 0x6303, 0xF000, 0x0300, 0x5132, 0x5643]);
+        for _ in 0..6 {
+            e.step().unwrap();
+        }
+        assert_eq!(0x300, e.cpu.i);
+        assert_eq!(Some(&[1u8, 2, 3][..]), e.mem.get(0x300..0x303));
+        assert_eq!([3, 2, 1], e.cpu.regs[4..7]);
+        assert_eq!(0x20E, e.cpu.pc);
+    }
+
+    #[test]
+    fn xochip_draw_planes_test() {
+        let mut e = Emulator::new();
+        e.mem.store_arr(0x300, &[0x80, 0x40]);
+        // both planes, one byte sprite per plane
+        e.store_instr(&[0xF000, 0x0300, 0xF301, 0xD001]);
+        for _ in 0..3 {
+            e.step().unwrap();
+        }
+        assert_eq!(3, e.scr.planes());
+        assert_eq!(1, e.scr.pixel(0, 0));
+        assert_eq!(2, e.scr.pixel(1, 0));
+    }
+
+    #[test]
+    fn skip_long_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x3000, 0xF000, 0x0300, 0x6001]);
+        e.step().unwrap();
+        assert_eq!(0x206, e.cpu.pc);
+    }
+
     #[test]
     fn split_test() {
         match Emulator::split_val(145) {
@@ -389,13 +2214,760 @@ mod loadingtest {
         );
     }
 
+    #[test]
+    fn ret_underflow_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x6001, 0x00EE]);
+        assert_eq!(Err(Chip8Error::StackUnderflow(0x202)), e.run());
+        assert_eq!(0x202, e.cpu.pc);
+    }
+
+    #[test]
+    fn call_overflow_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x2200]);
+        assert_eq!(Err(Chip8Error::StackOverflow(0x200)), e.run());
+        assert_eq!(16, e.cpu.stack_depth());
+        e.stack_limit = 2;
+        e.cpu.set_stack(vec![]);
+        assert_eq!(Err(Chip8Error::StackOverflow(0x200)), e.run());
+        assert_eq!(2, e.cpu.stack_depth());
+    }
+
+    #[test]
+    fn invalid_register_test() {
+        use crate::cpu::Opcode;
+        let mut e = Emulator::new();
+        e.cpu.pc(0x200);
+        let err = Chip8Error::InvalidRegister {
+            addr: 0x200,
+            reg: 16,
+        };
+        assert_eq!(Err(err), e.exec(Opcode::LDR(1, 16)));
+        assert_eq!(Err(err), e.exec(Opcode::REGSSTORE(16)));
+        assert_eq!(Err(err), e.exec(Opcode::DRW(16, 0, 1)));
+        assert_eq!(0x200, e.cpu.pc);
+        assert_eq!(0, e.stats.executed);
+    }
+
+    #[test]
+    fn stack_error_not_executed_test() {
+        use crate::cpu::Opcode;
+        let mut e = Emulator::new();
+        e.enable_profiler();
+        e.enable_coverage();
+        e.store(&[Opcode::RET]);
+        assert_eq!(Err(Chip8Error::StackUnderflow(0x200)), e.step());
+        assert_eq!(0, e.stats.executed);
+        e.stack_limit = 0;
+        e.store(&[Opcode::CALL(0x200)]);
+        assert_eq!(Err(Chip8Error::StackOverflow(0x200)), e.step());
+        assert_eq!(0, e.stats.executed);
+        assert_eq!(0, e.profiler().unwrap().addr_count(0x200));
+        assert!(!e.coverage().unwrap().is_covered(0x200));
+    }
+
+    #[test]
+    fn frame_callback_test() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let mut e = Emulator::new();
+        let frames = Rc::new(Cell::new(0));
+        let counter = frames.clone();
+        e.cpu.dt = 5;
+        let id = e.on_frame(move |e| {
+            counter.set(counter.get() + 1);
+            e.cpu.regs[0] = e.cpu.dt;
+        });
+        e.tick();
+        e.tick();
+        assert_eq!(2, frames.get());
+        assert_eq!(3, e.cpu.regs[0]);
+
+        assert!(e.remove_frame_callback(id));
+        assert!(!e.remove_frame_callback(id));
+        e.tick();
+        assert_eq!(2, frames.get());
+    }
+
+    #[test]
+    fn with_screen_test() {
+        use crate::display::{Scr, Screen};
+
+        /// Frontend screen delegating to Screen
+        struct Wrapped(Screen);
+
+        impl Scr for Wrapped {
+            fn xor(&mut self, x: usize, y: usize, v: bool) -> bool {
+                self.0.xor(x, y, v)
+            }
+            fn xor_bytes(&mut self, x: usize, y: usize, bytes: &[u8]) -> bool {
+                self.0.xor_bytes(x, y, bytes)
+            }
+            fn get(&self, x: usize, y: usize) -> bool {
+                self.0.get(x, y)
+            }
+            fn clear(&mut self) {
+                self.0.clear()
+            }
+            fn set_hires(&mut self, hires: bool) {
+                self.0.set_hires(hires)
+            }
+            fn hires(&self) -> bool {
+                self.0.hires()
+            }
+        }
+
+        let mut e = Emulator::with_screen(Box::new(Wrapped(Screen::new())));
+        e.store_font();
+        e.store_instr(&[0xD005, 0x00FD]);
+        e.run().unwrap();
+        assert!(e.scr.get(0, 0));
+        assert_eq!(
+            5,
+            e.scr.to_packed_bytes().iter().filter(|&&b| b != 0).count()
+        );
+    }
+
+    #[test]
+    fn key_wait_test() {
+        use std::time::Duration;
+
+        let mut e = Emulator::new();
+        e.cpu.dt = 10;
+        e.store_instr(&[0xF30A, 0x00FD]);
+        assert_eq!(Ok(StopReason::KeyWait), e.run());
+        assert!(e.waiting_for_key());
+        assert_eq!(None, e.run_for(Duration::from_millis(50)).unwrap());
+        assert_eq!((0x200, 7), (e.cpu.pc, e.cpu.dt));
+        e.key_pressed(None, 0xB);
+        assert_eq!(Some(StopReason::KeyWait), e.step().unwrap());
+        e.key_released();
+        assert_eq!(None, e.step().unwrap());
+        assert_eq!((0x202, 0xB), (e.cpu.pc, e.cpu.regs[3]));
+        assert!(!e.waiting_for_key());
+    }
+
+    #[test]
+    fn key_wait_tap_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0xF10A, 0x00FD]);
+        e.step().unwrap();
+        e.key_pressed(None, 4);
+        e.key_released();
+        assert_eq!(Ok(StopReason::Halted), e.cont());
+        assert_eq!(4, e.cpu.regs[1]);
+    }
+
+    #[test]
+    fn key_wait_events_test() {
+        use crate::input::KeyEvent;
+
+        let mut e = Emulator::new();
+        e.store_instr(&[0xF20A, 0x00FD]);
+        e.kbd.push(KeyEvent::Pressed(1));
+        e.kbd.push(KeyEvent::Released(1));
+        e.step().unwrap();
+        e.kbd.push(KeyEvent::Pressed(7));
+        e.kbd.push(KeyEvent::Released(7));
+        e.kbd.push(KeyEvent::Pressed(7));
+        assert_eq!(Ok(StopReason::Halted), e.cont());
+        assert_eq!(7, e.cpu.regs[2]);
+    }
+
+    #[test]
+    fn address_policy_test() {
+        use crate::mem::AddressPolicy;
+
+        let mut e = Emulator::new();
+        e.store_instr(&[0xAFFE, 0xF233]);
+        e.run().unwrap_err();
+        assert_eq!(0x204, e.cpu.pc);
+        e.mem.set_policy(AddressPolicy::Error);
+        let err = Chip8Error::AddressOutOfRange { addr: 0x1000 };
+        assert_eq!(Err(err), e.run());
+        assert_eq!(0x202, e.cpu.pc);
+        e.cpu.pc(0xFFF);
+        assert_eq!(Err(err), e.step());
+    }
+
+    #[test]
+    fn decode_cache_invalidation_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x6001, 0x00FD]);
+        e.run().unwrap();
+        e.store_instr(&[0x6002, 0x00FD]);
+        e.run().unwrap();
+        assert_eq!(2, e.cpu.regs[0]);
+        // executes LD V1, 5 at 0x20E, overwrites it with LD V1, 9 and runs it again
+        e.store_instr(&[
+            0x6061, 0xA20E, 0x120E, 0, 0, 0, 0, 0x6105, 0x3201, 0x1216, 0x00FD, 0x6201, 0x6109,
+            0xF155, 0x6100, 0x120E,
+        ]);
+        e.run().unwrap();
+        assert_eq!(9, e.cpu.regs[1]);
+        e.mem
+            .get_mut(0x200..0x202)
+            .unwrap()
+            .copy_from_slice(&[0x63, 0x07]);
+        e.run().unwrap();
+        assert_eq!(7, e.cpu.regs[3]);
+        e.set_decode_cache(false);
+        e.run().unwrap();
+        assert_eq!(7, e.cpu.regs[3]);
+    }
+
+    #[test]
+    fn decode_cache_io_test() {
+        use crate::mem::IoHandler;
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        /// Serves ADD V0, n with n set by the test
+        struct Device(Rc<Cell<u8>>);
+
+        impl IoHandler for Device {
+            fn read(&mut self, addr: Addr) -> u8 {
+                if addr & 1 == 0 {
+                    0x70
+                } else {
+                    self.0.get()
+                }
+            }
+
+            fn write(&mut self, _addr: Addr, _value: u8) {}
+        }
+
+        let mut e = Emulator::new();
+        // ADD V0, 1; JP 0x200
+        e.store_instr(&[0x7001, 0x1200]);
+        e.step().unwrap();
+        e.step().unwrap();
+        let n = Rc::new(Cell::new(40));
+        let id = e.mem.map_io(0x200..0x202, Box::new(Device(n.clone())));
+        e.step().unwrap();
+        assert_eq!(41, e.cpu.regs[0]);
+        e.step().unwrap();
+        n.set(2);
+        e.step().unwrap();
+        assert_eq!(Some(Opcode::ADD(0, 2)), e.cpu.instr);
+        assert_eq!(43, e.cpu.regs[0]);
+        assert!(e.mem.unmap_io(id));
+        e.step().unwrap();
+        e.step().unwrap();
+        assert_eq!(44, e.cpu.regs[0]);
+    }
+
+    #[test]
+    fn key_wait_multiple_keys_test() {
+        use crate::input::KeyEvent;
+
+        let mut e = Emulator::new();
+        e.store_instr(&[0xF00A, 0xE19E, 0x00FD, 0x00FD]);
+        e.cpu.regs[1] = 2;
+        e.kbd.push(KeyEvent::Pressed(2));
+        e.step().unwrap();
+        assert_eq!(Some(StopReason::KeyWait), e.step().unwrap());
+        e.kbd.push(KeyEvent::Pressed(9));
+        e.kbd.push(KeyEvent::Released(9));
+        e.step().unwrap();
+        assert_eq!(9, e.cpu.regs[0]);
+        // key 2 still held along with the released 9: SKP V1 skips
+        e.step().unwrap();
+        assert_eq!(0x206, e.cpu.pc);
+        assert_eq!(vec![2], e.kbd.down_keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn store_sprite_test() {
+        let mut e = Emulator::new();
+        let art = "
+            .XX.
+            X..X
+            .XX.
+        ";
+        assert_eq!(Ok(3), e.store_sprite(0x300, art));
+        // LD I, 0x300; DRW V0, V0, 3; EXIT
+        e.store_instr(&[0xA300, 0xD003, 0x00FD]);
+        e.run().unwrap();
+        assert!(crate::display::same_pixels(
+            &*e.scr,
+            &crate::display::BitScreen::from_pattern(&art.replace('X', "#"))
+        ));
+        assert!(e.store_sprite(0x300, "?").is_err());
+    }
+
+    #[test]
+    fn write_protect_test() {
+        use crate::error::Chip8Error;
+        use crate::mem::{ProtectMode, WriteProtect};
+
+        // LD I, 0x1FF; LD [I], V1; LD I, 0x202; LD B, V0; EXIT
+        let rom = [0xA1FF, 0xF155, 0xA202, 0xF033, 0x00FD];
+        let mut e = Emulator::new();
+        e.store_instr(&rom);
+        e.write_protect.mode = ProtectMode::Error;
+        e.step().unwrap();
+        assert_eq!(
+            Err(Chip8Error::WriteProtected {
+                addr: 0x202,
+                target: 0x1FF
+            }),
+            e.step()
+        );
+        assert_eq!(0x202, e.cpu.pc);
+
+        let mut e = Emulator::new();
+        e.store_bytes(&rom.iter().flat_map(|w| w.to_be_bytes()).collect::<Vec<_>>());
+        e.enable_events();
+        e.write_protect = WriteProtect {
+            mode: ProtectMode::Report,
+            rom: true,
+        };
+        e.cpu.regs[0] = 0x12;
+        assert_eq!(Ok(StopReason::Halted), e.run());
+        assert_eq!(
+            vec![
+                Event::ProtectedWrite {
+                    pc: 0x202,
+                    addr: 0x1FF
+                },
+                Event::ProtectedWrite {
+                    pc: 0x206,
+                    addr: 0x202
+                },
+                Event::Halted
+            ],
+            e.drain_events()
+        );
+        assert_eq!(Some(&[0, 1, 8][..]), e.mem.get(0x202..0x205));
+    }
+
+    #[test]
+    fn key_source_test() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let held = Rc::new(Cell::new(0u16));
+        let keys = held.clone();
+        let mut e = Emulator::new();
+        e.set_key_source(Box::new(move |k: usize| keys.get() & 1 << k != 0));
+        // SKP V1; EXIT; LD V0, K; SKNP V1; EXIT; EXIT
+        e.store_instr(&[0xE19E, 0x00FD, 0xF00A, 0xE1A1, 0x00FD, 0x00FD]);
+        e.cpu.regs[1] = 3;
+        held.set(1 << 3);
+        e.step().unwrap();
+        assert_eq!(0x204, e.cpu.pc);
+        assert!(e.kbd.get(3));
+
+        // 3 held before the wait does not complete it; 5 tapped does
+        e.step().unwrap();
+        assert_eq!(Some(StopReason::KeyWait), e.step().unwrap());
+        held.set(1 << 3 | 1 << 5);
+        assert_eq!(Some(StopReason::KeyWait), e.step().unwrap());
+        held.set(0);
+        assert_eq!(None, e.step().unwrap());
+        assert_eq!(5, e.cpu.regs[0]);
+        e.step().unwrap();
+        assert_eq!(0x20A, e.cpu.pc);
+
+        assert!(e.remove_key_source().is_some());
+        assert!(e.remove_key_source().is_none());
+    }
+
     #[test]
     fn add_6ff_test() {
         let mut e = Emulator::new();
         e.cpu.regs[6] = 0x002B;
         e.store_instr(&[0x76FF]);
-        e.run();
+        assert_eq!(end_at(0x202), e.run());
         assert_eq!(0x002A, e.cpu.regs[6]);
         assert_eq!(e.cpu.i, 0);
     }
+
+    #[test]
+    fn run_frame_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x6003, 0xF018, 0xD001, 0x1206]);
+        let r = e.run_frame(10).unwrap();
+        assert_eq!(
+            (10, true, true, false),
+            (r.executed, r.drew, r.sound_started, r.sound_stopped)
+        );
+        assert_eq!((false, None), (r.waiting_for_key, r.stop));
+        assert_eq!(2, e.cpu.st);
+        let r = e.run_frame(10).unwrap();
+        assert!(!r.drew && !r.sound_started && !r.sound_stopped);
+        assert!(e.run_frame(10).unwrap().sound_stopped);
+
+        e.store_instr(&[0xF00A]);
+        let r = e.run_frame(10).unwrap();
+        assert!(r.waiting_for_key);
+        assert!(r.executed < 10);
+        let mut e = Emulator::new();
+        e.store_instr(&[0x00FD]);
+        assert_eq!(Some(StopReason::Halted), e.run_frame(10).unwrap().stop);
+
+        let mut e = Emulator::new();
+        e.store_instr(&[0x6005, 0xF015, 0x1204]);
+        e.add_breakpoint(0x204);
+        let r = e.run_frame(10).unwrap();
+        assert_eq!(Some(StopReason::Breakpoint(0x204)), r.stop);
+        assert_eq!(5, e.cpu.dt);
+    }
+
+    #[test]
+    fn run_with_test() {
+        use super::LoopConfig;
+        use crate::input::KeyEvent;
+        use std::ops::ControlFlow;
+
+        let config = LoopConfig {
+            realtime: false,
+            ..LoopConfig::default()
+        };
+        let mut e = Emulator::new();
+        // LD V0, 3; LD ST, V0; LD V1, K; EXIT
+        e.store_instr(&[0x6003, 0xF018, 0xF10A, 0x00FD]);
+        let mut log = vec![];
+        e.run_with(config, |frame| {
+            log.push((
+                frame.changed_rows != 0,
+                frame.sound_started,
+                frame.sound_stopped,
+            ));
+            if frame.number == 5 {
+                frame.emulator.kbd.push(KeyEvent::Pressed(4));
+                frame.emulator.kbd.push(KeyEvent::Released(4));
+            }
+            if frame.stop == Some(StopReason::Halted) {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .unwrap();
+        assert_eq!(6, log.len());
+        assert_eq!((true, true, false), log[0]);
+        assert_eq!((false, false, true), log[3]);
+        assert_eq!(4, e.cpu.regs[1]);
+
+        let mut frames = 0;
+        e.run_with(config, |frame| {
+            frames = frame.number;
+            ControlFlow::Break(())
+        })
+        .unwrap();
+        assert_eq!(1, frames);
+    }
+
+    #[test]
+    fn step_n_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x6003, 0xF018, 0x00E0, 0xF00A, 0x1200]);
+        let s = e.step_n(2).unwrap();
+        assert_eq!(
+            (2, false, true, false),
+            (
+                s.executed,
+                s.display_changed,
+                s.sound_changed,
+                s.blocked_on_input
+            )
+        );
+        assert_eq!(3, e.cpu.st);
+        let s = e.step_n(100).unwrap();
+        assert_eq!(
+            (2, true, false, true),
+            (
+                s.executed,
+                s.display_changed,
+                s.sound_changed,
+                s.blocked_on_input
+            )
+        );
+        assert_eq!(None, s.stop);
+        assert_eq!(0, e.step_n(100).unwrap().executed);
+
+        let mut e = Emulator::new();
+        e.store_instr(&[0x00FD]);
+        let s = e.step_n(5).unwrap();
+        assert_eq!((1, Some(StopReason::Halted)), (s.executed, s.stop));
+        let s = e.step_n(5).unwrap();
+        assert_eq!((0, Some(StopReason::Halted)), (s.executed, s.stop));
+    }
+
+    #[test]
+    fn step_n_error_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x6003, 0xFFFF]);
+        assert_eq!(
+            Err(Chip8Error::InvalidOpcode {
+                addr: 0x202,
+                raw: 0xFFFF
+            }),
+            e.step_n(5)
+        );
+        assert_eq!((1, 0x202), (e.stats.executed, e.cpu.pc));
+        e.store_instr(&[0x6003, 0x00FD]);
+        let s = e.step_n(5).unwrap();
+        assert_eq!((2, Some(StopReason::Halted)), (s.executed, s.stop));
+    }
+
+    #[test]
+    fn events_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x00E0, 0xD001, 0x6002, 0xF018, 0xF00A, 0x00FD]);
+        e.step().unwrap();
+        e.enable_events();
+        for _ in 0..5 {
+            e.step().unwrap();
+        }
+        assert_eq!(
+            vec![
+                Event::DisplayUpdated,
+                Event::SoundStarted,
+                Event::WaitingForKey
+            ],
+            e.drain_events()
+        );
+        e.key_pressed(None, 1);
+        e.step().unwrap();
+        e.key_released();
+        e.step().unwrap();
+        e.step().unwrap();
+        e.tick();
+        e.tick();
+        assert_eq!(Some(Event::Halted), e.poll_event());
+        assert_eq!(Some(Event::SoundStopped), e.poll_event());
+        assert_eq!(None, e.poll_event());
+        e.disable_events();
+        e.cpu.regs[0] = 3;
+        e.exec(crate::cpu::Opcode::STSET(0)).unwrap();
+        assert!(e.drain_events().is_empty());
+    }
+
+    #[test]
+    fn resolution_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x00FF, 0x00FF, 0x00FE]);
+        e.enable_events();
+        e.step().unwrap();
+        assert_eq!((128, 64), e.resolution());
+        e.step().unwrap();
+        e.step().unwrap();
+        let changed = |width, height| Event::ResolutionChanged { width, height };
+        assert_eq!(
+            vec![
+                changed(128, 64),
+                Event::DisplayUpdated,
+                changed(64, 32),
+                Event::DisplayUpdated
+            ],
+            e.drain_events()
+        );
+        e.scr.set_hires(true);
+        e.reset();
+        assert_eq!(vec![changed(64, 32)], e.drain_events());
+        assert_eq!((64, 32), e.resolution());
+    }
+
+    #[test]
+    fn pause_test() {
+        let mut e = Emulator::builder().ips(600).build();
+        // LD V0, 60; LD DT, V0; LD V1, K; EXIT
+        e.store_instr(&[0x603C, 0xF015, 0xF10A, 0x00FD]);
+        assert_eq!(State::Running, e.state());
+        e.run_for(Duration::from_millis(50)).unwrap();
+        assert_eq!(State::WaitingForKey, e.state());
+        let dt = e.cpu.dt;
+        e.pause();
+        assert_eq!(State::Paused, e.state());
+        e.key_pressed(None, 4);
+        e.key_released();
+        assert_eq!(
+            Ok(Some(StopReason::Paused)),
+            e.run_for(Duration::from_millis(500))
+        );
+        assert_eq!(Ok(StopReason::Paused), e.cont());
+        e.tick();
+        assert_eq!((dt, 0), (e.cpu.dt, e.cpu.regs[1]));
+        let report = e.run_frame(10).unwrap();
+        assert_eq!(
+            (0, Some(StopReason::Paused)),
+            (report.executed, report.stop)
+        );
+        assert_eq!(dt, e.cpu.dt);
+
+        e.tick_while_paused = true;
+        e.run_for(Duration::from_millis(100)).unwrap();
+        assert_eq!(dt - 6, e.cpu.dt);
+        e.resume();
+        // the key event queued while paused completes FX0A
+        let report = e.run_frame(10).unwrap();
+        assert_eq!(Some(StopReason::Halted), report.stop);
+        assert_eq!((4, State::Halted), (e.cpu.regs[1], e.state()));
+    }
+
+    #[test]
+    fn load_rom_test() {
+        let mut e = Emulator::builder().ips(1000).build();
+        e.quirks.shift_vy = true;
+        e.add_breakpoint(0x202);
+        e.enable_rewind(10);
+        e.enable_coverage();
+        e.store_instr(&[0x6105, 0x00FF, 0x00FD]);
+        e.cont().unwrap();
+        e.step().unwrap();
+        assert_eq!(2, e.rewind_len());
+
+        assert!(e.load_rom(&vec![0; 0x2000]).is_err());
+        assert_eq!(128, e.scr.width());
+        assert_eq!(4, e.load_rom(&[0x62, 0x07, 0x00, 0xFD]).unwrap());
+        assert_eq!((0x200, 0, 64), (e.cpu.pc, e.cpu.regs[1], e.scr.width()));
+        assert_eq!(0, e.rewind_len());
+        assert_eq!(0, e.coverage().unwrap().count());
+        assert!(e.quirks.shift_vy);
+        assert_eq!(1000, e.ips());
+        assert!(e.mem.font_set().is_some());
+        assert_eq!(Ok(StopReason::Breakpoint(0x202)), e.cont());
+        assert_eq!(7, e.cpu.regs[2]);
+    }
+
+    #[test]
+    fn dump_state_test() {
+        let mut e = Emulator::new();
+        // LD V1, 0xAB; LD I, 0x300; CALL 0x20A; EXIT; RET
+        e.store_instr(&[0x61AB, 0xA300, 0x220A, 0x00FD, 0x0000, 0x00EE]);
+        for _ in 0..3 {
+            e.step().unwrap();
+        }
+        let dump = e.dump_state(false);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!("PC 0x20A  I 0x300  DT 0  ST 0", lines[0]);
+        assert!(lines[1].starts_with("V0 00  V1 AB  V2 00"));
+        assert!(lines[2].starts_with("V8 00  V9 00  VA 00"));
+        assert_eq!("stack [0x204]", lines[3]);
+        assert_eq!("   0x202  A300  LD I, 0x300", lines[4]);
+        assert_eq!("=> 0x20A  00EE  RET", lines[8]);
+        assert_eq!(13, lines.len());
+
+        e.step().unwrap();
+        e.step().unwrap();
+        let dump = e.dump_state(true);
+        assert!(dump.starts_with("PC 0x206  I 0x300  DT 0  ST 0  halted\n"));
+        assert_eq!(13 + 32, dump.lines().count());
+
+        let mut e = Emulator::builder()
+            .memory_size(crate::mem::Mem::MAX_SIZE)
+            .build();
+        e.cpu.pc = 0xFFFC;
+        let dump = e.dump_state(false);
+        assert!(dump.contains("=> 0xFFFC  0000"));
+        assert!(dump.ends_with("0xFFFE  0000  DW 0x0000\n"), "{}", dump);
+    }
+
+    #[test]
+    fn call_trace_test() {
+        let mut e = Emulator::new();
+        // CALL 0x206; EXIT; 0; CALL 0x20A; 0; RET
+        e.store_instr(&[0x2206, 0x00FD, 0x0000, 0x220A, 0x0000, 0x00EE]);
+        assert!(e.call_trace().is_empty());
+        e.step().unwrap();
+        e.step().unwrap();
+        let trace = e.call_trace();
+        assert_eq!(2, trace.len());
+        assert_eq!(
+            CallFrame {
+                depth: 2,
+                call_site: 0x206,
+                target: Some(0x20A)
+            },
+            trace[0]
+        );
+        assert_eq!(0x202, trace[1].return_addr());
+        assert_eq!("#1 0x206 called from 0x200", trace[1].to_string());
+        e.mem.store(0x206, 0);
+        assert_eq!(None, e.call_trace()[0].target);
+        assert_eq!("#2 ??? called from 0x206", e.call_trace()[0].to_string());
+    }
+
+    #[test]
+    fn state_hash_test() {
+        let run = |seed| {
+            let mut e = Emulator::builder().seed(seed).build();
+            // RND V0; LD F, V0; DRW V1, V1, 5; EXIT
+            e.store_instr(&[0xC0FF, 0xF029, 0xD115, 0x00FD]);
+            e.run().unwrap();
+            e
+        };
+        let e = run(7);
+        assert_eq!(e.state_hash(), run(7).state_hash());
+        assert_ne!(e.state_hash(), run(8).state_hash());
+        let mut f = run(7);
+        f.scr.xor(63, 31, true);
+        assert_ne!(e.state_hash(), f.state_hash());
+        f.scr.xor(63, 31, true);
+        f.kbd.switch(3);
+        assert_eq!(e.state_hash(), f.state_hash());
+
+        let mut e = Emulator::builder()
+            .memory_size(crate::mem::Mem::MAX_SIZE)
+            .build();
+        let hash = e.state_hash();
+        e.mem.store(0xFFFF, 1);
+        assert_ne!(hash, e.state_hash());
+    }
+
+    #[test]
+    fn reset_test() {
+        let mut e = Emulator::builder().build();
+        e.store_instr(&[0x6107, 0xA300, 0xF155, 0xD005, 0x2206]);
+        for _ in 0..5 {
+            e.step().unwrap();
+        }
+        e.kbd.switch(2);
+        e.soft_reset();
+        assert_eq!(
+            (0x200, 0, 0, 0),
+            (e.cpu.pc, e.cpu.i, e.cpu.regs[1], e.cpu.stack_depth())
+        );
+        assert_eq!(Some(&[0, 0][..]), e.mem.get(0x300..0x302));
+        assert_eq!(Some(&[0x61, 0x07][..]), e.mem.get(0x200..0x202));
+        assert_eq!(Some(&0xF0), e.mem.get(0));
+        assert!(!e.scr.get(0, 0) && !e.kbd.get(2));
+        e.step().unwrap();
+        assert_eq!(7, e.cpu.regs[1]);
+
+        e.reset();
+        assert_eq!(Some(&[0, 0][..]), e.mem.get(0x200..0x202));
+        assert_eq!(Some(&0xF0), e.mem.get(0));
+        let mut e = Emulator::new();
+        e.reset();
+        assert_eq!(Some(&0), e.mem.get(0));
+    }
+
+    #[test]
+    fn replay_test() {
+        let mut e = Emulator::builder().build();
+        // random sprite position, wait for key, draw digit of key, repeat
+        e.store_instr(&[0xC03F, 0xC11F, 0xF20A, 0xF229, 0xD015, 0x1200]);
+        e.start_recording(9);
+        e.run_for(crate::clock::FRAME * 3).unwrap();
+        e.key_pressed(None, 7);
+        e.run_for(crate::clock::FRAME).unwrap();
+        e.key_released();
+        e.run_for(crate::clock::FRAME * 2).unwrap();
+        let hash = e.state_hash();
+        let rec = e.stop_recording().unwrap();
+        assert!(!e.is_recording());
+        assert_eq!(2, rec.inputs.len());
+        assert!(rec.inputs[0].frame < rec.inputs[1].frame);
+
+        let mut other = Emulator::builder().build();
+        other.replay(&rec).unwrap();
+        assert_eq!(hash, other.state_hash());
+        assert_eq!(7, other.cpu.regs[2]);
+    }
 }
@@ -0,0 +1,48 @@
+use crate::cpu::{Addr, Instr};
+use std::error::Error;
+use std::fmt;
+
+/// Errors reported while executing chip-8 programs
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Chip8Error {
+    /// RET executed at given address with empty stack
+    StackUnderflow(Addr),
+    /// CALL executed at given address with full stack
+    StackOverflow(Addr),
+    /// instruction at addr refers to register outside V0..VF
+    InvalidRegister { addr: Addr, reg: usize },
+    /// memory past its end accessed at addr with [`crate::mem::AddressPolicy::Error`]
+    AddressOutOfRange { addr: Addr },
+    /// word fetched from addr is not a valid instruction
+    InvalidOpcode { addr: Addr, raw: Instr },
+    /// instruction at addr would write target protected with
+    /// [`crate::mem::ProtectMode::Error`]
+    WriteProtected { addr: Addr, target: Addr },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::StackUnderflow(a) => {
+                write!(f, "return with empty stack at 0x{:04X}", a)
+            }
+            Chip8Error::StackOverflow(a) => {
+                write!(f, "call with full stack at 0x{:04X}", a)
+            }
+            Chip8Error::InvalidRegister { addr, reg } => {
+                write!(f, "invalid register {} at 0x{:04X}", reg, addr)
+            }
+            Chip8Error::AddressOutOfRange { addr } => {
+                write!(f, "address 0x{:04X} outside memory", addr)
+            }
+            Chip8Error::InvalidOpcode { addr, raw } => {
+                write!(f, "invalid opcode 0x{:04X} at 0x{:04X}", raw, addr)
+            }
+            Chip8Error::WriteProtected { addr, target } => {
+                write!(f, "write to protected 0x{:04X} at 0x{:04X}", target, addr)
+            }
+        }
+    }
+}
+
+impl Error for Chip8Error {}
@@ -0,0 +1,285 @@
+//! C API for embedding the emulator in other languages (`ffi` feature)
+//!
+//! The crate is also built as a cdylib; `include/libchip8.h` declares the
+//! functions below. An emulator is an opaque `Chip8 *` created with
+//! [`chip8_new`] and released with [`chip8_free`]. Functions returning
+//! `int` report one of the `CHIP8_*` codes; negative ones are errors.
+//!
+//! ```c
+//! Chip8 *c = chip8_new(CHIP8_PROFILE_DEFAULT);
+//! chip8_load_rom(c, rom, rom_len);
+//! while (chip8_run_frame(c) >= 0) {
+//!     draw(chip8_framebuffer(c), chip8_screen_width(c), chip8_screen_height(c));
+//! }
+//! chip8_free(c);
+//! ```
+
+use crate::clock::FRAME;
+use crate::debug::StopReason;
+use crate::emulator::Emulator;
+use crate::error::Chip8Error;
+use crate::input::KeyEvent;
+use crate::quirks::Profile;
+use std::os::raw::c_int;
+use std::ptr;
+use std::slice;
+
+/// Execution continues
+pub const CHIP8_OK: c_int = 0;
+/// EXIT was executed
+pub const CHIP8_HALTED: c_int = 1;
+/// pc reached a breakpoint
+pub const CHIP8_BREAKPOINT: c_int = 2;
+/// FX0A waits for a key
+pub const CHIP8_KEY_WAIT: c_int = 3;
+/// null emulator or invalid argument
+pub const CHIP8_INVALID_ARGUMENT: c_int = -1;
+/// ROM does not fit in memory
+pub const CHIP8_ROM_TOO_LARGE: c_int = -2;
+/// undecodable instruction at pc
+pub const CHIP8_INVALID_OPCODE: c_int = -3;
+/// any other execution failure, e.g. stack overflow
+pub const CHIP8_EXECUTION_ERROR: c_int = -4;
+
+pub const CHIP8_PROFILE_DEFAULT: c_int = 0;
+pub const CHIP8_PROFILE_VIP: c_int = 1;
+pub const CHIP8_PROFILE_CHIP48: c_int = 2;
+pub const CHIP8_PROFILE_SCHIP: c_int = 3;
+pub const CHIP8_PROFILE_XOCHIP: c_int = 4;
+
+/// Emulator with the pixel buffer handed out by [`chip8_framebuffer`]
+pub struct Chip8 {
+    emulator: Emulator,
+    pixels: Vec<u8>,
+}
+
+fn status(result: Result<Option<StopReason>, Chip8Error>) -> c_int {
+    match result {
+        Ok(None) | Ok(Some(StopReason::Paused)) => CHIP8_OK,
+        Ok(Some(StopReason::Halted)) => CHIP8_HALTED,
+        Ok(Some(StopReason::Breakpoint(_))) | Ok(Some(StopReason::Watchpoint { .. })) => {
+            CHIP8_BREAKPOINT
+        }
+        Ok(Some(StopReason::KeyWait)) => CHIP8_KEY_WAIT,
+        Err(Chip8Error::InvalidOpcode { .. }) => CHIP8_INVALID_OPCODE,
+        Err(_) => CHIP8_EXECUTION_ERROR,
+    }
+}
+
+/// Creates emulator configured for one of the `CHIP8_PROFILE_*`
+/// profiles; returns null for unknown ones. Free it with [`chip8_free`].
+#[no_mangle]
+pub extern "C" fn chip8_new(profile: c_int) -> *mut Chip8 {
+    let profile = match profile {
+        CHIP8_PROFILE_DEFAULT => None,
+        CHIP8_PROFILE_VIP => Some(Profile::CosmacVip),
+        CHIP8_PROFILE_CHIP48 => Some(Profile::Chip48),
+        CHIP8_PROFILE_SCHIP => Some(Profile::SuperChip),
+        CHIP8_PROFILE_XOCHIP => Some(Profile::XoChip),
+        _ => return ptr::null_mut(),
+    };
+    let mut builder = Emulator::builder();
+    if let Some(p) = profile {
+        builder = builder.profile(p);
+    }
+    Box::into_raw(Box::new(Chip8 {
+        emulator: builder.build(),
+        pixels: vec![],
+    }))
+}
+
+/// Releases emulator; null is ignored
+///
+/// # Safety
+///
+/// c must be null or returned by [`chip8_new`] and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(c: *mut Chip8) {
+    if !c.is_null() {
+        drop(Box::from_raw(c));
+    }
+}
+
+/// Swaps in ROM of len bytes, see [`Emulator::load_rom`]; a ROM that
+/// does not fit leaves the emulator untouched
+///
+/// # Safety
+///
+/// c must be null or a live emulator, and rom point to len readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(c: *mut Chip8, rom: *const u8, len: usize) -> c_int {
+    let c = match c.as_mut() {
+        Some(c) if !rom.is_null() || len == 0 => c,
+        _ => return CHIP8_INVALID_ARGUMENT,
+    };
+    let bytes = if len == 0 {
+        &[][..]
+    } else {
+        slice::from_raw_parts(rom, len)
+    };
+    match c.emulator.load_rom(bytes) {
+        Ok(_) => CHIP8_OK,
+        Err(_) => CHIP8_ROM_TOO_LARGE,
+    }
+}
+
+/// Restarts loaded ROM, see [`Emulator::soft_reset`]
+///
+/// # Safety
+///
+/// c must be null or a live emulator.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_reset(c: *mut Chip8) -> c_int {
+    match c.as_mut() {
+        Some(c) => {
+            c.emulator.soft_reset();
+            CHIP8_OK
+        }
+        None => CHIP8_INVALID_ARGUMENT,
+    }
+}
+
+/// Executes one instruction without ticking timers
+///
+/// # Safety
+///
+/// c must be null or a live emulator.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_step(c: *mut Chip8) -> c_int {
+    match c.as_mut() {
+        Some(c) => status(c.emulator.step()),
+        None => CHIP8_INVALID_ARGUMENT,
+    }
+}
+
+/// Runs one 60 Hz frame at the emulator's speed, ticking timers; meant to
+/// be called once per displayed frame
+///
+/// # Safety
+///
+/// c must be null or a live emulator.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(c: *mut Chip8) -> c_int {
+    match c.as_mut() {
+        Some(c) => status(c.emulator.run_for(FRAME)),
+        None => CHIP8_INVALID_ARGUMENT,
+    }
+}
+
+/// Color index of every pixel of the current resolution, row by row;
+/// valid until the next call taking c. Null if c is null.
+///
+/// # Safety
+///
+/// c must be null or a live emulator.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(c: *mut Chip8) -> *const u8 {
+    match c.as_mut() {
+        Some(c) => {
+            c.pixels = c.emulator.scr.save().pixels;
+            c.pixels.as_ptr()
+        }
+        None => ptr::null(),
+    }
+}
+
+/// Current screen width in pixels, 0 if c is null
+///
+/// # Safety
+///
+/// c must be null or a live emulator.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_screen_width(c: *const Chip8) -> usize {
+    c.as_ref().map_or(0, |c| c.emulator.resolution().0)
+}
+
+/// Current screen height in pixels, 0 if c is null
+///
+/// # Safety
+///
+/// c must be null or a live emulator.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_screen_height(c: *const Chip8) -> usize {
+    c.as_ref().map_or(0, |c| c.emulator.resolution().1)
+}
+
+unsafe fn key_event(c: *mut Chip8, key: c_int, ev: fn(usize) -> KeyEvent) -> c_int {
+    match c.as_mut() {
+        Some(c) if (0..16).contains(&key) => {
+            c.emulator.kbd.push(ev(key as usize));
+            CHIP8_OK
+        }
+        _ => CHIP8_INVALID_ARGUMENT,
+    }
+}
+
+/// Presses hex keypad key 0..15
+///
+/// # Safety
+///
+/// c must be null or a live emulator.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_key_down(c: *mut Chip8, key: c_int) -> c_int {
+    key_event(c, key, KeyEvent::Pressed)
+}
+
+/// Releases hex keypad key 0..15
+///
+/// # Safety
+///
+/// c must be null or a live emulator.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_key_up(c: *mut Chip8, key: c_int) -> c_int {
+    key_event(c, key, KeyEvent::Released)
+}
+
+/// 1 while sound timer runs, 0 otherwise or if c is null
+///
+/// # Safety
+///
+/// c must be null or a live emulator.
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_active(c: *const Chip8) -> c_int {
+    c.as_ref().map_or(0, |c| c.emulator.sounding() as c_int)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ffi_test() {
+        unsafe {
+            assert!(chip8_new(99).is_null());
+            let c = chip8_new(CHIP8_PROFILE_VIP);
+            // draw digit 0; LD V0, 30; LD ST, V0; LD V0, K; EXIT
+            let rom = [0xD0, 0x15, 0x60, 0x1E, 0xF0, 0x18, 0xF0, 0x0A, 0x00, 0xFD];
+            assert_eq!(CHIP8_OK, chip8_load_rom(c, rom.as_ptr(), rom.len()));
+            assert_eq!(CHIP8_OK, chip8_step(c));
+            let (w, h) = (chip8_screen_width(c), chip8_screen_height(c));
+            assert_eq!((64, 32), (w, h));
+            let pixels = slice::from_raw_parts(chip8_framebuffer(c), w * h);
+            assert_eq!((1, 0), (pixels[0], pixels[4]));
+            assert_eq!(CHIP8_OK, chip8_run_frame(c));
+            assert_eq!(1, chip8_sound_active(c));
+            assert_eq!(CHIP8_OK, chip8_key_down(c, 5));
+            assert_eq!(CHIP8_INVALID_ARGUMENT, chip8_key_down(c, 16));
+            chip8_key_up(c, 5);
+            assert_eq!(CHIP8_HALTED, chip8_run_frame(c));
+            assert_eq!(5, (*c).emulator.cpu.regs[0]);
+
+            let large = vec![0; 0x1000];
+            assert_eq!(
+                CHIP8_ROM_TOO_LARGE,
+                chip8_load_rom(c, large.as_ptr(), large.len())
+            );
+            assert_eq!(0xD0, (*c).emulator.mem.load(0x200));
+            assert_eq!(CHIP8_OK, chip8_reset(c));
+            chip8_free(c);
+
+            assert_eq!(CHIP8_INVALID_ARGUMENT, chip8_step(ptr::null_mut()));
+            assert!(chip8_framebuffer(ptr::null_mut()).is_null());
+            chip8_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! SUPER-CHIP persistent user flags (HP48 RPL flags)
+//!
+//! FX75 saves V0..=VX to the flags and FX85 restores them; games use
+//! this for high scores. Flags outlive a session only if the frontend
+//! installs a [`FlagStore`] writing them somewhere, e.g. [`FileFlags`].
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Number of flag registers (XO-CHIP extends SUPER-CHIP's 8 to 16)
+pub const FLAGS: usize = 16;
+
+/// Persistence of flag registers between sessions
+pub trait FlagStore {
+    /// Returns flags saved by previous session
+    fn load(&mut self) -> [u8; FLAGS];
+    /// Called with all flags each time FX75 changes them
+    fn save(&mut self, flags: &[u8; FLAGS]);
+}
+
+/// Keeps flags in a file, one byte per flag.
+///
+/// Missing or short file reads as zero flags; write errors are ignored
+/// so that a read-only location does not stop the game.
+#[derive(Debug, Clone)]
+pub struct FileFlags {
+    path: PathBuf,
+}
+
+impl FileFlags {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        FileFlags { path: path.into() }
+    }
+}
+
+impl FlagStore for FileFlags {
+    fn load(&mut self) -> [u8; FLAGS] {
+        let mut flags = [0; FLAGS];
+        if let Ok(bytes) = fs::read(&self.path) {
+            let n = bytes.len().min(FLAGS);
+            flags[..n].copy_from_slice(&bytes[..n]);
+        }
+        flags
+    }
+
+    fn save(&mut self, flags: &[u8; FLAGS]) {
+        let _ = fs::write(&self.path, flags);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn file_flags_test() {
+        let path = std::env::temp_dir().join(format!("libchip8-flags-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut e = Emulator::new();
+        e.set_flag_store(Box::new(FileFlags::new(&path)));
+        e.store_instr(&[0x6007, 0x6109, 0xF175]);
+        for _ in 0..3 {
+            e.step().unwrap();
+        }
+        let flags = FileFlags::new(&path).load();
+        fs::remove_file(&path).unwrap();
+        assert_eq!([7, 9, 0], flags[..3]);
+
+        let mut e = Emulator::new();
+        e.set_flag_store(Box::new(FileFlags::new(&path)));
+        assert_eq!([0; FLAGS], e.flags());
+    }
+}
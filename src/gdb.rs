@@ -0,0 +1,348 @@
+//! GDB remote serial protocol server (`gdb` feature)
+//!
+//! [`serve`] waits for a single debugger connection, e.g. from gdb's
+//! `target remote :9000`, and then serves register and memory access,
+//! breakpoints, watchpoints, single steps and continuing until the
+//! debugger detaches. Continuing runs in real time and can be interrupted
+//! with Ctrl-C.
+//!
+//! Registers, as sent by the `g` packet: V0..VF (one byte each), I and
+//! PC (two bytes each, big-endian), DT and ST.
+
+use crate::clock::FRAME;
+use crate::cpu::Addr;
+use crate::debug::{Access, StopReason};
+use crate::emulator::Emulator;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Instant;
+
+/// What to do after handling a packet
+#[derive(Debug, PartialEq)]
+enum Action {
+    Reply(String),
+    /// run until stop or interrupt, then send stop reply
+    Resume,
+    /// send optional reply and end the session
+    Detach(Option<String>),
+}
+
+/// Accepts one connection on addr and serves it until detached
+pub fn serve<A: ToSocketAddrs>(e: &mut Emulator, addr: A) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    session(e, stream)
+}
+
+/// Serves connected debugger until it detaches or disconnects
+pub fn session(e: &mut Emulator, mut stream: TcpStream) -> io::Result<()> {
+    while let Some(packet) = read_packet(&mut stream)? {
+        let reply = match command(e, &packet) {
+            Action::Reply(r) => r,
+            Action::Resume => resume(e, &mut stream)?,
+            Action::Detach(r) => {
+                if let Some(r) = r {
+                    write_packet(&mut stream, &r)?;
+                }
+                return Ok(());
+            }
+        };
+        write_packet(&mut stream, &reply)?;
+    }
+    Ok(())
+}
+
+/// Reads next `$data#checksum` packet, acknowledging it; `None` at end of
+/// stream. Acks, interrupts outside of running and corrupted packets
+/// (answered with `-`) are skipped.
+fn read_packet<S: Read + Write>(s: &mut S) -> io::Result<Option<String>> {
+    let mut byte = [0u8];
+    loop {
+        loop {
+            if s.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+        let mut data = vec![];
+        loop {
+            if s.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut sum = [0u8; 2];
+        s.read_exact(&mut sum)?;
+        let expected = std::str::from_utf8(&sum)
+            .ok()
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        if expected == Some(checksum(&data)) {
+            s.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+        s.write_all(b"-")?;
+    }
+}
+
+fn write_packet<W: Write>(w: &mut W, data: &str) -> io::Result<()> {
+    write!(w, "${}#{:02x}", data, checksum(data.as_bytes()))?;
+    w.flush()
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |s, &b| s.wrapping_add(b))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if s.len() & 1 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses `addr,len` hex pair
+fn addr_len(s: &str) -> Option<(Addr, usize)> {
+    let mut it = s.splitn(2, ',');
+    let addr = u16::from_str_radix(it.next()?, 16).ok()?;
+    let len = usize::from_str_radix(it.next()?, 16).ok()?;
+    Some((addr, len))
+}
+
+fn registers(e: &Emulator) -> Vec<u8> {
+    let cpu = &e.cpu;
+    let mut v = cpu.regs.to_vec();
+    v.extend_from_slice(&cpu.i.to_be_bytes());
+    v.extend_from_slice(&cpu.pc.to_be_bytes());
+    v.extend_from_slice(&[cpu.dt, cpu.st]);
+    v
+}
+
+fn set_registers(e: &mut Emulator, v: &[u8]) -> bool {
+    if v.len() != 22 {
+        return false;
+    }
+    e.cpu.regs.copy_from_slice(&v[..16]);
+    e.cpu.i = u16::from_be_bytes([v[16], v[17]]);
+    e.cpu.pc = u16::from_be_bytes([v[18], v[19]]);
+    e.cpu.dt = v[20];
+    e.cpu.st = v[21];
+    true
+}
+
+fn stop_reply(reason: StopReason) -> String {
+    match reason {
+        StopReason::Halted => "W00".to_string(),
+        StopReason::Watchpoint { addr, access } => {
+            let kind = match access {
+                Access::Write => "watch",
+                Access::Read => "rwatch",
+                Access::ReadWrite => "awatch",
+            };
+            format!("T05{}:{:x};", kind, addr)
+        }
+        StopReason::Breakpoint(_) | StopReason::KeyWait | StopReason::Paused => "S05".to_string(),
+    }
+}
+
+/// Breakpoint (`Z0`/`Z1`) or watchpoint (`Z2`..`Z4`) packet arguments
+fn point(e: &mut Emulator, args: &str, insert: bool) -> Option<()> {
+    let mut it = args.splitn(2, ',');
+    let kind = it.next()?;
+    let (addr, len) = addr_len(it.next()?)?;
+    let access = match kind {
+        "0" | "1" => {
+            if insert {
+                e.add_breakpoint(addr);
+            } else {
+                e.remove_breakpoint(addr);
+            }
+            return Some(());
+        }
+        "2" => Access::Write,
+        "3" => Access::Read,
+        "4" => Access::ReadWrite,
+        _ => return None,
+    };
+    let range = addr..addr.wrapping_add(len.max(1) as Addr);
+    if insert {
+        e.add_watchpoint(range, access);
+    } else {
+        e.remove_watchpoint(&range);
+    }
+    Some(())
+}
+
+fn command(e: &mut Emulator, packet: &str) -> Action {
+    let ok = |done: Option<()>| Action::Reply(if done.is_some() { "OK" } else { "E01" }.into());
+    let (cmd, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+    match cmd {
+        "?" => Action::Reply("S05".into()),
+        "g" => Action::Reply(hex(&registers(e))),
+        "G" => ok(unhex(args).filter(|v| set_registers(e, v)).map(|_| ())),
+        "m" => Action::Reply(
+            addr_len(args)
+                .and_then(|(addr, len)| {
+                    let start = addr as usize;
+                    e.mem.get(start..start.checked_add(len)?)
+                })
+                .map_or_else(|| "E01".into(), hex),
+        ),
+        "M" => ok((|| {
+            let (range, data) = args.split_at(args.find(':')?);
+            let (addr, len) = addr_len(range)?;
+            let bytes = unhex(&data[1..]).filter(|b| b.len() == len)?;
+            e.mem.write_slice(addr, &bytes).ok()
+        })()),
+        "Z" => ok(point(e, args, true)),
+        "z" => ok(point(e, args, false)),
+        "s" => Action::Reply(match e.step() {
+            Ok(Some(StopReason::Halted)) => "W00".into(),
+            Ok(_) => "S05".into(),
+            Err(_) => "S04".into(),
+        }),
+        "c" => Action::Resume,
+        "H" => Action::Reply("OK".into()),
+        "D" => Action::Detach(Some("OK".into())),
+        "k" => Action::Detach(None),
+        _ if packet == "qAttached" => Action::Reply("1".into()),
+        _ if packet.starts_with("qSupported") => Action::Reply("PacketSize=1000".into()),
+        _ => Action::Reply(String::new()),
+    }
+}
+
+/// Runs in real time until stop, execution error (reported as SIGILL) or
+/// Ctrl-C from the debugger (SIGINT)
+fn resume(e: &mut Emulator, stream: &mut TcpStream) -> io::Result<String> {
+    stream.set_nonblocking(true)?;
+    let mut deadline = Instant::now();
+    let reply = loop {
+        match e.run_for(FRAME) {
+            Ok(Some(reason)) => break stop_reply(reason),
+            Ok(None) => {}
+            Err(_) => break "S04".to_string(),
+        }
+        let mut byte = [0u8];
+        match stream.read(&mut byte) {
+            Ok(0) => break "S02".to_string(),
+            Ok(_) if byte[0] == 0x03 => break "S02".to_string(),
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err),
+        }
+        deadline += FRAME;
+        let now = Instant::now();
+        if deadline > now {
+            std::thread::sleep(deadline - now);
+        }
+    };
+    stream.set_nonblocking(false)?;
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn reply(e: &mut Emulator, packet: &str) -> String {
+        match command(e, packet) {
+            Action::Reply(r) => r,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    /// Debugger connection reading from input and writing to output
+    struct Pipe {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn packet_test() {
+        let mut out = vec![];
+        write_packet(&mut out, "OK").unwrap();
+        assert_eq!(b"$OK#9a", &out[..]);
+        let mut s = Pipe {
+            input: Cursor::new(b"+$g#67$m0,2#00".to_vec()),
+            output: vec![],
+        };
+        assert_eq!(Some("g".to_string()), read_packet(&mut s).unwrap());
+        assert_eq!(None, read_packet(&mut s).unwrap());
+        assert_eq!(b"+-", &s.output[..]);
+    }
+
+    #[test]
+    fn command_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x6107, 0x6208, 0x00FD]);
+        assert_eq!("PacketSize=1000", reply(&mut e, "qSupported:swbreak+"));
+        assert_eq!(
+            format!("{}000002000000", "00".repeat(16)),
+            &reply(&mut e, "g")[..]
+        );
+        assert_eq!("S05", reply(&mut e, "s"));
+        assert_eq!("07", &reply(&mut e, "g")[2..4]);
+        assert_eq!("6208", reply(&mut e, "m202,2"));
+        assert_eq!("E01", reply(&mut e, "mfff,2"));
+        assert_eq!("E01", reply(&mut e, "m2,ffffffffffffffff"));
+        assert_eq!("", reply(&mut e, "\u{FFFD}"));
+        assert_eq!("OK", reply(&mut e, "M300,2:abcd"));
+        assert_eq!(Some(&[0xAB, 0xCD][..]), e.mem.get(0x300..0x302));
+
+        let mut regs = registers(&e);
+        regs[3] = 0x33;
+        assert_eq!("OK", reply(&mut e, &format!("G{}", hex(&regs))));
+        assert_eq!(0x33, e.cpu.regs[3]);
+        assert_eq!("E01", reply(&mut e, "G00"));
+
+        assert_eq!("OK", reply(&mut e, "Z0,204,2"));
+        assert_eq!("S05", reply(&mut e, "s"));
+        assert_eq!("OK", reply(&mut e, "z0,204,2"));
+        assert_eq!("W00", reply(&mut e, "s"));
+        assert_eq!("OK", reply(&mut e, "Z2,300,1"));
+        assert_eq!("E01", reply(&mut e, "Z9,300,1"));
+        assert_eq!("", reply(&mut e, "vMustReplyEmpty"));
+        assert_eq!(Action::Resume, command(&mut e, "c"));
+        assert_eq!(Action::Detach(Some("OK".into())), command(&mut e, "D"));
+    }
+
+    #[test]
+    fn stop_reply_test() {
+        let watch = StopReason::Watchpoint {
+            addr: 0x300,
+            access: Access::Read,
+        };
+        assert_eq!("T05rwatch:300;", stop_reply(watch));
+        assert_eq!("S05", stop_reply(StopReason::Breakpoint(0x200)));
+        assert_eq!("W00", stop_reply(StopReason::Halted));
+    }
+}
@@ -0,0 +1,279 @@
+//! Emulator running on a background thread, see [`Emulator::spawn`]
+//!
+//! The emulator holds boxed screens, sinks and hooks that need not be
+//! `Send`, so it is built on the thread by the closure passed to spawn.
+//! The thread runs it in real time at its configured speed, taking
+//! [`Command`]s and sending [`Update`]s through channels.
+//!
+//! [`Emulator::spawn`]: crate::emulator::Emulator::spawn
+
+use crate::clock::FRAME;
+use crate::debug::StopReason;
+use crate::display::ScreenState;
+use crate::emulator::{Emulator, Event};
+use crate::error::Chip8Error;
+use crate::input::KeyEvent;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Request sent to emulator thread
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Key(KeyEvent),
+    Pause,
+    Resume,
+    /// swaps in ROM with [`Emulator::load_rom`] and resumes
+    Load(Vec<u8>),
+    /// restarts loaded ROM with [`Emulator::soft_reset`] and resumes
+    Reset,
+    /// ends the thread
+    Quit,
+}
+
+/// Notification sent by emulator thread
+#[derive(Debug, Clone, PartialEq)]
+pub enum Update {
+    /// screen at the end of a frame that changed it
+    Frame(ScreenState),
+    SoundStarted,
+    SoundStopped,
+    /// FX0A started waiting for key press and release
+    WaitingForKey,
+    /// execution stopped on EXIT, breakpoint or watchpoint; the thread
+    /// pauses until resumed or given a ROM
+    Stopped(StopReason),
+    /// execution failed; the thread pauses
+    Error(Chip8Error),
+    /// ROM sent with [`Command::Load`] could not be loaded
+    LoadFailed(String),
+}
+
+/// Owner of emulator thread; dropping it ends the thread
+pub struct EmulatorHandle {
+    commands: Sender<Command>,
+    updates: Receiver<Update>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EmulatorHandle {
+    pub(crate) fn spawn<F>(make: F) -> Self
+    where
+        F: FnOnce() -> Emulator + Send + 'static,
+    {
+        let (commands, command_rx) = mpsc::channel();
+        let (update_tx, updates) = mpsc::channel();
+        let thread = thread::spawn(move || run(make(), command_rx, update_tx));
+        EmulatorHandle {
+            commands,
+            updates,
+            thread: Some(thread),
+        }
+    }
+
+    /// Sends command; returns false if the thread has ended
+    pub fn send(&self, cmd: Command) -> bool {
+        self.commands.send(cmd).is_ok()
+    }
+
+    pub fn key(&self, ev: KeyEvent) -> bool {
+        self.send(Command::Key(ev))
+    }
+
+    pub fn pause(&self) -> bool {
+        self.send(Command::Pause)
+    }
+
+    pub fn resume(&self) -> bool {
+        self.send(Command::Resume)
+    }
+
+    pub fn load(&self, rom: Vec<u8>) -> bool {
+        self.send(Command::Load(rom))
+    }
+
+    /// Oldest pending update, if any
+    pub fn try_recv(&self) -> Option<Update> {
+        self.updates.try_recv().ok()
+    }
+
+    /// Waits up to timeout for next update
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Update> {
+        self.updates.recv_timeout(timeout).ok()
+    }
+
+    /// All pending updates, oldest first
+    pub fn drain(&self) -> Vec<Update> {
+        self.updates.try_iter().collect()
+    }
+
+    /// Ends the thread and waits for it
+    pub fn quit(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        let _ = self.commands.send(Command::Quit);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+impl Drop for EmulatorHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Applies command; returns false on quit
+fn apply(e: &mut Emulator, cmd: Command, paused: &mut bool, tx: &Sender<Update>) -> bool {
+    match cmd {
+        Command::Key(ev) => e.kbd.push(ev),
+        Command::Pause => *paused = true,
+        Command::Resume => *paused = false,
+        Command::Load(rom) => match e.load_rom(&rom) {
+            Ok(_) => *paused = false,
+            Err(err) => {
+                let _ = tx.send(Update::LoadFailed(err.to_string()));
+            }
+        },
+        Command::Reset => {
+            e.soft_reset();
+            *paused = false;
+        }
+        Command::Quit => return false,
+    }
+    true
+}
+
+fn run(mut e: Emulator, commands: Receiver<Command>, tx: Sender<Update>) {
+    e.enable_events();
+    let mut paused = false;
+    let mut deadline = Instant::now();
+    loop {
+        if paused {
+            match commands.recv() {
+                Ok(cmd) => {
+                    if !apply(&mut e, cmd, &mut paused, &tx) {
+                        return;
+                    }
+                }
+                Err(_) => return,
+            }
+            deadline = Instant::now();
+            continue;
+        }
+        loop {
+            match commands.try_recv() {
+                Ok(cmd) => {
+                    if !apply(&mut e, cmd, &mut paused, &tx) {
+                        return;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        if paused {
+            continue;
+        }
+        let result = e.run_for(FRAME);
+        let events = e.drain_events();
+        if events.contains(&Event::DisplayUpdated) {
+            let _ = tx.send(Update::Frame(e.scr.save()));
+        }
+        for ev in events {
+            let _ = tx.send(match ev {
+                Event::SoundStarted => Update::SoundStarted,
+                Event::SoundStopped => Update::SoundStopped,
+                Event::WaitingForKey => Update::WaitingForKey,
+                _ => continue,
+            });
+        }
+        match result {
+            Ok(None) => {}
+            Ok(Some(reason)) => {
+                let _ = tx.send(Update::Stopped(reason));
+                paused = true;
+            }
+            Err(err) => {
+                let _ = tx.send(Update::Error(err));
+                paused = true;
+            }
+        }
+        deadline += FRAME;
+        // commands arriving early are applied, the next frame still waits
+        while !paused {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            match commands.recv_timeout(left) {
+                Ok(cmd) => {
+                    if !apply(&mut e, cmd, &mut paused, &tx) {
+                        return;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAIT: Duration = Duration::from_secs(2);
+
+    /// Next update that is not a frame
+    fn next_event(h: &EmulatorHandle) -> Option<Update> {
+        std::iter::from_fn(|| h.recv_timeout(WAIT)).find(|u| !matches!(u, Update::Frame(_)))
+    }
+
+    #[test]
+    fn spawn_test() {
+        // wait for key, draw its digit, beep, exit
+        let rom = vec![
+            0xF0, 0x0A, 0xF0, 0x29, 0xD1, 0x15, 0x60, 0x02, 0xF0, 0x18, 0x00, 0xFD,
+        ];
+        let h = Emulator::spawn(move || {
+            let mut e = Emulator::builder().build();
+            e.store_bytes(&rom);
+            e
+        });
+        assert_eq!(Some(Update::WaitingForKey), next_event(&h));
+        h.key(KeyEvent::Pressed(8));
+        h.key(KeyEvent::Released(8));
+        match h.recv_timeout(WAIT) {
+            Some(Update::Frame(s)) => assert_eq!(1, s.pixels[0]),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(Some(Update::SoundStarted), next_event(&h));
+        assert_eq!(Some(Update::Stopped(StopReason::Halted)), next_event(&h));
+        h.load(vec![0; 0x2000]);
+        assert!(matches!(next_event(&h), Some(Update::LoadFailed(_))));
+        assert!(h.send(Command::Reset));
+        h.pause();
+        h.quit();
+    }
+
+    #[test]
+    fn commands_keep_pace_test() {
+        // CLS; JP 0x200: a frame update every frame
+        let h = Emulator::spawn(|| {
+            let mut e = Emulator::new();
+            e.store_instr(&[0x00E0, 0x1200]);
+            e
+        });
+        for _ in 0..100 {
+            h.key(KeyEvent::Pressed(1));
+            thread::sleep(Duration::from_millis(1));
+        }
+        let frames = h.drain().len();
+        assert!(frames < 30, "{} frames during 100 keys", frames);
+        h.quit();
+    }
+}
@@ -0,0 +1,107 @@
+//! Opt-in per-address memory access counts enabled with
+//! [`crate::emulator::Emulator::enable_heat_map`]
+use crate::coverage::Coverage;
+use crate::cpu::Addr;
+use crate::debug::Access;
+
+/// Data reads and writes of executed instructions per memory address;
+/// instruction fetches are not counted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeatMap {
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+}
+
+impl HeatMap {
+    /// Empty map of memory with size bytes
+    pub fn new(size: usize) -> Self {
+        HeatMap {
+            reads: vec![0; size],
+            writes: vec![0; size],
+        }
+    }
+
+    /// Counts access to len bytes from start, wrapping around memory
+    pub(crate) fn record(&mut self, start: Addr, len: u16, access: Access) {
+        let size = self.reads.len();
+        if size == 0 {
+            return;
+        }
+        let counts = match access {
+            Access::Write => &mut self.writes,
+            _ => &mut self.reads,
+        };
+        for off in 0..len as usize {
+            counts[(start as usize + off) % size] += 1;
+        }
+    }
+
+    pub fn reads(&self, addr: Addr) -> u64 {
+        self.reads.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    pub fn writes(&self, addr: Addr) -> u64 {
+        self.writes.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    /// (address, reads, writes) of accessed addresses in ascending order
+    pub fn accessed(&self) -> Vec<(Addr, u64, u64)> {
+        (0..self.reads.len())
+            .filter(|&a| self.reads[a] + self.writes[a] > 0)
+            .map(|a| (a as Addr, self.reads[a], self.writes[a]))
+            .collect()
+    }
+
+    /// Addresses that were both written and executed, i.e. self-modifying
+    /// code
+    pub fn modified_code(&self, coverage: &Coverage) -> Vec<Addr> {
+        (0..self.writes.len())
+            .map(|a| a as Addr)
+            .filter(|&a| self.writes(a) > 0 && is_executed(coverage, a))
+            .collect()
+    }
+
+    /// `addr,reads,writes` header and a line for every accessed address,
+    /// address in hex
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("addr,reads,writes\n");
+        for (addr, r, w) in self.accessed() {
+            out.push_str(&format!("0x{:03X},{},{}\n", addr, r, w));
+        }
+        out
+    }
+
+    pub fn reset(&mut self) {
+        self.reads.iter_mut().for_each(|c| *c = 0);
+        self.writes.iter_mut().for_each(|c| *c = 0);
+    }
+}
+
+/// Whether an executed instruction covers addr with either of its bytes
+fn is_executed(coverage: &Coverage, addr: Addr) -> bool {
+    coverage.is_covered(addr) || addr > 0 && coverage.is_covered(addr - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn heat_map_test() {
+        let mut e = Emulator::new();
+        // LD I, 0x20A; LD V0, 0x60; LD [I], V0 (overwrites EXIT's first
+        // byte); DRW V0, V0, 2; LD V0, [I]; 60FD is LD V0, 0xFD, then EXIT
+        e.store_instr(&[0xA20A, 0x6060, 0xF055, 0xD002, 0xF065, 0x00FD, 0x00FD]);
+        assert!(e.heat_map().is_none());
+        e.enable_heat_map();
+        e.enable_coverage();
+        e.run().unwrap();
+        let h = e.heat_map().unwrap();
+        assert_eq!(vec![(0x20A, 2, 1), (0x20B, 1, 0)], h.accessed());
+        assert_eq!((2, 1), (h.reads(0x20A), h.writes(0x20A)));
+        assert_eq!(vec![0x20A], h.modified_code(e.coverage().unwrap()));
+        assert_eq!("addr,reads,writes\n0x20A,2,1\n0x20B,1,0\n", h.to_csv());
+        e.disable_heat_map();
+        assert!(e.heat_map().is_none());
+    }
+}
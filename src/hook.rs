@@ -0,0 +1,211 @@
+//! Observer hooks called by [`crate::emulator::Emulator::exec`] around every
+//! executed instruction, and handlers of SYS (`0NNN`) machine code calls
+use crate::cpu::{Addr, Opcode, CPU};
+use crate::error::Chip8Error;
+use crate::mem::Mem;
+
+/// Observer of executed instructions.
+///
+/// `pc` is the address the instruction was fetched from. Both methods have
+/// empty default implementations so hooks implement only what they need;
+/// `after` may modify cpu state, e.g. to freeze registers.
+pub trait ExecHook {
+    fn before(&mut self, _pc: Addr, _op: Opcode, _cpu: &CPU) {}
+    fn after(&mut self, _pc: Addr, _op: Opcode, _cpu: &mut CPU) {}
+}
+
+/// Hook calling closure after every instruction
+pub struct AfterFn<F>(pub F);
+
+impl<F> ExecHook for AfterFn<F>
+where
+    F: FnMut(Addr, Opcode, &mut CPU),
+{
+    fn after(&mut self, pc: Addr, op: Opcode, cpu: &mut CPU) {
+        (self.0)(pc, op, cpu)
+    }
+}
+
+/// How [`crate::emulator::Emulator::step`] treats SYS `0NNN` words, which
+/// called machine code routines on the original interpreter. `0000` is
+/// never a SYS call, so running into empty memory still fails.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum SysMode {
+    /// fail with [`Chip8Error::InvalidOpcode`], as without SYS support
+    #[default]
+    Reject,
+    /// skip the instruction
+    Ignore,
+}
+
+/// Host routine run for SYS instructions in place of machine code,
+/// installed with [`crate::emulator::Emulator::set_sys_handler`].
+///
+/// Called with the routine address NNN; pc still points at the SYS
+/// instruction and advances past it afterwards unless the handler moved
+/// it. Errors stop execution like failing instructions. Exec hooks,
+/// journal and profiler see SYS instructions as [`Opcode::Unknown`].
+pub trait SysHandler {
+    fn call(&mut self, addr: Addr, cpu: &mut CPU, mem: &mut Mem) -> Result<(), Chip8Error>;
+}
+
+impl<F> SysHandler for F
+where
+    F: FnMut(Addr, &mut CPU, &mut Mem) -> Result<(), Chip8Error>,
+{
+    fn call(&mut self, addr: Addr, cpu: &mut CPU, mem: &mut Mem) -> Result<(), Chip8Error> {
+        self(addr, cpu, mem)
+    }
+}
+
+/// Handle identifying registered exec hook
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct HookId(pub(crate) usize);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Trace(Rc<RefCell<Vec<(Addr, Opcode, u8)>>>);
+
+    impl ExecHook for Trace {
+        fn before(&mut self, pc: Addr, op: Opcode, cpu: &CPU) {
+            self.0.borrow_mut().push((pc, op, cpu.regs[1]));
+        }
+    }
+
+    #[test]
+    fn before_hook_test() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut e = Emulator::new();
+        e.add_hook(Box::new(Trace(log.clone())));
+        e.store(&[Opcode::LD(1, 4), Opcode::ADD(1, 1), Opcode::EXIT]);
+        e.run().unwrap();
+        let expected = vec![
+            (0x200, Opcode::LD(1, 4), 0),
+            (0x202, Opcode::ADD(1, 1), 4),
+            (0x204, Opcode::EXIT, 5),
+        ];
+        assert_eq!(expected, *log.borrow());
+    }
+
+    #[test]
+    fn after_hook_modifies_test() {
+        let mut e = Emulator::new();
+        let id = e.add_hook(Box::new(AfterFn(|_, _, cpu: &mut CPU| cpu.regs[2] = 99)));
+        e.store(&[Opcode::LD(2, 1), Opcode::EXIT]);
+        e.run().unwrap();
+        assert_eq!(99, e.cpu.regs[2]);
+        assert!(e.remove_hook(id));
+        assert!(!e.remove_hook(id));
+        e.run().unwrap();
+        assert_eq!(1, e.cpu.regs[2]);
+    }
+
+    #[test]
+    fn sys_test() {
+        // SYS 0x123; LD V0, 1; EXIT
+        let rom = [0x0123, 0x6001, 0x00FD];
+        let mut e = Emulator::new();
+        e.store_instr(&rom);
+        let err = Chip8Error::InvalidOpcode {
+            addr: 0x200,
+            raw: 0x0123,
+        };
+        assert_eq!(Err(err), e.run());
+
+        e.sys_mode = SysMode::Ignore;
+        e.run().unwrap();
+        assert_eq!((1, 3), (e.cpu.regs[0], e.stats.executed));
+        e.store_instr(&[0x0000]);
+        assert!(e.run().is_err());
+
+        let calls = Rc::new(RefCell::new(vec![]));
+        let log = calls.clone();
+        e.store_instr(&rom);
+        e.set_sys_handler(Box::new(move |addr, cpu: &mut CPU, mem: &mut Mem| {
+            log.borrow_mut().push(addr);
+            cpu.regs[5] = mem.load(addr);
+            Ok(())
+        }));
+        e.mem.store(0x123, 0x42);
+        e.run().unwrap();
+        assert_eq!((vec![0x123], 0x42), (calls.borrow().clone(), e.cpu.regs[5]));
+
+        // handler jumping elsewhere, then failing
+        e.set_sys_handler(Box::new(|addr, cpu: &mut CPU, _: &mut Mem| {
+            if addr == 0x123 {
+                cpu.pc(0x204);
+                Ok(())
+            } else {
+                Err(Chip8Error::InvalidOpcode {
+                    addr: cpu.pc,
+                    raw: addr,
+                })
+            }
+        }));
+        e.cpu.regs[0] = 0;
+        e.run().unwrap();
+        assert_eq!(0, e.cpu.regs[0]);
+        e.store_instr(&[0x0456]);
+        assert!(e.run().is_err());
+        assert!(e.remove_sys_handler().is_some());
+    }
+
+    #[test]
+    fn sys_bookkeeping_test() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut e = Emulator::new();
+        e.sys_mode = SysMode::Ignore;
+        e.add_hook(Box::new(Trace(log.clone())));
+        e.enable_rewind(10);
+        e.enable_journal(10);
+        e.enable_coverage();
+        e.set_sys_handler(Box::new(|_, cpu: &mut CPU, _: &mut Mem| {
+            cpu.regs[1] = 7;
+            Ok(())
+        }));
+        // LD V1, 1; SYS 0x123; EXIT
+        e.store_instr(&[0x6101, 0x0123, 0x00FD]);
+        e.run().unwrap();
+        assert_eq!((0x202, Opcode::Unknown(0x0123), 1), log.borrow()[1]);
+        assert!(e.coverage().unwrap().is_covered(0x202));
+        let journal = e.journal().unwrap();
+        let sys = journal.iter().nth(1).unwrap();
+        assert!(sys.pc == 0x202 && sys.changed(crate::diff::Register::V(1)));
+        assert_eq!(2, e.rewind(2));
+        assert_eq!((0x202, 1), (e.cpu.pc, e.cpu.regs[1]));
+    }
+
+    #[test]
+    fn sys_io_reads_test() {
+        use crate::mem::IoHandler;
+
+        /// Serves SYS 0x123, counting reads
+        struct Device(Rc<RefCell<usize>>);
+
+        impl IoHandler for Device {
+            fn read(&mut self, addr: Addr) -> u8 {
+                *self.0.borrow_mut() += 1;
+                [0x01, 0x23][addr as usize & 1]
+            }
+
+            fn write(&mut self, _addr: Addr, _value: u8) {}
+        }
+
+        let reads = Rc::new(RefCell::new(0));
+        let mut e = Emulator::builder().two_page_hires(true).build();
+        e.sys_mode = SysMode::Ignore;
+        e.store_instr(&[0x0000, 0x00FD]);
+        e.mem.map_io(0x200..0x202, Box::new(Device(reads.clone())));
+        e.step().unwrap();
+        assert_eq!((2, 0x202), (*reads.borrow(), e.cpu.pc));
+        e.sys_mode = SysMode::Reject;
+        e.cpu.pc(0x200);
+        assert!(e.step().is_err());
+        assert_eq!(4, *reads.borrow());
+    }
+}
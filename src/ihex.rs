@@ -0,0 +1,197 @@
+//! Intel HEX encoding of memory images, as used by EPROM programmers
+//!
+//! Only what 8-bit images need is supported: data, end of file, extended
+//! segment and linear address records; start address records are
+//! accepted and ignored.
+
+use std::error::Error;
+use std::fmt;
+
+/// Data bytes per record written by [`encode`]
+const RECORD_LEN: usize = 16;
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const SEGMENT_ADDRESS: u8 = 0x02;
+const SEGMENT_START: u8 = 0x03;
+const LINEAR_ADDRESS: u8 = 0x04;
+const LINEAR_START: u8 = 0x05;
+
+/// Reasons text cannot be decoded; lines are numbered from 1
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IhexError {
+    /// line is not `:` followed by pairs of hex digits
+    Syntax {
+        line: usize,
+    },
+    /// byte count does not match length of the line
+    Length {
+        line: usize,
+    },
+    Checksum {
+        line: usize,
+    },
+    UnsupportedRecord {
+        line: usize,
+        kind: u8,
+    },
+    /// data for address past the end of memory
+    OutOfRange {
+        addr: u32,
+    },
+    /// text ends without end of file record
+    MissingEof,
+}
+
+impl fmt::Display for IhexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IhexError::Syntax { line } => write!(f, "line {}: not an Intel HEX record", line),
+            IhexError::Length { line } => write!(f, "line {}: wrong record length", line),
+            IhexError::Checksum { line } => write!(f, "line {}: checksum mismatch", line),
+            IhexError::UnsupportedRecord { line, kind } => {
+                write!(f, "line {}: unsupported record type {:02X}", line, kind)
+            }
+            IhexError::OutOfRange { addr } => {
+                write!(f, "data at 0x{:X} outside memory", addr)
+            }
+            IhexError::MissingEof => write!(f, "missing end of file record"),
+        }
+    }
+}
+
+impl Error for IhexError {}
+
+fn record(out: &mut String, kind: u8, addr: u16, data: &[u8]) {
+    let [hi, lo] = addr.to_be_bytes();
+    let mut bytes = vec![data.len() as u8, hi, lo, kind];
+    bytes.extend_from_slice(data);
+    let sum = bytes.iter().fold(0u8, |s, b| s.wrapping_add(*b));
+    bytes.push(sum.wrapping_neg());
+    out.push(':');
+    for b in bytes {
+        out.push_str(&format!("{:02X}", b));
+    }
+    out.push('\n');
+}
+
+/// Records of bytes stored from base, 16 per line, with extended linear
+/// address records past 64K, followed by end of file record
+pub fn encode(base: u32, bytes: &[u8]) -> String {
+    let mut out = String::new();
+    let mut upper = 0;
+    let mut addr = base;
+    for chunk in bytes.chunks(RECORD_LEN) {
+        // split records crossing a 64K boundary
+        let room = 0x10000 - (addr & 0xFFFF) as usize;
+        let (first, rest) = chunk.split_at(chunk.len().min(room));
+        for part in [first, rest].iter().filter(|p| !p.is_empty()) {
+            if addr >> 16 != upper {
+                upper = addr >> 16;
+                record(&mut out, LINEAR_ADDRESS, 0, &(upper as u16).to_be_bytes());
+            }
+            record(&mut out, DATA, addr as u16, part);
+            addr += part.len() as u32;
+        }
+    }
+    record(&mut out, END_OF_FILE, 0, &[]);
+    out
+}
+
+/// Data of all records as (address, bytes) runs in file order, adjacent
+/// records merged
+pub fn decode(text: &str) -> Result<Vec<(u32, Vec<u8>)>, IhexError> {
+    let mut out: Vec<(u32, Vec<u8>)> = vec![];
+    let mut offset = 0u32;
+    for (n, line) in text.lines().enumerate() {
+        let line_no = n + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bytes = parse_line(line).ok_or(IhexError::Syntax { line: line_no })?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(IhexError::Length { line: line_no });
+        }
+        if bytes.iter().fold(0u8, |s, b| s.wrapping_add(*b)) != 0 {
+            return Err(IhexError::Checksum { line: line_no });
+        }
+        let addr = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+        let value = || data.iter().fold(0u32, |v, b| v << 8 | *b as u32);
+        match bytes[3] {
+            DATA => {
+                let start = offset + addr;
+                // runs end at most at u32::MAX, so merged ends cannot overflow
+                if start.checked_add(data.len() as u32).is_none() {
+                    return Err(IhexError::OutOfRange { addr: start });
+                }
+                match out.last_mut() {
+                    Some((a, run)) if *a + run.len() as u32 == start => run.extend_from_slice(data),
+                    _ => out.push((start, data.to_vec())),
+                }
+            }
+            END_OF_FILE => return Ok(out),
+            SEGMENT_ADDRESS => offset = value() << 4,
+            LINEAR_ADDRESS => offset = value() << 16,
+            SEGMENT_START | LINEAR_START => {}
+            kind => {
+                return Err(IhexError::UnsupportedRecord {
+                    line: line_no,
+                    kind,
+                })
+            }
+        }
+    }
+    Err(IhexError::MissingEof)
+}
+
+/// Bytes of `:`-prefixed hex digit pairs
+fn parse_line(line: &str) -> Option<Vec<u8>> {
+    let digits = line.strip_prefix(':')?;
+    if digits.len() % 2 != 0 || !digits.is_ascii() {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_test() {
+        let hex = encode(0x200, &[0x00, 0xE0, 0x12, 0x00]);
+        assert_eq!(":0402000000E0120008\n:00000001FF\n", hex);
+        let long = encode(0xFFF8, &[0xAA; 20]);
+        let lines: Vec<&str> = long.lines().collect();
+        assert_eq!(5, lines.len());
+        assert_eq!(":020000040001F9", lines[1]);
+        assert_eq!(Ok(vec![(0xFFF8, vec![0xAA; 20])]), decode(&long));
+    }
+
+    #[test]
+    fn decode_test() {
+        let text = ":02010000ABCD85\n\n:020000021000EC\n:0100000042BD\n:00000001FF\n";
+        assert_eq!(
+            Ok(vec![(0x100, vec![0xAB, 0xCD]), (0x10000, vec![0x42])]),
+            decode(text)
+        );
+        assert_eq!(Err(IhexError::Syntax { line: 1 }), decode("0000"));
+        assert_eq!(Err(IhexError::Length { line: 1 }), decode(":0100000001"));
+        assert_eq!(Err(IhexError::Checksum { line: 1 }), decode(":00000001FE"));
+        assert_eq!(
+            Err(IhexError::UnsupportedRecord { line: 1, kind: 7 }),
+            decode(":00000007F9")
+        );
+        assert_eq!(Err(IhexError::MissingEof), decode(":02010000ABCD85"));
+        let past_4g = ":02000004FFFFFC\n:01FFFF00AA57\n:01000000BB44\n:00000001FF\n";
+        assert_eq!(
+            Err(IhexError::OutOfRange { addr: 0xFFFF_FFFF }),
+            decode(past_4g)
+        );
+    }
+}
@@ -1,7 +1,29 @@
-const KEY_COUNT: usize = 0x10;
-#[derive(Debug, Default)]
+use std::collections::{HashMap, VecDeque};
+
+pub(crate) const KEY_COUNT: usize = 0x10;
+
+/// Keypad keys in `1234 qwer asdf zxcv` order of the standard layout
+const QWERTY_KEYS: [usize; KEY_COUNT] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+const QWERTY_CHARS: &str = "1234qwerasdfzxcv";
+/// PC set 1 scancodes (also Linux evdev key codes) of `1234 qwer asdf zxcv`
+const QWERTY_SCANCODES: [u32; KEY_COUNT] = [
+    0x02, 0x03, 0x04, 0x05, 0x10, 0x11, 0x12, 0x13, 0x1E, 0x1F, 0x20, 0x21, 0x2C, 0x2D, 0x2E, 0x2F,
+];
+
+/// Change of single key state reported by frontend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    Pressed(usize),
+    Released(usize),
+}
+
+/// Keypad state; queued events are transient and not compared by `==`
+#[derive(Debug, Default, Clone)]
 pub struct Keyboard {
     pub states: [bool; KEY_COUNT],
+    events: VecDeque<KeyEvent>,
 }
 
 impl Keyboard {
@@ -9,20 +31,205 @@ impl Keyboard {
         Default::default()
     }
 
+    /// Toggles key state, queueing matching event
     pub fn switch(&mut self, idx: usize) {
-        self.states[idx] = !self.states[idx];
+        if self.get(idx) {
+            self.push(KeyEvent::Released(idx));
+        } else {
+            self.push(KeyEvent::Pressed(idx));
+        }
     }
 
+    /// Applies event to key states and queues it for [`Keyboard::pop_event`];
+    /// events for keys outside keypad are ignored, repeated ones are queued
+    /// but do not change state
+    pub fn push(&mut self, ev: KeyEvent) {
+        let (idx, down) = match ev {
+            KeyEvent::Pressed(k) => (k, true),
+            KeyEvent::Released(k) => (k, false),
+        };
+        if let Some(state) = self.states.get_mut(idx) {
+            *state = down;
+            self.events.push_back(ev);
+        }
+    }
+
+    /// Removes oldest queued event
+    pub fn pop_event(&mut self) -> Option<KeyEvent> {
+        self.events.pop_front()
+    }
+
+    /// Queued events, oldest first
+    pub(crate) fn queued(&self) -> impl Iterator<Item = KeyEvent> + '_ {
+        self.events.iter().copied()
+    }
+
+    /// Drops queued events keeping key states
+    pub fn clear_events(&mut self) {
+        self.events.clear();
+    }
+
+    /// Returns if key is pressed; indices outside keypad are never pressed
     pub fn get(&self, idx: usize) -> bool {
-        self.states[idx]
+        self.states.get(idx).copied().unwrap_or(false)
     }
 
+    /// First pressed key
     pub fn down_key(&self) -> Option<usize> {
-        self.states.iter().position(|&i| i == true)
+        self.states.iter().position(|&i| i)
+    }
+
+    /// All pressed keys in ascending order
+    pub fn down_keys(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..KEY_COUNT).filter(move |&k| self.states[k])
+    }
+}
+
+/// Key state kept by the frontend (SDL, winit, GPIO...) and queried by
+/// the emulator when SKP, SKNP or FX0A need it, instead of being mirrored
+/// into [`Keyboard`]; see
+/// [`Emulator::set_key_source`](crate::emulator::Emulator::set_key_source)
+pub trait KeySource {
+    /// Whether keypad key (0..=0xF) is held
+    fn is_down(&mut self, key: usize) -> bool;
+
+    /// Lowest held key
+    fn first_pressed(&mut self) -> Option<usize> {
+        (0..KEY_COUNT).find(|&k| self.is_down(k))
+    }
+}
+
+impl<F: FnMut(usize) -> bool> KeySource for F {
+    fn is_down(&mut self, key: usize) -> bool {
+        self(key)
+    }
+}
+
+impl KeySource for Keyboard {
+    fn is_down(&mut self, key: usize) -> bool {
+        self.get(key)
+    }
+
+    fn first_pressed(&mut self) -> Option<usize> {
+        self.down_key()
+    }
+}
+
+/// Key of host keyboard as reported by frontend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HostKey {
+    /// typed character, e.g. from a terminal
+    Char(char),
+    /// layout independent physical key code
+    Scancode(u32),
+}
+
+/// Custom layout as stored in frontend configuration
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyMapConfig {
+    /// characters and keypad keys they press
+    pub chars: Vec<(char, usize)>,
+    /// scancodes and keypad keys they press
+    pub scancodes: Vec<(u32, usize)>,
+}
+
+/// Translation of host keys to keypad key indices.
+///
+/// Characters are matched ignoring case. The default is [`KeyMap::qwerty`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMap {
+    keys: HashMap<HostKey, usize>,
+}
+
+impl KeyMap {
+    /// Map without bindings
+    pub fn empty() -> Self {
+        KeyMap {
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Standard layout: `1234 qwer asdf zxcv` press `123C 456D 789E A0BF`,
+    /// both as characters and as scancodes of these keys
+    pub fn qwerty() -> Self {
+        let mut m = KeyMap::empty();
+        for ((c, code), &key) in QWERTY_CHARS
+            .chars()
+            .zip(QWERTY_SCANCODES.iter())
+            .zip(QWERTY_KEYS.iter())
+        {
+            m.bind(HostKey::Char(c), key);
+            m.bind(HostKey::Scancode(*code), key);
+        }
+        m
+    }
+
+    /// Map with bindings of config only; fails naming first binding to a
+    /// key outside keypad
+    pub fn from_config(config: &KeyMapConfig) -> Result<Self, String> {
+        let mut m = KeyMap::empty();
+        let chars = config.chars.iter().map(|&(c, k)| (HostKey::Char(c), k));
+        let codes = config
+            .scancodes
+            .iter()
+            .map(|&(c, k)| (HostKey::Scancode(c), k));
+        for (host, key) in chars.chain(codes) {
+            if key >= KEY_COUNT {
+                return Err(format!("{:?} bound to key {}, not on keypad", host, key));
+            }
+            m.bind(host, key);
+        }
+        Ok(m)
+    }
+
+    /// Binds host key to keypad key, replacing its previous binding
+    pub fn bind(&mut self, host: HostKey, key: usize) {
+        self.keys.insert(normalize(host), key);
+    }
+
+    /// Removes binding returning keypad key it pressed
+    pub fn unbind(&mut self, host: HostKey) -> Option<usize> {
+        self.keys.remove(&normalize(host))
+    }
+
+    /// Keypad key pressed by host key
+    pub fn get(&self, host: HostKey) -> Option<usize> {
+        self.keys.get(&normalize(host)).copied()
+    }
+
+    /// Keypad event for press or release of host key, if it is bound
+    pub fn event(&self, host: HostKey, pressed: bool) -> Option<KeyEvent> {
+        self.get(host).map(|k| {
+            if pressed {
+                KeyEvent::Pressed(k)
+            } else {
+                KeyEvent::Released(k)
+            }
+        })
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap::qwerty()
+    }
+}
+
+fn normalize(host: HostKey) -> HostKey {
+    match host {
+        HostKey::Char(c) => HostKey::Char(c.to_lowercase().next().unwrap_or(c)),
+        other => other,
+    }
+}
+
+impl PartialEq for Keyboard {
+    fn eq(&self, other: &Self) -> bool {
+        self.states == other.states
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -33,6 +240,80 @@ mod tests {
         assert_eq!(k.get(3), true);
     }
 
+    #[test]
+    fn get_out_of_range_test() {
+        let k = Keyboard::new();
+        assert!(!k.get(0xFF));
+    }
+
+    #[test]
+    fn key_source_test() {
+        let mut k = Keyboard::new();
+        k.switch(9);
+        k.switch(4);
+        assert_eq!(Some(4), KeySource::first_pressed(&mut k));
+        let mut odd = |key: usize| key % 2 == 1;
+        assert_eq!(Some(1), odd.first_pressed());
+        let mut none = |_| false;
+        assert_eq!(None, none.first_pressed());
+    }
+
+    #[test]
+    fn events_test() {
+        let mut k = Keyboard::new();
+        k.push(KeyEvent::Pressed(2));
+        k.push(KeyEvent::Pressed(2));
+        k.push(KeyEvent::Released(2));
+        k.push(KeyEvent::Pressed(0x10));
+        k.switch(5);
+        assert!(!k.get(2));
+        assert!(k.get(5));
+        assert_eq!(Some(KeyEvent::Pressed(2)), k.pop_event());
+        assert_eq!(Some(KeyEvent::Pressed(2)), k.pop_event());
+        assert_eq!(Some(KeyEvent::Released(2)), k.pop_event());
+        assert_eq!(Some(KeyEvent::Pressed(5)), k.pop_event());
+        assert_eq!(None, k.pop_event());
+    }
+
+    #[test]
+    fn down_keys_test() {
+        let mut k = Keyboard::new();
+        k.push(KeyEvent::Pressed(0xA));
+        k.push(KeyEvent::Pressed(1));
+        assert_eq!(vec![1, 0xA], k.down_keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn keymap_test() {
+        let m = KeyMap::default();
+        assert_eq!(Some(0xC), m.get(HostKey::Char('4')));
+        assert_eq!(Some(0xF), m.get(HostKey::Char('V')));
+        assert_eq!(Some(0x0), m.get(HostKey::Scancode(0x2D)));
+        assert_eq!(None, m.get(HostKey::Char('y')));
+        assert_eq!(
+            Some(KeyEvent::Released(0x5)),
+            m.event(HostKey::Char('w'), false)
+        );
+        let keys: Vec<usize> = "1234qwerasdfzxcv"
+            .chars()
+            .filter_map(|c| m.get(HostKey::Char(c)))
+            .collect();
+        assert_eq!(QWERTY_KEYS.to_vec(), keys);
+
+        let mut config = KeyMapConfig {
+            chars: vec![('k', 0x5)],
+            scancodes: vec![(57, 0xA)],
+        };
+        let mut m = KeyMap::from_config(&config).unwrap();
+        assert_eq!(Some(0x5), m.get(HostKey::Char('K')));
+        assert_eq!(Some(0xA), m.get(HostKey::Scancode(57)));
+        assert_eq!(None, m.get(HostKey::Char('1')));
+        assert_eq!(Some(0x5), m.unbind(HostKey::Char('k')));
+        assert_eq!(None, m.get(HostKey::Char('k')));
+        config.chars.push(('j', 0x10));
+        assert!(KeyMap::from_config(&config).is_err());
+    }
+
     #[test]
     fn down_key_test() {
         let mut k = Keyboard::new();
@@ -0,0 +1,144 @@
+//! Per-instruction record of changed state, see
+//! [`Emulator::enable_journal`](crate::emulator::Emulator::enable_journal)
+//!
+//! Unlike [`StateDiff`](crate::diff::StateDiff), which compares whole
+//! snapshots, the journal is filled as instructions execute, so debugger
+//! views can highlight what the last step changed without taking
+//! snapshots themselves.
+use crate::cpu::{Addr, Opcode, CPU};
+use crate::diff::{self, Change, Register};
+use std::collections::VecDeque;
+
+/// Changes made by one executed instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// address of the instruction
+    pub pc: Addr,
+    pub op: Opcode,
+    /// registers other than pc (V0..VF first), then memory bytes
+    /// ([`Register::Mem`]) in ascending address order
+    pub changes: Vec<Change>,
+}
+
+impl Entry {
+    pub(crate) fn new(before: &CPU, op: Opcode, after: &CPU, memory: Vec<Change>) -> Self {
+        let mut changes = diff::register_changes(before, after);
+        changes.extend(memory);
+        Entry {
+            pc: before.pc,
+            op,
+            changes,
+        }
+    }
+
+    /// Whether the instruction changed reg
+    pub fn changed(&self, reg: Register) -> bool {
+        self.changes.iter().any(|c| c.reg == reg)
+    }
+}
+
+/// Entries of at most capacity most recently executed instructions
+#[derive(Debug, Clone)]
+pub struct Journal {
+    capacity: usize,
+    entries: VecDeque<Entry>,
+}
+
+impl Journal {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Journal {
+            capacity,
+            entries: VecDeque::with_capacity(capacity.min(1024)),
+        }
+    }
+
+    pub(crate) fn push(&mut self, entry: Entry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Entry of most recently executed instruction
+    pub fn last(&self) -> Option<&Entry> {
+        self.entries.back()
+    }
+
+    /// Entries oldest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Entry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn journal_test() {
+        let mut e = Emulator::new();
+        assert!(e.journal().is_none());
+        e.enable_journal(2);
+        // LD V0, 0x7B; LD I, 0x300; LD B, V0; JP 0x206
+        e.store_instr(&[0x607B, 0xA300, 0xF033, 0x1206]);
+        e.mem.store(0x301, 2);
+        e.step().unwrap();
+        let last = e.journal().unwrap().last().unwrap();
+        assert_eq!((0x200, Opcode::LD(0, 0x7B)), (last.pc, last.op));
+        assert!(last.changed(Register::V(0)));
+        e.step().unwrap();
+        e.step().unwrap();
+        let journal = e.journal().unwrap();
+        assert_eq!(2, journal.len());
+        let first = journal.iter().next().unwrap();
+        assert_eq!(Opcode::LDI(0x300), first.op);
+        // 0x301 already held 2
+        let bcd: Vec<String> = journal
+            .last()
+            .unwrap()
+            .changes
+            .iter()
+            .map(Change::to_string)
+            .collect();
+        assert_eq!(vec!["[0x300]=0x00->0x01", "[0x302]=0x00->0x03"], bcd);
+        e.step().unwrap();
+        assert!(e.journal().unwrap().last().unwrap().changes.is_empty());
+        e.disable_journal();
+        assert!(e.journal().is_none());
+    }
+
+    #[test]
+    fn key_wait_test() {
+        let mut e = Emulator::new();
+        e.enable_journal(8);
+        // LD V3, K
+        e.store_instr(&[0xF30A]);
+        e.step().unwrap();
+        assert!(e.journal().unwrap().last().unwrap().changes.is_empty());
+        e.key_pressed(None, 5);
+        e.step().unwrap();
+        e.key_released();
+        e.step().unwrap();
+        let journal = e.journal().unwrap();
+        assert_eq!(2, journal.len());
+        let last = journal.last().unwrap();
+        assert_eq!((0x200, Opcode::KEYSET(3)), (last.pc, last.op));
+        assert!(last.changed(Register::V(3)));
+    }
+}
@@ -1,9 +1,49 @@
+pub mod asm;
+pub mod builder;
+pub mod c8b;
+pub mod clock;
+pub mod compare;
+pub mod corpus;
+pub mod coverage;
 pub mod cpu;
+pub mod debug;
+pub mod diff;
+pub mod disasm;
 pub mod display;
 pub mod emulator;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod flags;
+#[cfg(feature = "gdb")]
+pub mod gdb;
+pub mod handle;
+mod hash;
+pub mod heatmap;
+pub mod hook;
+pub mod ihex;
 pub mod input;
+pub mod journal;
 pub mod loader;
 pub mod mem;
+pub mod opinfo;
+#[cfg(feature = "png")]
+mod png;
+pub mod profile;
+pub mod quirks;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod replay;
+pub mod rewind;
+pub mod rng;
+pub mod romdb;
+pub mod savestate;
+pub mod sound;
+pub mod stats;
+pub mod testing;
+#[cfg(feature = "trace")]
+pub mod trace;
+pub mod validate;
 
 #[cfg(test)]
 /// Tests
@@ -11,6 +51,7 @@ pub mod mem;
 mod tests {
     use super::cpu;
     use super::emulator;
+    use super::error::Chip8Error;
     use super::mem;
 
     #[test]
@@ -66,7 +107,11 @@ mod tests {
 
         e.store_instr(&[cpu::Opcode::JP(0x0123).to_instr()]);
 
-        e.run();
+        let end = Chip8Error::InvalidOpcode {
+            addr: 0x123,
+            raw: 0,
+        };
+        assert_eq!(Err(end), e.run());
         assert_eq!(e.cpu.pc, 0x0123);
     }
 
@@ -79,7 +124,11 @@ mod tests {
             cpu::Opcode::CLS,
             cpu::Opcode::RET,
         ]);
-        e.run();
+        let end = Chip8Error::InvalidOpcode {
+            addr: 0x209,
+            raw: 0,
+        };
+        assert_eq!(Err(end), e.run());
         assert_eq!(
             e.mem.get(0x200..=0x208),
             Some(&[0x22, 0x04, 0x12, 0x09, 0x00, 0xE0, 0x00, 0xEE, 0x00][..])
@@ -1,18 +1,387 @@
+use crate::c8b::{self, C8b, C8bError};
+use crate::cpu::Addr;
 use crate::emulator::Emulator;
-use std::fs;
+use crate::hash;
+use crate::ihex::{self, IhexError};
+use crate::mem::Mem;
+use crate::quirks::Profile;
+use crate::validate::{self, Report};
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
+use std::io;
 use std::io::Read;
+use std::path::Path;
 
-pub fn load(e: &mut Emulator, fname: &String) {
-    let bytes: Vec<u8> = get_file_as_byte_vec(fname);
-    e.store_bytes(&bytes[..]);
+/// Failures while loading ROM into emulator memory
+#[derive(Debug)]
+pub enum LoaderError {
+    /// reading ROM failed
+    Io(io::Error),
+    /// ROM of given size does not fit in max bytes available from start address
+    TooLarge { size: usize, max: usize },
+    /// Intel HEX file is invalid or holds data past the end of memory
+    Ihex(IhexError),
+    /// c8b container cannot be loaded, e.g. holds no program
+    C8b(C8bError),
 }
 
-fn get_file_as_byte_vec(filename: &String) -> Vec<u8> {
-    let mut f = File::open(&filename).expect("no file found");
-    let metadata = fs::metadata(&filename).expect("unable to read metadata");
-    let mut buffer = vec![0; metadata.len() as usize];
-    f.read(&mut buffer).expect("buffer overflow");
+impl fmt::Display for LoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoaderError::Io(e) => write!(f, "cannot read ROM: {}", e),
+            LoaderError::TooLarge { size, max } => {
+                write!(f, "ROM of {} bytes exceeds {} bytes of memory", size, max)
+            }
+            LoaderError::Ihex(e) => write!(f, "invalid Intel HEX file: {}", e),
+            LoaderError::C8b(e) => write!(f, "invalid c8b container: {}", e),
+        }
+    }
+}
+
+impl Error for LoaderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoaderError::Io(e) => Some(e),
+            LoaderError::TooLarge { .. } => None,
+            LoaderError::Ihex(e) => Some(e),
+            LoaderError::C8b(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for LoaderError {
+    fn from(e: io::Error) -> Self {
+        LoaderError::Io(e)
+    }
+}
+
+impl From<IhexError> for LoaderError {
+    fn from(e: IhexError) -> Self {
+        LoaderError::Ihex(e)
+    }
+}
+
+impl From<C8bError> for LoaderError {
+    fn from(e: C8bError) -> Self {
+        LoaderError::C8b(e)
+    }
+}
+
+/// Size and checksums of a ROM, identifying it e.g. in
+/// [`crate::romdb::RomDb`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomInfo {
+    pub size: usize,
+    pub sha1: [u8; 20],
+    pub crc32: u32,
+}
+
+impl RomInfo {
+    pub fn of(bytes: &[u8]) -> Self {
+        RomInfo {
+            size: bytes.len(),
+            sha1: hash::sha1(bytes),
+            crc32: hash::crc32(bytes),
+        }
+    }
+
+    /// SHA-1 as lowercase hex digits
+    pub fn sha1_hex(&self) -> String {
+        self.sha1.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Loads ROM file at start address; returns its size. Files with `.hex`
+/// extension are loaded with [`load_ihex`].
+pub fn load<P: AsRef<Path>>(e: &mut Emulator, path: P) -> Result<usize, LoaderError> {
+    let path = path.as_ref();
+    let is_hex = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("hex"));
+    if is_hex {
+        return load_ihex(e, &std::fs::read_to_string(path)?);
+    }
+    load_from_reader(e, File::open(path)?)
+}
+
+/// Loads ROM read until end of reader at start address; returns its size
+pub fn load_from_reader<R: Read>(e: &mut Emulator, mut reader: R) -> Result<usize, LoaderError> {
+    let max = max_rom_size(e);
+    let mut bytes = vec![];
+    // one byte over the limit is enough to tell the ROM is too large
+    (&mut reader).take(max as u64 + 1).read_to_end(&mut bytes)?;
+    if c8b::is_c8b(&bytes) {
+        // containers may hold programs for several platforms
+        let rest = MAX_CONTAINER_SIZE.saturating_sub(bytes.len());
+        reader.take(rest as u64).read_to_end(&mut bytes)?;
+    }
+    load_from_bytes(e, &bytes)
+}
+
+/// Loads ROM at start address; returns its size. Valid c8b containers
+/// are loaded with [`load_c8b`], anything else as raw bytes.
+pub fn load_from_bytes(e: &mut Emulator, bytes: &[u8]) -> Result<usize, LoaderError> {
+    if let Ok(c) = C8b::parse(bytes) {
+        return load_c8b(e, &c);
+    }
+    check_size(e, bytes.len())?;
+    e.store_bytes(bytes);
+    Ok(bytes.len())
+}
+
+/// Loads first program of container, switching to the profile of its
+/// platform and to speed and start address given by metadata; returns
+/// program size. Memory of another size than the platform's is replaced
+/// by blank memory of that size, keeping only the font. Leaves the
+/// emulator untouched if the program does not fit.
+pub fn load_c8b(e: &mut Emulator, c: &C8b) -> Result<usize, LoaderError> {
+    let program = c.first_program()?;
+    let start = c.metadata.start_addr.unwrap_or_else(|| e.start_addr());
+    let mem_size = program.platform.memory_size();
+    let max = mem_size.saturating_sub(start as usize);
+    if program.bytes.len() > max {
+        return Err(LoaderError::TooLarge {
+            size: program.bytes.len(),
+            max,
+        });
+    }
+    if e.mem.size() != mem_size {
+        e.resize_mem(mem_size);
+    }
+    e.quirks = program.platform.quirks();
+    e.stack_limit = program.platform.stack_limit();
+    e.two_page_hires &= program.platform == Profile::CosmacVip;
+    if let Some(t) = c.metadata.ticks_per_frame {
+        e.set_ips(t as u32 * 60);
+    }
+    e.set_start_addr(start);
+    e.store_bytes(&program.bytes);
+    Ok(program.bytes.len())
+}
+
+/// Loads memory image in Intel HEX format; returns size of the program,
+/// which spans data from start address to the end of the last record.
+/// Data below start address (e.g. a custom font) is stored as is. Leaves
+/// the emulator untouched if text is invalid or data does not fit.
+pub fn load_ihex(e: &mut Emulator, text: &str) -> Result<usize, LoaderError> {
+    let runs = ihex::decode(text)?;
+    let size = e.mem.size() as u64;
+    let run_end = |(a, d): &(u32, Vec<u8>)| *a as u64 + d.len() as u64;
+    if let Some(run) = runs.iter().find(|&run| run_end(run) > size) {
+        let addr = run.0.max(size as u32);
+        return Err(LoaderError::Ihex(IhexError::OutOfRange { addr }));
+    }
+    // all runs end within memory from here on
+    let start = e.start_addr() as u32;
+    let end = runs
+        .iter()
+        .map(|run| run_end(run) as u32)
+        .filter(|&end| end > start)
+        .max()
+        .unwrap_or(start);
+    let mut program = vec![0; (end - start) as usize];
+    for (addr, data) in &runs {
+        for (i, b) in data.iter().enumerate() {
+            let a = addr + i as u32;
+            if a >= start {
+                program[(a - start) as usize] = *b;
+            } else {
+                e.mem.store(a as Addr, *b);
+            }
+        }
+    }
+    e.store_bytes(&program);
+    Ok(program.len())
+}
+
+/// Fails if ROM of size bytes does not fit in memory from start address
+pub(crate) fn check_size(e: &Emulator, size: usize) -> Result<(), LoaderError> {
+    let max = max_rom_size(e);
+    if size > max {
+        return Err(LoaderError::TooLarge { size, max });
+    }
+    Ok(())
+}
+
+/// Whether ROM starts with the `JP 0x260` trampoline of two-page hi-res
+/// chip-8 programs, which need a 64x64 screen (see
+/// [`Emulator::two_page_hires`]); ordinary ROMs may start with the same
+/// jump, so frontends should only act on it for COSMAC VIP programs
+pub fn is_two_page_hires(bytes: &[u8]) -> bool {
+    bytes.starts_with(&Emulator::TWO_PAGE_TRAMPOLINE.to_be_bytes())
+}
+
+/// Statically checks ROM loading at default start address for problems
+/// that would show up at run time, see [`validate::Issue`]
+pub fn validate(bytes: &[u8]) -> Report {
+    validate_at(bytes, Emulator::DEFAULT_START_ADDR)
+}
+
+/// Like [`validate`], for ROM loading at base
+pub fn validate_at(bytes: &[u8], base: Addr) -> Report {
+    validate::check_rom(bytes, base, Mem::new().size())
+}
+
+/// Largest c8b container read by [`load_from_reader`]
+const MAX_CONTAINER_SIZE: usize = 0x10000;
+
+/// Bytes of memory from start address to its end
+fn max_rom_size(e: &Emulator) -> usize {
+    e.mem.size().saturating_sub(e.start_addr() as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug::StopReason;
+
+    #[test]
+    fn load_from_bytes_test() {
+        let mut e = Emulator::new();
+        assert_eq!(3, load_from_bytes(&mut e, &[1, 2, 3]).unwrap());
+        assert_eq!(Some(&[1, 2, 3][..]), e.mem.get(0x200..0x203));
+        assert_eq!(0x200, e.cpu.pc);
+    }
+
+    #[test]
+    fn load_c8b_test() {
+        use crate::c8b::{Metadata, Program};
+        use crate::quirks::Profile;
+
+        let c = C8b {
+            metadata: Metadata {
+                ticks_per_frame: Some(15),
+                ..Metadata::default()
+            },
+            programs: vec![Program {
+                platform: Profile::CosmacVip,
+                bytes: vec![0x61, 0x02, 0x00, 0xFD],
+            }],
+        };
+        let mut e = Emulator::new();
+        let bytes = c.to_bytes().unwrap();
+        assert_eq!(4, load_from_reader(&mut e, &bytes[..]).unwrap());
+        assert_eq!((900, 12), (e.ips(), e.stack_limit));
+        assert_eq!(Profile::CosmacVip.quirks(), e.quirks);
+        assert_eq!(Ok(StopReason::Halted), e.run());
+        assert_eq!(2, e.cpu.regs[1]);
+
+        // broken container loads as raw bytes
+        let raw = &bytes[..6];
+        assert_eq!(6, load_from_bytes(&mut e, raw).unwrap());
+
+        let big = C8b {
+            metadata: Metadata::default(),
+            programs: vec![Program {
+                platform: Profile::XoChip,
+                bytes: vec![0; 0x1000],
+            }],
+        };
+        let mut e = Emulator::builder().build();
+        assert_eq!(0x1000, load_c8b(&mut e, &big).unwrap());
+        assert_eq!((Mem::MAX_SIZE, Some(&0xF0)), (e.mem.size(), e.mem.get(0)));
+        assert!(load_c8b(&mut e, &c).is_ok());
+        assert_eq!(Mem::DEFAULT_SIZE, e.mem.size());
+        let empty = C8b {
+            programs: vec![],
+            ..c
+        };
+        assert!(matches!(
+            load_c8b(&mut e, &empty),
+            Err(LoaderError::C8b(C8bError::NoProgram))
+        ));
+    }
+
+    #[test]
+    fn load_ihex_test() {
+        let mut e = Emulator::new();
+        // font byte at 0x000; LD V0, 1; JP 0x206; gap; EXIT
+        let text = ":01000000AA55\n:040200006001120681\n:0202060000FDF9\n:00000001FF\n";
+        assert_eq!(8, load_ihex(&mut e, text).unwrap());
+        assert_eq!(0xAA, e.mem.load(0));
+        assert_eq!(
+            Some(&[0x60, 0x01, 0x12, 0x06, 0, 0, 0x00, 0xFD][..]),
+            e.mem.get(0x200..0x208)
+        );
+        assert_eq!(Ok(StopReason::Halted), e.run());
+        assert_eq!(1, e.cpu.regs[0]);
+
+        let path = std::env::temp_dir().join("libchip8_load_ihex_test.HEX");
+        std::fs::write(&path, e.mem.to_ihex(0x200..0x208)).unwrap();
+        let mut copy = Emulator::new();
+        assert_eq!(8, load(&mut copy, &path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(e.mem.get(0x200..0x208), copy.mem.get(0x200..0x208));
+
+        let err = load_ihex(&mut e, ":00000001FE").unwrap_err();
+        assert!(matches!(
+            err,
+            LoaderError::Ihex(IhexError::Checksum { line: 1 })
+        ));
+        assert!(err.source().is_some());
+        let past_4g = ":02000004FFFFFC\n:01FFFF00AA57\n:01000000BB44\n:00000001FF\n";
+        assert!(matches!(
+            load_ihex(&mut e, past_4g),
+            Err(LoaderError::Ihex(IhexError::OutOfRange { .. }))
+        ));
+        let past_end = ":020000040000FA\n:01FFFF00AA57\n:00000001FF\n";
+        assert!(matches!(
+            load_ihex(&mut e, past_end),
+            Err(LoaderError::Ihex(IhexError::OutOfRange { addr: 0xFFFF }))
+        ));
+    }
+
+    #[test]
+    fn rom_info_test() {
+        let info = RomInfo::of(b"123456789");
+        assert_eq!((9, 0xCBF4_3926), (info.size, info.crc32));
+        assert_eq!("f7c3bc1d808e04732adf679965ccc34ca7ae3441", info.sha1_hex());
+    }
+
+    #[test]
+    fn too_large_test() {
+        let mut e = Emulator::new();
+        assert_eq!(0xE00, load_from_bytes(&mut e, &[0xAA; 0xE00]).unwrap());
+        let err = load_from_reader(&mut e, &[0u8; 0x1000][..]).unwrap_err();
+        assert!(matches!(
+            err,
+            LoaderError::TooLarge {
+                size: 0xE01,
+                max: 0xE00
+            }
+        ));
+    }
+
+    #[test]
+    fn start_addr_test() {
+        let mut e = Emulator::builder()
+            .start_addr(Emulator::ETI660_START_ADDR)
+            .build();
+        // LD V0, 7; JP 0x606; (skipped); EXIT
+        let rom = [0x60, 0x07, 0x16, 0x06, 0x60, 0x09, 0x00, 0xFD];
+        assert_eq!(8, load_from_bytes(&mut e, &rom).unwrap());
+        assert_eq!(Some(&rom[..]), e.mem.get(0x600..0x608));
+        assert_eq!(0x600, e.cpu.pc);
+        assert_eq!(Ok(StopReason::Halted), e.run());
+        assert_eq!(7, e.cpu.regs[0]);
+        let err = load_from_bytes(&mut e, &[0; 0xA01]).unwrap_err();
+        assert!(matches!(err, LoaderError::TooLarge { max: 0xA00, .. }));
+    }
+
+    #[test]
+    fn validate_test() {
+        assert!(validate(&[0x00, 0xFD]).is_clean());
+        let report = validate_at(&[0x00, 0xEE], 0x600);
+        assert_eq!(1, report.issues.len());
+        assert_eq!("0x600: RET without CALL\n", report.to_string());
+    }
 
-    buffer
+    #[test]
+    fn missing_file_test() {
+        let mut e = Emulator::new();
+        let err = load(&mut e, "no/such/rom.ch8").unwrap_err();
+        assert!(matches!(err, LoaderError::Io(_)));
+        assert!(err.source().is_some());
+    }
 }
@@ -1,6 +1,13 @@
 use crate::cpu::Addr;
+use crate::error::Chip8Error;
+use crate::ihex::{self, IhexError};
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
 use std::slice::SliceIndex;
 
+/// Font common to most modern interpreters
 const FONT: [[u8; 5]; 16] = [
     [0xF0, 0x90, 0x90, 0x90, 0xF0],
     [0x20, 0x60, 0x20, 0x20, 0x70],
@@ -20,27 +27,456 @@ const FONT: [[u8; 5]; 16] = [
     [0xF0, 0x80, 0xF0, 0x80, 0x80],
 ];
 
+/// SUPER-CHIP 8x10 digits
+const LARGE_FONT: [[u8; 10]; 16] = [
+    [0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF],
+    [0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF],
+    [0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03],
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18],
+    [0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF],
+    [0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3],
+    [0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC],
+    [0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C],
+    [0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC],
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0],
+];
+
+/// COSMAC VIP font
+const VIP_FONT: [[u8; 5]; 16] = [
+    [0xF0, 0x90, 0x90, 0x90, 0xF0],
+    [0x60, 0x20, 0x20, 0x20, 0x70],
+    [0xF0, 0x10, 0xF0, 0x80, 0xF0],
+    [0xF0, 0x10, 0xF0, 0x10, 0xF0],
+    [0xA0, 0xA0, 0xF0, 0x20, 0x20],
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0],
+    [0xF0, 0x80, 0xF0, 0x90, 0xF0],
+    [0xF0, 0x10, 0x10, 0x10, 0x10],
+    [0xF0, 0x90, 0xF0, 0x90, 0xF0],
+    [0xF0, 0x90, 0xF0, 0x10, 0xF0],
+    [0xF0, 0x90, 0xF0, 0x90, 0x90],
+    [0xF0, 0x50, 0x70, 0x50, 0xF0],
+    [0xF0, 0x80, 0x80, 0x80, 0xF0],
+    [0xF0, 0x50, 0x50, 0x50, 0xF0],
+    [0xF0, 0x80, 0xF0, 0x80, 0xF0],
+    [0xF0, 0x80, 0xF0, 0x80, 0x80],
+];
+
+/// DREAM 6800 font, 3 pixels wide
+const DREAM_6800_FONT: [[u8; 5]; 16] = [
+    [0xE0, 0xA0, 0xA0, 0xA0, 0xE0],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0xE0, 0x20, 0xE0, 0x80, 0xE0],
+    [0xE0, 0x20, 0xE0, 0x20, 0xE0],
+    [0x80, 0xA0, 0xA0, 0xE0, 0x20],
+    [0xE0, 0x80, 0xE0, 0x20, 0xE0],
+    [0xE0, 0x80, 0xE0, 0xA0, 0xE0],
+    [0xE0, 0x20, 0x20, 0x20, 0x20],
+    [0xE0, 0xA0, 0xE0, 0xA0, 0xE0],
+    [0xE0, 0xA0, 0xE0, 0x20, 0xE0],
+    [0xE0, 0xA0, 0xE0, 0xA0, 0xA0],
+    [0xC0, 0xA0, 0xE0, 0xA0, 0xC0],
+    [0xE0, 0x80, 0x80, 0x80, 0xE0],
+    [0xC0, 0xA0, 0xA0, 0xA0, 0xC0],
+    [0xE0, 0x80, 0xE0, 0x80, 0xE0],
+    [0xE0, 0x80, 0xC0, 0x80, 0x80],
+];
+
+/// ETI-660 font, 3 pixels wide
+const ETI_660_FONT: [[u8; 5]; 16] = [
+    [0xE0, 0xA0, 0xA0, 0xA0, 0xE0],
+    [0x20, 0x20, 0x20, 0x20, 0x20],
+    [0xE0, 0x20, 0xE0, 0x80, 0xE0],
+    [0xE0, 0x20, 0xE0, 0x20, 0xE0],
+    [0xA0, 0xA0, 0xE0, 0x20, 0x20],
+    [0xE0, 0x80, 0xE0, 0x20, 0xE0],
+    [0xE0, 0x80, 0xE0, 0xA0, 0xE0],
+    [0xE0, 0x20, 0x20, 0x20, 0x20],
+    [0xE0, 0xA0, 0xE0, 0xA0, 0xE0],
+    [0xE0, 0xA0, 0xE0, 0x20, 0xE0],
+    [0xE0, 0xA0, 0xE0, 0xA0, 0xA0],
+    [0xC0, 0xA0, 0xC0, 0xA0, 0xC0],
+    [0xE0, 0x80, 0x80, 0x80, 0xE0],
+    [0xC0, 0xA0, 0xA0, 0xA0, 0xC0],
+    [0xE0, 0x80, 0xE0, 0x80, 0xE0],
+    [0xE0, 0x80, 0xC0, 0x80, 0x80],
+];
+
+/// Digit sprites stored by [`Mem::store_font_set`]: 5 rows per small digit
+/// (FX29) and 10 rows per large digit (FX30)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FontSet {
+    pub small: [[u8; 5]; 16],
+    pub large: [[u8; 10]; 16],
+}
+
+impl FontSet {
+    /// Font used by most modern interpreters, stored by [`Mem::store_font`]
+    pub const DEFAULT: FontSet = FontSet::new(FONT);
+    pub const VIP: FontSet = FontSet::new(VIP_FONT);
+    pub const DREAM_6800: FontSet = FontSet::new(DREAM_6800_FONT);
+    pub const ETI_660: FontSet = FontSet::new(ETI_660_FONT);
+
+    /// Small digits with SUPER-CHIP large digits
+    pub const fn new(small: [[u8; 5]; 16]) -> Self {
+        FontSet {
+            small,
+            large: LARGE_FONT,
+        }
+    }
+
+    pub const fn with_large(self, large: [[u8; 10]; 16]) -> Self {
+        FontSet {
+            small: self.small,
+            large,
+        }
+    }
+}
+
+impl Default for FontSet {
+    fn default() -> Self {
+        FontSet::DEFAULT
+    }
+}
+
+/// How instructions accessing memory past its end are handled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AddressPolicy {
+    /// addresses wrap around to the start of memory
+    #[default]
+    Wrap,
+    /// access fails with [`Chip8Error::AddressOutOfRange`]
+    Error,
+}
+
+/// How executed instructions writing protected memory are handled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ProtectMode {
+    /// nothing is protected
+    #[default]
+    Off,
+    /// the write happens and
+    /// [`Event::ProtectedWrite`](crate::emulator::Event::ProtectedWrite)
+    /// is queued, e.g. to find self-modifying code
+    Report,
+    /// the instruction fails with [`Chip8Error::WriteProtected`] without
+    /// writing anything
+    Error,
+}
+
+/// Memory kept read-only for executed instructions, see
+/// [`Emulator::write_protect`](crate::emulator::Emulator::write_protect)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WriteProtect {
+    pub mode: ProtectMode,
+    /// also protect the bytes of the loaded program (see
+    /// [`Mem::set_program`]), besides the interpreter area below the start
+    /// address holding the fonts
+    pub rom: bool,
+}
+
+/// Part of memory an address belongs to, see [`Mem::region`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// small digits stored by [`Mem::store_font_set`]
+    Font,
+    /// SUPER-CHIP 8x10 digits stored by [`Mem::store_font_set`]
+    LargeFont,
+    /// bytes of last loaded program, see [`Mem::set_program`]
+    Program,
+    /// anything else
+    Free,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Region::Font => "font",
+            Region::LargeFont => "large font",
+            Region::Program => "program",
+            Region::Free => "",
+        })
+    }
+}
+
+/// Device taking over memory accesses in a range, see [`Mem::map_io`]
+pub trait IoHandler {
+    /// Returns byte read from addr
+    fn read(&mut self, addr: Addr) -> u8;
+    /// Handles value written to addr
+    fn write(&mut self, addr: Addr, value: u8);
+}
+
+/// Handle identifying mapped [`IoHandler`]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct IoId(usize);
+
+#[derive(Clone)]
+struct IoMapping {
+    id: IoId,
+    range: Range<Addr>,
+    handler: Rc<RefCell<Box<dyn IoHandler>>>,
+}
+
+/// Storage of memory cells
+#[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
+enum Cells {
+    /// default size, kept inline so that no allocation is needed
+    Inline([u8; Mem::DEFAULT_SIZE]),
+    Heap(Box<[u8]>),
+}
+
+impl std::ops::Deref for Cells {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Cells::Inline(a) => a,
+            Cells::Heap(b) => b,
+        }
+    }
+}
+
+impl std::ops::DerefMut for Cells {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            Cells::Inline(a) => a,
+            Cells::Heap(b) => b,
+        }
+    }
+}
+
 pub struct Mem {
-    cells: [u8; 4096],
+    cells: Cells,
     start_addr: Addr,
+    /// set stored at start_addr, if any
+    font: Option<FontSet>,
+    program: Option<(usize, usize)>,
+    policy: AddressPolicy,
+    /// lowest and highest address written since last `take_writes`
+    written: Option<(usize, usize)>,
+    io: Vec<IoMapping>,
+    next_io_id: usize,
 }
 
 impl Mem {
     const FONT_SIZE_BYTES: u16 = 5;
+    const LARGE_FONT_SIZE_BYTES: u16 = 10;
+
+    /// Size of original chip-8 memory, used by [`Mem::new`]
+    pub const DEFAULT_SIZE: usize = 4096;
+
+    /// Largest size addressable by [`Addr`], used by XO-CHIP
+    pub const MAX_SIZE: usize = 0x10000;
 
+    /// Memory of [`Mem::DEFAULT_SIZE`] bytes stored inline
     pub fn new() -> Self {
+        Mem::from_cells(Cells::Inline([0; Mem::DEFAULT_SIZE]))
+    }
+
+    /// Heap-allocated memory of size bytes; addresses past the end wrap
+    /// around (or fail, see [`AddressPolicy`]) at size instead of 4096.
+    ///
+    /// Panics if size is 0 or over [`Mem::MAX_SIZE`].
+    pub fn with_size(size: usize) -> Self {
+        assert!(
+            size > 0 && size <= Mem::MAX_SIZE,
+            "memory size {} not in 1..={}",
+            size,
+            Mem::MAX_SIZE
+        );
+        Mem::from_cells(Cells::Heap(vec![0; size].into_boxed_slice()))
+    }
+
+    fn from_cells(cells: Cells) -> Self {
+        let size = cells.len();
         Mem {
-            cells: [0; 4096],
+            cells,
             start_addr: 0x0000,
+            font: None,
+            program: None,
+            policy: AddressPolicy::Wrap,
+            written: Some((0, size - 1)),
+            io: vec![],
+            next_io_id: 0,
+        }
+    }
+
+    /// Zeroed memory of the same size and storage
+    pub(crate) fn blank(&self) -> Self {
+        match self.cells {
+            Cells::Inline(_) => Mem::new(),
+            Cells::Heap(_) => Mem::with_size(self.cells.len()),
         }
     }
 
+    /// Whether cells are heap-allocated, see [`Mem::with_size`]
+    pub fn is_heap(&self) -> bool {
+        matches!(self.cells, Cells::Heap(_))
+    }
+
+    /// Delegates [`Mem::load`], [`Mem::store`] and their checked variants
+    /// for addresses in range to handler, so that instructions reading and
+    /// writing there talk to it. Cells in range keep their contents and are
+    /// still returned by [`Mem::get`] and [`Mem::inspect`]; an address in
+    /// several ranges is handled by the one mapped last.
+    pub fn map_io(&mut self, range: Range<Addr>, handler: Box<dyn IoHandler>) -> IoId {
+        let id = IoId(self.next_io_id);
+        self.next_io_id += 1;
+        self.mark_mapped(&range);
+        self.io.push(IoMapping {
+            id,
+            range,
+            handler: Rc::new(RefCell::new(handler)),
+        });
+        id
+    }
+
+    /// Removes mapping; returns false if id was not mapped
+    pub fn unmap_io(&mut self, id: IoId) -> bool {
+        match self.io.iter().position(|m| m.id == id) {
+            Some(idx) => {
+                let m = self.io.remove(idx);
+                self.mark_mapped(&m.range);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// True when accesses to addr are delegated to a mapped handler
+    pub(crate) fn is_io(&self, addr: Addr) -> bool {
+        let idx = addr as usize % self.cells.len();
+        self.io_handler(idx as Addr).is_some()
+    }
+
+    /// Reports range as written, as its contents change with the mapping
+    fn mark_mapped(&mut self, range: &Range<Addr>) {
+        let last = self.cells.len() - 1;
+        if range.start < range.end {
+            let lo = (range.start as usize).min(last);
+            self.mark_written(lo, (range.end as usize - 1).min(last));
+        }
+    }
+
+    /// Takes over policy and io mappings of memory this one replaces
+    pub(crate) fn inherit(&mut self, old: &Mem) {
+        self.policy = old.policy;
+        self.io = old.io.clone();
+        self.next_io_id = old.next_io_id;
+    }
+
+    fn io_handler(&self, addr: Addr) -> Option<&RefCell<Box<dyn IoHandler>>> {
+        self.io
+            .iter()
+            .rev()
+            .find(|m| m.range.contains(&addr))
+            .map(|m| &*m.handler)
+    }
+
+    fn mark_written(&mut self, lo: usize, hi: usize) {
+        self.written = Some(match self.written {
+            Some((l, h)) => (l.min(lo), h.max(hi)),
+            None => (lo, hi),
+        });
+    }
+
+    /// Range of addresses written since previous call, used to invalidate
+    /// decoded instructions; new and cloned memory reports all addresses,
+    /// mapping and unmapping io reports its range
+    pub(crate) fn take_writes(&mut self) -> Option<(usize, usize)> {
+        self.written.take()
+    }
+
+    /// Addressing policy applied by the emulator to instruction memory access
+    pub fn policy(&self) -> AddressPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: AddressPolicy) {
+        self.policy = policy;
+    }
+
+    /// Checks that len bytes from addr may be accessed under current policy
+    pub fn check_range(&self, addr: Addr, len: usize) -> Result<(), Chip8Error> {
+        match self.policy {
+            AddressPolicy::Wrap => Ok(()),
+            AddressPolicy::Error => self.bounds(addr, len).map(|_| ()),
+        }
+    }
+
+    fn bounds(&self, addr: Addr, len: usize) -> Result<std::ops::Range<usize>, Chip8Error> {
+        let start = addr as usize;
+        if start + len > self.cells.len() {
+            // first address past the end, unless it is not addressable
+            let addr = match start.max(self.cells.len()) {
+                past if past > Addr::MAX as usize => start,
+                past => past,
+            } as Addr;
+            Err(Chip8Error::AddressOutOfRange { addr })
+        } else {
+            Ok(start..start + len)
+        }
+    }
+
+    /// Loads byte, failing for addresses past the end regardless of policy
+    pub fn checked_load(&self, i: Addr) -> Result<u8, Chip8Error> {
+        self.bounds(i, 1).map(|_| self.load(i))
+    }
+
+    /// Stores byte, failing for addresses past the end regardless of policy
+    pub fn checked_store(&mut self, i: Addr, v: u8) -> Result<(), Chip8Error> {
+        self.bounds(i, 1)?;
+        self.store(i, v);
+        Ok(())
+    }
+
+    /// Copies slice to addr; fails without writing anything if it does not fit
+    pub fn write_slice(&mut self, addr: Addr, v: &[u8]) -> Result<(), Chip8Error> {
+        let r = self.bounds(addr, v.len())?;
+        if !v.is_empty() {
+            self.mark_written(r.start, r.end - 1);
+        }
+        self.cells[r].copy_from_slice(v);
+        Ok(())
+    }
+
+    pub fn get_mut<I>(&mut self, index: I) -> Option<&mut <I as SliceIndex<[u8]>>::Output>
+    where
+        I: SliceIndex<[u8]>,
+    {
+        self.mark_written(0, self.cells.len() - 1);
+        self.cells.get_mut(index)
+    }
+
+    /// Number of addressable bytes
+    pub fn size(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Stores byte at given address; addresses past the end wrap around
     pub fn store(&mut self, i: Addr, v: u8) {
-        self.cells[i as usize] = v;
+        let idx = i as usize % self.cells.len();
+        if let Some(h) = self.io_handler(idx as Addr) {
+            return h.borrow_mut().write(idx as Addr, v);
+        }
+        self.mark_written(idx, idx);
+        self.cells[idx] = v;
     }
 
+    /// Loads byte from given address; addresses past the end wrap around
     pub fn load(&self, i: Addr) -> u8 {
-        self.cells[i as usize]
+        let idx = i as usize % self.cells.len();
+        match self.io_handler(idx as Addr) {
+            Some(h) => h.borrow_mut().read(idx as Addr),
+            None => self.cells[idx],
+        }
     }
 
     pub fn get<I>(&self, index: I) -> Option<&<I as SliceIndex<[u8]>>::Output>
@@ -52,20 +488,173 @@ impl Mem {
 
     pub fn store_arr(&mut self, addr: Addr, v: &[u8]) {
         for (idx, e) in v.iter().enumerate() {
-            self.store(addr + idx as u16, *e)
+            self.store(addr.wrapping_add(idx as u16), *e)
         }
     }
 
+    /// Stores default small font at start, followed by large font
     pub fn store_font(&mut self, start: Addr) {
+        self.store_font_set(start, &FontSet::DEFAULT);
+    }
+
+    /// Stores small digits of set at start, followed by its large digits
+    pub fn store_font_set(&mut self, start: Addr, set: &FontSet) {
         self.start_addr = start;
+        self.font = Some(*set);
         for i in 0..16 {
-            let a: Addr = start + i * Mem::FONT_SIZE_BYTES;
-            self.store_arr(a, &FONT[i as usize]);
+            let a: Addr = start.wrapping_add(i * Mem::FONT_SIZE_BYTES);
+            self.store_arr(a, &set.small[i as usize]);
+        }
+        for i in 0..16 {
+            let a: Addr = self
+                .large_font_start()
+                .wrapping_add(i * Mem::LARGE_FONT_SIZE_BYTES);
+            self.store_arr(a, &set.large[i as usize]);
         }
     }
 
     pub fn addr_of_font(&self, digit: u8) -> u16 {
-        self.start_addr + Mem::FONT_SIZE_BYTES * digit as u16
+        self.start_addr
+            .wrapping_add(Mem::FONT_SIZE_BYTES * (digit & 0xF) as u16)
+    }
+
+    /// Set last stored with [`Mem::store_font_set`]
+    pub fn font_set(&self) -> Option<&FontSet> {
+        self.font.as_ref()
+    }
+
+    /// Address of small font
+    pub(crate) fn font_start(&self) -> Addr {
+        self.start_addr
+    }
+
+    pub(crate) fn set_font_start(&mut self, start: Addr) {
+        self.start_addr = start;
+    }
+
+    /// Address past the large font
+    pub(crate) fn font_end(&self) -> Addr {
+        self.large_font_start()
+            .wrapping_add(16 * Mem::LARGE_FONT_SIZE_BYTES)
+    }
+
+    fn large_font_start(&self) -> Addr {
+        self.start_addr.wrapping_add(16 * Mem::FONT_SIZE_BYTES)
+    }
+
+    pub fn addr_of_large_font(&self, digit: u8) -> u16 {
+        self.large_font_start()
+            .wrapping_add(Mem::LARGE_FONT_SIZE_BYTES * (digit & 0xF) as u16)
+    }
+
+    /// Whether addr lies in the program area recorded by [`Mem::set_program`]
+    pub(crate) fn in_program(&self, addr: Addr) -> bool {
+        self.program
+            .is_some_and(|(start, len)| (start..start + len).contains(&(addr as usize)))
+    }
+
+    /// Records len bytes from start as program area reported by [`Mem::region`]
+    pub fn set_program(&mut self, start: Addr, len: usize) {
+        self.program = Some((start as usize, len));
+    }
+
+    /// Which area addr belongs to; fonts take precedence over program
+    pub fn region(&self, addr: Addr) -> Region {
+        let offset = (addr.wrapping_sub(self.start_addr) as usize) % self.cells.len();
+        let small = 16 * Mem::FONT_SIZE_BYTES as usize;
+        let large = 16 * Mem::LARGE_FONT_SIZE_BYTES as usize;
+        match self.program {
+            _ if self.font.is_some() && offset < small => Region::Font,
+            _ if self.font.is_some() && offset < small + large => Region::LargeFont,
+            Some((start, len)) if (start..start + len).contains(&(addr as usize)) => {
+                Region::Program
+            }
+            _ => Region::Free,
+        }
+    }
+
+    /// Bytes in range, clamped to memory size
+    pub fn inspect(&self, range: Range<Addr>) -> &[u8] {
+        let end = (range.end as usize).min(self.cells.len());
+        let start = (range.start as usize).min(end);
+        &self.cells[start..end]
+    }
+
+    /// Formats range as lines of up to 16 bytes, each starting with its
+    /// address and ending with regions (see [`Mem::region`]) it covers
+    pub fn hexdump(&self, range: Range<Addr>) -> String {
+        let bytes = self.inspect(range.clone());
+        let mut out = String::new();
+        for (row, chunk) in bytes.chunks(16).enumerate() {
+            let addr = range.start + row as u16 * 16;
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+            let mut regions: Vec<Region> = vec![];
+            for a in addr..addr + chunk.len() as u16 {
+                let r = self.region(a);
+                if r != Region::Free && !regions.contains(&r) {
+                    regions.push(r);
+                }
+            }
+            let names: Vec<String> = regions.iter().map(Region::to_string).collect();
+            let line = format!("{:03X}: {:<47}  {}", addr, hex.join(" "), names.join(", "));
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Range as Intel HEX records, see [`ihex::encode`]
+    pub fn to_ihex(&self, range: Range<Addr>) -> String {
+        let start = (range.start as usize).min(self.cells.len());
+        ihex::encode(start as u32, self.inspect(range))
+    }
+
+    /// Stores data of Intel HEX records at their addresses; returns number
+    /// of bytes stored. Fails without writing anything if text is invalid
+    /// or data does not fit in memory.
+    pub fn from_ihex(&mut self, text: &str) -> Result<usize, IhexError> {
+        let runs = ihex::decode(text)?;
+        for (addr, data) in &runs {
+            if *addr as usize + data.len() > self.cells.len() {
+                let addr = (*addr).max(self.cells.len() as u32);
+                return Err(IhexError::OutOfRange { addr });
+            }
+        }
+        for (addr, data) in &runs {
+            self.store_arr(*addr as Addr, data);
+        }
+        Ok(runs.iter().map(|(_, data)| data.len()).sum())
+    }
+}
+
+impl Clone for Mem {
+    fn clone(&self) -> Self {
+        Mem {
+            cells: self.cells.clone(),
+            start_addr: self.start_addr,
+            font: self.font,
+            program: self.program,
+            policy: self.policy,
+            written: Some((0, self.cells.len() - 1)),
+            io: self.io.clone(),
+            next_io_id: self.next_io_id,
+        }
+    }
+}
+
+impl PartialEq for Mem {
+    fn eq(&self, other: &Self) -> bool {
+        self.cells[..] == other.cells[..] && self.start_addr == other.start_addr
+    }
+}
+
+impl fmt::Debug for Mem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Mem")
+            .field("size", &self.cells.len())
+            .field("start_addr", &self.start_addr)
+            .field("io", &self.io.iter().map(|m| &m.range).collect::<Vec<_>>())
+            .finish()
     }
 }
 
@@ -74,3 +663,164 @@ impl Default for Mem {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Port {
+        log: Rc<RefCell<Vec<(Addr, u8)>>>,
+    }
+
+    impl IoHandler for Port {
+        fn read(&mut self, addr: Addr) -> u8 {
+            addr as u8
+        }
+
+        fn write(&mut self, addr: Addr, value: u8) {
+            self.log.borrow_mut().push((addr, value));
+        }
+    }
+
+    #[test]
+    fn io_test() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut m = Mem::new();
+        let id = m.map_io(0xF00..0xF10, Box::new(Port { log: log.clone() }));
+        m.store(0xF01, 9);
+        m.store_arr(0xF0F, &[1, 2]);
+        assert_eq!(Ok(()), m.checked_store(0xF02, 3));
+        assert_eq!(vec![(0xF01, 9), (0xF0F, 1), (0xF02, 3)], *log.borrow());
+        assert_eq!(2, m.load(0xF10));
+        assert_eq!(0x05, m.load(0x1F05));
+        assert_eq!(Ok(0x0E), m.checked_load(0xF0E));
+        assert_eq!(Some(&0), m.get(0xF01));
+        assert_eq!(0x05, m.clone().load(0xF05));
+        assert!(m.unmap_io(id));
+        assert!(!m.unmap_io(id));
+        assert_eq!(0, m.load(0xF05));
+    }
+
+    #[test]
+    fn checked_test() {
+        let mut m = Mem::new();
+        assert_eq!(Ok(()), m.checked_store(0xFFF, 7));
+        assert_eq!(Ok(7), m.checked_load(0xFFF));
+        let err = Chip8Error::AddressOutOfRange { addr: 0x1000 };
+        assert_eq!(Err(err), m.checked_load(0x1000));
+        assert_eq!(Err(err), m.checked_store(0x1000, 1));
+        assert_eq!(Err(err), m.write_slice(0xFFE, &[1, 2, 3]));
+        assert_eq!(Some(&[0, 7][..]), m.get(0xFFE..));
+        assert_eq!(Ok(()), m.write_slice(0xFFD, &[1, 2, 3]));
+        m.get_mut(0..2).unwrap().copy_from_slice(&[9, 8]);
+        assert_eq!(Some(&[9, 8][..]), m.get(0..2));
+    }
+
+    #[test]
+    fn take_writes_test() {
+        let mut m = Mem::new();
+        assert_eq!(Some((0, 4095)), m.take_writes());
+        assert_eq!(None, m.take_writes());
+        m.store(0x300, 1);
+        m.store_arr(0x200, &[1, 2]);
+        assert_eq!(Some((0x200, 0x300)), m.take_writes());
+        m.write_slice(0x10, &[]).unwrap();
+        assert_eq!(None, m.take_writes());
+        assert_eq!(Some((0, 4095)), m.clone().take_writes());
+    }
+
+    #[test]
+    fn hexdump_test() {
+        let mut m = Mem::new();
+        assert_eq!(Region::Free, m.region(0));
+        m.store_font(0);
+        m.store_arr(0x200, &[0x00, 0xE0, 0x12, 0x00]);
+        m.set_program(0x200, 4);
+        assert_eq!(Region::Font, m.region(0x4F));
+        assert_eq!(Region::LargeFont, m.region(0x50));
+        assert_eq!(Region::Free, m.region(0xF0));
+        assert_eq!(Region::Program, m.region(0x203));
+        assert_eq!(Region::Free, m.region(0x204));
+        assert_eq!(&[0xE0, 0x12][..], m.inspect(0x201..0x203));
+        assert_eq!(&[0u8; 0][..], m.inspect(0x2000..0x3000));
+        let dump = m.hexdump(0x48..0x68);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with("048: F0 80 F0 F0 80 F0 80 80 FF"));
+        assert!(lines[0].ends_with("  font, large font"));
+        assert_eq!(
+            format!("200: {:<47}  program", "00 E0 12 00"),
+            m.hexdump(0x200..0x204).trim_end()
+        );
+        assert_eq!("FFF: 00", m.hexdump(0xFFF..0x1000).trim_end());
+    }
+
+    #[test]
+    fn with_size_test() {
+        let mut m = Mem::with_size(0x800);
+        assert_eq!(0x800, m.size());
+        assert_eq!(
+            Err(Chip8Error::AddressOutOfRange { addr: 0x900 }),
+            m.checked_store(0x900, 1)
+        );
+        let mut m = Mem::with_size(Mem::MAX_SIZE);
+        m.store(0xFFFF, 1);
+        assert_eq!(1, m.load(0xFFFF));
+        assert_eq!(
+            Err(Chip8Error::AddressOutOfRange { addr: 0xFFFF }),
+            m.write_slice(0xFFFF, &[1, 2])
+        );
+        assert_eq!(m.size(), m.blank().size());
+    }
+
+    #[test]
+    fn ihex_test() {
+        let mut m = Mem::new();
+        m.store_arr(0x200, &[0x00, 0xE0, 0x12, 0x00]);
+        let hex = m.to_ihex(0x200..0x204);
+        assert_eq!(":0402000000E0120008\n:00000001FF\n", hex);
+        let mut copy = Mem::new();
+        assert_eq!(Ok(4), copy.from_ihex(&hex));
+        assert_eq!(m, copy);
+        assert_eq!(m, {
+            let mut whole = Mem::new();
+            whole.from_ihex(&m.to_ihex(0..0x1000)).unwrap();
+            whole
+        });
+        // nothing is written when data runs past the end
+        let text = ":010000007788\n:020FFF00AABB8B\n:00000001FF\n";
+        let err = copy.from_ihex(text);
+        assert_eq!(Err(IhexError::OutOfRange { addr: 0x1000 }), err);
+        assert_eq!(0, copy.load(0));
+    }
+
+    #[test]
+    fn policy_test() {
+        let mut m = Mem::new();
+        assert_eq!(Ok(()), m.check_range(0xFFF, 3));
+        m.set_policy(AddressPolicy::Error);
+        assert_eq!(Ok(()), m.check_range(0xFFE, 2));
+        assert_eq!(
+            Err(Chip8Error::AddressOutOfRange { addr: 0x1000 }),
+            m.check_range(0xFFF, 3)
+        );
+    }
+
+    #[test]
+    fn font_set_test() {
+        let mut m = Mem::new();
+        let set = FontSet::DREAM_6800.with_large([[0x55; 10]; 16]);
+        m.store_font_set(0x50, &set);
+        assert_eq!(Some(&set), m.font_set());
+        let one = m.addr_of_font(1);
+        assert_eq!(&[0x40; 5], m.inspect(one..one + 5));
+        let f = m.addr_of_large_font(0xF);
+        assert_eq!(&[0x55; 10], m.inspect(f..f + 10));
+        assert_eq!(Region::LargeFont, m.region(f));
+
+        m.store_font(0);
+        assert_eq!(Some(&FontSet::DEFAULT), m.font_set());
+        assert_eq!(&[0x20, 0x60, 0x20, 0x20, 0x70], m.inspect(5..10));
+        assert_ne!(FontSet::VIP, FontSet::ETI_660);
+    }
+}
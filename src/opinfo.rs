@@ -0,0 +1,220 @@
+//! Static description of instructions for assemblers, highlighters and
+//! analyzers, see [`Opcode::info`]
+
+use crate::cpu::Opcode;
+use crate::debug::Access;
+use crate::quirks::Profile;
+
+/// Kind of operand encoded in an instruction word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// register index in the x or y nibble
+    Register,
+    /// 12-bit address
+    Address,
+    /// 4-bit value
+    Nibble,
+    /// 8-bit value
+    Byte,
+}
+
+/// Where execution continues after an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// next instruction
+    Next,
+    /// next instruction or the one after it
+    Skip,
+    /// encoded address (plus V0 or Vx for JP V0)
+    Jump,
+    /// encoded address, pushing the return address
+    Call,
+    /// address popped from the stack
+    Return,
+    /// nowhere, the interpreter stops
+    Halt,
+    /// next instruction once a key is pressed and released
+    Wait,
+}
+
+/// What an instruction is and what it affects besides its operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpInfo {
+    /// leading word of the assembly syntax, e.g. `LD` for both `LD Vx, kk`
+    /// and `LD I, nnn`
+    pub mnemonic: &'static str,
+    /// operands in encoding order
+    pub operands: &'static [Operand],
+    pub flow: Flow,
+    /// VF is set as flag (AND, OR and XOR only with the `vf_reset` quirk)
+    pub writes_vf: bool,
+    pub reads_i: bool,
+    /// I is changed (FX55 and FX65 only with the `load_store_inc_i` quirk)
+    pub writes_i: bool,
+    /// memory at I read or written
+    pub memory: Option<Access>,
+    /// screen is drawn to, cleared, scrolled or switched
+    pub screen: bool,
+    /// earliest platform providing the instruction
+    pub platform: Profile,
+}
+
+const NONE: &[Operand] = &[];
+const X: &[Operand] = &[Operand::Register];
+const XY: &[Operand] = &[Operand::Register, Operand::Register];
+const XYN: &[Operand] = &[Operand::Register, Operand::Register, Operand::Nibble];
+const XKK: &[Operand] = &[Operand::Register, Operand::Byte];
+const NNN: &[Operand] = &[Operand::Address];
+const N: &[Operand] = &[Operand::Nibble];
+
+impl OpInfo {
+    const fn new(mnemonic: &'static str, operands: &'static [Operand]) -> Self {
+        OpInfo {
+            mnemonic,
+            operands,
+            flow: Flow::Next,
+            writes_vf: false,
+            reads_i: false,
+            writes_i: false,
+            memory: None,
+            screen: false,
+            platform: Profile::CosmacVip,
+        }
+    }
+
+    const fn flow(mut self, flow: Flow) -> Self {
+        self.flow = flow;
+        self
+    }
+
+    const fn vf(mut self) -> Self {
+        self.writes_vf = true;
+        self
+    }
+
+    const fn sets_i(mut self) -> Self {
+        self.writes_i = true;
+        self
+    }
+
+    const fn mem(mut self, access: Access) -> Self {
+        self.reads_i = true;
+        self.memory = Some(access);
+        self
+    }
+
+    const fn screen(mut self) -> Self {
+        self.screen = true;
+        self
+    }
+
+    const fn on(mut self, platform: Profile) -> Self {
+        self.platform = platform;
+        self
+    }
+}
+
+impl Opcode {
+    /// Operands, control flow and side effects of the instruction;
+    /// [`Opcode::Unknown`] has mnemonic `DW` and halts
+    pub fn info(&self) -> OpInfo {
+        use Profile::{SuperChip, XoChip};
+        match self {
+            Opcode::CLS => OpInfo::new("CLS", NONE).screen(),
+            Opcode::RET => OpInfo::new("RET", NONE).flow(Flow::Return),
+            Opcode::JP(_) => OpInfo::new("JP", NNN).flow(Flow::Jump),
+            Opcode::CALL(_) => OpInfo::new("CALL", NNN).flow(Flow::Call),
+            Opcode::SE(..) => OpInfo::new("SE", XKK).flow(Flow::Skip),
+            Opcode::SNE(..) => OpInfo::new("SNE", XKK).flow(Flow::Skip),
+            Opcode::SER(..) => OpInfo::new("SE", XY).flow(Flow::Skip),
+            Opcode::LD(..) => OpInfo::new("LD", XKK),
+            Opcode::ADD(..) => OpInfo::new("ADD", XKK),
+            Opcode::LDR(..) => OpInfo::new("LD", XY),
+            Opcode::OR(..) => OpInfo::new("OR", XY).vf(),
+            Opcode::AND(..) => OpInfo::new("AND", XY).vf(),
+            Opcode::XOR(..) => OpInfo::new("XOR", XY).vf(),
+            Opcode::ADDR(..) => OpInfo::new("ADD", XY).vf(),
+            Opcode::SUBR(..) => OpInfo::new("SUB", XY).vf(),
+            Opcode::SHR(..) => OpInfo::new("SHR", XY).vf(),
+            Opcode::SUBRN(..) => OpInfo::new("SUBN", XY).vf(),
+            Opcode::SHL(..) => OpInfo::new("SHL", XY).vf(),
+            Opcode::SNER(..) => OpInfo::new("SNE", XY).flow(Flow::Skip),
+            Opcode::LDI(_) => OpInfo::new("LD", NNN).sets_i(),
+            Opcode::JPOFF(_) => OpInfo::new("JP", NNN).flow(Flow::Jump),
+            Opcode::RND(..) => OpInfo::new("RND", XKK),
+            Opcode::DRW(..) => OpInfo::new("DRW", XYN).vf().mem(Access::Read).screen(),
+            Opcode::SKP(_) => OpInfo::new("SKP", X).flow(Flow::Skip),
+            Opcode::SKNP(_) => OpInfo::new("SKNP", X).flow(Flow::Skip),
+            Opcode::KEYSET(_) => OpInfo::new("LD", X).flow(Flow::Wait),
+            Opcode::DTSET(_) | Opcode::DTGET(_) | Opcode::STSET(_) => OpInfo::new("LD", X),
+            Opcode::IINC(_) => {
+                let info = OpInfo::new("ADD", X).sets_i();
+                OpInfo {
+                    reads_i: true,
+                    ..info
+                }
+            }
+            Opcode::IDIG(_) => OpInfo::new("LD", X).sets_i(),
+            Opcode::BCD(_) => OpInfo::new("LD", X).mem(Access::Write),
+            Opcode::REGSSTORE(_) => OpInfo::new("LD", X).mem(Access::Write).sets_i(),
+            Opcode::REGLOAD(_) => OpInfo::new("LD", X).mem(Access::Read).sets_i(),
+            Opcode::SCD(_) => OpInfo::new("SCD", N).screen().on(SuperChip),
+            Opcode::SCR => OpInfo::new("SCR", NONE).screen().on(SuperChip),
+            Opcode::SCL => OpInfo::new("SCL", NONE).screen().on(SuperChip),
+            Opcode::EXIT => OpInfo::new("EXIT", NONE).flow(Flow::Halt).on(SuperChip),
+            Opcode::LOW => OpInfo::new("LOW", NONE).screen().on(SuperChip),
+            Opcode::HIGH => OpInfo::new("HIGH", NONE).screen().on(SuperChip),
+            Opcode::LDHF(_) => OpInfo::new("LD", X).sets_i().on(SuperChip),
+            Opcode::RPLSTORE(_) | Opcode::RPLLOAD(_) => OpInfo::new("LD", X).on(SuperChip),
+            Opcode::SAVE(..) => OpInfo::new("SAVE", XY).mem(Access::Write).on(XoChip),
+            Opcode::LOAD(..) => OpInfo::new("LOAD", XY).mem(Access::Read).on(XoChip),
+            Opcode::LDIL => OpInfo::new("LD", NONE).sets_i().on(XoChip),
+            Opcode::PLANE(_) => OpInfo::new("PLANE", N).on(XoChip),
+            Opcode::Unknown(_) => OpInfo::new("DW", NONE).flow(Flow::Halt),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm;
+
+    #[test]
+    fn info_test() {
+        let drw = Opcode::DRW(1, 2, 3).info();
+        assert_eq!(
+            ("DRW", XYN, Flow::Next),
+            (drw.mnemonic, drw.operands, drw.flow)
+        );
+        assert!(drw.writes_vf && drw.reads_i && !drw.writes_i && drw.screen);
+        assert_eq!(Some(Access::Read), drw.memory);
+        assert_eq!(Flow::Call, Opcode::CALL(0x300).info().flow);
+        assert_eq!(Profile::XoChip, Opcode::LDIL.info().platform);
+        assert!(!Opcode::LD(0, 1).info().writes_vf);
+        assert!(Opcode::IINC(0).info().reads_i);
+    }
+
+    #[test]
+    fn matches_disassembly_test() {
+        for op in Opcode::iter_examples() {
+            let info = op.info();
+            let text = disasm::mnemonic(&op);
+            assert!(text.starts_with(info.mnemonic), "{}", text);
+            let registers = info
+                .operands
+                .iter()
+                .filter(|&&o| o == Operand::Register)
+                .count();
+            assert_eq!(
+                registers > 0,
+                op.max_register().is_some() && op != Opcode::JPOFF(0x123),
+                "{}",
+                text
+            );
+            if info.memory.is_some() {
+                assert!(info.reads_i, "{}", text);
+            }
+        }
+    }
+}
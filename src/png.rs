@@ -0,0 +1,99 @@
+//! Minimal PNG encoder backing [`crate::display::export_png`]
+//!
+//! Writes 8-bit RGBA images with uncompressed deflate blocks; screenshots
+//! are small, so compression is not worth a dependency.
+
+use crate::hash::crc32;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Largest payload of a stored deflate block
+const MAX_BLOCK: usize = 0xFFFF;
+
+/// Encodes rows of width RGBA pixels as PNG file
+pub(crate) fn encode_rgba(width: usize, height: usize, rgba: &[u8]) -> Vec<u8> {
+    let mut ihdr = vec![];
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // bit depth 8, color type RGBA, deflate, adaptive filtering, no interlace
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    // each row starts with filter type 0 (none)
+    let mut raw = Vec::with_capacity((width * 4 + 1) * height);
+    for row in rgba.chunks(width * 4).take(height) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut out = SIGNATURE.to_vec();
+    chunk(&mut out, b"IHDR", &ihdr);
+    chunk(&mut out, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &x| {
+        let a = (a + x as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adler32_test() {
+        assert_eq!(0x11E6_0398, adler32(b"Wikipedia"));
+    }
+
+    #[test]
+    fn zlib_stored_test() {
+        assert_eq!(
+            vec![0x78, 0x01, 1, 2, 0, 0xFD, 0xFF, 7, 9, 0x00, 0x19, 0x00, 0x11],
+            zlib_stored(&[7, 9])
+        );
+        let big = zlib_stored(&vec![0; MAX_BLOCK + 1]);
+        assert_eq!(2 + 5 + MAX_BLOCK + 5 + 1 + 4, big.len());
+        assert_eq!(&[0, 0xFF, 0xFF, 0, 0][..], &big[2..7]);
+    }
+
+    #[test]
+    fn encode_test() {
+        let png = encode_rgba(1, 1, &[1, 2, 3, 4]);
+        assert_eq!(&SIGNATURE[..], &png[..8]);
+        assert_eq!(&[0, 0, 0, 13][..], &png[8..12]);
+        assert_eq!(b"IHDR", &png[12..16]);
+        assert_eq!(&[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0][..], &png[16..29]);
+        assert_eq!(b"IEND", &png[png.len() - 8..png.len() - 4]);
+    }
+}
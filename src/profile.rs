@@ -0,0 +1,81 @@
+//! Opt-in execution profiler enabled with [`crate::emulator::Emulator::enable_profiler`]
+use crate::cpu::{Addr, Opcode};
+use std::collections::HashMap;
+
+/// Execution counts per opcode kind and per instruction address
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Profiler {
+    by_kind: HashMap<&'static str, u64>,
+    by_addr: HashMap<Addr, u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub(crate) fn record(&mut self, addr: Addr, op: &Opcode) {
+        *self.by_kind.entry(op.name()).or_insert(0) += 1;
+        *self.by_addr.entry(addr).or_insert(0) += 1;
+    }
+
+    /// Times instructions of given kind (see [`Opcode::name`]) were executed
+    pub fn kind_count(&self, name: &str) -> u64 {
+        self.by_kind.get(name).copied().unwrap_or(0)
+    }
+
+    /// Times instruction at addr was executed
+    pub fn addr_count(&self, addr: Addr) -> u64 {
+        self.by_addr.get(&addr).copied().unwrap_or(0)
+    }
+
+    /// n most executed addresses, most executed first (ties by address)
+    pub fn top_hotspots(&self, n: usize) -> Vec<(Addr, u64)> {
+        top(&self.by_addr, n)
+    }
+
+    /// n most executed opcode kinds, most executed first (ties by name)
+    pub fn top_opcodes(&self, n: usize) -> Vec<(&'static str, u64)> {
+        top(&self.by_kind, n)
+    }
+
+    pub fn reset(&mut self) {
+        self.by_kind.clear();
+        self.by_addr.clear();
+    }
+}
+
+fn top<K: Copy + Ord>(counts: &HashMap<K, u64>, n: usize) -> Vec<(K, u64)> {
+    let mut v: Vec<(K, u64)> = counts.iter().map(|(k, c)| (*k, *c)).collect();
+    v.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    v.truncate(n);
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Opcode;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn hotspots_test() {
+        let mut e = Emulator::new();
+        e.store(&[
+            Opcode::LD(0, 3),
+            Opcode::ADD(0, 0xFF),
+            Opcode::SE(0, 0),
+            Opcode::JP(0x202),
+            Opcode::EXIT,
+        ]);
+        assert!(e.profiler().is_none());
+        e.enable_profiler();
+        e.run().unwrap();
+        let p = e.profiler().unwrap();
+        assert_eq!(vec![(0x202, 3), (0x204, 3)], p.top_hotspots(2));
+        assert_eq!(2, p.addr_count(0x206));
+        assert_eq!(vec![("ADD", 3), ("SE", 3), ("JP", 2)], p.top_opcodes(3));
+        assert_eq!(1, p.kind_count("EXIT"));
+        e.disable_profiler();
+        assert!(e.profiler().is_none());
+    }
+}
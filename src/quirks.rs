@@ -0,0 +1,232 @@
+use crate::cpu::CPU;
+use crate::mem::Mem;
+use std::str::FromStr;
+
+/// How DXYN treats sprites crossing the right or bottom screen edge.
+///
+/// The origin always wraps; this only decides what happens to the part
+/// of the sprite that does not fit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DrawMode {
+    /// Pixels past the edge appear on the opposite side
+    #[default]
+    Wrap,
+    /// Pixels past the edge are dropped
+    Clip,
+}
+
+/// Behavioral switches differing between chip-8 interpreters.
+///
+/// Default leaves all quirks off, which matches CHIP-48/SUPER-CHIP style
+/// shifting and loading used by most modern ROMs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift Vy and store result in Vx (instead of shifting Vx)
+    pub shift_vy: bool,
+    /// FX55/FX65 leave I incremented by X + 1
+    pub load_store_inc_i: bool,
+    /// BNNN jumps to XNN + VX (instead of NNN + V0)
+    pub jump_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0
+    pub vf_reset: bool,
+    /// DXYN clipping or wrapping at screen edges
+    pub draw_mode: DrawMode,
+}
+
+impl Quirks {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Original COSMAC VIP interpreter
+    pub fn cosmac() -> Self {
+        Quirks {
+            shift_vy: true,
+            load_store_inc_i: true,
+            jump_vx: false,
+            vf_reset: true,
+            draw_mode: DrawMode::Clip,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on HP48
+    pub fn superchip() -> Self {
+        Quirks {
+            jump_vx: true,
+            draw_mode: DrawMode::Clip,
+            ..Quirks::new()
+        }
+    }
+
+    /// XO-CHIP (Octo)
+    pub fn xochip() -> Self {
+        Quirks {
+            shift_vy: true,
+            load_store_inc_i: true,
+            ..Quirks::new()
+        }
+    }
+}
+
+/// Interpreter platforms with everything differing between them bundled,
+/// applied with [`EmulatorBuilder::profile`](crate::builder::EmulatorBuilder::profile).
+/// Ordered by descent, later platforms providing all instructions of
+/// earlier ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Profile {
+    /// Original COSMAC VIP interpreter
+    CosmacVip,
+    /// CHIP-48 on HP48
+    Chip48,
+    /// SUPER-CHIP 1.1 on HP48
+    SuperChip,
+    /// XO-CHIP (Octo)
+    XoChip,
+}
+
+impl Profile {
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Profile::CosmacVip => Quirks::cosmac(),
+            // CHIP-48 introduced behavior SUPER-CHIP kept
+            Profile::Chip48 | Profile::SuperChip => Quirks::superchip(),
+            Profile::XoChip => Quirks::xochip(),
+        }
+    }
+
+    /// Maximum subroutine nesting depth
+    pub fn stack_limit(self) -> usize {
+        match self {
+            Profile::CosmacVip => 12,
+            _ => CPU::STACK_SIZE,
+        }
+    }
+
+    /// Bytes of memory, 64 KB for XO-CHIP
+    pub fn memory_size(self) -> usize {
+        match self {
+            Profile::XoChip => Mem::MAX_SIZE,
+            _ => Mem::DEFAULT_SIZE,
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    /// Parses `vip`, `chip48`, `schip` or `xochip` (case insensitive)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "vip" | "cosmac" => Ok(Profile::CosmacVip),
+            "chip48" => Ok(Profile::Chip48),
+            "schip" | "superchip" => Ok(Profile::SuperChip),
+            "xochip" => Ok(Profile::XoChip),
+            _ => Err(format!("unknown profile '{}'", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DrawMode, Profile, Quirks};
+    use crate::emulator::Emulator;
+
+    fn run_with(q: Quirks, instrs: &[u16]) -> Emulator {
+        let mut e = Emulator::new();
+        e.quirks = q;
+        e.store_instr(instrs);
+        for _ in instrs {
+            e.step().unwrap();
+        }
+        e
+    }
+
+    #[test]
+    fn shift_test() {
+        let prog = [0x6103, 0x6240, 0x8126];
+        let e = run_with(Quirks::new(), &prog);
+        assert_eq!(0x01, e.cpu.regs[1]);
+        assert_eq!(1, e.cpu.regs[0xF]);
+
+        let q = Quirks {
+            shift_vy: true,
+            ..Quirks::new()
+        };
+        let e = run_with(q, &prog);
+        assert_eq!(0x20, e.cpu.regs[1]);
+        assert_eq!(0, e.cpu.regs[0xF]);
+    }
+
+    #[test]
+    fn load_store_inc_i_test() {
+        let prog = [0xA300, 0xF255];
+        assert_eq!(0x300, run_with(Quirks::new(), &prog).cpu.i);
+        let q = Quirks {
+            load_store_inc_i: true,
+            ..Quirks::new()
+        };
+        assert_eq!(0x303, run_with(q, &prog).cpu.i);
+    }
+
+    #[test]
+    fn jump_vx_test() {
+        let prog = [0x6010, 0x6320, 0xB300];
+        assert_eq!(0x310, run_with(Quirks::new(), &prog).cpu.pc);
+        let q = Quirks {
+            jump_vx: true,
+            ..Quirks::new()
+        };
+        assert_eq!(0x320, run_with(q, &prog).cpu.pc);
+    }
+
+    #[test]
+    fn vf_reset_test() {
+        let prog = [0x6F05, 0x8011];
+        assert_eq!(5, run_with(Quirks::new(), &prog).cpu.regs[0xF]);
+        let q = Quirks {
+            vf_reset: true,
+            ..Quirks::new()
+        };
+        assert_eq!(0, run_with(q, &prog).cpu.regs[0xF]);
+    }
+
+    #[test]
+    fn draw_mode_test() {
+        // sprite byte 0xFF at (60, 31), two rows
+        let prog = [0xA300, 0x603C, 0x611F, 0xD012];
+        let draw = |q: Quirks| {
+            let mut e = Emulator::new();
+            e.quirks = q;
+            e.mem.store(0x300, 0xFF);
+            e.mem.store(0x301, 0xFF);
+            e.store_instr(&prog);
+            for _ in &prog {
+                e.step().unwrap();
+            }
+            e
+        };
+        let e = draw(Quirks::new());
+        assert!(e.scr.get(63, 31));
+        assert!(e.scr.get(0, 31));
+        assert!(e.scr.get(0, 0));
+        let e = draw(Quirks {
+            draw_mode: DrawMode::Clip,
+            ..Quirks::new()
+        });
+        assert!(e.scr.get(63, 31));
+        assert!(e.scr.get(60, 31));
+        assert!(!e.scr.get(0, 31));
+        assert!(!e.scr.get(0, 0));
+        assert!(!e.scr.get(60, 0));
+    }
+
+    #[test]
+    fn profile_test() {
+        assert_eq!(Ok(Profile::CosmacVip), "VIP".parse());
+        assert_eq!(Ok(Profile::XoChip), "xochip".parse());
+        assert!("chip9".parse::<Profile>().is_err());
+        assert_eq!(Quirks::cosmac(), Profile::CosmacVip.quirks());
+        assert_eq!(DrawMode::Clip, Profile::Chip48.quirks().draw_mode);
+        assert_eq!(12, Profile::CosmacVip.stack_limit());
+    }
+}
@@ -0,0 +1,243 @@
+//! Remote display and keypad over TCP (`remote` feature)
+//!
+//! [`serve`] runs the emulator in real time for a single thin client,
+//! sending it screen changes and sound state and taking key events back.
+//! All numbers are big-endian.
+//!
+//! Server to client:
+//!
+//! | message                  | meaning                                      |
+//! |--------------------------|----------------------------------------------|
+//! | `'F' w:u16 h:u16`        | resolution, sent first and when it changes; all rows follow |
+//! | `'R' y:u16 pixels:[u8; w]` | row y, color index of each pixel (see [`Scr::pixel`]) |
+//! | `'E'`                    | end of frame, client may present it          |
+//! | `'S' on:u8`              | sound turned on (1) or off (0)               |
+//! | `'H'`                    | program exited, connection closes            |
+//! | `'X' len:u16 text:[u8; len]` | execution failed with UTF-8 message, connection closes |
+//!
+//! Client to server:
+//!
+//! | message   | meaning                       |
+//! |-----------|-------------------------------|
+//! | `'D' k:u8` | key k of the keypad pressed  |
+//! | `'U' k:u8` | key k released               |
+//! | `'Q'`      | end the session              |
+//!
+//! Unknown client bytes are skipped. Only frames that changed something
+//! are sent.
+//!
+//! [`Scr::pixel`]: crate::display::Scr::pixel
+
+use crate::clock::FRAME;
+use crate::debug::StopReason;
+use crate::display::ScreenState;
+use crate::emulator::Emulator;
+use crate::error::Chip8Error;
+use crate::input::KeyEvent;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Instant;
+
+/// Accepts one connection on addr and serves it until it ends
+pub fn serve<A: ToSocketAddrs>(e: &mut Emulator, addr: A) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    session(e, stream)
+}
+
+/// Runs emulator for connected client until it quits or disconnects, or
+/// the program exits. Execution errors are sent to the client and
+/// returned as [`ErrorKind::Other`] wrapping the [`Chip8Error`].
+pub fn session(e: &mut Emulator, mut stream: TcpStream) -> io::Result<()> {
+    stream.set_nonblocking(true)?;
+    let mut remote = Remote::default();
+    let mut deadline = Instant::now();
+    loop {
+        let mut buf = [0u8; 64];
+        match stream.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => {
+                if !remote.input(e, &buf[..n]) {
+                    return Ok(());
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err),
+        }
+        let result = e.run_for(FRAME);
+        let mut out = remote.update(e);
+        match &result {
+            Ok(Some(StopReason::Halted)) => out.push(b'H'),
+            Ok(_) => {}
+            Err(err) => error_message(&mut out, err),
+        }
+        stream.set_nonblocking(false)?;
+        stream.write_all(&out)?;
+        stream.set_nonblocking(true)?;
+        match result {
+            Ok(Some(StopReason::Halted)) => return Ok(()),
+            Ok(_) => {}
+            Err(err) => return Err(io::Error::other(err)),
+        }
+        deadline += FRAME;
+        let now = Instant::now();
+        if deadline > now {
+            std::thread::sleep(deadline - now);
+        }
+    }
+}
+
+/// Appends `'X'` message describing err
+fn error_message(out: &mut Vec<u8>, err: &Chip8Error) {
+    let text = err.to_string();
+    let len = text.len().min(u16::MAX as usize);
+    out.push(b'X');
+    out.extend_from_slice(&(len as u16).to_be_bytes());
+    out.extend_from_slice(&text.as_bytes()[..len]);
+}
+
+/// What the client was last sent, and partial input
+#[derive(Debug, Default)]
+struct Remote {
+    screen: Option<ScreenState>,
+    sounding: bool,
+    /// start of message split between reads
+    pending: Vec<u8>,
+}
+
+impl Remote {
+    /// Applies client messages; returns false once the client quit
+    fn input(&mut self, e: &mut Emulator, bytes: &[u8]) -> bool {
+        self.pending.extend_from_slice(bytes);
+        let mut used = 0;
+        let mut quit = false;
+        while let Some(&kind) = self.pending.get(used) {
+            match kind {
+                b'D' | b'U' => match self.pending.get(used + 1) {
+                    Some(&k) => {
+                        let k = k as usize;
+                        e.kbd.push(if kind == b'D' {
+                            KeyEvent::Pressed(k)
+                        } else {
+                            KeyEvent::Released(k)
+                        });
+                        used += 2;
+                    }
+                    None => break,
+                },
+                b'Q' => {
+                    quit = true;
+                    break;
+                }
+                _ => used += 1,
+            }
+        }
+        self.pending.drain(..used);
+        !quit
+    }
+
+    /// Messages describing changes since previous update
+    fn update(&mut self, e: &Emulator) -> Vec<u8> {
+        let mut out = vec![];
+        let screen = e.scr.save();
+        let (w, h) = (screen.width, screen.height);
+        let prev = self
+            .screen
+            .as_ref()
+            .filter(|p| (p.width, p.height) == (w, h));
+        if prev.is_none() {
+            out.push(b'F');
+            out.extend_from_slice(&(w as u16).to_be_bytes());
+            out.extend_from_slice(&(h as u16).to_be_bytes());
+        }
+        for (y, row) in screen.pixels.chunks(w).enumerate() {
+            if prev.is_none_or(|p| p.pixels[y * w..(y + 1) * w] != *row) {
+                out.push(b'R');
+                out.extend_from_slice(&(y as u16).to_be_bytes());
+                out.extend_from_slice(row);
+            }
+        }
+        if !out.is_empty() {
+            out.push(b'E');
+        }
+        let sounding = e.sounding();
+        if sounding != self.sounding {
+            out.extend_from_slice(&[b'S', sounding as u8]);
+            self.sounding = sounding;
+        }
+        self.screen = Some(screen);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Opcode;
+
+    #[test]
+    fn update_test() {
+        let mut e = Emulator::new();
+        let mut r = Remote::default();
+        let out = r.update(&e);
+        assert_eq!(&[b'F', 0, 64, 0, 32, b'R', 0, 0][..], &out[..8]);
+        assert_eq!(5 + 32 * (3 + 64) + 1, out.len());
+        assert!(r.update(&e).is_empty());
+
+        e.scr.xor(2, 3, true);
+        e.cpu.regs[0] = 4;
+        e.exec(Opcode::STSET(0)).unwrap();
+        let mut expected = vec![b'R', 0, 3, 0, 0, 1];
+        expected.extend_from_slice(&[0; 61]);
+        expected.extend_from_slice(&[b'E', b'S', 1]);
+        assert_eq!(expected, r.update(&e));
+
+        e.scr.set_hires(true);
+        assert_eq!(&[b'F', 0, 128, 0, 64][..], &r.update(&e)[..5]);
+    }
+
+    #[test]
+    fn error_message_test() {
+        let mut out = vec![];
+        error_message(&mut out, &Chip8Error::StackUnderflow(0x204));
+        let text = Chip8Error::StackUnderflow(0x204).to_string();
+        assert_eq!(b'X', out[0]);
+        assert_eq!(text.len() as u16, u16::from_be_bytes([out[1], out[2]]));
+        assert_eq!(text.as_bytes(), &out[3..]);
+    }
+
+    #[test]
+    fn session_error_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let mut received = vec![];
+            stream.read_to_end(&mut received).unwrap();
+            received
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let mut e = Emulator::new();
+        e.store(&[Opcode::RET]);
+        let err = session(&mut e, stream).unwrap_err();
+        assert_eq!(ErrorKind::Other, err.kind());
+        let text = Chip8Error::StackUnderflow(0x200).to_string();
+        assert_eq!(text, err.to_string());
+        let received = client.join().unwrap();
+        assert!(received.ends_with(text.as_bytes()));
+    }
+
+    #[test]
+    fn input_test() {
+        let mut e = Emulator::new();
+        let mut r = Remote::default();
+        assert!(r.input(&mut e, b"D\x05xU"));
+        assert!(e.kbd.get(5));
+        assert!(r.input(&mut e, b"\x05D"));
+        assert!(!e.kbd.get(5));
+        assert!(r.input(&mut e, b"\x0A"));
+        assert!(e.kbd.get(0xA));
+        assert!(!r.input(&mut e, b"Q"));
+    }
+}
@@ -0,0 +1,153 @@
+//! Input recording and deterministic replay
+//!
+//! While recording, the emulator logs every key event together with the
+//! number of [`Emulator::step`] calls and timer ticks that preceded it, as
+//! well as the instruction count at each tick. Since RND draws from an
+//! [`XorShift`](crate::rng::XorShift) seeded with the recorded seed,
+//! [`Emulator::replay`] reproduces the run exactly on an emulator
+//! configured the same way (quirks, start address, font).
+//!
+//! Key input has to reach the emulator as events, i.e. through
+//! [`Keyboard::push`](crate::input::Keyboard::push) or the helpers built on
+//! it; assigning key states directly is not recorded.
+//!
+//! [`Emulator::step`]: crate::emulator::Emulator::step
+//! [`Emulator::replay`]: crate::emulator::Emulator::replay
+
+use crate::input::KeyEvent;
+use crate::savestate::{Reader, SaveStateError};
+
+const MAGIC: &[u8; 4] = b"C8RC";
+const VERSION: u8 = 1;
+
+/// Key event with the moment it reached the emulator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Input {
+    /// timer ticks before the event
+    pub frame: u64,
+    /// steps before the event; it is seen by the next step
+    pub instruction: u64,
+    pub event: KeyEvent,
+}
+
+/// Inputs and RNG seed of a run started by
+/// [`Emulator::start_recording`](crate::emulator::Emulator::start_recording)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub seed: u64,
+    /// program stored at start address when recording started
+    pub rom: Vec<u8>,
+    pub inputs: Vec<Input>,
+    /// steps before each timer tick
+    pub ticks: Vec<u64>,
+    /// steps in the whole run
+    pub instructions: u64,
+}
+
+impl Recording {
+    pub(crate) fn new(seed: u64, rom: Vec<u8>) -> Self {
+        Recording {
+            seed,
+            rom,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn record_events<I: IntoIterator<Item = KeyEvent>>(&mut self, events: I) {
+        let (frame, instruction) = (self.ticks.len() as u64, self.instructions);
+        self.inputs.extend(events.into_iter().map(|event| Input {
+            frame,
+            instruction,
+            event,
+        }));
+    }
+
+    pub(crate) fn record_tick(&mut self) {
+        self.ticks.push(self.instructions);
+    }
+
+    /// Encodes recording in a compact binary form, e.g. for attaching to
+    /// bug reports
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut v = MAGIC.to_vec();
+        v.push(VERSION);
+        v.extend_from_slice(&self.seed.to_be_bytes());
+        v.extend_from_slice(&self.instructions.to_be_bytes());
+        v.extend_from_slice(&(self.rom.len() as u32).to_be_bytes());
+        v.extend_from_slice(&self.rom);
+        v.extend_from_slice(&(self.ticks.len() as u32).to_be_bytes());
+        self.ticks
+            .iter()
+            .for_each(|t| v.extend_from_slice(&t.to_be_bytes()));
+        v.extend_from_slice(&(self.inputs.len() as u32).to_be_bytes());
+        for input in &self.inputs {
+            v.extend_from_slice(&input.frame.to_be_bytes());
+            v.extend_from_slice(&input.instruction.to_be_bytes());
+            let (pressed, key) = match input.event {
+                KeyEvent::Pressed(k) => (1, k),
+                KeyEvent::Released(k) => (0, k),
+            };
+            v.extend_from_slice(&[pressed, key as u8]);
+        }
+        v
+    }
+
+    /// Decodes recording written by [`Recording::to_bytes`]; `None` if bytes
+    /// are not a complete recording of known version
+    pub fn from_bytes(bytes: &[u8]) -> Option<Recording> {
+        Recording::read(&mut Reader::new(bytes)).ok()
+    }
+
+    fn read(r: &mut Reader) -> Result<Recording, SaveStateError> {
+        if r.take(4)? != MAGIC || r.u8()? != VERSION {
+            return Err(SaveStateError::BadMagic);
+        }
+        let seed = r.u64()?;
+        let instructions = r.u64()?;
+        let len = r.u32()? as usize;
+        let rom = r.take(len)?.to_vec();
+        let len = r.u32()?;
+        let ticks = (0..len).map(|_| r.u64()).collect::<Result<Vec<_>, _>>()?;
+        let len = r.u32()?;
+        let mut inputs = vec![];
+        for _ in 0..len {
+            let frame = r.u64()?;
+            let instruction = r.u64()?;
+            let pressed = r.u8()? != 0;
+            let key = r.u8()? as usize;
+            inputs.push(Input {
+                frame,
+                instruction,
+                event: if pressed {
+                    KeyEvent::Pressed(key)
+                } else {
+                    KeyEvent::Released(key)
+                },
+            });
+        }
+        Ok(Recording {
+            seed,
+            rom,
+            inputs,
+            ticks,
+            instructions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_test() {
+        let mut r = Recording::new(42, vec![0x12, 0x00]);
+        r.instructions = 3;
+        r.record_tick();
+        r.record_events(vec![KeyEvent::Pressed(5), KeyEvent::Released(0xF)]);
+        let bytes = r.to_bytes();
+        assert_eq!(Some(r), Recording::from_bytes(&bytes));
+        assert_eq!(None, Recording::from_bytes(&bytes[..bytes.len() - 1]));
+        assert_eq!(None, Recording::from_bytes(b"C8SS"));
+    }
+}
@@ -0,0 +1,104 @@
+//! Bounded history of emulator snapshots backing [`crate::emulator::Emulator::rewind`]
+use crate::savestate::SaveState;
+use std::collections::VecDeque;
+
+/// Ring buffer keeping at most capacity most recent states
+#[derive(Debug, Clone)]
+pub(crate) struct History {
+    capacity: usize,
+    states: VecDeque<SaveState>,
+}
+
+impl History {
+    pub(crate) fn new(capacity: usize) -> Self {
+        History {
+            capacity,
+            states: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn push(&mut self, state: SaveState) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(state);
+    }
+
+    /// Drops up to n latest states, returning the oldest dropped one
+    /// together with number of states dropped
+    pub(crate) fn pop(&mut self, n: usize) -> Option<(SaveState, usize)> {
+        let n = n.min(self.states.len());
+        let mut last = None;
+        for _ in 0..n {
+            last = self.states.pop_back();
+        }
+        last.map(|s| (s, n))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.states.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::Opcode;
+    use crate::emulator::Emulator;
+
+    fn counting_emulator() -> Emulator {
+        let mut e = Emulator::new();
+        e.store(&[Opcode::ADD(0, 1), Opcode::JP(0x200)]);
+        e.cpu.pc(0x200);
+        e
+    }
+
+    #[test]
+    fn rewind_test() {
+        let mut e = counting_emulator();
+        e.enable_rewind(100);
+        for _ in 0..10 {
+            e.step().unwrap();
+        }
+        assert_eq!(5, e.cpu.regs[0]);
+        assert_eq!(4, e.rewind(4));
+        assert_eq!((3, 0x200), (e.cpu.regs[0], e.cpu.pc));
+        assert_eq!(6, e.rewind_len());
+        assert_eq!(6, e.rewind(10));
+        assert_eq!((0, 0x200), (e.cpu.regs[0], e.cpu.pc));
+        assert_eq!(0, e.rewind(1));
+    }
+
+    #[test]
+    fn failed_step_test() {
+        let mut e = Emulator::new();
+        e.store(&[Opcode::LD(0, 1), Opcode::RET]);
+        e.enable_rewind(10);
+        e.step().unwrap();
+        assert!(e.step().is_err());
+        assert_eq!(1, e.rewind_len());
+        assert_eq!(1, e.rewind(1));
+        assert_eq!((0, 0x200), (e.cpu.regs[0], e.cpu.pc));
+    }
+
+    #[test]
+    fn bounded_test() {
+        let mut e = counting_emulator();
+        e.enable_rewind(3);
+        for _ in 0..10 {
+            e.step().unwrap();
+        }
+        assert_eq!(3, e.rewind_len());
+        assert_eq!(3, e.rewind(5));
+        assert_eq!(4, e.cpu.regs[0]);
+        e.disable_rewind();
+        e.step().unwrap();
+        assert_eq!(0, e.rewind(1));
+    }
+}
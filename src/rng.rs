@@ -0,0 +1,93 @@
+//! Random number sources for the RND instruction
+//!
+//! The emulator draws random bytes from a boxed [`Rng`]; use [`XorShift`]
+//! with a fixed seed for deterministic runs, tests and replays.
+
+/// Source of random bytes
+pub trait Rng {
+    fn next_u8(&mut self) -> u8;
+}
+
+/// Small seedable xorshift64* generator, available on every target
+#[derive(Debug, Clone, PartialEq)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    /// Seed used by [`default_rng`] without the `rand` feature
+    pub const DEFAULT_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+    /// Creates generator; seed 0 is replaced by [`XorShift::DEFAULT_SEED`]
+    pub fn new(seed: u64) -> Self {
+        XorShift {
+            state: if seed == 0 {
+                XorShift::DEFAULT_SEED
+            } else {
+                seed
+            },
+        }
+    }
+}
+
+impl Rng for XorShift {
+    fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
+
+/// Generator backed by `rand::random`
+#[cfg(feature = "rand")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadRng;
+
+#[cfg(feature = "rand")]
+impl Rng for ThreadRng {
+    fn next_u8(&mut self) -> u8 {
+        rand::random()
+    }
+}
+
+/// Generator used by new emulators: [`ThreadRng`] with the `rand` feature,
+/// otherwise [`XorShift`] with default seed
+pub fn default_rng() -> Box<dyn Rng> {
+    #[cfg(feature = "rand")]
+    return Box::new(ThreadRng);
+    #[cfg(not(feature = "rand"))]
+    return Box::new(XorShift::new(XorShift::DEFAULT_SEED));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Opcode;
+    use crate::emulator::Emulator;
+
+    #[test]
+    fn xorshift_deterministic_test() {
+        let mut a = XorShift::new(42);
+        let mut b = XorShift::new(42);
+        let xs: Vec<u8> = (0..16).map(|_| a.next_u8()).collect();
+        let ys: Vec<u8> = (0..16).map(|_| b.next_u8()).collect();
+        assert_eq!(xs, ys);
+        assert!(xs.iter().any(|&x| x != xs[0]));
+        assert_eq!(XorShift::new(0), XorShift::new(XorShift::DEFAULT_SEED));
+    }
+
+    #[test]
+    fn emulator_rng_test() {
+        let run = || {
+            let mut e = Emulator::new();
+            e.rng = Box::new(XorShift::new(7));
+            e.store(&[Opcode::RND(0, 0xFF), Opcode::RND(1, 0x0F), Opcode::EXIT]);
+            e.run().unwrap();
+            (e.cpu.regs[0], e.cpu.regs[1])
+        };
+        let (r0, r1) = run();
+        assert_eq!((r0, r1), run());
+        assert_eq!(r1, r1 & 0x0F);
+    }
+}
@@ -0,0 +1,161 @@
+//! Database of known ROMs for configuring the emulator per game
+//!
+//! A [`RomDb`] is parsed from text, so frontends can embed theirs with
+//! `include_str!` or read it from a file. Each non-empty line not starting
+//! with `#` describes one ROM:
+//!
+//! ```text
+//! <sha1 hex> <profile> <ips or -> <title>
+//! ```
+//!
+//! where profile is anything [`Profile`] parses (`vip`, `chip48`, `schip`,
+//! `xochip`) and `-` keeps the default speed.
+
+use crate::builder::EmulatorBuilder;
+use crate::loader::RomInfo;
+use crate::quirks::Profile;
+
+/// ROM described by database entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownRom {
+    pub sha1: [u8; 20],
+    pub title: String,
+    /// platform the ROM was written for
+    pub profile: Profile,
+    /// preferred instructions per second
+    pub ips: Option<u32>,
+}
+
+impl KnownRom {
+    /// Applies recommended profile and speed to builder
+    pub fn configure(&self, builder: EmulatorBuilder) -> EmulatorBuilder {
+        let builder = builder.profile(self.profile);
+        match self.ips {
+            Some(ips) => builder.ips(ips),
+            None => builder,
+        }
+    }
+}
+
+/// Known ROMs looked up by contents
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RomDb {
+    roms: Vec<KnownRom>,
+}
+
+impl RomDb {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Parses database text (see module documentation); fails naming the
+    /// first malformed line
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut db = RomDb::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rom = parse_line(line).map_err(|e| format!("line {}: {}", n + 1, e))?;
+            db.add(rom);
+        }
+        Ok(db)
+    }
+
+    /// Adds entry, replacing one with the same hash
+    pub fn add(&mut self, rom: KnownRom) {
+        self.roms.retain(|r| r.sha1 != rom.sha1);
+        self.roms.push(rom);
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    /// Entry of ROM with given metadata
+    pub fn lookup(&self, info: &RomInfo) -> Option<&KnownRom> {
+        self.roms.iter().find(|r| r.sha1 == info.sha1)
+    }
+
+    /// Entry of ROM with given contents
+    pub fn identify(&self, rom: &[u8]) -> Option<&KnownRom> {
+        self.lookup(&RomInfo::of(rom))
+    }
+}
+
+fn parse_line(line: &str) -> Result<KnownRom, String> {
+    let mut fields = line.splitn(4, char::is_whitespace);
+    let mut field = |name: &str| {
+        fields
+            .next()
+            .filter(|f| !f.is_empty())
+            .ok_or(format!("missing {}", name))
+    };
+    let hash = field("hash")?;
+    let sha1 = parse_sha1(hash).ok_or(format!("bad SHA-1 '{}'", hash))?;
+    let profile = field("profile")?.parse()?;
+    let ips = match field("ips")? {
+        "-" => None,
+        s => Some(s.parse().map_err(|_| format!("bad ips '{}'", s))?),
+    };
+    let title = field("title")?.trim().to_string();
+    Ok(KnownRom {
+        sha1,
+        title,
+        profile,
+        ips,
+    })
+}
+
+fn parse_sha1(hex: &str) -> Option<[u8; 20]> {
+    if hex.len() != 40 || !hex.is_ascii() {
+        return None;
+    }
+    let mut out = [0u8; 20];
+    for (i, b) in out.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    const ROM: [u8; 4] = [0x00, 0xE0, 0x12, 0x00];
+
+    #[test]
+    fn parse_test() {
+        let hash = RomInfo::of(&ROM).sha1_hex();
+        let text = format!(
+            "# test roms\n\n{} schip 1000 Clear   loop\n{} vip - Other\n",
+            hash,
+            "00".repeat(20)
+        );
+        let db = RomDb::parse(&text).unwrap();
+        assert_eq!(2, db.len());
+        let rom = db.identify(&ROM).unwrap();
+        assert_eq!("Clear   loop", rom.title);
+        assert_eq!((Profile::SuperChip, Some(1000)), (rom.profile, rom.ips));
+        assert_eq!(None, db.identify(&ROM[..2]));
+        let e = rom.configure(Emulator::builder()).build();
+        assert_eq!(1000, e.ips());
+        assert_eq!(Profile::SuperChip.quirks(), e.quirks);
+
+        assert_eq!(
+            Err("line 2: bad ips 'x'".to_string()),
+            RomDb::parse(&format!("\n{} vip x T", hash))
+        );
+        assert!(RomDb::parse("abc vip - T").unwrap_err().contains("SHA-1"));
+        assert!(RomDb::parse(&format!("{} vip -", hash))
+            .unwrap_err()
+            .ends_with("missing title"));
+        assert!(RomDb::parse(&format!("{} pdp11 - T", hash)).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use crate::cpu::{Addr, Opcode, CPU};
+use crate::display::ScreenState;
+use crate::input::Keyboard;
+use crate::mem::Mem;
+use std::error::Error;
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"C8SS";
+const VERSION: u8 = 1;
+
+/// Snapshot of complete machine state taken by [`Emulator::save_state`]
+///
+/// [`Emulator::save_state`]: crate::emulator::Emulator::save_state
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveState {
+    pub cpu: CPU,
+    pub mem: Mem,
+    pub screen: ScreenState,
+    pub kbd: Keyboard,
+    pub halted: bool,
+}
+
+/// Reasons a byte buffer cannot be decoded as save state
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SaveStateError {
+    /// buffer does not start with save state signature
+    BadMagic,
+    /// save state written by unknown format version
+    UnsupportedVersion(u8),
+    /// buffer ended prematurely
+    Truncated,
+    /// memory size is 0 or over [`Mem::MAX_SIZE`]
+    BadMemorySize(u32),
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "not a chip-8 save state"),
+            SaveStateError::UnsupportedVersion(v) => {
+                write!(f, "unsupported save state version {}", v)
+            }
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::BadMemorySize(size) => {
+                write!(f, "invalid memory size {} in save state", size)
+            }
+        }
+    }
+}
+
+impl Error for SaveStateError {}
+
+impl SaveState {
+    /// Encodes state in a compact binary form suitable for files
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut v = MAGIC.to_vec();
+        v.push(VERSION);
+
+        let cpu = &self.cpu;
+        put_u16(&mut v, cpu.pc);
+        put_u16(&mut v, cpu.i);
+        v.extend_from_slice(&cpu.regs);
+        put_u16(&mut v, cpu.sp);
+        v.push(cpu.dt);
+        v.push(cpu.st);
+        match cpu.instr {
+            Some(op) => {
+                v.push(1);
+                put_u16(&mut v, op.to_instr());
+            }
+            None => v.extend_from_slice(&[0, 0, 0]),
+        }
+        put_u16(&mut v, cpu.stack().len() as u16);
+        cpu.stack().iter().for_each(|a| put_u16(&mut v, *a));
+
+        put_u16(&mut v, self.mem.font_start());
+        put_u32(&mut v, self.mem.size() as u32);
+        v.extend_from_slice(self.mem.get(..).unwrap_or(&[]));
+
+        let scr = &self.screen;
+        v.push(scr.hires as u8);
+        v.push(scr.planes);
+        put_u16(&mut v, scr.width as u16);
+        put_u16(&mut v, scr.height as u16);
+        v.extend_from_slice(&scr.pixels);
+
+        v.extend(self.kbd.states.iter().map(|&s| s as u8));
+        v.push(self.halted as u8);
+        v
+    }
+
+    /// Decodes state written by [`SaveState::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<SaveState, SaveStateError> {
+        let mut r = Reader::new(bytes);
+        if r.take(4)? != MAGIC {
+            return Err(SaveStateError::BadMagic);
+        }
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+
+        let mut cpu = CPU::new();
+        cpu.pc = r.u16()?;
+        cpu.i = r.u16()?;
+        let regs = r.take(cpu.regs.len())?;
+        cpu.regs.copy_from_slice(regs);
+        cpu.sp = r.u16()?;
+        cpu.dt = r.u8()?;
+        cpu.st = r.u8()?;
+        let has_instr = r.u8()? != 0;
+        let instr = r.u16()?;
+        cpu.instr = if has_instr { Opcode::from(instr) } else { None };
+        let depth = r.u16()?;
+        let stack = (0..depth)
+            .map(|_| r.u16())
+            .collect::<Result<Vec<Addr>, _>>()?;
+        cpu.set_stack(stack);
+
+        let font_start = r.u16()?;
+        let size = r.u32()?;
+        let mut mem = match size as usize {
+            Mem::DEFAULT_SIZE => Mem::new(),
+            s if s > 0 && s <= Mem::MAX_SIZE => Mem::with_size(s),
+            _ => return Err(SaveStateError::BadMemorySize(size)),
+        };
+        mem.store_arr(0, r.take(size as usize)?);
+        mem.set_font_start(font_start);
+
+        let hires = r.u8()? != 0;
+        let planes = r.u8()?;
+        let width = r.u16()? as usize;
+        let height = r.u16()? as usize;
+        let pixels = r.take(width * height)?.to_vec();
+        let screen = ScreenState {
+            hires,
+            planes,
+            width,
+            height,
+            pixels,
+        };
+
+        let mut kbd = Keyboard::new();
+        for s in kbd.states.iter_mut() {
+            *s = r.u8()? != 0;
+        }
+        let halted = r.u8()? != 0;
+        Ok(SaveState {
+            cpu,
+            mem,
+            screen,
+            kbd,
+            halted,
+        })
+    }
+}
+
+fn put_u16(v: &mut Vec<u8>, val: u16) {
+    v.extend_from_slice(&val.to_be_bytes());
+}
+
+fn put_u32(v: &mut Vec<u8>, val: u32) {
+    v.extend_from_slice(&val.to_be_bytes());
+}
+
+/// Big-endian cursor over encoded bytes, shared with [`crate::replay`] and
+/// [`crate::c8b`]
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, pos: 0 }
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], SaveStateError> {
+        let res = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .ok_or(SaveStateError::Truncated)?;
+        self.pos += n;
+        Ok(res)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, SaveStateError> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, SaveStateError> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+
+    fn running_emulator() -> Emulator {
+        let mut e = Emulator::new();
+        e.store_font();
+        // call subroutine drawing digit 5 in hires and stay there
+        e.store_instr(&[0x00FF, 0x2206, 0x0000, 0x6005, 0xF029, 0xD115, 0x120A]);
+        for _ in 0..6 {
+            e.step().unwrap();
+        }
+        e.kbd.switch(7);
+        e.cpu.dt = 9;
+        e
+    }
+
+    #[test]
+    fn save_load_test() {
+        let mut e = running_emulator();
+        let state = e.save_state();
+        assert_eq!(vec![0x202], state.cpu.stack().to_vec());
+
+        let mut other = Emulator::new();
+        other.load_state(&state);
+        assert_eq!(state, other.save_state());
+        assert!(other.scr.hires());
+        assert!(other.scr.get(0, 0));
+
+        e.step().unwrap();
+        e.scr.clear();
+        e.load_state(&state);
+        assert!(e.scr.get(0, 0));
+        assert_eq!(state, e.save_state());
+    }
+
+    #[test]
+    fn bytes_roundtrip_test() {
+        let state = running_emulator().save_state();
+        let bytes = state.to_bytes();
+        assert_eq!(Ok(state), SaveState::from_bytes(&bytes));
+    }
+
+    #[test]
+    fn memory_size_test() {
+        let mut e = Emulator::builder().memory_size(Mem::MAX_SIZE).build();
+        e.mem.store(0xFFFF, 9);
+        let state = SaveState::from_bytes(&e.save_state().to_bytes()).unwrap();
+        assert_eq!(
+            (Mem::MAX_SIZE, 9),
+            (state.mem.size(), state.mem.load(0xFFFF))
+        );
+        let mut bytes = e.save_state().to_bytes();
+        // magic, version, cpu state with empty stack and font start
+        let at = 36;
+        assert_eq!([0, 1, 0, 0], bytes[at..at + 4]);
+        bytes[at..at + 4].copy_from_slice(&[0, 2, 0, 0]);
+        assert_eq!(
+            Err(SaveStateError::BadMemorySize(0x20000)),
+            SaveState::from_bytes(&bytes)
+        );
+    }
+
+    #[test]
+    fn bytes_errors_test() {
+        let bytes = running_emulator().save_state().to_bytes();
+        assert_eq!(
+            Err(SaveStateError::Truncated),
+            SaveState::from_bytes(&bytes[..bytes.len() - 1])
+        );
+        assert_eq!(
+            Err(SaveStateError::BadMagic),
+            SaveState::from_bytes(b"NOPE")
+        );
+        let mut newer = bytes;
+        newer[4] = 9;
+        assert_eq!(
+            Err(SaveStateError::UnsupportedVersion(9)),
+            SaveState::from_bytes(&newer)
+        );
+    }
+}
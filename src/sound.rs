@@ -0,0 +1,114 @@
+//! Sound timer integration point and beep sample generation
+//!
+//! The emulator calls [`AudioSink`] when the sound timer becomes non-zero
+//! and when it reaches zero again; frontends start and stop playback of
+//! a tone, e.g. produced by [`SquareWave`].
+
+/// Receiver of sound timer transitions
+pub trait AudioSink {
+    /// Sound timer was set to non-zero value while silent
+    fn start(&mut self);
+    /// Sound timer reached zero
+    fn stop(&mut self);
+}
+
+/// Square wave generator producing f32 samples in -amplitude..=amplitude
+#[derive(Debug, Clone, PartialEq)]
+pub struct SquareWave {
+    frequency: f32,
+    sample_rate: u32,
+    amplitude: f32,
+    phase: f32,
+}
+
+impl SquareWave {
+    /// Typical beep frequency in Hz
+    pub const DEFAULT_FREQUENCY: f32 = 440.0;
+
+    pub fn new(frequency: f32, sample_rate: u32) -> Self {
+        SquareWave {
+            frequency,
+            sample_rate,
+            amplitude: 0.25,
+            phase: 0.0,
+        }
+    }
+
+    /// Sets peak sample value
+    pub fn with_amplitude(mut self, amplitude: f32) -> Self {
+        self.amplitude = amplitude;
+        self
+    }
+
+    /// Fills buffer with consecutive samples, continuing previous phase
+    pub fn fill(&mut self, buf: &mut [f32]) {
+        for s in buf.iter_mut() {
+            *s = self.next_sample();
+        }
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        let s = if self.phase < 0.5 {
+            self.amplitude
+        } else {
+            -self.amplitude
+        };
+        if self.sample_rate > 0 {
+            self.phase = (self.phase + self.frequency / self.sample_rate as f32).fract();
+        }
+        s
+    }
+}
+
+impl Iterator for SquareWave {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        Some(self.next_sample())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Opcode;
+    use crate::emulator::Emulator;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn square_wave_test() {
+        let mut w = SquareWave::new(2000.0, 8000).with_amplitude(1.0);
+        let mut buf = [0.0; 8];
+        w.fill(&mut buf);
+        assert_eq!([1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0, -1.0], buf);
+        assert_eq!(Some(1.0), w.next());
+    }
+
+    struct Log(Rc<RefCell<Vec<&'static str>>>);
+
+    impl AudioSink for Log {
+        fn start(&mut self) {
+            self.0.borrow_mut().push("start");
+        }
+        fn stop(&mut self) {
+            self.0.borrow_mut().push("stop");
+        }
+    }
+
+    #[test]
+    fn sink_test() {
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut e = Emulator::new();
+        e.set_audio_sink(Box::new(Log(log.clone())));
+        e.store(&[Opcode::LD(0, 2), Opcode::STSET(0), Opcode::STSET(0)]);
+        e.step().unwrap();
+        e.step().unwrap();
+        e.step().unwrap();
+        assert_eq!(vec!["start"], *log.borrow());
+        e.tick();
+        e.tick();
+        e.tick();
+        assert_eq!(vec!["start", "stop"], *log.borrow());
+    }
+}
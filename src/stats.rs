@@ -0,0 +1,61 @@
+use crate::cpu::{Addr, Instr};
+use std::collections::HashMap;
+
+/// Statistics collected while running a program
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RunStats {
+    /// number of executed instructions
+    pub executed: u64,
+    /// undecodable instruction words and how many times each was fetched
+    pub unknown_by_word: HashMap<Instr, u64>,
+    /// addresses undecodable words were fetched from and how many times
+    pub unknown_by_addr: HashMap<Addr, u64>,
+}
+
+impl RunStats {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Total number of undecodable words fetched
+    pub fn unknown_total(&self) -> u64 {
+        self.unknown_by_word.values().sum()
+    }
+
+    pub(crate) fn record_unknown(&mut self, addr: Addr, word: Instr) {
+        *self.unknown_by_word.entry(word).or_insert(0) += 1;
+        *self.unknown_by_addr.entry(addr).or_insert(0) += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use crate::error::Chip8Error;
+
+    #[test]
+    fn unknown_counts_test() {
+        let mut s = RunStats::new();
+        s.record_unknown(0x200, 0xFFFF);
+        s.record_unknown(0x204, 0xFFFF);
+        s.record_unknown(0x204, 0x5001);
+        assert_eq!(3, s.unknown_total());
+        assert_eq!(Some(&2), s.unknown_by_word.get(&0xFFFF));
+        assert_eq!(Some(&2), s.unknown_by_addr.get(&0x204));
+    }
+
+    #[test]
+    fn run_records_stats_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x6001, 0x7001, 0x5121]);
+        let end = Chip8Error::InvalidOpcode {
+            addr: 0x204,
+            raw: 0x5121,
+        };
+        assert_eq!(Err(end), e.run());
+        assert_eq!(2, e.stats.executed);
+        assert_eq!(Some(&1), e.stats.unknown_by_word.get(&0x5121));
+        assert_eq!(Some(&1), e.stats.unknown_by_addr.get(&0x204));
+    }
+}
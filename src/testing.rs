@@ -0,0 +1,220 @@
+//! Headless running of test ROMs with pass/fail checks
+//!
+//! A [`Case`] runs a ROM for a number of frames and then compares the
+//! screen, memory or registers with expectations. [`timendus_cases`]
+//! builds cases for Timendus' CHIP-8 test suite from a directory holding
+//! its ROMs together with expected screens recorded by [`record_screen`].
+
+use crate::clock::FRAME;
+use crate::cpu::Addr;
+use crate::display;
+use crate::emulator::Emulator;
+use crate::loader;
+use crate::quirks::Profile;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Expected machine state after running a case
+#[derive(Debug, Clone, PartialEq)]
+pub enum Check {
+    /// screen as rendered by [`display::render_text`]
+    Screen(String),
+    /// bytes stored from addr
+    Memory { addr: Addr, bytes: Vec<u8> },
+    /// value of register vx
+    Register { reg: usize, value: u8 },
+}
+
+/// One test ROM run
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub name: String,
+    pub rom: Vec<u8>,
+    pub profile: Profile,
+    /// frames to run for (at default speed) before checking
+    pub frames: u32,
+    /// bytes stored in memory before start, e.g. menu choices
+    pub setup: Vec<(Addr, u8)>,
+    pub checks: Vec<Check>,
+}
+
+impl Case {
+    pub fn new<S: Into<String>>(name: S, rom: Vec<u8>) -> Self {
+        Case {
+            name: name.into(),
+            rom,
+            profile: Profile::CosmacVip,
+            frames: 60,
+            setup: vec![],
+            checks: vec![],
+        }
+    }
+
+    /// Runs the case and compares the outcome with its checks
+    pub fn run(&self) -> Outcome {
+        let mut e = Emulator::builder().profile(self.profile).build();
+        let mut failures = vec![];
+        if let Err(err) = loader::load_from_bytes(&mut e, &self.rom) {
+            failures.push(format!("load: {}", err));
+        }
+        for &(addr, value) in &self.setup {
+            e.mem.store(addr, value);
+        }
+        if let Err(err) = e.run_for(FRAME * self.frames) {
+            failures.push(format!("execution: {}", err));
+        }
+        let screen = display::render_text(e.scr.as_ref());
+        if self.checks.is_empty() {
+            failures.push("no checks".to_string());
+        }
+        for check in &self.checks {
+            match check {
+                Check::Screen(expected) if *expected != screen => {
+                    failures.push("screen differs".to_string())
+                }
+                Check::Memory { addr, bytes } => {
+                    let end = addr.wrapping_add(bytes.len() as Addr);
+                    let actual = e.mem.inspect(*addr..end);
+                    if actual != &bytes[..] {
+                        failures.push(format!(
+                            "memory at 0x{:03X}: expected {:02X?}, found {:02X?}",
+                            addr, bytes, actual
+                        ));
+                    }
+                }
+                Check::Register { reg, value } => match e.cpu.regs.get(*reg) {
+                    None => failures.push(format!("no register V{:X}", reg)),
+                    Some(actual) if actual != value => failures.push(format!(
+                        "V{:X}: expected 0x{:02X}, found 0x{:02X}",
+                        reg, value, actual
+                    )),
+                    Some(_) => {}
+                },
+                _ => {}
+            }
+        }
+        Outcome {
+            name: self.name.clone(),
+            failures,
+            screen,
+        }
+    }
+}
+
+/// Result of running a [`Case`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub name: String,
+    /// descriptions of checks that failed
+    pub failures: Vec<String>,
+    /// screen at the end of the run
+    pub screen: String,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Runs all cases in order
+pub fn run_suite(cases: &[Case]) -> Vec<Outcome> {
+    cases.iter().map(Case::run).collect()
+}
+
+/// ROM of Timendus' test suite: file name, platform, frames it needs to
+/// finish and menu choice stored at 0x1FF
+pub const TIMENDUS: [(&str, Profile, u32, Option<u8>); 5] = [
+    ("1-chip8-logo.ch8", Profile::CosmacVip, 60, None),
+    ("2-ibm-logo.ch8", Profile::CosmacVip, 60, None),
+    ("3-corax+.ch8", Profile::CosmacVip, 60, None),
+    ("4-flags.ch8", Profile::CosmacVip, 60, None),
+    ("5-quirks.ch8", Profile::CosmacVip, 600, Some(1)),
+];
+
+/// Path of expected screen stored for rom
+fn screen_path(rom: &Path) -> std::path::PathBuf {
+    rom.with_extension("txt")
+}
+
+/// Cases for [`TIMENDUS`] ROMs present in dir; each checks the screen
+/// against `<rom name>.txt` next to it, if there is one
+pub fn timendus_cases<P: AsRef<Path>>(dir: P) -> io::Result<Vec<Case>> {
+    let mut cases = vec![];
+    for &(file, profile, frames, choice) in TIMENDUS.iter() {
+        let path = dir.as_ref().join(file);
+        if !path.exists() {
+            continue;
+        }
+        let mut case = Case::new(file, fs::read(&path)?);
+        case.profile = profile;
+        case.frames = frames;
+        case.setup.extend(choice.map(|c| (0x1FF, c)));
+        if let Ok(screen) = fs::read_to_string(screen_path(&path)) {
+            case.checks.push(Check::Screen(screen));
+        }
+        cases.push(case);
+    }
+    Ok(cases)
+}
+
+/// Stores screen of outcome as expected screen of rom, after it was
+/// verified by looking at it
+pub fn record_screen<P: AsRef<Path>>(rom: P, outcome: &Outcome) -> io::Result<()> {
+    fs::write(screen_path(rom.as_ref()), &outcome.screen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case() -> Case {
+        // LD I, 0x300; LD V0, [I]; ADD V0, 1; EXIT
+        let rom = vec![0xA3, 0x00, 0xF0, 0x65, 0x70, 0x01, 0x00, 0xFD];
+        let mut c = Case::new("inc", rom);
+        c.setup.push((0x300, 4));
+        c
+    }
+
+    #[test]
+    fn case_test() {
+        let mut c = case();
+        assert_eq!(vec!["no checks".to_string()], c.run().failures);
+        c.checks.push(Check::Register { reg: 0, value: 5 });
+        c.checks.push(Check::Memory {
+            addr: 0x200,
+            bytes: vec![0xA3, 0x00],
+        });
+        assert!(c.run().passed());
+        c.checks.push(Check::Register { reg: 0, value: 6 });
+        c.checks.push(Check::Screen(String::new()));
+        c.checks.push(Check::Register { reg: 16, value: 0 });
+        let outcome = &run_suite(&[c])[0];
+        assert_eq!(
+            vec![
+                "V0: expected 0x06, found 0x05".to_string(),
+                "screen differs".to_string(),
+                "no register V10".to_string()
+            ],
+            outcome.failures
+        );
+        assert_eq!(32, outcome.screen.lines().count());
+    }
+
+    #[test]
+    fn timendus_cases_test() {
+        let dir = std::env::temp_dir().join(format!("libchip8-suite-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("5-quirks.ch8");
+        fs::write(&rom, &case().rom).unwrap();
+        let cases = timendus_cases(&dir).unwrap();
+        assert_eq!(1, cases.len());
+        assert_eq!(vec![(0x1FF, 1)], cases[0].setup);
+        assert!(cases[0].checks.is_empty());
+        record_screen(&rom, &cases[0].run()).unwrap();
+        let cases = timendus_cases(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(cases[0].run().passed());
+    }
+}
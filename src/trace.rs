@@ -0,0 +1,335 @@
+//! Structured tracing of executed instructions (`trace` feature)
+//!
+//! [`Tracer`] is an [`ExecHook`] turning every executed instruction into a
+//! [`TraceEvent`] with its address, raw word, mnemonic and the registers it
+//! changed, and passing it to a [`TraceSink`]: [`TracingSink`] emitting
+//! it as a [`tracing`] event for any subscriber (e.g. `tracing-subscriber`
+//! or a `log` bridge), a closure, or [`WriteSink`] printing one
+//! `key=value` line per instruction. An optional [`RateLimit`] keeps fast
+//! ROMs from flooding the sink.
+//!
+//! ```
+//! use libchip8::emulator::Emulator;
+//! use libchip8::trace::{RateLimit, Tracer, TracingSink};
+//!
+//! let mut e = Emulator::new();
+//! e.store_instr(&[0x6105, 0x00FD]);
+//! let tracer = Tracer::new(TracingSink).with_limit(RateLimit::per_second(1000));
+//! e.add_hook(Box::new(tracer));
+//! e.run().unwrap();
+//! ```
+
+use crate::cpu::{Addr, Instr, Opcode, CPU};
+use crate::diff;
+pub use crate::diff::{Change, Register};
+use crate::hook::ExecHook;
+use std::fmt;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// One executed instruction
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent {
+    pub pc: Addr,
+    pub raw: Instr,
+    pub op: Opcode,
+    /// registers other than pc changed by the instruction, V0..VF first
+    pub changes: Vec<Change>,
+}
+
+/// Logfmt line, e.g. `pc=0x200 raw=0x6105 op="LD V1, 0x05" V1=0x00->0x05`
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "pc=0x{:03X} raw=0x{:04X} op=\"{}\"",
+            self.pc, self.raw, self.op
+        )?;
+        for c in &self.changes {
+            write!(f, " {}", c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Receiver of trace events
+pub trait TraceSink {
+    fn event(&mut self, ev: &TraceEvent);
+
+    /// Called before the first event let through after rate limiting
+    /// dropped n events
+    fn dropped(&mut self, _n: u64) {}
+}
+
+impl<F: FnMut(&TraceEvent)> TraceSink for F {
+    fn event(&mut self, ev: &TraceEvent) {
+        self(ev)
+    }
+}
+
+/// Sink emitting each event at TRACE level with target `libchip8::trace`
+/// and fields `pc`, `raw`, `op` (mnemonic) and `changes` (e.g.
+/// `V1=0x00->0x05 VF=0x00->0x01`); dropped events are reported by a WARN
+/// event with field `dropped`
+pub struct TracingSink;
+
+impl TraceSink for TracingSink {
+    fn event(&mut self, ev: &TraceEvent) {
+        tracing::trace!(
+            target: "libchip8::trace",
+            pc = ev.pc,
+            raw = ev.raw,
+            op = %ev.op,
+            changes = %ev.changes.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(" "),
+        );
+    }
+
+    fn dropped(&mut self, n: u64) {
+        tracing::warn!(target: "libchip8::trace", dropped = n, "trace events dropped");
+    }
+}
+
+/// Sink writing each event as a line (see [`TraceEvent`]'s `Display`);
+/// write errors are ignored
+pub struct WriteSink<W>(pub W);
+
+impl<W: Write> TraceSink for WriteSink<W> {
+    fn event(&mut self, ev: &TraceEvent) {
+        let _ = writeln!(self.0, "{}", ev);
+    }
+
+    fn dropped(&mut self, n: u64) {
+        let _ = writeln!(self.0, "dropped={}", n);
+    }
+}
+
+/// At most max events per window of wall-clock time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub max: u32,
+    pub window: Duration,
+}
+
+impl RateLimit {
+    pub fn per_second(max: u32) -> Self {
+        RateLimit {
+            max,
+            window: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Exec hook passing executed instructions to a sink
+pub struct Tracer<S> {
+    sink: S,
+    limit: Option<RateLimit>,
+    window_start: Option<Instant>,
+    in_window: u32,
+    dropped: u64,
+    before: CPU,
+}
+
+impl<S: TraceSink> Tracer<S> {
+    pub fn new(sink: S) -> Self {
+        Tracer {
+            sink,
+            limit: None,
+            window_start: None,
+            in_window: 0,
+            dropped: 0,
+            before: CPU::new(),
+        }
+    }
+
+    /// Drops events over the limit
+    pub fn with_limit(mut self, limit: RateLimit) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Events dropped since the last one let through
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Whether the next event fits the rate limit
+    fn admit(&mut self) -> bool {
+        let limit = match self.limit {
+            Some(l) => l,
+            None => return true,
+        };
+        let now = Instant::now();
+        if self
+            .window_start
+            .is_none_or(|start| now.duration_since(start) >= limit.window)
+        {
+            self.window_start = Some(now);
+            self.in_window = 0;
+        }
+        if self.in_window < limit.max {
+            self.in_window += 1;
+            true
+        } else {
+            self.dropped += 1;
+            false
+        }
+    }
+}
+
+impl<S: TraceSink> ExecHook for Tracer<S> {
+    fn before(&mut self, _pc: Addr, _op: Opcode, cpu: &CPU) {
+        self.before.clone_from(cpu);
+    }
+
+    fn after(&mut self, pc: Addr, op: Opcode, cpu: &mut CPU) {
+        if !self.admit() {
+            return;
+        }
+        if self.dropped > 0 {
+            self.sink.dropped(std::mem::take(&mut self.dropped));
+        }
+        let ev = TraceEvent {
+            pc,
+            raw: op.to_instr(),
+            op,
+            changes: diff::register_changes(&self.before, cpu),
+        };
+        self.sink.event(&ev);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::Emulator;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    #[test]
+    fn tracer_test() {
+        let events = Rc::new(RefCell::new(vec![]));
+        let log = events.clone();
+        let mut e = Emulator::new();
+        e.add_hook(Box::new(Tracer::new(move |ev: &TraceEvent| {
+            log.borrow_mut().push(ev.clone())
+        })));
+        // LD V1, 5; LD I, 0x300; CALL 0x208; EXIT; RET
+        e.store_instr(&[0x6105, 0xA300, 0x2208, 0x00FD, 0x00EE]);
+        e.run().unwrap();
+        let events = events.borrow();
+        assert_eq!(5, events.len());
+        assert_eq!(
+            "pc=0x200 raw=0x6105 op=\"LD V1, 0x05\" V1=0x00->0x05",
+            events[0].to_string()
+        );
+        assert_eq!(
+            "pc=0x202 raw=0xA300 op=\"LD I, 0x300\" I=0x000->0x300",
+            events[1].to_string()
+        );
+        assert_eq!(
+            vec![Change {
+                reg: Register::SP,
+                old: 0,
+                new: 1
+            }],
+            events[2].changes
+        );
+        assert_eq!((0x208, Opcode::RET), (events[3].pc, events[3].op));
+        assert!(events[4].changes.is_empty());
+    }
+
+    /// Subscriber keeping fields of every event as `name=value` strings
+    struct Collect(Arc<Mutex<Vec<Vec<String>>>>);
+
+    struct Fields<'a>(&'a mut Vec<String>);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.push(format!("{}={:?}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Collect {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = vec![];
+            event.record(&mut Fields(&mut fields));
+            self.0.lock().unwrap().push(fields);
+        }
+
+        fn enter(&self, _: &Id) {}
+
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[test]
+    fn tracing_sink_test() {
+        let events = Arc::new(Mutex::new(vec![]));
+        let limit = RateLimit {
+            max: 1,
+            window: Duration::from_secs(3600),
+        };
+        let mut t = Tracer::new(TracingSink).with_limit(limit);
+        let mut cpu = CPU::new();
+        tracing::subscriber::with_default(Collect(events.clone()), || {
+            t.before(0x200, Opcode::LD(1, 5), &cpu);
+            cpu.regs[1] = 5;
+            t.after(0x200, Opcode::LD(1, 5), &mut cpu);
+            t.after(0x202, Opcode::EXIT, &mut cpu);
+            t.window_start = None;
+            t.after(0x202, Opcode::EXIT, &mut cpu);
+        });
+        let events = events.lock().unwrap();
+        assert_eq!(
+            vec![
+                "pc=512",
+                "raw=24837",
+                "op=LD V1, 0x05",
+                "changes=V1=0x00->0x05"
+            ],
+            events[0]
+        );
+        assert_eq!(vec!["message=trace events dropped", "dropped=1"], events[1]);
+        assert_eq!("pc=514", events[2][0]);
+    }
+
+    #[test]
+    fn rate_limit_test() {
+        let limit = RateLimit {
+            max: 2,
+            window: Duration::from_secs(3600),
+        };
+        let mut t = Tracer::new(WriteSink(vec![])).with_limit(limit);
+        let mut cpu = CPU::new();
+        for _ in 0..5 {
+            t.before(0x200, Opcode::ADD(0, 1), &cpu);
+            t.after(0x200, Opcode::ADD(0, 1), &mut cpu);
+        }
+        assert_eq!(3, t.dropped());
+        assert_eq!(2, String::from_utf8_lossy(&t.sink().0).lines().count());
+        t.window_start = None;
+        t.after(0x200, Opcode::ADD(0, 1), &mut cpu);
+        assert_eq!(0, t.dropped());
+        let out = String::from_utf8_lossy(&t.sink().0).into_owned();
+        assert_eq!("dropped=3", out.lines().nth(2).unwrap());
+    }
+}
@@ -0,0 +1,402 @@
+use crate::cpu::{Addr, Instr, Opcode, CPU};
+use crate::emulator::Emulator;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Broken invariant of emulator state found by [`Emulator::validate`]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Violation {
+    /// stack pointer does not match number of return addresses on stack
+    StackPointer { sp: Addr, depth: usize },
+    /// return address stored on stack points outside memory
+    StackAddrOutOfBounds { depth: usize, addr: Addr },
+    /// pc does not leave room for a full instruction in memory
+    PcOutOfBounds(Addr),
+    /// pc is odd (reported only by strict validation)
+    PcMisaligned(Addr),
+    /// I register points outside memory
+    IOutOfBounds(Addr),
+    /// stack holds more return addresses than [`Emulator::stack_limit`]
+    /// allows
+    StackTooDeep { depth: usize, limit: usize },
+    /// stored font starting at font overlaps program start address
+    FontOverlapsProgram { font: Addr, start: Addr },
+    /// screen is in 64x64 mode with [`Emulator::two_page_hires`] off
+    TwoPageDisabled,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::StackPointer { sp, depth } => {
+                write!(f, "sp is {} but stack holds {} addresses", sp, depth)
+            }
+            Violation::StackAddrOutOfBounds { depth, addr } => write!(
+                f,
+                "return address 0x{:04X} at stack depth {} is outside memory",
+                addr, depth
+            ),
+            Violation::PcOutOfBounds(a) => write!(f, "pc 0x{:04X} is outside memory", a),
+            Violation::PcMisaligned(a) => write!(f, "pc 0x{:04X} is not even", a),
+            Violation::IOutOfBounds(a) => write!(f, "I 0x{:04X} is outside memory", a),
+            Violation::StackTooDeep { depth, limit } => {
+                write!(f, "stack holds {} addresses, limit is {}", depth, limit)
+            }
+            Violation::FontOverlapsProgram { font, start } => write!(
+                f,
+                "font at 0x{:04X} overlaps program start 0x{:04X}",
+                font, start
+            ),
+            Violation::TwoPageDisabled => {
+                write!(f, "screen is 64x64 but two-page hi-res is disabled")
+            }
+        }
+    }
+}
+
+/// Collects all violations found in emulator state
+pub(crate) fn check(e: &Emulator, strict: bool) -> Vec<Violation> {
+    let mut res = vec![];
+    let size = e.mem.size();
+    let cpu = &e.cpu;
+
+    if cpu.sp as usize != cpu.stack_depth() {
+        res.push(Violation::StackPointer {
+            sp: cpu.sp,
+            depth: cpu.stack_depth(),
+        });
+    }
+    for (depth, &addr) in cpu.stack().iter().enumerate() {
+        if addr as usize + 1 >= size {
+            res.push(Violation::StackAddrOutOfBounds { depth, addr });
+        }
+    }
+    if cpu.pc as usize + 1 >= size {
+        res.push(Violation::PcOutOfBounds(cpu.pc));
+    }
+    if strict && cpu.pc & 1 == 1 {
+        res.push(Violation::PcMisaligned(cpu.pc));
+    }
+    if cpu.i as usize >= size {
+        res.push(Violation::IOutOfBounds(cpu.i));
+    }
+    if cpu.stack_depth() > e.stack_limit {
+        res.push(Violation::StackTooDeep {
+            depth: cpu.stack_depth(),
+            limit: e.stack_limit,
+        });
+    }
+    if e.mem.font_set().is_some() {
+        let (font, start) = (e.mem.font_start(), e.start_addr());
+        if (font..e.mem.font_end()).contains(&start) {
+            res.push(Violation::FontOverlapsProgram { font, start });
+        }
+    }
+    if e.scr.two_page() && !e.two_page_hires {
+        res.push(Violation::TwoPageDisabled);
+    }
+    res
+}
+
+/// Problem found by static ROM validation ([`crate::loader::validate`])
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Issue {
+    /// reachable word does not decode to an instruction
+    Undecodable { addr: Addr, raw: Instr },
+    /// execution continues past the last byte of the ROM
+    RunsPastEnd { addr: Addr },
+    /// JP or CALL at addr targets address outside the ROM
+    TargetOutsideRom { addr: Addr, target: Addr },
+    /// instruction at addr accesses len bytes from I past end of memory
+    IOverflow { addr: Addr, i: Addr, len: usize },
+    /// RET at addr is reachable with empty stack
+    RetWithoutCall { addr: Addr },
+    /// CALL at addr is reachable with full stack
+    StackOverflow { addr: Addr },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::Undecodable { addr, raw } => {
+                write!(f, "0x{:03X}: 0x{:04X} is not an instruction", addr, raw)
+            }
+            Issue::RunsPastEnd { addr } => {
+                write!(f, "0x{:03X}: execution runs past end of ROM", addr)
+            }
+            Issue::TargetOutsideRom { addr, target } => {
+                write!(f, "0x{:03X}: target 0x{:03X} is outside ROM", addr, target)
+            }
+            Issue::IOverflow { addr, i, len } => write!(
+                f,
+                "0x{:03X}: {} bytes at I = 0x{:03X} exceed memory",
+                addr, len, i
+            ),
+            Issue::RetWithoutCall { addr } => write!(f, "0x{:03X}: RET without CALL", addr),
+            Issue::StackOverflow { addr } => write!(f, "0x{:03X}: CALL nested too deep", addr),
+        }
+    }
+}
+
+/// Result of static ROM validation, issues ordered by address
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Report {
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Execution state followed by [`check_rom`]: pc, I if known, return addresses
+type Path = (Addr, Option<Addr>, Vec<Addr>);
+
+/// Upper bound on states visited, keeping validation of ROMs with many
+/// I values fast
+const MAX_STATES: usize = 1 << 16;
+
+fn add(issues: &mut Vec<Issue>, issue: Issue) {
+    if !issues.contains(&issue) {
+        issues.push(issue);
+    }
+}
+
+/// Follows every path through ROM loaded at base, tracking constant I
+/// loads and the call stack
+pub(crate) fn check_rom(bytes: &[u8], base: Addr, mem_size: usize) -> Report {
+    let end = base as usize + bytes.len();
+    let inside = |a: Addr| (base as usize..end).contains(&(a as usize));
+    let word = |a: Addr| {
+        let off = a.wrapping_sub(base) as usize;
+        bytes
+            .get(off..off + 2)
+            .map(|w| (w[0] as Instr) << 8 | w[1] as Instr)
+    };
+    let mut issues = vec![];
+    let mut seen: HashSet<Path> = HashSet::new();
+    let mut todo: Vec<Path> = vec![(base, None, vec![])];
+    while let Some(state) = todo.pop() {
+        if seen.len() >= MAX_STATES || !seen.insert(state.clone()) {
+            continue;
+        }
+        let (pc, i, stack) = state;
+        let raw = match word(pc) {
+            Some(raw) => raw,
+            None => {
+                add(&mut issues, Issue::RunsPastEnd { addr: pc });
+                continue;
+            }
+        };
+        let op = match Opcode::from(raw) {
+            Some(op) => op,
+            None => {
+                add(&mut issues, Issue::Undecodable { addr: pc, raw });
+                continue;
+            }
+        };
+        let size = |a: Addr| match word(a).and_then(Opcode::from) {
+            Some(Opcode::LDIL) => 4,
+            _ => 2,
+        };
+        let next = pc.wrapping_add(size(pc));
+        let accessed = match op {
+            Opcode::DRW(_, _, 0) => Some(32),
+            Opcode::DRW(_, _, n) => Some(n as usize),
+            Opcode::BCD(_) => Some(3),
+            Opcode::REGSSTORE(x) | Opcode::REGLOAD(x) => Some(x + 1),
+            _ => None,
+        };
+        if let (Some(len), Some(i)) = (accessed, i) {
+            if i as usize + len > mem_size {
+                add(&mut issues, Issue::IOverflow { addr: pc, i, len });
+            }
+        }
+        let jump = match op {
+            Opcode::JP(a) => Some((a, stack.clone())),
+            Opcode::CALL(_) if stack.len() >= CPU::STACK_SIZE => {
+                add(&mut issues, Issue::StackOverflow { addr: pc });
+                None
+            }
+            Opcode::CALL(a) => {
+                let mut inner = stack.clone();
+                inner.push(next);
+                Some((a, inner))
+            }
+            _ => None,
+        };
+        if let Some((target, stack)) = jump {
+            if inside(target) {
+                todo.push((target, i, stack));
+            } else {
+                add(&mut issues, Issue::TargetOutsideRom { addr: pc, target });
+            }
+        }
+        match op {
+            // JPOFF target depends on V0
+            Opcode::EXIT | Opcode::JP(_) | Opcode::CALL(_) | Opcode::JPOFF(_) => {}
+            Opcode::RET => {
+                let mut outer = stack;
+                match outer.pop() {
+                    Some(ret) => todo.push((ret, i, outer)),
+                    None => add(&mut issues, Issue::RetWithoutCall { addr: pc }),
+                }
+            }
+            Opcode::SE(..)
+            | Opcode::SNE(..)
+            | Opcode::SER(..)
+            | Opcode::SNER(..)
+            | Opcode::SKP(_)
+            | Opcode::SKNP(_) => {
+                todo.push((next, i, stack.clone()));
+                todo.push((next.wrapping_add(size(next)), i, stack));
+            }
+            Opcode::LDI(a) => todo.push((next, Some(a), stack)),
+            Opcode::LDIL => todo.push((next, word(pc.wrapping_add(2)), stack)),
+            // I changes by register value or, depending on quirks, by X + 1
+            Opcode::IINC(_)
+            | Opcode::IDIG(_)
+            | Opcode::LDHF(_)
+            | Opcode::REGSSTORE(_)
+            | Opcode::REGLOAD(_) => todo.push((next, None, stack)),
+            _ => todo.push((next, i, stack)),
+        }
+    }
+    issues.sort_by_key(|issue| match *issue {
+        Issue::Undecodable { addr, .. }
+        | Issue::RunsPastEnd { addr }
+        | Issue::TargetOutsideRom { addr, .. }
+        | Issue::IOverflow { addr, .. }
+        | Issue::RetWithoutCall { addr }
+        | Issue::StackOverflow { addr } => addr,
+    });
+    Report { issues }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fresh_emulator_is_valid_test() {
+        let mut e = Emulator::new();
+        e.store_instr(&[0x6001]);
+        assert_eq!(Vec::<Violation>::new(), e.validate_strict());
+    }
+
+    #[test]
+    fn sp_mismatch_test() {
+        let mut e = Emulator::new();
+        e.cpu.sp = 2;
+        assert_eq!(
+            vec![Violation::StackPointer { sp: 2, depth: 0 }],
+            e.validate()
+        );
+    }
+
+    #[test]
+    fn bounds_test() {
+        let mut e = Emulator::new();
+        e.cpu.pc = 0x0FFF;
+        e.cpu.i = 0x1000;
+        assert_eq!(
+            vec![
+                Violation::PcOutOfBounds(0x0FFF),
+                Violation::IOutOfBounds(0x1000)
+            ],
+            e.validate()
+        );
+    }
+
+    #[test]
+    fn quirk_state_test() {
+        let mut e = Emulator::new();
+        e.stack_limit = 1;
+        e.two_page_hires = false;
+        e.cpu.call(0x300).unwrap();
+        e.cpu.call(0x400).unwrap();
+        e.mem.store_font(0x180);
+        e.scr.set_two_page(true);
+        assert_eq!(
+            vec![
+                Violation::StackTooDeep { depth: 2, limit: 1 },
+                Violation::FontOverlapsProgram {
+                    font: 0x180,
+                    start: 0x200
+                },
+                Violation::TwoPageDisabled,
+            ],
+            e.validate()
+        );
+        e.two_page_hires = true;
+        e.mem.store_font(0);
+        e.stack_limit = 2;
+        assert!(e.validate().is_empty());
+    }
+
+    #[test]
+    fn strict_alignment_test() {
+        let mut e = Emulator::new();
+        e.cpu.pc = 0x201;
+        assert!(e.validate().is_empty());
+        assert_eq!(vec![Violation::PcMisaligned(0x201)], e.validate_strict());
+    }
+
+    fn rom_issues(src: &str) -> Vec<Issue> {
+        let bytes = crate::asm::assemble(src).unwrap();
+        check_rom(&bytes, 0x200, 0x1000).issues
+    }
+
+    #[test]
+    fn clean_rom_test() {
+        let src = "CALL sub\nloop: JP loop\nsub: LD I, 0xF00\nDRW V0, V1, 15\nRET";
+        assert_eq!(Vec::<Issue>::new(), rom_issues(src));
+    }
+
+    #[test]
+    fn rom_issues_test() {
+        let src = "
+            SE V0, 1
+            JP 0x100
+            LD I, 0xFFE
+            LD [I], V2
+            RET
+        ";
+        assert_eq!(
+            vec![
+                Issue::TargetOutsideRom {
+                    addr: 0x202,
+                    target: 0x100
+                },
+                Issue::IOverflow {
+                    addr: 0x206,
+                    i: 0xFFE,
+                    len: 3
+                },
+                Issue::RetWithoutCall { addr: 0x208 },
+            ],
+            rom_issues(src)
+        );
+        assert_eq!(
+            vec![Issue::Undecodable {
+                addr: 0x202,
+                raw: 0xFFFF
+            }],
+            rom_issues("CLS\nDW 0xFFFF")
+        );
+        assert_eq!(vec![Issue::RunsPastEnd { addr: 0x202 }], rom_issues("CLS"));
+        let deep = rom_issues("again: CALL again");
+        assert_eq!(vec![Issue::StackOverflow { addr: 0x200 }], deep);
+        assert_eq!("0x200: CALL nested too deep", deep[0].to_string());
+    }
+}
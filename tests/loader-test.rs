@@ -2,13 +2,18 @@
 mod xtests {
 
     use libchip8::emulator::Emulator;
+    use libchip8::error::Chip8Error;
     use libchip8::loader::load;
 
     #[test]
     fn add_test() {
         let mut e = Emulator::new();
-        load(&mut e, &String::from("tests/hex.b"));
-        e.run();
+        assert_eq!(6, load(&mut e, "tests/hex.b").unwrap());
+        let end = Chip8Error::InvalidOpcode {
+            addr: 0x206,
+            raw: 0,
+        };
+        assert_eq!(Err(end), e.run());
         assert_eq!(e.cpu.regs[1], 0xE);
     }
 }
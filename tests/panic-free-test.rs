@@ -0,0 +1,47 @@
+//! Runs random ROMs to back the panic-free guarantee of `Emulator::step`;
+//! `fuzz/` holds the corresponding cargo-fuzz target.
+use libchip8::emulator::Emulator;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+fn run_random_rom(rng: &mut StdRng, len: usize, steps: usize) {
+    let mut e = Emulator::new();
+    e.store_font();
+    let rom: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+    e.store_bytes(&rom);
+    for _ in 0..steps {
+        // errors are fine, only panics are not
+        let _ = e.step();
+        e.tick();
+    }
+}
+
+#[test]
+fn random_roms_test() {
+    let mut rng = StdRng::seed_from_u64(0x0C8);
+    for _ in 0..200 {
+        run_random_rom(&mut rng, 3584, 2_000);
+    }
+}
+
+#[test]
+fn memory_edges_test() {
+    let mut e = Emulator::new();
+    // I = 0xFFF, V0 = 255: bcd digits wrap around to the start of memory
+    e.store_instr(&[0xAFFF, 0x60FF, 0xF033]);
+    for _ in 0..3 {
+        e.step().unwrap();
+    }
+    assert_eq!(Some(&[5u8, 5u8][..]), e.mem.get(0..2));
+    assert_eq!(2, e.mem.load(0xFFF));
+
+    // draw, store, load and jump across the end of memory
+    e.store_instr(&[0xAFFF, 0xD0FF, 0xFF55, 0xFF65, 0xF01E, 0x1FFE]);
+    for _ in 0..6 {
+        e.step().unwrap();
+    }
+    assert_eq!(0xFFE, e.cpu.pc);
+    for _ in 0..16 {
+        let _ = e.step();
+    }
+}
@@ -0,0 +1,25 @@
+use libchip8::testing;
+
+/// Runs Timendus' test suite ROMs found in directory given by
+/// `CHIP8_TEST_ROMS`; does nothing when it is not set
+#[test]
+fn timendus_suite() {
+    let dir = match std::env::var("CHIP8_TEST_ROMS") {
+        Ok(dir) => dir,
+        Err(_) => return,
+    };
+    let cases = testing::timendus_cases(&dir).unwrap();
+    let failed: Vec<_> = testing::run_suite(&cases)
+        .into_iter()
+        .filter(|o| !o.passed())
+        .collect();
+    for o in &failed {
+        eprintln!("{}: {}\n{}", o.name, o.failures.join("; "), o.screen);
+    }
+    assert!(
+        failed.is_empty(),
+        "{} of {} ROMs failed",
+        failed.len(),
+        cases.len()
+    );
+}